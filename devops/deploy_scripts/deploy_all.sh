@@ -9,6 +9,7 @@ dfx canister create --all --network "$NETWORK"
 dfx build --all --network "$NETWORK"
 
 bash "$SCRIPT_DIR/deploy_token.sh" "$NETWORK"
+bash "$SCRIPT_DIR/deploy_ckusdc.sh" "$NETWORK"
 bash "$SCRIPT_DIR/deploy_nft.sh" "$NETWORK"
 bash "$SCRIPT_DIR/deploy_dao.sh" "$NETWORK"
 bash "$SCRIPT_DIR/deploy_hiving.sh" "$NETWORK"
@@ -0,0 +1,32 @@
+#!/bin/bash
+
+# Deploys an ICRC-2 ledger standing in for ckUSDC on local networks.
+# On ic the mainnet ckUSDC ledger is used as a remote canister.
+
+NETWORK="${1}"
+OWNER="pvd_owner"
+
+if [ "$NETWORK" == "ic" ]; then
+  exit 0
+fi
+
+CKUSDC_MINTER=$(dfx --identity "$OWNER" identity get-principal)
+
+ARGUMENT="(
+  record {
+    token_symbol = \"ckUSDC\";
+    token_name = \"ckUSDC local\";
+    minting_account = record {
+        owner = principal \"${CKUSDC_MINTER}\";
+    };
+    transfer_fee = 10_000;
+    metadata = vec {};
+    decimals = opt (6 : nat8);
+  }
+)"
+echo "$ARGUMENT"
+
+dfx deploy ckusdc \
+  --mode reinstall \
+  --network "$NETWORK" \
+  --argument "$ARGUMENT"
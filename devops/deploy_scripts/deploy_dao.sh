@@ -9,7 +9,7 @@ GENESIS=$(date +%s)000000000 # now
 #CYCLE_LEN_SEC=864000 # 10 days
 CYCLE_LEN_SEC=$((60*60)) # 60 minutes
 
-ARGUMENT="(
+ARGUMENT="(variant { Init =
   record {
     staking = record {};
     cycles = record {
@@ -20,17 +20,33 @@ ARGUMENT="(
     };
     discounts = record {
       discounts_per_cycle = 5;
+      default_validity_cycles = 4;
+      discounts_per_wallet_per_cycle = 3;
+    };
+    hiving = record {
+      allowlist = vec {};
+      heartbeat_interval_ns = (3_600_000_000_000 : nat64);
+      max_missed_heartbeats = (3 : nat32);
+    };
+    emission = record {
+      budget_per_cycle = 0;
+      minting_subaccount = null;
+    };
+    voter_rewards = record {
+      pool_per_cycle = 0;
+      treasury_subaccount = null;
     };
     token_canister_id = principal \"${TOKEN_CANISTER}\";
     nft_canister_id = principal \"${NFT_CANISTER}\";
   }
-)"
+})"
 
 echo "$ARGUMENT"
 
+# upgrades keep the stored config unless sections are passed, e.g. "(variant { Upgrade = null })"
 #dfx deploy dao \
 #  --network "$NETWORK" \
-#  --argument "$ARGUMENT"
+#  --argument "(variant { Upgrade = null })"
 
 dfx deploy dao \
   --mode reinstall \
@@ -3,10 +3,12 @@
 NETWORK="${1}"
 
 DAO_CANISTER=$(dfx canister id --network "$NETWORK" dao)
+CKUSDC_CANISTER=$(dfx canister id --network "$NETWORK" ckusdc)
 
 ARGUMENT="(
   record {
     dao_address = principal \"${DAO_CANISTER}\";
+    ckusdc_address = principal \"${CKUSDC_CANISTER}\";
   }
 )"
 
@@ -0,0 +1,31 @@
+#!/bin/bash
+
+# Uploads the built hiving or hiving_pool WASM to the DAO in chunks and approves it as the next
+# version spawned by the DAO factory. Has to be run by a DAO controller.
+# Usage: upload_hiving_wasm.sh <network> [hiving|hiving_pool]
+
+NETWORK="${1}"
+CANISTER="${2:-hiving}"
+CHUNK_SIZE=1000000
+
+case "$CANISTER" in
+  hiving) KIND="Hiving" ;;
+  hiving_pool) KIND="HivingPool" ;;
+  *) echo "Unknown canister $CANISTER" && exit 1 ;;
+esac
+
+WASM=".dfx/${NETWORK}/canisters/${CANISTER}/${CANISTER}.wasm"
+CHUNKS_DIR=$(mktemp -d)
+trap 'rm -rf "$CHUNKS_DIR"' EXIT
+
+dfx canister call --network "$NETWORK" dao wasm_clear_upload "(variant { ${KIND} })"
+
+split -b "$CHUNK_SIZE" -d -a 4 "$WASM" "$CHUNKS_DIR/chunk_"
+for CHUNK in "$CHUNKS_DIR"/chunk_*; do
+  BLOB=$(od -An -v -tx1 "$CHUNK" | tr -d ' \n' | sed 's/../\\&/g')
+  echo "(variant { ${KIND} }, blob \"${BLOB}\")" > "$CHUNK.args"
+  dfx canister call --network "$NETWORK" dao wasm_upload_chunk --argument-file "$CHUNK.args"
+done
+
+HASH=$(sha256sum "$WASM" | cut -d ' ' -f 1 | sed 's/../\\&/g')
+dfx canister call --network "$NETWORK" dao wasm_approve "(variant { ${KIND} }, blob \"${HASH}\")"
@@ -1,82 +1,332 @@
-use crate::app::{app_services, AppConfig};
+use crate::app::{app_services, jobs, AppConfig, ConfigPatch, DaoArgs};
+use crate::domain::cycles::CycleEpoch;
 use abstractions::dao::*;
-use abstractions::Account;
-use candid::Nat;
-use ic_cdk::{init, query, update};
+use abstractions::hiving::{HivingHeartbeat, RegisterHivingCanisterArgs};
+use abstractions::{Account, MetadataValue};
+use candid::{Nat, Principal};
+use ic_cdk::{init, post_upgrade, query, update};
 
 // canister mgmt
 
 #[init]
-fn init(config: AppConfig) {
-    app_services::mgmt::init(config);
+fn init(args: DaoArgs) {
+    app_services::mgmt::init(args);
+    jobs::start_timer();
+}
+
+#[post_upgrade]
+fn post_upgrade(args: Option<DaoArgs>) {
+    app_services::mgmt::post_upgrade(args);
+    jobs::start_timer();
+}
+
+#[query]
+pub fn dao_get_config() -> Result<AppConfig, DaoError> {
+    Ok(app_services::config::get_config())
+}
+
+#[update]
+pub fn dao_update_config(patch: ConfigPatch) -> Result<AppConfig, DaoError> {
+    app_services::config::update_config(patch)
 }
 
 // hiving
 
 #[update]
-pub fn hiving_join() {
-    app_services::hiving::join()
+pub fn hiving_join(args: RegisterHivingCanisterArgs) -> Result<(), DaoError> {
+    app_services::hiving::join(args)
 }
 
 #[update]
-pub fn hiving_leave() {
+pub fn hiving_leave() -> Result<(), DaoError> {
     app_services::hiving::leave()
 }
 
+#[update]
+pub fn hiving_heartbeat(heartbeat: HivingHeartbeat) -> Result<u64, DaoError> {
+    app_services::hiving::heartbeat(heartbeat)
+}
+
+#[update]
+pub fn hiving_update_metadata(metadata: Vec<(String, MetadataValue)>) -> Result<(), DaoError> {
+    app_services::hiving::update_metadata(metadata)
+}
+
+#[update]
+pub fn hiving_approve(canister_id: Principal) -> Result<(), DaoError> {
+    app_services::hiving::approve(canister_id)
+}
+
+#[update]
+pub fn hiving_suspend(canister_id: Principal) -> Result<(), DaoError> {
+    app_services::hiving::suspend(canister_id)
+}
+
+#[update]
+pub fn hiving_reactivate(canister_id: Principal) -> Result<(), DaoError> {
+    app_services::hiving::reactivate(canister_id)
+}
+
+#[query]
+pub fn hiving_get(canister_id: Principal) -> Result<Option<HivingCanister>, DaoError> {
+    Ok(app_services::hiving::get(canister_id))
+}
+
+#[query]
+pub fn hiving_list(status: Option<HivingCanisterStatus>, prev: Option<Principal>, take: Option<u32>) -> Result<Vec<HivingCanister>, DaoError> {
+    Ok(app_services::hiving::list(status, prev, take))
+}
+
+#[update]
+pub fn hiving_authorize(canister_id: Principal) -> Result<(), DaoError> {
+    app_services::hiving::authorize(canister_id);
+    Ok(())
+}
+
+#[update]
+pub fn hiving_revoke(canister_id: Principal) -> Result<(), DaoError> {
+    app_services::hiving::revoke(canister_id);
+    Ok(())
+}
+
+// hiving canister factory
+
+#[update]
+pub fn wasm_upload_chunk(kind: WasmKind, chunk: Vec<u8>) -> Result<u32, DaoError> {
+    app_services::factory::upload_wasm_chunk(kind, chunk)
+}
+
+#[update]
+pub fn wasm_clear_upload(kind: WasmKind) -> Result<(), DaoError> {
+    app_services::factory::clear_wasm_upload(kind)
+}
+
+#[update]
+pub fn wasm_approve(kind: WasmKind, hash: Vec<u8>) -> Result<WasmModule, DaoError> {
+    app_services::factory::approve_wasm(kind, hash)
+}
+
+#[query]
+pub fn wasm_list(kind: WasmKind) -> Result<Vec<WasmModule>, DaoError> {
+    Ok(app_services::factory::list_wasms(kind))
+}
+
+#[update]
+pub fn spawn_allow(owner: Principal) -> Result<(), DaoError> {
+    app_services::factory::allow_spawn(owner)
+}
+
+#[update]
+pub async fn spawn_hiving_canister(owner: Principal, config: SpawnConfig) -> Result<Principal, DaoError> {
+    app_services::factory::spawn_hiving_canister(owner, config).await
+}
+
+// hiving canister fleet upgrades
+
+#[update]
+pub fn fleet_upgrade_pause(kind: WasmKind) -> Result<FleetUpgrade, DaoError> {
+    app_services::fleet::pause_upgrade(kind)
+}
+
+#[query]
+pub fn fleet_upgrade_get(kind: WasmKind) -> Result<Option<FleetUpgrade>, DaoError> {
+    Ok(app_services::fleet::get_upgrade(kind))
+}
+
+#[query]
+pub fn fleet_upgrade_canisters(kind: WasmKind, prev: Option<Principal>, take: Option<u32>) -> Result<Vec<CanisterUpgrade>, DaoError> {
+    Ok(app_services::fleet::get_canister_upgrades(kind, prev, take))
+}
+
 // voting
 
 #[update]
-pub async fn voting_create_proposal(proposal_type: ProposalType, data: String) -> u64 {
+pub async fn voting_create_proposal(proposal_type: ProposalType, data: String) -> Result<u64, DaoError> {
     app_services::voting::voting_create_proposal(proposal_type, data).await
 }
 
 #[query]
-pub fn voting_get_proposal(proposal_id: u64) -> Option<Proposal> {
-    app_services::voting::voting_get_proposal(proposal_id)
+pub fn voting_get_proposal(proposal_id: u64) -> Result<Option<Proposal>, DaoError> {
+    Ok(app_services::voting::voting_get_proposal(proposal_id))
 }
 
 #[update]
-pub async fn voting_vote(proposal_id: u64, vote: VoteOption) -> u64 {
+pub async fn voting_vote(proposal_id: u64, vote: VoteOption) -> Result<u64, DaoError> {
     app_services::voting::voting_vote(proposal_id, vote).await
 }
 
+#[update]
+pub fn voting_execute_proposal(proposal_id: u64) -> Result<(), DaoError> {
+    app_services::voting::voting_execute_proposal(proposal_id)
+}
+
 #[query]
-pub fn voting_get_vote(vote_id: u64) -> Option<Vote> {
-    app_services::voting::voting_get_vote(vote_id)
+pub fn voting_get_vote(vote_id: u64) -> Result<Option<Vote>, DaoError> {
+    Ok(app_services::voting::voting_get_vote(vote_id))
 }
 
 #[query]
-pub fn voting_get_all_votes(proposal_id: u64) -> Vec<Vote> {
+pub fn voting_get_all_votes(proposal_id: u64) -> Result<Vec<Vote>, DaoError> {
     app_services::voting::voting_get_all_votes(proposal_id)
 }
 
 // cycles
 
 #[query]
-pub fn get_current_cycle() -> Cycle {
+pub fn get_current_cycle() -> Result<Cycle, DaoError> {
     app_services::discounts::get_current_cycle()
 }
 
+#[query]
+pub fn get_cycle(cycle_number: u64) -> Result<Cycle, DaoError> {
+    app_services::discounts::get_cycle(cycle_number)
+}
+
+#[query]
+pub fn list_cycles(from: u64, to: u64) -> Result<Vec<Cycle>, DaoError> {
+    app_services::discounts::list_cycles(from, to)
+}
+
+#[query]
+pub fn get_next_voting_cycle() -> Result<Cycle, DaoError> {
+    app_services::discounts::get_next_voting_cycle()
+}
+
+#[query]
+pub fn get_cycle_calendar() -> Result<Vec<CycleEpoch>, DaoError> {
+    app_services::discounts::get_cycle_calendar()
+}
+
+#[update]
+pub fn schedule_cycle_epoch(start_cycle: u64, cycle_len_ns: u64) -> Result<Vec<CycleEpoch>, DaoError> {
+    app_services::discounts::schedule_cycle_epoch(start_cycle, cycle_len_ns)
+}
+
 // staking
 
 #[update]
-pub async fn get_staking_score(principal: Account) -> Nat {
+pub async fn get_staking_score(principal: Account) -> Result<Nat, DaoError> {
     app_services::discounts::get_staking_score(principal).await
 }
 
 // discounts
 
 #[update]
-pub async fn calculate_discount(hiver: Account, price: u128) -> f32 {
+pub async fn calculate_discount(hiver: Account, price: u128) -> Result<f32, DaoError> {
     app_services::discounts::calculate_discount(hiver, price).await
 }
 
 #[update]
-pub async fn mint_discount(hiver: Account, discount: DiscountRequest) -> u128 {
+pub async fn mint_discount(hiver: Account, discount: DiscountRequest) -> Result<u128, DaoError> {
     app_services::discounts::mint_discount(hiver, discount).await
 }
 
+#[query]
+pub fn get_discount_quota(buyer: Account, hiver: Account) -> Result<DiscountQuota, DaoError> {
+    app_services::discounts::get_quota(buyer, hiver)
+}
+
+#[update]
+pub async fn audit_discount_mints() -> Result<DiscountMintAudit, DaoError> {
+    app_services::discounts::audit_mints().await
+}
+
+#[query]
+pub fn get_discount_mint(mint_key: u64) -> Result<Option<DiscountMintState>, DaoError> {
+    Ok(app_services::discounts::get_mint_state(mint_key))
+}
+
 #[update]
-pub async fn get_discount(dicount_id: u128) -> Discount {
+pub async fn get_discount(dicount_id: u128) -> Result<Discount, DaoError> {
     app_services::discounts::get_discount(dicount_id).await
 }
+
+#[query]
+pub fn list_discounts_by_cycle(cycle_number: u64, prev: Option<u128>, take: Option<u32>) -> Result<Vec<Discount>, DaoError> {
+    Ok(app_services::discounts::list_discounts_by_cycle(cycle_number, prev, take))
+}
+
+#[query]
+pub fn list_discounts_by_owner(owner: Account, prev: Option<u128>, take: Option<u32>) -> Result<Vec<Discount>, DaoError> {
+    Ok(app_services::discounts::list_discounts_by_owner(owner, prev, take))
+}
+
+#[query]
+pub fn list_discounts_by_hiver(hiver: Account, prev: Option<u128>, take: Option<u32>) -> Result<Vec<Discount>, DaoError> {
+    Ok(app_services::discounts::list_discounts_by_hiver(hiver, prev, take))
+}
+
+// merchants
+
+#[update]
+pub fn merchant_register(merchant_id: Principal, name: String) -> Result<(), DaoError> {
+    app_services::merchants::register_merchant(merchant_id, name)
+}
+
+#[update]
+pub fn merchant_remove(merchant_id: Principal) -> Result<(), DaoError> {
+    app_services::merchants::remove_merchant(merchant_id)
+}
+
+#[query]
+pub fn merchant_list() -> Result<Vec<Merchant>, DaoError> {
+    Ok(app_services::merchants::get_merchants())
+}
+
+#[update]
+pub async fn redeem_discount(token_id: u128, customer: Account, order_ref: String) -> Result<Discount, DaoError> {
+    app_services::merchants::redeem_discount(token_id, customer, order_ref).await
+}
+
+#[query]
+pub fn verify_discount(token_id: u128) -> Result<DiscountValidity, DaoError> {
+    Ok(app_services::merchants::verify_discount(token_id))
+}
+
+// emission
+
+#[update]
+pub async fn claim_staking_rewards() -> Result<StakingRewardsClaim, DaoError> {
+    app_services::emission::claim_staking_rewards().await
+}
+
+#[query]
+pub fn get_cycle_emission(cycle_number: u64) -> Result<Option<CycleEmission>, DaoError> {
+    Ok(app_services::emission::get_cycle_emission(cycle_number))
+}
+
+// voter rewards
+
+#[update]
+pub async fn claim_voter_rewards() -> Result<Nat, DaoError> {
+    app_services::voter_rewards::claim_voter_rewards().await
+}
+
+#[query]
+pub fn get_voter_rewards(voter: Principal) -> Result<Nat, DaoError> {
+    Ok(app_services::voter_rewards::get_balance(voter))
+}
+
+#[query]
+pub fn get_voter_reward_allocation(cycle_number: u64) -> Result<Option<VoterRewardAllocation>, DaoError> {
+    Ok(app_services::voter_rewards::get_allocation(cycle_number))
+}
+
+// events
+
+#[query]
+pub fn get_events(start: u64, limit: Option<u32>, filter: Option<DaoEventFilter>) -> Result<DaoEventPage, DaoError> {
+    Ok(app_services::events::get_events(start, limit, filter))
+}
+
+// reconciliation
+
+#[update]
+pub fn reconciliation_start() -> Result<(), DaoError> {
+    app_services::reconciliation::start()
+}
+
+#[query]
+pub fn reconciliation_report() -> Result<Option<ReconciliationReport>, DaoError> {
+    Ok(app_services::reconciliation::get_report())
+}
@@ -0,0 +1,28 @@
+use crate::app::service_builder;
+use abstractions::dao::{DaoError, SpawnConfig, WasmKind, WasmModule};
+use candid::Principal;
+
+pub fn upload_wasm_chunk(kind: WasmKind, chunk: Vec<u8>) -> Result<u32, DaoError> {
+    service_builder::build_factory_service().upload_wasm_chunk(kind, chunk)
+}
+
+pub fn clear_wasm_upload(kind: WasmKind) -> Result<(), DaoError> {
+    service_builder::build_factory_service().clear_wasm_upload(kind)
+}
+
+pub fn approve_wasm(kind: WasmKind, hash: Vec<u8>) -> Result<WasmModule, DaoError> {
+    service_builder::build_factory_service().approve_wasm(kind, hash)
+}
+
+pub fn list_wasms(kind: WasmKind) -> Vec<WasmModule> {
+    service_builder::build_factory_service().get_wasm_modules(kind)
+}
+
+pub fn allow_spawn(owner: Principal) -> Result<(), DaoError> {
+    service_builder::build_factory_service().allow_spawn(owner)
+}
+
+pub async fn spawn_hiving_canister(owner: Principal, config: SpawnConfig) -> Result<Principal, DaoError> {
+    let service = service_builder::build_factory_service();
+    service.spawn_hiving_canister(owner, config).await
+}
@@ -0,0 +1,32 @@
+use crate::app::service_builder;
+use abstractions::dao::{CanisterUpgrade, DaoError, FleetUpgrade, WasmKind};
+use candid::Principal;
+
+pub fn start_upgrade(kind: WasmKind, version: u32) -> Result<FleetUpgrade, DaoError> {
+    service_builder::build_fleet_service().start_upgrade(kind, version)
+}
+
+pub fn pause_upgrade(kind: WasmKind) -> Result<FleetUpgrade, DaoError> {
+    service_builder::build_fleet_service().pause_upgrade(kind)
+}
+
+pub fn resume_upgrade(kind: WasmKind) -> Result<FleetUpgrade, DaoError> {
+    service_builder::build_fleet_service().resume_upgrade(kind)
+}
+
+pub fn rollback_upgrade(kind: WasmKind) -> Result<FleetUpgrade, DaoError> {
+    service_builder::build_fleet_service().rollback_upgrade(kind)
+}
+
+pub fn get_upgrade(kind: WasmKind) -> Option<FleetUpgrade> {
+    service_builder::build_fleet_service().get_upgrade(kind)
+}
+
+pub fn get_canister_upgrades(kind: WasmKind, prev: Option<Principal>, take: Option<u32>) -> Vec<CanisterUpgrade> {
+    service_builder::build_fleet_service().get_canister_upgrades(kind, prev, take)
+}
+
+pub async fn upgrade_next_batch() {
+    let service = service_builder::build_fleet_service();
+    service.upgrade_next_batch().await
+}
@@ -1,11 +1,62 @@
 use crate::app::service_builder;
+use abstractions::dao::{DaoError, HivingCanister, HivingCanisterStatus};
+use abstractions::hiving::{HivingHeartbeat, RegisterHivingCanisterArgs};
+use abstractions::MetadataValue;
+use candid::Principal;
 
-pub fn join() {
+pub fn join(args: RegisterHivingCanisterArgs) -> Result<(), DaoError> {
     let service = service_builder::build_hiving_service();
-    service.add_hiving_canister();
+    service.add_hiving_canister(args)
 }
 
-pub fn leave() {
+pub fn leave() -> Result<(), DaoError> {
     let service = service_builder::build_hiving_service();
-    service.remove_hiving_canister();
+    service.remove_hiving_canister()
+}
+
+pub fn heartbeat(heartbeat: HivingHeartbeat) -> Result<u64, DaoError> {
+    let service = service_builder::build_hiving_service();
+    service.record_heartbeat(heartbeat)
+}
+
+pub fn update_metadata(metadata: Vec<(String, MetadataValue)>) -> Result<(), DaoError> {
+    let service = service_builder::build_hiving_service();
+    service.update_metadata(metadata)
+}
+
+pub fn approve(canister_id: Principal) -> Result<(), DaoError> {
+    let service = service_builder::build_hiving_service();
+    service.approve_hiving_canister(canister_id)
+}
+
+pub fn suspend(canister_id: Principal) -> Result<(), DaoError> {
+    let service = service_builder::build_hiving_service();
+    service.suspend_hiving_canister(canister_id)
+}
+
+pub fn reactivate(canister_id: Principal) -> Result<(), DaoError> {
+    let service = service_builder::build_hiving_service();
+    service.reactivate_hiving_canister(canister_id)
+}
+
+pub fn evict_stale_canisters() -> u32 {
+    service_builder::build_hiving_service().evict_stale_canisters()
+}
+
+pub fn get(canister_id: Principal) -> Option<HivingCanister> {
+    service_builder::build_hiving_service().get_hiving_canister(canister_id)
+}
+
+pub fn list(status: Option<HivingCanisterStatus>, prev: Option<Principal>, take: Option<u32>) -> Vec<HivingCanister> {
+    service_builder::build_hiving_service().get_hiving_canisters(status, prev, take)
+}
+
+pub fn authorize(canister_id: Principal) {
+    let service = service_builder::build_hiving_service();
+    service.authorize_canister(canister_id);
+}
+
+pub fn revoke(canister_id: Principal) {
+    let service = service_builder::build_hiving_service();
+    service.revoke_canister(canister_id);
 }
@@ -1,67 +1,268 @@
+pub mod factory;
+pub mod fleet;
 pub mod hiving;
 
 use super::service_builder;
 
 pub mod mgmt {
-    use self::config::AppConfig;
+    use self::config::DaoArgs;
     use super::*;
 
-    pub fn init(config: AppConfig) {
-        let config_storage = service_builder::build_config_storage();
-        config_storage.borrow_mut().set_config(config)
+    pub fn init(args: DaoArgs) {
+        match args {
+            DaoArgs::Init(config) => config::set_config(config),
+            DaoArgs::Upgrade(_) => panic!("Install requires the Init arguments"),
+        }
+        service_builder::build_cycles_service().borrow().ensure_calendar();
+    }
+
+    /// rewrites the config so that a layout read from an older version is stored in the current one,
+    /// then applies the sections passed with the upgrade. Canisters installed before the cycle
+    /// calendar get it stored from their config; discounts stored before the composite-key
+    /// indexes are indexed by the backfill job
+    pub fn post_upgrade(args: Option<DaoArgs>) {
+        let mut config = config::get_config();
+        match args {
+            None | Some(DaoArgs::Upgrade(None)) => {}
+            Some(DaoArgs::Upgrade(Some(patch))) => config.apply(patch).unwrap_or_else(|err| panic!("{:?}", err)),
+            Some(DaoArgs::Init(_)) => panic!("Upgrade requires the Upgrade arguments"),
+        }
+        config::set_config(config);
+        service_builder::build_cycles_service().borrow().ensure_calendar();
     }
 }
 
 pub mod discounts {
     use super::*;
-    use abstractions::dao::{Cycle, Discount, DiscountRequest};
+    use crate::domain::cycles::CycleEpoch;
+    use abstractions::dao::{Cycle, DaoError, Discount, DiscountMintAudit, DiscountMintState, DiscountQuota, DiscountRequest};
     use candid::Nat;
     use icrc_ledger_types::icrc1::account::Account;
 
-    pub fn get_current_cycle() -> Cycle {
+    pub fn get_current_cycle() -> Result<Cycle, DaoError> {
         let service = service_builder::build_cycles_service();
         let result = service.borrow().get_current_cycle();
         result
     }
 
-    pub async fn get_discount(discount_id: u128) -> Discount {
+    pub fn get_cycle(cycle_number: u64) -> Result<Cycle, DaoError> {
+        service_builder::build_cycles_service().borrow().get_cycle(cycle_number)
+    }
+
+    pub fn list_cycles(from: u64, to: u64) -> Result<Vec<Cycle>, DaoError> {
+        service_builder::build_cycles_service().borrow().list_cycles(from, to)
+    }
+
+    pub fn get_next_voting_cycle() -> Result<Cycle, DaoError> {
+        service_builder::build_cycles_service().borrow().get_next_voting_cycle()
+    }
+
+    pub fn get_cycle_calendar() -> Result<Vec<CycleEpoch>, DaoError> {
+        service_builder::build_cycles_service().borrow().get_calendar()
+    }
+
+    pub fn schedule_cycle_epoch(start_cycle: u64, cycle_len_ns: u64) -> Result<Vec<CycleEpoch>, DaoError> {
+        service_builder::build_cycles_service().borrow().schedule_epoch(start_cycle, cycle_len_ns)
+    }
+
+    pub async fn get_discount(discount_id: u128) -> Result<Discount, DaoError> {
         let service = service_builder::build_discount_service();
         let result = service.get_discount(discount_id).await;
         result
     }
 
-    pub async fn mint_discount(hiver: Account, request: DiscountRequest) -> u128 {
+    pub fn list_discounts_by_cycle(cycle_number: u64, prev: Option<u128>, take: Option<u32>) -> Vec<Discount> {
+        service_builder::build_discount_service().list_discounts_by_cycle(cycle_number, prev, take)
+    }
+
+    pub fn list_discounts_by_owner(owner: Account, prev: Option<u128>, take: Option<u32>) -> Vec<Discount> {
+        service_builder::build_discount_service().list_discounts_by_owner(owner, prev, take)
+    }
+
+    pub fn list_discounts_by_hiver(hiver: Account, prev: Option<u128>, take: Option<u32>) -> Vec<Discount> {
+        service_builder::build_discount_service().list_discounts_by_hiver(hiver, prev, take)
+    }
+
+    pub async fn expire_discounts() -> Result<usize, DaoError> {
+        const BATCH_SIZE: usize = 50;
+
+        let service = service_builder::build_discount_service();
+        let result = service.expire_discounts(BATCH_SIZE).await;
+        result
+    }
+
+    /// indexes the discounts stored before the composite-key indexes, a page per call
+    pub fn backfill_indexes() -> bool {
+        const BATCH_SIZE: usize = 1_000;
+
+        service_builder::build_discount_storage().borrow_mut().backfill_indexes(BATCH_SIZE)
+    }
+
+    pub async fn recover_pending_mints() -> usize {
+        const BATCH_SIZE: usize = 20;
+
+        let service = service_builder::build_discount_service();
+        let result = service.recover_pending_mints(BATCH_SIZE).await;
+        result
+    }
+
+    pub fn get_mint_state(mint_key: u64) -> Option<DiscountMintState> {
+        service_builder::build_discount_service().get_mint_state(mint_key)
+    }
+
+    pub async fn audit_mints() -> Result<DiscountMintAudit, DaoError> {
+        let service = service_builder::build_discount_service();
+        let result = service.audit_mints().await;
+        result
+    }
+
+    pub async fn mint_discount(hiver: Account, request: DiscountRequest) -> Result<u128, DaoError> {
         let service = service_builder::build_discount_service();
         let result = service.mint_discount(hiver, request).await;
         result
     }
 
-    pub async fn get_staking_score(principal: Account) -> Nat {
+    pub fn get_quota(buyer: Account, hiver: Account) -> Result<DiscountQuota, DaoError> {
+        let service = service_builder::build_discount_service();
+        service.get_quota(buyer, hiver)
+    }
+
+    pub async fn get_staking_score(principal: Account) -> Result<Nat, DaoError> {
         let service = service_builder::build_staking_service();
         let result = service.get_current_staking_score(principal).await;
         result
     }
 
-    pub async fn calculate_discount(hiver: Account, price: u128) -> f32 {
+    pub async fn calculate_discount(hiver: Account, price: u128) -> Result<f32, DaoError> {
         let service = service_builder::build_discount_service();
         let result = service.get_max_discount(hiver, price).await;
         result
     }
 }
 
+pub mod merchants {
+    use super::*;
+    use abstractions::dao::{DaoError, Discount, DiscountValidity, Merchant};
+    use candid::Principal;
+    use icrc_ledger_types::icrc1::account::Account;
+
+    pub fn register_merchant(merchant_id: Principal, name: String) -> Result<(), DaoError> {
+        service_builder::build_merchant_service().register_merchant(merchant_id, name)
+    }
+
+    pub fn remove_merchant(merchant_id: Principal) -> Result<(), DaoError> {
+        service_builder::build_merchant_service().remove_merchant(merchant_id)
+    }
+
+    pub fn get_merchants() -> Vec<Merchant> {
+        service_builder::build_merchant_service().get_merchants()
+    }
+
+    pub async fn redeem_discount(token_id: u128, customer: Account, order_ref: String) -> Result<Discount, DaoError> {
+        let service = service_builder::build_discount_service();
+        let result = service.redeem_discount(token_id, customer, order_ref).await;
+        result
+    }
+
+    pub fn verify_discount(token_id: u128) -> DiscountValidity {
+        let service = service_builder::build_discount_service();
+        service.verify_discount(token_id)
+    }
+}
+
+pub mod emission {
+    use super::*;
+    use abstractions::dao::{CycleEmission, DaoError, StakingRewardsClaim};
+
+    pub async fn claim_staking_rewards() -> Result<StakingRewardsClaim, DaoError> {
+        let service = service_builder::build_emission_service();
+        let result = service.claim_staking_rewards().await;
+        result
+    }
+
+    pub async fn snapshot_scores() -> Result<usize, DaoError> {
+        let service = service_builder::build_emission_service();
+        let result = service.snapshot_scores().await;
+        result
+    }
+
+    pub fn get_cycle_emission(cycle_number: u64) -> Option<CycleEmission> {
+        service_builder::build_emission_service().get_cycle_emission(cycle_number)
+    }
+}
+
+pub mod voter_rewards {
+    use super::*;
+    use abstractions::dao::{DaoError, VoterRewardAllocation};
+    use candid::{Nat, Principal};
+
+    pub fn allocate_next_cycle() -> Result<bool, DaoError> {
+        service_builder::build_voter_rewards_service().allocate_next_cycle()
+    }
+
+    pub async fn claim_voter_rewards() -> Result<Nat, DaoError> {
+        let service = service_builder::build_voter_rewards_service();
+        let result = service.claim_voter_rewards().await;
+        result
+    }
+
+    pub fn get_balance(voter: Principal) -> Nat {
+        service_builder::build_voter_rewards_service().get_balance(voter)
+    }
+
+    pub fn get_allocation(cycle_number: u64) -> Option<VoterRewardAllocation> {
+        service_builder::build_voter_rewards_service().get_allocation(cycle_number)
+    }
+}
+
+pub mod events {
+    use super::*;
+    use abstractions::dao::{DaoEventFilter, DaoEventPage};
+
+    pub fn get_events(start: u64, limit: Option<u32>, filter: Option<DaoEventFilter>) -> DaoEventPage {
+        service_builder::build_event_log().get_events(start, limit, filter)
+    }
+}
+
+pub mod reconciliation {
+    use super::*;
+    use abstractions::dao::{DaoError, ReconciliationReport};
+
+    pub fn start() -> Result<(), DaoError> {
+        service_builder::build_reconciliation_service().start()
+    }
+
+    pub async fn reconcile_page() -> bool {
+        let service = service_builder::build_reconciliation_service();
+        let result = service.reconcile_page().await;
+        result
+    }
+
+    pub fn get_report() -> Option<ReconciliationReport> {
+        service_builder::build_reconciliation_service().get_report()
+    }
+}
+
 pub mod voting {
     use super::*;
-    use abstractions::dao::{CodeProposalData, Proposal, ProposalType, Vote, VoteOption};
-
-    pub async fn voting_create_proposal(proposal_type: ProposalType, data: String) -> u64 {
-        match proposal_type {
-            ProposalType::UpdateCode => {
-                if let Err(msg) = validate_code_proposal(data.clone()) {
-                    panic!("{msg}")
-                }
-            }
-            _ => {}
-        };
+    use abstractions::dao::{CodeProposalData, DaoError, Proposal, ProposalType, Vote, VoteOption};
+
+    pub async fn voting_create_proposal(proposal_type: ProposalType, data: String) -> Result<u64, DaoError> {
+        if let ProposalType::UpdateCode = proposal_type
+            && let Err(reason) = validate_code_proposal(data.clone())
+        {
+            return Err(DaoError::InvalidArgument { reason });
+        }
+        if let ProposalType::FleetUpgrade { kind, version } = proposal_type
+            && !service_builder::build_factory_service().get_wasm_modules(kind).iter().any(|module| module.version == version)
+        {
+            return Err(DaoError::InvalidArgument { reason: format!("Version {} is not an approved WASM", version) });
+        }
+        if let ProposalType::FleetRollback { kind } | ProposalType::FleetResume { kind } = proposal_type
+            && super::fleet::get_upgrade(kind).is_none()
+        {
+            return Err(DaoError::InvalidArgument { reason: "No fleet upgrade of this kind".to_string() });
+        }
         let voting_service = service_builder::build_voting_service();
         let proposal_id = voting_service.create_proposal(proposal_type, data).await;
 
@@ -82,16 +283,37 @@ pub mod voting {
         proposal
     }
 
-    pub async fn voting_vote(proposal_id: u64, vote: VoteOption) -> u64 {
+    pub async fn voting_vote(proposal_id: u64, vote: VoteOption) -> Result<u64, DaoError> {
         let mut voting_service = service_builder::build_voting_service();
         voting_service.vote(proposal_id, vote).await
     }
 
+    /// executes an approved proposal. Fleet proposals start, roll back or resume a rollout
+    pub fn voting_execute_proposal(proposal_id: u64) -> Result<(), DaoError> {
+        let voting_service = service_builder::build_voting_service();
+        let proposal = voting_service.get_executable_proposal(proposal_id)?;
+        match proposal.proposal_type {
+            ProposalType::FleetUpgrade { kind, version } => {
+                super::fleet::start_upgrade(kind, version)?;
+            }
+            ProposalType::FleetRollback { kind } => {
+                super::fleet::rollback_upgrade(kind)?;
+            }
+            ProposalType::FleetResume { kind } => {
+                super::fleet::resume_upgrade(kind)?;
+            }
+            ProposalType::UpdateCode | ProposalType::Generic => {}
+        }
+        voting_service.mark_executed(proposal);
+
+        Ok(())
+    }
+
     pub fn voting_get_vote(vote_id: u64) -> Option<Vote> {
         service_builder::build_voting_service().get_vote(&vote_id)
     }
 
-    pub fn voting_get_all_votes(proposal_id: u64) -> Vec<Vote> {
+    pub fn voting_get_all_votes(proposal_id: u64) -> Result<Vec<Vote>, DaoError> {
         service_builder::build_voting_service().get_all_votes(&proposal_id)
     }
 }
@@ -100,7 +322,14 @@ pub mod config {
     use super::{super::IConfigStorage, service_builder};
     use crate::domain::cycles::CyclesConfig;
     use crate::domain::discounts::DiscountConfig;
+    use crate::domain::emission::EmissionConfig;
+    use crate::domain::events::EventLog;
+    use crate::domain::governance;
+    use crate::domain::hiving::HivingConfig;
     use crate::domain::staking::StakingConfig;
+    use crate::domain::voter_rewards::VoterRewardsConfig;
+    use abstractions::dao::{DaoError, DaoEvent};
+    use abstractions::runtime::ICanisterRuntime;
     use candid::{CandidType, Deserialize, Principal};
     use serde::Serialize;
     use std::cell::RefCell;
@@ -108,11 +337,13 @@ pub mod config {
 
     pub struct ConfigService {
         storage: Rc<RefCell<dyn IConfigStorage>>,
+        runtime: Rc<RefCell<dyn ICanisterRuntime>>,
+        events: EventLog,
     }
 
     impl ConfigService {
-        pub fn new(storage: Rc<RefCell<dyn IConfigStorage>>) -> Self {
-            Self { storage }
+        pub fn new(storage: Rc<RefCell<dyn IConfigStorage>>, runtime: Rc<RefCell<dyn ICanisterRuntime>>, events: EventLog) -> Self {
+            Self { storage, runtime, events }
         }
 
         pub fn set_config(&self, config: AppConfig) {
@@ -122,6 +353,18 @@ pub mod config {
         pub fn get_config(&self) -> AppConfig {
             self.storage.borrow().get_config()
         }
+
+        /// config is changed either by controllers or by the DAO itself executing a proposal
+        pub fn update_config(&self, patch: ConfigPatch) -> Result<AppConfig, DaoError> {
+            governance::ensure_governance(&*self.runtime.borrow(), "change the config")?;
+
+            let mut config = self.get_config();
+            config.apply(patch)?;
+            self.set_config(config.clone());
+            self.events.record(DaoEvent::ConfigUpdated);
+
+            Ok(config)
+        }
     }
 
     pub fn set_config(config: AppConfig) {
@@ -134,9 +377,87 @@ pub mod config {
         config_service.get_config()
     }
 
+    pub fn update_config(patch: ConfigPatch) -> Result<AppConfig, DaoError> {
+        let config = build_config_service().update_config(patch)?;
+        service_builder::build_cycles_service().borrow().ensure_calendar();
+
+        Ok(config)
+    }
+
     fn build_config_service() -> ConfigService {
         let config_storage = service_builder::build_config_storage();
-        ConfigService::new(config_storage)
+        let runtime = service_builder::build_runtime();
+        let events = service_builder::build_event_log();
+        ConfigService::new(config_storage, runtime, events)
+    }
+
+    /// canister arguments: the whole config on install, the sections to change on upgrade
+    #[derive(Clone, Debug, Deserialize, CandidType)]
+    pub enum DaoArgs {
+        Init(AppConfig),
+        Upgrade(Option<ConfigPatch>),
+    }
+
+    /// config sections to replace; missing sections keep their current value
+    #[derive(Clone, Debug, Default, Deserialize, CandidType)]
+    pub struct ConfigPatch {
+        pub staking: Option<StakingConfig>,
+        pub cycles: Option<CyclesConfig>,
+        pub discounts: Option<DiscountConfig>,
+        pub hiving: Option<HivingConfig>,
+        pub emission: Option<EmissionConfig>,
+        pub voter_rewards: Option<VoterRewardsConfig>,
+        pub token_canister_id: Option<Principal>,
+        pub nft_canister_id: Option<Principal>,
+    }
+
+    impl AppConfig {
+        /// genesis and cycle length only seed the cycle calendar, changing them once set
+        /// would renumber past cycles
+        pub fn apply(&mut self, patch: ConfigPatch) -> Result<(), DaoError> {
+            if let Some(cycles) = &patch.cycles
+                && self.cycles.genesis.is_some()
+                && (cycles.genesis != self.cycles.genesis || cycles.cycle_len_ns != self.cycles.cycle_len_ns)
+            {
+                return Err(DaoError::InvalidArgument {
+                    reason: "Cycle length changes are scheduled in the cycle calendar".to_string(),
+                });
+            }
+            if let Some(hiving) = &patch.hiving
+                && hiving.heartbeat_interval_ns == 0
+            {
+                return Err(DaoError::InvalidArgument {
+                    reason: "Hiving heartbeat interval must be positive".to_string(),
+                });
+            }
+
+            if let Some(staking) = patch.staking {
+                self.staking = staking;
+            }
+            if let Some(cycles) = patch.cycles {
+                self.cycles = cycles;
+            }
+            if let Some(discounts) = patch.discounts {
+                self.discounts = discounts;
+            }
+            if let Some(hiving) = patch.hiving {
+                self.hiving = hiving;
+            }
+            if let Some(emission) = patch.emission {
+                self.emission = emission;
+            }
+            if let Some(voter_rewards) = patch.voter_rewards {
+                self.voter_rewards = voter_rewards;
+            }
+            if let Some(token_canister_id) = patch.token_canister_id {
+                self.token_canister_id = token_canister_id;
+            }
+            if let Some(nft_canister_id) = patch.nft_canister_id {
+                self.nft_canister_id = nft_canister_id;
+            }
+
+            Ok(())
+        }
     }
 
     #[derive(Clone, Debug, Deserialize, Serialize, CandidType)]
@@ -144,6 +465,12 @@ pub mod config {
         pub staking: StakingConfig,
         pub cycles: CyclesConfig,
         pub discounts: DiscountConfig,
+        #[serde(default)]
+        pub hiving: HivingConfig,
+        #[serde(default)]
+        pub emission: EmissionConfig,
+        #[serde(default)]
+        pub voter_rewards: VoterRewardsConfig,
         pub token_canister_id: Principal,
         pub nft_canister_id: Principal,
     }
@@ -154,6 +481,9 @@ pub mod config {
                 staking: StakingConfig::default(),
                 cycles: CyclesConfig::default(),
                 discounts: DiscountConfig::default(),
+                hiving: HivingConfig::default(),
+                emission: EmissionConfig::default(),
+                voter_rewards: VoterRewardsConfig::default(),
                 token_canister_id: Principal::anonymous(),
                 nft_canister_id: Principal::anonymous(),
             }
@@ -0,0 +1,118 @@
+use crate::app::{app_services, service_builder};
+use abstractions::Timestamp;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+const NSEC_IN_SEC: u64 = 1_000_000_000;
+/// shortest job interval, each tick starts the jobs which are due
+const TICK: Duration = Duration::from_secs(60);
+
+/// periodic housekeeping jobs, driven by a timer ticking every `TICK`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Job {
+    ExpireDiscounts,
+    RecoverMints,
+    ReconcileDiscounts,
+    AllocateVoterRewards,
+    UpgradeFleet,
+    EvictStaleCanisters,
+    SnapshotStakingScores,
+    BackfillIndexes,
+}
+
+impl Job {
+    const ALL: [Job; 8] = [
+        Job::ExpireDiscounts,
+        Job::RecoverMints,
+        Job::ReconcileDiscounts,
+        Job::AllocateVoterRewards,
+        Job::UpgradeFleet,
+        Job::EvictStaleCanisters,
+        Job::SnapshotStakingScores,
+        Job::BackfillIndexes,
+    ];
+
+    fn interval_ns(&self) -> u64 {
+        match self {
+            Job::ExpireDiscounts => 60 * NSEC_IN_SEC,
+            Job::RecoverMints => 5 * 60 * NSEC_IN_SEC,
+            Job::ReconcileDiscounts => 60 * NSEC_IN_SEC,
+            Job::AllocateVoterRewards => 60 * NSEC_IN_SEC,
+            Job::UpgradeFleet => 60 * NSEC_IN_SEC,
+            Job::EvictStaleCanisters => 5 * 60 * NSEC_IN_SEC,
+            Job::SnapshotStakingScores => 60 * NSEC_IN_SEC,
+            Job::BackfillIndexes => 60 * NSEC_IN_SEC,
+        }
+    }
+
+    async fn run(&self) {
+        match self {
+            Job::ExpireDiscounts => {
+                // discounts whose NFT could not be updated are picked up again by the next run
+                let _ = app_services::discounts::expire_discounts().await;
+            }
+            Job::RecoverMints => {
+                app_services::discounts::recover_pending_mints().await;
+            }
+            Job::ReconcileDiscounts => {
+                app_services::reconciliation::reconcile_page().await;
+            }
+            Job::AllocateVoterRewards => {
+                // one voting cycle per run, a backlog is worked off by the following runs
+                let _ = app_services::voter_rewards::allocate_next_cycle();
+            }
+            Job::UpgradeFleet => {
+                // one batch per run, so a broken version stops spreading once the rollout is paused
+                app_services::fleet::upgrade_next_batch().await;
+            }
+            Job::EvictStaleCanisters => {
+                app_services::hiving::evict_stale_canisters();
+            }
+            Job::SnapshotStakingScores => {
+                // one page of stakers per run, a failed page is retried by the next run
+                let _ = app_services::emission::snapshot_scores().await;
+            }
+            Job::BackfillIndexes => {
+                // one page per run, owner and hiver listings scan the discounts until it is done
+                app_services::discounts::backfill_indexes();
+            }
+        }
+    }
+}
+
+thread_local! {
+    static LAST_RUNS: RefCell<BTreeMap<Job, Timestamp>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+/// timers do not survive upgrades, so this is called on init and post_upgrade
+pub fn start_timer() {
+    ic_cdk_timers::set_timer_interval(TICK, || ic_cdk::futures::spawn(run_due_jobs()));
+}
+
+/// runs every job whose interval has elapsed since its previous start.
+/// The start is recorded before the job awaits, so a slow job is never started twice
+pub async fn run_due_jobs() {
+    let now = service_builder::build_runtime().borrow().get_time();
+
+    for job in Job::ALL {
+        if !try_start(job, now) {
+            continue;
+        }
+        job.run().await;
+    }
+}
+
+fn try_start(job: Job, now: Timestamp) -> bool {
+    LAST_RUNS.with(|runs| {
+        let mut runs = runs.borrow_mut();
+        let is_due = match runs.get(&job) {
+            Some(last_run) => now >= last_run + job.interval_ns(),
+            None => true,
+        };
+        if is_due {
+            runs.insert(job, now);
+        }
+        is_due
+    })
+}
@@ -1,6 +1,7 @@
 pub mod service_builder;
 pub mod app_services;
-pub use app_services::config::AppConfig;
+pub mod jobs;
+pub use app_services::config::{AppConfig, ConfigPatch, DaoArgs};
 
 pub trait IConfigStorage {
     fn set_config(&mut self, config: AppConfig);
@@ -1,13 +1,15 @@
 use crate::{
     app::IConfigStorage,
     domain::{
-        cycles::CycleService, discounts::DiscountService, hiving::HivingService, interfaces::storage::*, staking::StakingService,
-        voting::VotingService,
+        cycles::CycleService, discounts::DiscountService, emission::EmissionService, events::EventLog, factory::FactoryService, fleet::FleetService,
+        hiving::HivingService,
+        interfaces::storage::*, merchants::MerchantService,
+        reconciliation::ReconciliationService, staking::StakingService, voter_rewards::VoterRewardsService, voting::VotingService,
     },
     icp::service_builder_icp,
 };
 
-use abstractions::{nft::NftClient, runtime::ICanisterRuntime, token::TokenClient};
+use abstractions::{nft::NftClient, runtime::{ICanisterManager, ICanisterRuntime}, token::TokenClient};
 use canister_runtime::CdkCallContext;
 use std::{cell::RefCell, rc::Rc};
 
@@ -17,6 +19,10 @@ pub fn build_runtime() -> Rc<RefCell<dyn ICanisterRuntime>> {
     service_builder_icp::build_runtime()
 }
 
+pub fn build_canister_manager() -> Rc<dyn ICanisterManager> {
+    service_builder_icp::build_canister_manager()
+}
+
 // storages
 
 pub fn build_config_storage() -> Rc<RefCell<dyn IConfigStorage>> {
@@ -27,7 +33,7 @@ fn build_voting_storage() -> Rc<RefCell<dyn IVotingStorage>> {
     service_builder_icp::build_voting_storage()
 }
 
-fn build_discount_storage() -> Rc<RefCell<dyn IDiscountStorage>> {
+pub fn build_discount_storage() -> Rc<RefCell<dyn IDiscountStorage>> {
     service_builder_icp::build_discount_storage()
 }
 
@@ -35,6 +41,38 @@ fn build_hiving_storage() -> Rc<RefCell<dyn IHivingStorage>> {
     service_builder_icp::build_hiving_storage()
 }
 
+fn build_merchant_storage() -> Rc<RefCell<dyn IMerchantStorage>> {
+    service_builder_icp::build_merchant_storage()
+}
+
+fn build_reconciliation_storage() -> Rc<RefCell<dyn IReconciliationStorage>> {
+    service_builder_icp::build_reconciliation_storage()
+}
+
+fn build_cycle_storage() -> Rc<RefCell<dyn ICycleStorage>> {
+    service_builder_icp::build_cycle_storage()
+}
+
+fn build_emission_storage() -> Rc<RefCell<dyn IEmissionStorage>> {
+    service_builder_icp::build_emission_storage()
+}
+
+fn build_voter_rewards_storage() -> Rc<RefCell<dyn IVoterRewardsStorage>> {
+    service_builder_icp::build_voter_rewards_storage()
+}
+
+fn build_event_storage() -> Rc<RefCell<dyn IEventStorage>> {
+    service_builder_icp::build_event_storage()
+}
+
+fn build_wasm_storage() -> Rc<RefCell<dyn IWasmStorage>> {
+    service_builder_icp::build_wasm_storage()
+}
+
+fn build_fleet_storage() -> Rc<RefCell<dyn IFleetStorage>> {
+    service_builder_icp::build_fleet_storage()
+}
+
 // canister clients
 
 pub fn build_token_service() -> Rc<RefCell<TokenClient<CdkCallContext>>> {
@@ -49,38 +87,50 @@ pub fn build_nft_service() -> Rc<RefCell<NftClient<CdkCallContext>>> {
 
 // domain services
 
-pub fn build_voting_service() -> VotingService {
+pub fn build_event_log() -> EventLog {
+    EventLog::new(build_event_storage(), build_runtime())
+}
+
+pub fn build_voting_service() -> VotingService<CdkCallContext> {
     let cycles_service = build_cycles_service();
     let voting_storage = build_voting_storage();
     let runtime = build_runtime();
     let token = build_token_service();
+    let locks = service_builder_icp::build_in_flight_locks();
+    let events = build_event_log();
 
-    let voting_service = VotingService::new(cycles_service, voting_storage, runtime, token);
+    let voting_service = VotingService::new(cycles_service, voting_storage, runtime, token, locks, events);
 
     voting_service
 }
 
 pub fn build_cycles_service() -> Rc<RefCell<CycleService>> {
     let cycles_config = build_config_storage().borrow().get_config().cycles.clone();
+    let storage = build_cycle_storage();
     let runtime = build_runtime();
-    let cycle_service = CycleService::new(cycles_config, runtime);
+    let events = build_event_log();
+    let cycle_service = CycleService::new(cycles_config, storage, runtime, events);
     let cycle_service = Rc::new(RefCell::new(cycle_service));
 
     cycle_service
 }
 
-pub fn build_discount_service() -> DiscountService {
+pub fn build_discount_service() -> DiscountService<CdkCallContext> {
     let config = build_config_storage().borrow().get_config().discounts.clone();
     let cycles = build_cycles_service();
     let storage = build_discount_storage();
     let nft = build_nft_service();
     let staking = Rc::new(RefCell::new(build_staking_service()));
+    let merchants = Rc::new(RefCell::new(build_merchant_service()));
+    let hiving = Rc::new(RefCell::new(build_hiving_service()));
+    let locks = service_builder_icp::build_in_flight_locks();
     let runtime = build_runtime();
+    let events = build_event_log();
 
-    DiscountService::new(config, cycles, storage, nft, staking, runtime)
+    DiscountService::new(config, cycles, storage, nft, staking, merchants, hiving, locks, runtime, events)
 }
 
-pub fn build_staking_service() -> StakingService {
+pub fn build_staking_service() -> StakingService<CdkCallContext> {
     let config = build_config_storage().borrow().get_config().staking.clone();
     let token = build_token_service();
     let cycles_service = build_cycles_service();
@@ -89,8 +139,76 @@ pub fn build_staking_service() -> StakingService {
 }
 
 pub fn build_hiving_service() -> HivingService {
+    let config = build_config_storage().borrow().get_config().hiving.clone();
     let storage = build_hiving_storage();
     let runtime = build_runtime();
+    let events = build_event_log();
+
+    HivingService::new(config, storage, runtime, events)
+}
+
+pub fn build_factory_service() -> FactoryService {
+    let storage = build_wasm_storage();
+    let hiving = Rc::new(RefCell::new(build_hiving_service()));
+    let manager = build_canister_manager();
+    let locks = service_builder_icp::build_in_flight_locks();
+    let runtime = build_runtime();
+    let events = build_event_log();
+
+    FactoryService::new(storage, hiving, manager, locks, runtime, events)
+}
+
+pub fn build_fleet_service() -> FleetService {
+    let storage = build_fleet_storage();
+    let wasms = build_wasm_storage();
+    let hiving = Rc::new(RefCell::new(build_hiving_service()));
+    let manager = build_canister_manager();
+    let locks = service_builder_icp::build_in_flight_locks();
+    let runtime = build_runtime();
+    let events = build_event_log();
+
+    FleetService::new(storage, wasms, hiving, manager, locks, runtime, events)
+}
+
+pub fn build_merchant_service() -> MerchantService {
+    let storage = build_merchant_storage();
+    let runtime = build_runtime();
+    let events = build_event_log();
+
+    MerchantService::new(storage, runtime, events)
+}
+
+pub fn build_emission_service() -> EmissionService<CdkCallContext> {
+    let config = build_config_storage().borrow().get_config().emission.clone();
+    let cycles = build_cycles_service();
+    let staking = Rc::new(RefCell::new(build_staking_service()));
+    let storage = build_emission_storage();
+    let token = build_token_service();
+    let locks = service_builder_icp::build_in_flight_locks();
+    let runtime = build_runtime();
+    let events = build_event_log();
+
+    EmissionService::new(config, cycles, staking, storage, token, locks, runtime, events)
+}
+
+pub fn build_voter_rewards_service() -> VoterRewardsService<CdkCallContext> {
+    let config = build_config_storage().borrow().get_config().voter_rewards.clone();
+    let cycles = build_cycles_service();
+    let voting = build_voting_storage();
+    let storage = build_voter_rewards_storage();
+    let token = build_token_service();
+    let locks = service_builder_icp::build_in_flight_locks();
+    let runtime = build_runtime();
+    let events = build_event_log();
+
+    VoterRewardsService::new(config, cycles, voting, storage, token, locks, runtime, events)
+}
+
+pub fn build_reconciliation_service() -> ReconciliationService<CdkCallContext> {
+    let storage = build_reconciliation_storage();
+    let discounts = build_discount_storage();
+    let nft = build_nft_service();
+    let runtime = build_runtime();
 
-    HivingService::new(storage, runtime)
+    ReconciliationService::new(storage, discounts, nft, runtime)
 }
@@ -1,4 +1,7 @@
-use abstractions::dao::Cycle;
+use crate::domain::events::EventLog;
+use crate::domain::governance;
+use crate::domain::interfaces::storage::ICycleStorage;
+use abstractions::dao::{Cycle, DaoError, DaoEvent};
 use abstractions::runtime::ICanisterRuntime;
 use abstractions::Timestamp;
 use candid::{CandidType, Deserialize};
@@ -27,58 +30,151 @@ impl Default for CyclesConfig {
     }
 }
 
+/// A run of cycles sharing one length. Cycle `start_cycle` begins at `start` and the following
+/// ones are laid out back to back until the next epoch takes over
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, CandidType)]
+pub struct CycleEpoch {
+    pub start_cycle: u64,
+    pub start: Timestamp,
+    pub cycle_len_ns: u64,
+}
+
+impl CycleEpoch {
+    fn cycle(&self, cycle_number: u64) -> Cycle {
+        let start = self.start + (cycle_number - self.start_cycle) * self.cycle_len_ns;
+
+        Cycle {
+            number: cycle_number,
+            start,
+            end: start + self.cycle_len_ns,
+        }
+    }
+
+    /// a cycle covers the time after its start up to and including its end
+    fn cycle_number_at(&self, timestamp: Timestamp) -> u64 {
+        self.start_cycle + (timestamp - self.start - 1) / self.cycle_len_ns
+    }
+}
+
 pub struct CycleService {
     pub config: CyclesConfig,
+    storage: Rc<RefCell<dyn ICycleStorage>>,
     runtime: Rc<RefCell<dyn ICanisterRuntime>>,
+    events: EventLog,
 }
 
 impl CycleService {
-    pub fn new(config: CyclesConfig, runtime: Rc<RefCell<dyn ICanisterRuntime>>) -> Self {
-        Self { config, runtime }
+    const MAX_LISTED_CYCLES: u64 = 1000;
+
+    pub fn new(
+        config: CyclesConfig,
+        storage: Rc<RefCell<dyn ICycleStorage>>,
+        runtime: Rc<RefCell<dyn ICanisterRuntime>>,
+        events: EventLog,
+    ) -> Self {
+        Self { config, storage, runtime, events }
     }
-    
-    pub fn get_current_cycle(&self) -> Cycle {
+
+    /// the stored calendar, or the single epoch described by the config until the calendar is stored
+    pub fn get_calendar(&self) -> Result<Vec<CycleEpoch>, DaoError> {
+        let epochs = self.storage.borrow().get_epochs();
+        if !epochs.is_empty() {
+            return Ok(epochs);
+        }
+
+        let genesis = self.config.genesis.ok_or(DaoError::CalendarNotStarted)?;
+        Ok(vec![CycleEpoch {
+            start_cycle: 1,
+            start: genesis,
+            cycle_len_ns: self.config.cycle_len_ns,
+        }])
+    }
+
+    /// stores the calendar once the genesis is known. From then on `genesis` and `cycle_len_ns`
+    /// of the config no longer move cycles, changes are scheduled with `schedule_epoch`
+    pub fn ensure_calendar(&self) {
+        if !self.storage.borrow().get_epochs().is_empty() {
+            return;
+        }
+        if let Ok(epochs) = self.get_calendar() {
+            self.storage.borrow_mut().set_epochs(epochs);
+        }
+    }
+
+    /// changes the cycle length starting with `start_cycle`, replacing the epochs scheduled
+    /// from that cycle on. Cycles that have already started cannot be changed
+    pub fn schedule_epoch(&self, start_cycle: u64, cycle_len_ns: u64) -> Result<Vec<CycleEpoch>, DaoError> {
+        governance::ensure_governance(&*self.runtime.borrow(), "change the cycle calendar")?;
+        if cycle_len_ns == 0 {
+            return Err(DaoError::InvalidArgument { reason: "Cycle length must be positive".to_string() });
+        }
+
+        let mut epochs = self.get_calendar()?;
+        let current_cycle = self.get_current_cycle().map(|cycle| cycle.number).unwrap_or(0);
+        if start_cycle <= current_cycle || start_cycle <= epochs[0].start_cycle {
+            return Err(DaoError::InvalidArgument { reason: "Only future cycles can be rescheduled".to_string() });
+        }
+
+        epochs.retain(|epoch| epoch.start_cycle < start_cycle);
+        let start = epochs.last().unwrap().cycle(start_cycle).start;
+        epochs.push(CycleEpoch { start_cycle, start, cycle_len_ns });
+        self.storage.borrow_mut().set_epochs(epochs.clone());
+        self.events.record(DaoEvent::CycleEpochScheduled { start_cycle, cycle_len_ns });
+
+        Ok(epochs)
+    }
+
+    pub fn get_current_cycle(&self) -> Result<Cycle, DaoError> {
         let now = self.runtime.borrow().get_time();
         let cycle = self.resolve_cycle(now);
-        
+
         cycle
     }
 
-    pub fn get_next_voting_cycle(&self) -> Cycle {
+    pub fn get_next_voting_cycle(&self) -> Result<Cycle, DaoError> {
         fn next_strict_multiple(n: u64, divider: u64) -> u64 {
             n + divider - n % divider
         }
 
-        let current_cycle = self.get_current_cycle();
+        let current_cycle = self.get_current_cycle()?;
         let next_voting_cycle_number =
             next_strict_multiple(current_cycle.number, self.config.voting_cycles);
-        let cycle = self.get_cycle_details(next_voting_cycle_number);
+        let cycle = self.get_cycle(next_voting_cycle_number);
 
         cycle
     }
 
-    pub fn get_cycle_details(&self, cycle_number: u64) -> Cycle {
-        let genesis = self.config.genesis.unwrap();
-        let start = genesis + (cycle_number - 1) * self.config.cycle_len_ns;
-        let end = start + self.config.cycle_len_ns;
+    pub fn get_cycle(&self, cycle_number: u64) -> Result<Cycle, DaoError> {
+        if cycle_number == 0 {
+            return Err(DaoError::InvalidArgument { reason: "Cycles are numbered from 1".to_string() });
+        }
 
-        Cycle {
-            number: cycle_number,
-            start,
-            end,
+        let epochs = self.get_calendar()?;
+        let epoch = epochs.iter().rev().find(|epoch| epoch.start_cycle <= cycle_number).unwrap();
+
+        Ok(epoch.cycle(cycle_number))
+    }
+
+    /// cycles `from..=to`
+    pub fn list_cycles(&self, from: u64, to: u64) -> Result<Vec<Cycle>, DaoError> {
+        if from > to || to - from >= Self::MAX_LISTED_CYCLES {
+            return Err(DaoError::InvalidArgument {
+                reason: format!("Up to {} cycles can be listed at once", Self::MAX_LISTED_CYCLES),
+            });
         }
+
+        (from..=to).map(|cycle_number| self.get_cycle(cycle_number)).collect()
     }
-    
-    pub fn resolve_cycle(&self, timestamp: Timestamp) -> Cycle {
-        let genesis = self.config.genesis.unwrap();
-        let elapsed_nanosec = timestamp - genesis;
-        let div = elapsed_nanosec / self.config.cycle_len_ns;        
-        let rem = elapsed_nanosec % self.config.cycle_len_ns;
-        
-        let cycle_number = if rem > 0 {div+1} else {div};
-        let cycle = self.get_cycle_details(cycle_number);
-        
-        cycle
+
+    pub fn resolve_cycle(&self, timestamp: Timestamp) -> Result<Cycle, DaoError> {
+        let epochs = self.get_calendar()?;
+        let epoch = epochs
+            .iter()
+            .rev()
+            .find(|epoch| epoch.start < timestamp)
+            .ok_or(DaoError::CalendarNotStarted)?;
+
+        Ok(epoch.cycle(epoch.cycle_number_at(timestamp)))
     }
 }
 
@@ -87,8 +183,14 @@ impl CycleService {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::mocks::RuntimeMock;
+    use crate::icp::stable_storage::{CycleStorageStable, EventStorageStable};
     use candid::Principal;
 
+    fn event_log(runtime: Rc<RefCell<dyn ICanisterRuntime>>) -> EventLog {
+        EventLog::new(Rc::new(RefCell::new(EventStorageStable::init())), runtime)
+    }
+
     struct RtMock {
         time: u64,
     }
@@ -111,6 +213,14 @@ mod tests {
         fn get_time(&self) -> Timestamp {
             self.time
         }
+
+        fn is_controller(&self, _principal: &Principal) -> bool {
+            false
+        }
+
+        fn get_canister_id(&self) -> Principal {
+            Principal::anonymous()
+        }
     }
 
     #[test]
@@ -124,16 +234,49 @@ mod tests {
                 genesis: Some(0),
                 cycle_len_ns: 10 * NSEC_IN_SEC,
             },
+            Rc::new(RefCell::new(CycleStorageStable::init())),
             Rc::clone(&rt),
+            event_log(rt),
         );
 
         rt_mock.borrow_mut().set_time_since_genesis_sec(5);
 
-        let _cycle = cycles_service.get_cycle_details(2);
+        let _cycle = cycles_service.get_cycle(2).unwrap();
 
-        let cycle = cycles_service.get_current_cycle().number;
+        let cycle = cycles_service.get_current_cycle().unwrap().number;
         assert_eq!(cycle, 1);
     }
+
+    #[test]
+    fn rescheduling_keeps_past_cycles() {
+        let runtime = Rc::new(RefCell::new(RuntimeMock { caller: Principal::management_canister(), time: 25 * NSEC_IN_SEC }));
+        let config = CyclesConfig { genesis: Some(0), cycle_len_ns: 10 * NSEC_IN_SEC, ..CyclesConfig::default() };
+        let events = event_log(runtime.clone());
+        let cycles_service = CycleService::new(config, Rc::new(RefCell::new(CycleStorageStable::init())), runtime.clone(), events.clone());
+        let past = cycles_service.list_cycles(1, 3).unwrap();
+
+        assert!(cycles_service.schedule_epoch(3, 60 * NSEC_IN_SEC).is_err());
+        cycles_service.schedule_epoch(4, 60 * NSEC_IN_SEC).unwrap();
+
+        assert_eq!(cycles_service.list_cycles(1, 3).unwrap(), past);
+        let cycle = cycles_service.get_cycle(5).unwrap();
+        assert_eq!((cycle.start, cycle.end), (90 * NSEC_IN_SEC, 150 * NSEC_IN_SEC));
+        runtime.borrow_mut().time = 100 * NSEC_IN_SEC;
+        assert_eq!(cycles_service.get_current_cycle().unwrap().number, 5);
+        assert_eq!(
+            events.get_events(0, None, None).events[0].event,
+            DaoEvent::CycleEpochScheduled { start_cycle: 4, cycle_len_ns: 60 * NSEC_IN_SEC }
+        );
+    }
+
+    #[test]
+    fn missing_genesis_is_an_error() {
+        let runtime = Rc::new(RefCell::new(RtMock::new()));
+        let config = CyclesConfig { genesis: None, ..CyclesConfig::default() };
+        let cycles_service = CycleService::new(config, Rc::new(RefCell::new(CycleStorageStable::init())), runtime.clone(), event_log(runtime));
+
+        assert_eq!(cycles_service.get_current_cycle(), Err(DaoError::CalendarNotStarted));
+    }
     
     #[test]
     fn resolve_cycle() {
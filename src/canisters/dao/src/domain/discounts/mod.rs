@@ -3,53 +3,95 @@ mod calculators;
 use calculators::ProportionCalculator;
 
 use super::cycles::CycleService;
+use super::events::EventLog;
+use super::hiving::HivingService;
 use super::interfaces::storage::*;
+use super::locks::{InFlightLocks, LockKey};
+use super::merchants::MerchantService;
 use super::staking::StakingService;
 
-use abstractions::dao::{Cycle, Discount, DiscountRequest};
-use abstractions::MetadataValue;
+use abstractions::dao::{
+    Cycle, DaoError, DaoEvent, Discount, DiscountExpiry, DiscountMintAudit, DiscountMintState, DiscountQuota, DiscountRedemption, DiscountRequest, DiscountValidity,
+    MintDiscountError, MintRecord, MintStatus,
+};
+use abstractions::{MetadataValue, Timestamp};
 use abstractions::nft::NftClient;
-use canister_runtime::CdkCallContext;
 
 use std::cell::RefCell;
+use std::fmt::Debug;
 use std::rc::Rc;
 use candid::{CandidType, Deserialize};
 use icrc_ledger_types::icrc1::account::Account;
 use serde::Serialize;
-use abstractions::runtime::ICanisterRuntime;
+use abstractions::runtime::{ICallContext, ICanisterRuntime};
 
 #[derive(Clone, Debug, Deserialize, Serialize, CandidType)]
 pub struct DiscountConfig {
     pub discounts_per_cycle: u128,
+    /// validity window, in cycles, of discounts minted without an explicit expiry
+    #[serde(default = "DiscountConfig::default_validity_cycles")]
+    pub default_validity_cycles: u64,
+    /// number of discounts a single buyer can get per cycle across all hivers
+    #[serde(default = "DiscountConfig::default_discounts_per_wallet_per_cycle")]
+    pub discounts_per_wallet_per_cycle: u32,
+}
+
+impl DiscountConfig {
+    fn default_validity_cycles() -> u64 {
+        4
+    }
+
+    fn default_discounts_per_wallet_per_cycle() -> u32 {
+        3
+    }
 }
 
 impl Default for DiscountConfig {
     fn default() -> Self {
         Self {
             discounts_per_cycle: 5,
+            default_validity_cycles: Self::default_validity_cycles(),
+            discounts_per_wallet_per_cycle: Self::default_discounts_per_wallet_per_cycle(),
         }
     }
 }
 
-pub struct DiscountService {
+pub struct DiscountService<R: ICallContext> {
     config: DiscountConfig,
     cycles: Rc<RefCell<CycleService>>,
     storage: Rc<RefCell<dyn IDiscountStorage>>,
-    nft: Rc<RefCell<NftClient<CdkCallContext>>>,
-    staking: Rc<RefCell<StakingService>>,
+    nft: Rc<RefCell<NftClient<R>>>,
+    staking: Rc<RefCell<StakingService<R>>>,
+    merchants: Rc<RefCell<MerchantService>>,
+    hiving: Rc<RefCell<HivingService>>,
+    locks: InFlightLocks,
     runtime: Rc<RefCell<dyn ICanisterRuntime>>,
+    events: EventLog,
  }
 
-impl DiscountService {
+impl<R: ICallContext> DiscountService<R>
+where
+    R::Error: Debug,
+{
     const MAX_DISCOUNT: f32 = 25.0;
+    const MAX_PAGE_SIZE: usize = 100;
+    /// mint attempts of a pending discount before its quota is given back
+    const MAX_MINT_ATTEMPTS: u32 = 5;
+    /// delay before the NFT of an expired discount is marked again after a failed update
+    const EXPIRY_RETRY_DELAY_NS: u64 = 10 * 60 * 1_000_000_000;
 
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         config: DiscountConfig,
         cycles: Rc<RefCell<CycleService>>,
         storage: Rc<RefCell<dyn IDiscountStorage>>,
-        nft: Rc<RefCell<NftClient<CdkCallContext>>>,
-        staking: Rc<RefCell<StakingService>>,
+        nft: Rc<RefCell<NftClient<R>>>,
+        staking: Rc<RefCell<StakingService<R>>>,
+        merchants: Rc<RefCell<MerchantService>>,
+        hiving: Rc<RefCell<HivingService>>,
+        locks: InFlightLocks,
         runtime: Rc<RefCell<dyn ICanisterRuntime>>,
+        events: EventLog,
     ) -> Self {
         Self {
             config,
@@ -57,83 +99,395 @@ impl DiscountService {
             storage,
             nft,
             staking,
-            runtime
+            merchants,
+            hiving,
+            locks,
+            runtime,
+            events,
         }
     }
 
-    pub async fn get_max_discount(&self, hiver: Account, price: u128) -> f32 {
-        let score = self
-            .staking
-            .borrow()
-            .get_current_staking_score(hiver)
-            .await;
+    pub async fn get_max_discount(&self, hiver: Account, price: u128) -> Result<f32, DaoError> {
+        let staking = self.staking.borrow().clone();
+        let score = staking.get_current_staking_score(hiver).await?;
 
         let calculator = self.build_calculator();
         let result = calculator.calculate_discount(price, score);
 
-        result
+        Ok(result)
     }
 
     fn build_calculator(&self) -> ProportionCalculator {
         ProportionCalculator::new(Self::MAX_DISCOUNT)
     }
 
-    fn validate_account(&self, account: &Account, cycle: &Cycle) -> Result<(), String> {
-        let discounts_count = self.storage.borrow().get_discount_index(account, cycle.number);
-        if discounts_count >= self.config.discounts_per_cycle {
-            return Err("Max number of discounts per cycle reached".to_string())
+    fn validate_account(&self, account: &Account, cycle: &Cycle) -> Result<(), MintDiscountError> {
+        if self.get_hiver_remaining(account, cycle) == 0 {
+            return Err(MintDiscountError::DiscountsPerCycleLimitReached)
+        }
+
+        Ok(())
+    }
+
+    fn validate_buyer(&self, buyer: Account, cycle: &Cycle) -> Result<(), MintDiscountError> {
+        if self.get_buyer_remaining(buyer, cycle) == 0 {
+            return Err(MintDiscountError::WalletLimitReached)
         }
 
         Ok(())
     }
 
-    pub async fn mint_discount(&self, hiver: Account, discount_request: DiscountRequest) -> u128 {
-        let current_cycle = self.cycles.borrow().get_current_cycle();
-        if let Err(msg) = self.validate_account(&hiver, &current_cycle) {
-             panic!("{}", msg);
+    fn get_hiver_remaining(&self, hiver: &Account, cycle: &Cycle) -> u128 {
+        let discounts_count = self.storage.borrow().get_discount_index(hiver, cycle.number);
+        self.config.discounts_per_cycle.saturating_sub(discounts_count)
+    }
+
+    fn get_buyer_remaining(&self, buyer: Account, cycle: &Cycle) -> u32 {
+        let usage = self.hiving.borrow().get_wallet_usage(cycle.number, buyer);
+        self.config.discounts_per_wallet_per_cycle.saturating_sub(usage)
+    }
+
+    pub fn get_quota(&self, buyer: Account, hiver: Account) -> Result<DiscountQuota, DaoError> {
+        let current_cycle = self.cycles.borrow().get_current_cycle()?;
+
+        Ok(DiscountQuota {
+            cycle: current_cycle.number,
+            buyer_remaining: self.get_buyer_remaining(buyer, &current_cycle),
+            hiver_remaining: self.get_hiver_remaining(&hiver, &current_cycle),
+        })
+    }
+
+    /// resolves the requested validity window into an absolute expiration timestamp.
+    /// A window of N cycles lasts until the end of the N-th cycle following the current one
+    fn resolve_expiry(&self, expiry: Option<DiscountExpiry>, current_cycle: &Cycle) -> Result<Timestamp, DaoError> {
+        let invalid = |reason: &str| MintDiscountError::InvalidExpiry { reason: reason.to_string() }.into();
+        let expiry = expiry.unwrap_or(DiscountExpiry::Cycles(self.config.default_validity_cycles));
+        match expiry {
+            DiscountExpiry::Cycles(0) => Err(invalid("Discount validity window must be at least one cycle")),
+            DiscountExpiry::Cycles(cycles) => {
+                let last_valid_cycle = self.cycles.borrow().get_cycle(current_cycle.number + cycles)?;
+                Ok(last_valid_cycle.end)
+            }
+            DiscountExpiry::Timestamp(timestamp) => {
+                let now = self.runtime.borrow().get_time();
+                if timestamp <= now {
+                    return Err(invalid("Discount expiration must be in the future"));
+                }
+                Ok(timestamp)
+            }
+        }
+    }
+
+    /// mints a discount for the hiver on behalf of the calling hiving canister.
+    /// The discount value is derived from the hiver's staking score. A value quoted in the request
+    /// is minted instead as long as the score still covers it.
+    ///
+    /// Minting is a saga: a pending record holding the reserved quota is written before the NFT
+    /// canister is called with the record key as idempotency key, and the record is resolved once
+    /// the NFT canister answers. Records left pending by a trap are resolved by `recover_pending_mints`
+    pub async fn mint_discount(&self, hiver: Account, discount_request: DiscountRequest) -> Result<u128, DaoError> {
+        self.hiving.borrow().ensure_can_mint_for(hiver)?;
+        let buyer = discount_request.owner;
+        let _guard = self
+            .locks
+            .try_acquire(LockKey::Mint(buyer))
+            .ok_or(MintDiscountError::MintInProgress)?;
+
+        let current_cycle = self.cycles.borrow().get_current_cycle()?;
+        self.validate_account(&hiver, &current_cycle)?;
+        self.validate_buyer(buyer, &current_cycle)?;
+        let expires_on = self.resolve_expiry(discount_request.expiry.clone(), &current_cycle)?;
+
+        // taken before the awaits so that concurrent calls see the quota as used
+        let reservation = self.reserve_quota(current_cycle.number, hiver, buyer);
+
+        let max_value = self.get_max_discount(hiver, discount_request.price).await?;
+        let value = match discount_request.value {
+            Some(value) if value > max_value => return Err(MintDiscountError::QuotedValueUnavailable { max_value }.into()),
+            Some(value) => value,
+            None => max_value,
+        };
+        if value <= 0.0 {
+            return Err(MintDiscountError::NoDiscountAvailable.into());
+        }
+
+        let mut discount = Discount::new(0, value, discount_request.owner);
+        discount.hiver = Some(hiver);
+        discount.expires_on = Some(expires_on);
+
+        let record = MintRecord {
+            key: self.storage.borrow_mut().next_mint_key(),
+            cycle_number: current_cycle.number,
+            hiver,
+            discount,
+            created_on: self.runtime.borrow().get_time(),
+            attempts: 1,
+            status: MintStatus::Pending,
+        };
+        self.storage.borrow_mut().save_mint_record(record.clone());
+        // from now on the quota is held by the pending record
+        reservation.confirm();
+
+        match self.mint_nft(&record).await {
+            Ok(token_id) => {
+                self.confirm_mint(record, token_id);
+                Ok(token_id)
+            }
+            Err(err) if R::is_clean_reject(&err) => {
+                self.compensate_mint(record);
+                Err(MintDiscountError::NftMintFailed { reason: format!("{:?}", err) }.into())
+            }
+            // the token may have been minted, the pending record keeps the quota until it is recovered
+            Err(_) => Err(MintDiscountError::NftMintPending { mint_key: record.key }.into()),
         }
+    }
+
+    /// retries minting of discounts left pending by interrupted or ambiguous calls.
+    /// Records whose mint is still in flight are skipped, and only mints rejected by the NFT
+    /// canister are given up. Returns the number of processed records
+    pub async fn recover_pending_mints(&self, limit: usize) -> usize {
+        let pending = self.storage.borrow().get_mint_records(MintStatus::Pending, limit);
+        let mut processed = 0;
 
-        let token_id = self.nft.borrow().privia_mint_token(discount_request.owner, discount_request.to_metadata()).await.unwrap();
+        for mut record in pending {
+            let Some(_guard) = self.locks.try_acquire(LockKey::Mint(record.discount.owner)) else {
+                continue;
+            };
+            processed += 1;
 
-        let discount = Discount::new(token_id, discount_request.value, discount_request.owner);
-        self.storage
-            .borrow_mut()
-            .add_discount(current_cycle.number, discount);
+            match self.mint_nft(&record).await {
+                Ok(token_id) => self.confirm_mint(record, token_id),
+                Err(err) if R::is_clean_reject(&err) && record.attempts + 1 >= Self::MAX_MINT_ATTEMPTS => self.compensate_mint(record),
+                Err(_) => {
+                    record.attempts += 1;
+                    self.storage.borrow_mut().save_mint_record(record);
+                }
+            }
+        }
 
-        self.storage.borrow_mut().increase_discount_index(hiver, current_cycle.number);
-        token_id
+        processed
     }
 
-    pub async fn get_discount(&self, token_id: u128) -> Discount {
+    /// the NFT canister returns the already minted token when called again with the same key
+    async fn mint_nft(&self, record: &MintRecord) -> Result<u128, R::Error> {
+        let discount = &record.discount;
+        let nft = self.nft.borrow().clone();
+        nft.privia_mint_token(discount.owner, discount.to_metadata(), Some(record.key)).await
+    }
+
+    fn confirm_mint(&self, record: MintRecord, token_id: u128) {
+        let mut discount = record.discount;
+        discount.id = token_id;
+        let event = DaoEvent::DiscountMinted { discount_id: token_id, owner: discount.owner, hiver: record.hiver, value: discount.value };
+
+        let mut storage = self.storage.borrow_mut();
+        storage.add_discount(record.cycle_number, discount);
+        storage.save_minted_key(record.key, token_id);
+        storage.remove_mint_record(record.key);
+        self.events.record(event);
+    }
+
+    /// gives the quota back. The record is kept so that failed mints remain auditable
+    fn compensate_mint(&self, mut record: MintRecord) {
+        release_quota(&self.storage, &self.hiving, record.cycle_number, record.hiver, record.discount.owner);
+
+        record.status = MintStatus::Compensated;
+        self.events.record(DaoEvent::DiscountMintCompensated { mint_key: record.key, owner: record.discount.owner, hiver: record.hiver });
+        self.storage.borrow_mut().save_mint_record(record);
+    }
+
+    pub fn get_mint_state(&self, mint_key: u64) -> Option<DiscountMintState> {
+        let storage = self.storage.borrow();
+        if let Some(discount_id) = storage.get_minted_key(mint_key) {
+            return Some(DiscountMintState::Minted { discount_id });
+        }
+
+        storage.get_mint_record(mint_key).map(|record| match record.status {
+            MintStatus::Pending => DiscountMintState::Pending,
+            MintStatus::Compensated => DiscountMintState::Compensated,
+        })
+    }
+
+    pub async fn audit_mints(&self) -> Result<DiscountMintAudit, DaoError> {
+        let nft = self.nft.borrow().clone();
+        let nft_supply = nft.icrc7_total_supply().await.map_err(DaoError::call_failed)?;
+        let storage = self.storage.borrow();
+
+        Ok(DiscountMintAudit {
+            discounts: storage.count_discounts(),
+            pending: storage.count_mint_records(MintStatus::Pending),
+            compensated: storage.count_mint_records(MintStatus::Compensated),
+            nft_supply,
+        })
+    }
+
+    fn reserve_quota(&self, cycle_number: u64, hiver: Account, buyer: Account) -> QuotaReservation {
+        self.storage.borrow_mut().increase_discount_index(hiver, cycle_number);
+        self.hiving.borrow().add_wallet_usage(cycle_number, buyer);
+
+        QuotaReservation {
+            storage: Rc::clone(&self.storage),
+            hiving: Rc::clone(&self.hiving),
+            cycle_number,
+            hiver,
+            buyer,
+            confirmed: false,
+        }
+    }
+
+    pub async fn get_discount(&self, token_id: u128) -> Result<Discount, DaoError> {
         let req_param = Vec::from([token_id]);
 
-        let metadata_response = self.nft.borrow().icrc7_token_metadata(req_param.clone()).await.unwrap();
-        let metadata_opt = metadata_response.first().unwrap().clone();
-        let metadata = metadata_opt.unwrap();
+        let nft = self.nft.borrow().clone();
+        let metadata_response = nft
+            .icrc7_token_metadata(req_param.clone())
+            .await
+            .map_err(DaoError::call_failed)?;
+        let metadata = metadata_response.into_iter().next().flatten().ok_or(DaoError::DiscountNotFound)?;
+
+        let owner_response = nft.icrc7_owner_of(req_param).await.map_err(DaoError::call_failed)?;
+        let owner = owner_response.into_iter().next().flatten().ok_or(DaoError::DiscountNotFound)?;
 
-        let owner_response = self.nft.borrow().icrc7_owner_of(req_param).await.unwrap();
-        let owner_opt = owner_response.first().unwrap().clone();
-        let owner = owner_opt.unwrap();
+        let mut discount = Self::build_discount(token_id, owner, &metadata).ok_or(DaoError::DiscountNotFound)?;
+        if let Some(stored) = self.storage.borrow().get_discount(token_id) {
+            discount.hiver = stored.hiver;
+            discount.expires_on = stored.expires_on;
+            discount.redemption = stored.redemption;
+        }
+
+        Ok(discount)
+    }
 
-        let discount = Self::build_discount(token_id, owner, &metadata);
-        discount
+    pub fn list_discounts_by_cycle(&self, cycle_number: u64, prev: Option<u128>, take: Option<u32>) -> Vec<Discount> {
+        self.storage.borrow().get_discounts_by_cycle(cycle_number, prev, Self::page_size(take))
     }
 
-    fn build_discount(id: u128, owner: Account, metadata: &Vec<(String, MetadataValue)>) -> Discount {
-        let discount_value_md =
-            Self::find_metadata_value(metadata, "value".to_string()).unwrap();
-        let discount_value = match discount_value_md {
-            MetadataValue::Text(discount_value) => discount_value.parse::<f32>().unwrap(),
-            _ => panic!("value"),
+    pub fn list_discounts_by_owner(&self, owner: Account, prev: Option<u128>, take: Option<u32>) -> Vec<Discount> {
+        self.storage.borrow().get_discounts_by_owner(&owner, prev, Self::page_size(take))
+    }
+
+    pub fn list_discounts_by_hiver(&self, hiver: Account, prev: Option<u128>, take: Option<u32>) -> Vec<Discount> {
+        self.storage.borrow().get_discounts_by_hiver(&hiver, prev, Self::page_size(take))
+    }
+
+    fn page_size(take: Option<u32>) -> usize {
+        take.map_or(Self::MAX_PAGE_SIZE, |take| (take as usize).min(Self::MAX_PAGE_SIZE))
+    }
+
+    pub fn verify_discount(&self, token_id: u128) -> DiscountValidity {
+        let now = self.runtime.borrow().get_time();
+        match self.storage.borrow().get_discount(token_id) {
+            Some(discount) => discount.validity(now),
+            None => DiscountValidity::NotFound,
+        }
+    }
+
+    /// marks discounts which expired before now as such in the NFT metadata and returns the
+    /// number of expired discounts. A discount whose NFT could not be updated is recorded as
+    /// failed and retried after `EXPIRY_RETRY_DELAY_NS`, so it does not hold up the others
+    pub async fn expire_discounts(&self, limit: usize) -> Result<usize, DaoError> {
+        let now = self.runtime.borrow().get_time();
+        let due = self.storage.borrow().get_expired_discounts(now, limit);
+
+        let nft = self.nft.borrow().clone();
+        let mut expired = 0;
+        for (due_on, discount) in due {
+            let discount_id = discount.id;
+            let result = match nft.privia_update_token_metadata(discount_id, Vec::from([Self::expired_metadata()])).await {
+                Ok(result) => result,
+                Err(err) => Err(format!("{:?}", err)),
+            };
+            match result {
+                Ok(()) => {
+                    self.storage.borrow_mut().remove_from_expiry_index(due_on, discount_id);
+                    self.events.record(DaoEvent::DiscountExpired { discount_id });
+                    expired += 1;
+                }
+                Err(reason) => {
+                    let retry_on = self.runtime.borrow().get_time() + Self::EXPIRY_RETRY_DELAY_NS;
+                    self.storage.borrow_mut().reschedule_expiry(due_on, discount_id, retry_on);
+                    self.events.record(DaoEvent::DiscountExpiryFailed { discount_id, reason });
+                }
+            }
+        }
+
+        Ok(expired)
+    }
+
+    fn expired_metadata() -> (String, MetadataValue) {
+        ("status".to_string(), MetadataValue::Text("expired".to_string()))
+    }
+
+    /// redeems the discount for the customer presenting it, who must own its NFT
+    pub async fn redeem_discount(&self, token_id: u128, customer: Account, order_ref: String) -> Result<Discount, DaoError> {
+        let caller = self.runtime.borrow().get_caller();
+        if !self.merchants.borrow().is_merchant(&caller) {
+            return Err(DaoError::Unauthorized { reason: "Only registered merchants can redeem discounts".to_string() });
+        }
+        let _guard = self
+            .locks
+            .try_acquire(LockKey::Redemption(token_id))
+            .ok_or(DaoError::RedemptionInProgress)?;
+        self.validate_redeemable(token_id)?;
+
+        let nft = self.nft.borrow().clone();
+        let owner_response = nft
+            .icrc7_owner_of(Vec::from([token_id]))
+            .await
+            .map_err(DaoError::call_failed)?;
+        let owner = owner_response.into_iter().next().flatten().ok_or(DaoError::DiscountNotFound)?;
+        if owner != customer {
+            return Err(DaoError::Unauthorized { reason: "The discount is not owned by the presenting customer".to_string() });
+        }
+
+        let redemption = DiscountRedemption {
+            merchant: caller,
+            order_ref,
+            redeemed_on: self.runtime.borrow().get_time(),
         };
+        // the DAO records the redemption only once the NFT metadata mirrors it,
+        // so a failed update leaves the discount redeemable
+        nft.privia_update_token_metadata(token_id, redemption.to_metadata())
+            .await
+            .map_err(DaoError::call_failed)?
+            .map_err(|reason| DaoError::CallFailed { reason })?;
+
+        let mut discount = self.storage.borrow().get_discount(token_id).ok_or(DaoError::DiscountNotFound)?;
+        discount.owner = owner;
+        discount.redemption = Some(redemption.clone());
+        self.storage.borrow_mut().update_discount(discount.clone());
+        if let Some(expires_on) = discount.expires_on {
+            self.storage.borrow_mut().remove_from_expiry_index(expires_on, token_id);
+        }
+        self.events.record(DaoEvent::DiscountRedeemed {
+            discount_id: token_id,
+            merchant: caller,
+            order_ref: redemption.order_ref.clone(),
+        });
+
+        Ok(discount)
+    }
 
-        let discount = Discount {
-            id,
-            owner,
-            value: discount_value,
+    fn validate_redeemable(&self, token_id: u128) -> Result<Discount, DaoError> {
+        let now = self.runtime.borrow().get_time();
+        let discount = self.storage.borrow().get_discount(token_id);
+        match discount {
+            None => Err(DaoError::DiscountNotFound),
+            Some(discount) => match discount.validity(now) {
+                DiscountValidity::Valid => Ok(discount),
+                DiscountValidity::Expired => Err(DaoError::DiscountExpired),
+                _ => Err(DaoError::DiscountAlreadyRedeemed),
+            },
+        }
+    }
+
+    fn build_discount(id: u128, owner: Account, metadata: &Vec<(String, MetadataValue)>) -> Option<Discount> {
+        let discount_value = match Self::find_metadata_value(metadata, "value".to_string())? {
+            MetadataValue::Text(discount_value) => discount_value.parse::<f32>().ok()?,
+            _ => return None,
         };
 
-        discount
+        Some(Discount::new(id, discount_value, owner))
     }
 
     fn find_metadata_value(
@@ -148,3 +502,327 @@ impl DiscountService {
         None
     }
 }
+
+fn release_quota(
+    storage: &Rc<RefCell<dyn IDiscountStorage>>,
+    hiving: &Rc<RefCell<HivingService>>,
+    cycle_number: u64,
+    hiver: Account,
+    buyer: Account,
+) {
+    storage.borrow_mut().decrease_discount_index(hiver, cycle_number);
+    hiving.borrow().remove_wallet_usage(cycle_number, buyer);
+}
+
+/// Per-cycle quota taken by a mint before its pending record is written. It is given back
+/// when dropped unconfirmed, i.e. when the call fails or traps before the record exists
+struct QuotaReservation {
+    storage: Rc<RefCell<dyn IDiscountStorage>>,
+    hiving: Rc<RefCell<HivingService>>,
+    cycle_number: u64,
+    hiver: Account,
+    buyer: Account,
+    confirmed: bool,
+}
+
+impl QuotaReservation {
+    fn confirm(mut self) {
+        self.confirmed = true;
+    }
+}
+
+impl Drop for QuotaReservation {
+    fn drop(&mut self) {
+        if self.confirmed {
+            return;
+        }
+        release_quota(&self.storage, &self.hiving, self.cycle_number, self.hiver, self.buyer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::cycles::CyclesConfig;
+    use crate::domain::hiving::HivingConfig;
+    use crate::domain::mocks::{poll_once, CallContextMock, RuntimeMock};
+    use crate::domain::staking::StakingConfig;
+    use crate::icp::stable_storage::{CycleStorageStable, DiscountStorageStable, EventStorageStable, HivingStorageStorable, MerchantStorageStable};
+    use abstractions::dao::Merchant;
+    use abstractions::hiving::RegisterHivingCanisterArgs;
+    use abstractions::token::{StakingLogEntry, StakingLogResult, TokenClient};
+    use candid::{Nat, Principal};
+    use std::pin::pin;
+    use std::task::Poll;
+
+    const MINT: &str = "privia_mint_token";
+    const STAKING_LOG: &str = "privia_staking_log";
+    const OWNER_OF: &str = "icrc7_owner_of";
+    const UPDATE_METADATA: &str = "privia_update_token_metadata";
+
+    struct Setup {
+        calls: CallContextMock,
+        runtime: Rc<RefCell<RuntimeMock>>,
+        discounts: Rc<RefCell<DiscountStorageStable>>,
+        hiving: Rc<RefCell<HivingStorageStorable>>,
+        merchants: Rc<RefCell<MerchantStorageStable>>,
+        cycles: Rc<RefCell<CycleStorageStable>>,
+        events: EventLog,
+        hiver: Account,
+        canister: Principal,
+        genesis: Timestamp,
+    }
+
+    impl Setup {
+        fn new() -> Self {
+            let cycles_config = CyclesConfig::default();
+            let genesis = cycles_config.genesis.unwrap();
+            let canister = Principal::from_slice(&[10]);
+            let hiver = Account::from(Principal::from_slice(&[20]));
+            let runtime = Rc::new(RefCell::new(RuntimeMock {
+                caller: canister,
+                time: genesis + 4 * cycles_config.cycle_len_ns + 1,
+            }));
+
+            let setup = Self {
+                calls: CallContextMock::default(),
+                events: EventLog::new(Rc::new(RefCell::new(EventStorageStable::init())), runtime.clone()),
+                runtime,
+                discounts: Rc::new(RefCell::new(DiscountStorageStable::init())),
+                hiving: Rc::new(RefCell::new(HivingStorageStorable::init())),
+                merchants: Rc::new(RefCell::new(MerchantStorageStable::init())),
+                cycles: Rc::new(RefCell::new(CycleStorageStable::init())),
+                hiver,
+                canister,
+                genesis,
+            };
+            let hiving = setup.build_hiving();
+            setup.runtime.borrow_mut().caller = hiver.owner;
+            hiving.authorize_canister(canister);
+            setup.runtime.borrow_mut().caller = canister;
+            hiving.add_hiving_canister(RegisterHivingCanisterArgs { canister_id: canister, owner: hiver.owner, metadata: vec![] }).unwrap();
+
+            setup
+        }
+
+        fn build_hiving(&self) -> HivingService {
+            let config = HivingConfig { allowlist: vec![self.canister], ..HivingConfig::default() };
+            HivingService::new(config, self.hiving.clone(), self.runtime.clone(), self.events.clone())
+        }
+
+        /// services share the storages, like concurrent calls of the canister do
+        fn build_service(&self, locks: &InFlightLocks) -> DiscountService<CallContextMock> {
+            let config = DiscountConfig { discounts_per_cycle: 1, ..DiscountConfig::default() };
+            let cycles = Rc::new(RefCell::new(CycleService::new(CyclesConfig::default(), self.cycles.clone(), self.runtime.clone(), self.events.clone())));
+            let calls = Rc::new(RefCell::new(self.calls.clone()));
+            let nft = NftClient { runtime: calls.clone(), canister_id: Principal::anonymous() };
+            let token = TokenClient { runtime: calls, canister_id: Principal::anonymous() };
+            let staking = StakingService::new(StakingConfig::default(), Rc::new(RefCell::new(token)), cycles.clone());
+            let merchants = MerchantService::new(self.merchants.clone(), self.runtime.clone(), self.events.clone());
+
+            DiscountService::new(
+                config,
+                cycles,
+                self.discounts.clone(),
+                Rc::new(RefCell::new(nft)),
+                Rc::new(RefCell::new(staking)),
+                Rc::new(RefCell::new(merchants)),
+                Rc::new(RefCell::new(self.build_hiving())),
+                locks.clone(),
+                self.runtime.clone(),
+                self.events.clone(),
+            )
+        }
+
+        fn respond_staking_log(&self) {
+            let entry = StakingLogEntry {
+                previous_amount: Nat::from(0u8),
+                current_amount: Nat::from(1000u32),
+                timestamp: self.genesis + 1,
+            };
+            self.calls.respond(STAKING_LOG, StakingLogResult { from: 0, to: 0, log: vec![entry] });
+        }
+    }
+
+    fn buyer(id: u8) -> Account {
+        Account::from(Principal::from_slice(&[id]))
+    }
+
+    #[test]
+    fn concurrent_mints_cannot_exceed_quota() {
+        let setup = Setup::new();
+        let locks = InFlightLocks::default();
+        let (first, second, third) = (setup.build_service(&locks), setup.build_service(&locks), setup.build_service(&locks));
+        setup.respond_staking_log();
+        setup.calls.respond(MINT, 7u128);
+        setup.calls.hold(MINT);
+
+        let mut first_mint = pin!(first.mint_discount(setup.hiver, DiscountRequest::new(100, buyer(1))));
+        assert!(poll_once(first_mint.as_mut()).is_pending());
+
+        let same_buyer = pin!(second.mint_discount(setup.hiver, DiscountRequest::new(100, buyer(1))));
+        assert_eq!(poll_once(same_buyer), Poll::Ready(Err(DaoError::Mint(MintDiscountError::MintInProgress))));
+
+        let same_hiver = pin!(third.mint_discount(setup.hiver, DiscountRequest::new(100, buyer(2))));
+        assert_eq!(poll_once(same_hiver), Poll::Ready(Err(DaoError::Mint(MintDiscountError::DiscountsPerCycleLimitReached))));
+
+        setup.calls.release(MINT);
+        assert_eq!(poll_once(first_mint), Poll::Ready(Ok(7)));
+        assert!(!locks.is_locked(&LockKey::Mint(buyer(1))));
+
+        let quota = first.get_quota(buyer(1), setup.hiver).unwrap();
+        assert_eq!(quota.hiver_remaining, 0);
+        assert_eq!(quota.buyer_remaining, 2);
+    }
+
+    #[test]
+    fn failed_mint_gives_quota_back() {
+        let setup = Setup::new();
+        let service = setup.build_service(&InFlightLocks::default());
+        setup.respond_staking_log();
+        setup.calls.fail(MINT, "nft canister is stopped");
+
+        let mint = pin!(service.mint_discount(setup.hiver, DiscountRequest::new(100, buyer(1))));
+        let result = poll_once(mint);
+        assert!(matches!(result, Poll::Ready(Err(DaoError::Mint(MintDiscountError::NftMintFailed { .. })))));
+
+        let quota = service.get_quota(buyer(1), setup.hiver).unwrap();
+        assert_eq!(quota.hiver_remaining, 1);
+        assert_eq!(quota.buyer_remaining, 3);
+        assert_eq!(setup.discounts.borrow().count_mint_records(MintStatus::Compensated), 1);
+    }
+
+    #[test]
+    fn quoted_value_is_minted_while_the_score_covers_it() {
+        let setup = Setup::new();
+        let service = setup.build_service(&InFlightLocks::default());
+        setup.respond_staking_log();
+        setup.calls.respond(MINT, 7u128);
+
+        let mut request = DiscountRequest::new(100, buyer(1));
+        request.value = Some(DiscountService::<CallContextMock>::MAX_DISCOUNT + 1.0);
+        let result = poll_once(pin!(service.mint_discount(setup.hiver, request.clone())));
+        assert!(matches!(result, Poll::Ready(Err(DaoError::Mint(MintDiscountError::QuotedValueUnavailable { .. })))));
+        assert_eq!(service.get_quota(buyer(1), setup.hiver).unwrap().hiver_remaining, 1);
+
+        request.value = Some(0.5);
+        setup.respond_staking_log();
+        assert_eq!(poll_once(pin!(service.mint_discount(setup.hiver, request))), Poll::Ready(Ok(7)));
+        assert_eq!(setup.discounts.borrow().get_discount(7).unwrap().value, 0.5);
+    }
+
+    #[test]
+    fn interrupted_mint_is_recovered() {
+        let setup = Setup::new();
+        let locks = InFlightLocks::default();
+        let service = setup.build_service(&locks);
+        setup.respond_staking_log();
+        setup.calls.hold(MINT);
+
+        // dropping the future while the NFT canister is called is what a trap in the callback does
+        {
+            let mut mint = pin!(service.mint_discount(setup.hiver, DiscountRequest::new(100, buyer(1))));
+            assert!(poll_once(mint.as_mut()).is_pending());
+            assert_eq!(poll_once(pin!(service.recover_pending_mints(10))), Poll::Ready(0));
+        }
+        assert!(!locks.is_locked(&LockKey::Mint(buyer(1))));
+        assert_eq!(setup.discounts.borrow().count_mint_records(MintStatus::Pending), 1);
+        assert_eq!(service.get_quota(buyer(1), setup.hiver).unwrap().hiver_remaining, 0);
+
+        setup.calls.respond(MINT, 7u128);
+        setup.calls.release(MINT);
+        assert_eq!(poll_once(pin!(service.recover_pending_mints(10))), Poll::Ready(1));
+
+        assert_eq!(setup.discounts.borrow().count_mint_records(MintStatus::Pending), 0);
+        let discount = setup.discounts.borrow().get_discount(7).unwrap();
+        assert_eq!(discount.owner, buyer(1));
+        assert_eq!(discount.hiver, Some(setup.hiver));
+    }
+
+    #[test]
+    fn redemption_is_recorded_after_the_nft_update() {
+        let setup = Setup::new();
+        let service = setup.build_service(&InFlightLocks::default());
+        let merchant = Principal::from_slice(&[30]);
+        setup.merchants.borrow_mut().add_merchant(Merchant { id: merchant, name: "shop".to_string(), registered_on: 0 });
+        setup.discounts.borrow_mut().add_discount(4, Discount::new(7, 10.0, buyer(1)));
+        setup.runtime.borrow_mut().caller = merchant;
+
+        setup.calls.respond(OWNER_OF, vec![Some(buyer(1))]);
+        let result = poll_once(pin!(service.redeem_discount(7, buyer(2), "order-1".to_string())));
+        assert!(matches!(result, Poll::Ready(Err(DaoError::Unauthorized { .. }))));
+
+        setup.calls.respond(OWNER_OF, vec![Some(buyer(1))]);
+        setup.calls.respond(UPDATE_METADATA, Err::<(), String>("Only the minting account can update token metadata".to_string()));
+        let result = poll_once(pin!(service.redeem_discount(7, buyer(1), "order-1".to_string())));
+        assert!(matches!(result, Poll::Ready(Err(DaoError::CallFailed { .. }))));
+        assert_eq!(service.verify_discount(7), DiscountValidity::Valid);
+
+        setup.calls.respond(OWNER_OF, vec![Some(buyer(1))]);
+        setup.calls.respond(UPDATE_METADATA, Ok::<(), String>(()));
+        let result = poll_once(pin!(service.redeem_discount(7, buyer(1), "order-1".to_string())));
+        assert!(matches!(result, Poll::Ready(Ok(_))));
+        assert_eq!(setup.discounts.borrow().get_discount(7).unwrap().redemption.unwrap().merchant, merchant);
+    }
+
+    #[test]
+    fn failed_expiry_does_not_block_the_batch() {
+        let setup = Setup::new();
+        let service = setup.build_service(&InFlightLocks::default());
+        let now = setup.runtime.borrow().time;
+        for id in [7, 8] {
+            let mut discount = Discount::new(id, 10.0, buyer(1));
+            discount.expires_on = Some(now - 1);
+            setup.discounts.borrow_mut().add_discount(4, discount);
+        }
+
+        setup.calls.fail(UPDATE_METADATA, "token is locked");
+        setup.calls.respond(UPDATE_METADATA, Ok::<(), String>(()));
+        assert_eq!(poll_once(pin!(service.expire_discounts(10))), Poll::Ready(Ok(1)));
+        let failed = setup.events.get_events(0, None, None).events.into_iter().any(|record| {
+            matches!(record.event, DaoEvent::DiscountExpiryFailed { discount_id: 7, .. })
+        });
+        assert!(failed);
+
+        // the failed discount waits for the retry delay
+        assert_eq!(poll_once(pin!(service.expire_discounts(10))), Poll::Ready(Ok(0)));
+        setup.runtime.borrow_mut().time = now + DiscountService::<CallContextMock>::EXPIRY_RETRY_DELAY_NS;
+        setup.calls.respond(UPDATE_METADATA, Ok::<(), String>(()));
+        assert_eq!(poll_once(pin!(service.expire_discounts(10))), Poll::Ready(Ok(1)));
+        assert!(setup.discounts.borrow().get_expired_discounts(u64::MAX, 10).is_empty());
+    }
+
+    #[test]
+    fn subaccounts_share_the_buyer_quota() {
+        let setup = Setup::new();
+        let service = setup.build_service(&InFlightLocks::default());
+        setup.respond_staking_log();
+        setup.calls.respond(MINT, 7u128);
+
+        let subaccount = Account { owner: buyer(1).owner, subaccount: Some([1; 32]) };
+        assert_eq!(poll_once(pin!(service.mint_discount(setup.hiver, DiscountRequest::new(100, subaccount)))), Poll::Ready(Ok(7)));
+
+        let quota = service.get_quota(buyer(1), setup.hiver).unwrap();
+        assert_eq!(quota.buyer_remaining, 2);
+    }
+
+    #[test]
+    fn ambiguous_mint_stays_pending() {
+        let setup = Setup::new();
+        let service = setup.build_service(&InFlightLocks::default());
+        setup.respond_staking_log();
+        setup.calls.fail_unknown(MINT, "response could not be decoded");
+
+        let result = poll_once(pin!(service.mint_discount(setup.hiver, DiscountRequest::new(100, buyer(1)))));
+        assert!(matches!(result, Poll::Ready(Err(DaoError::Mint(MintDiscountError::NftMintPending { .. })))));
+        assert_eq!(setup.discounts.borrow().count_mint_records(MintStatus::Pending), 1);
+        assert_eq!(service.get_quota(buyer(1), setup.hiver).unwrap().hiver_remaining, 0);
+        assert_eq!(service.get_mint_state(1), Some(DiscountMintState::Pending));
+
+        setup.calls.respond(MINT, 7u128);
+        assert_eq!(poll_once(pin!(service.recover_pending_mints(10))), Poll::Ready(1));
+        assert_eq!(setup.discounts.borrow().count_mint_records(MintStatus::Pending), 0);
+        assert_eq!(setup.discounts.borrow().get_discount(7).unwrap().owner, buyer(1));
+        assert_eq!(service.get_mint_state(1), Some(DiscountMintState::Minted { discount_id: 7 }));
+    }
+}
@@ -0,0 +1,319 @@
+use crate::domain::cycles::CycleService;
+use crate::domain::events::EventLog;
+use crate::domain::interfaces::storage::IEmissionStorage;
+use crate::domain::locks::{InFlightLocks, LockKey};
+use crate::domain::payouts::{Payout, PayoutOutcome};
+use crate::domain::staking::StakingService;
+use abstractions::dao::{CycleEmission, DaoError, DaoEvent, StakingRewardsClaim};
+use abstractions::runtime::{ICallContext, ICanisterRuntime};
+use abstractions::token::TokenClient;
+use candid::{CandidType, Deserialize, Nat};
+use icrc_ledger_types::icrc1::account::{Account, Subaccount};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::rc::Rc;
+
+/// PVT minted to stakers every cycle. The DAO has to be the minting account of the token
+#[derive(Clone, Debug, Default, Deserialize, Serialize, CandidType)]
+pub struct EmissionConfig {
+    /// emission of a cycle, 0 disables the emission
+    pub budget_per_cycle: u128,
+    /// subaccount of the DAO set as the token minting account
+    pub minting_subaccount: Option<Subaccount>,
+}
+
+/// Score of a staker in a cycle
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct StakeClaim {
+    pub score: Nat,
+    /// set once the claim is part of a payout
+    pub paid: bool,
+}
+
+/// Progress of counting the scores of all stakers for a cycle
+#[derive(Clone, Debug, Default, CandidType, Deserialize)]
+pub struct ScoreSnapshot {
+    pub cycle_number: u64,
+    /// last counted staker, the next page starts after it
+    pub last_staker: Option<Account>,
+    pub complete: bool,
+}
+
+/// Rewards of some cycles being paid to an account
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct PendingClaim {
+    pub cycles: Vec<u64>,
+    pub payout: Payout,
+}
+
+pub struct EmissionService<R: ICallContext> {
+    config: EmissionConfig,
+    cycles: Rc<RefCell<CycleService>>,
+    staking: Rc<RefCell<StakingService<R>>>,
+    storage: Rc<RefCell<dyn IEmissionStorage>>,
+    token: Rc<RefCell<TokenClient<R>>>,
+    locks: InFlightLocks,
+    runtime: Rc<RefCell<dyn ICanisterRuntime>>,
+    events: EventLog,
+}
+
+impl<R: ICallContext> EmissionService<R>
+where
+    R::Error: Debug,
+{
+    const STAKERS_PAGE_SIZE: u32 = 20;
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        config: EmissionConfig,
+        cycles: Rc<RefCell<CycleService>>,
+        staking: Rc<RefCell<StakingService<R>>>,
+        storage: Rc<RefCell<dyn IEmissionStorage>>,
+        token: Rc<RefCell<TokenClient<R>>>,
+        locks: InFlightLocks,
+        runtime: Rc<RefCell<dyn ICanisterRuntime>>,
+        events: EventLog,
+    ) -> Self {
+        Self { config, cycles, staking, storage, token, locks, runtime, events }
+    }
+
+    pub fn get_cycle_emission(&self, cycle_number: u64) -> Option<CycleEmission> {
+        self.storage.borrow().get_cycle_emission(cycle_number)
+    }
+
+    /// Counts the scores of the next page of stakers for the current cycle.
+    ///
+    /// The budget of a cycle is split among all stakers pro rata to their score. Scores are
+    /// taken at the cycle start, so they are counted while the cycle is current; stakers not
+    /// reached before the cycle ends get no share of it. Returns the number of counted stakers
+    pub async fn snapshot_scores(&self) -> Result<usize, DaoError> {
+        if self.config.budget_per_cycle == 0 {
+            return Ok(0);
+        }
+        let Some(_guard) = self.locks.try_acquire(LockKey::ScoreSnapshot) else {
+            return Ok(0);
+        };
+
+        let cycle_number = self.cycles.borrow().get_current_cycle()?.number;
+        let mut snapshot = self.storage.borrow().get_snapshot();
+        if snapshot.cycle_number != cycle_number {
+            snapshot = ScoreSnapshot { cycle_number, ..ScoreSnapshot::default() };
+        }
+        if snapshot.complete {
+            return Ok(0);
+        }
+
+        let token = self.token.borrow().clone();
+        let stakers = token
+            .privia_stakers(snapshot.last_staker, Self::STAKERS_PAGE_SIZE)
+            .await
+            .map_err(DaoError::call_failed)?;
+        let staking = self.staking.borrow().clone();
+        for staker in stakers.iter() {
+            let score = staking.get_current_staking_score(*staker).await?;
+            if self.cycles.borrow().get_current_cycle()?.number != cycle_number {
+                return Ok(0);
+            }
+            self.count(*staker, cycle_number, score);
+        }
+
+        snapshot.complete = stakers.len() < Self::STAKERS_PAGE_SIZE as usize;
+        snapshot.last_staker = stakers.last().copied().or(snapshot.last_staker);
+        self.storage.borrow_mut().save_snapshot(snapshot);
+
+        Ok(stakers.len())
+    }
+
+    fn count(&self, account: Account, cycle_number: u64, score: Nat) {
+        let mut storage = self.storage.borrow_mut();
+        if score == 0u8 || storage.get_claim(account, cycle_number).is_some() {
+            return;
+        }
+
+        let mut emission = storage.get_cycle_emission(cycle_number).unwrap_or(CycleEmission {
+            budget: Nat::from(self.config.budget_per_cycle),
+            total_score: Nat::from(0u8),
+            participants: 0,
+        });
+        emission.total_score += score.clone();
+        emission.participants += 1;
+        storage.save_cycle_emission(cycle_number, emission);
+        storage.save_claim(account, cycle_number, StakeClaim { score, paid: false });
+    }
+
+    /// Pays the caller's rewards of the finished cycles.
+    ///
+    /// A payout whose outcome is unknown is resent unchanged by the next claim, and the claims
+    /// it pays become claimable again only once the ledger rejected it
+    pub async fn claim_staking_rewards(&self) -> Result<StakingRewardsClaim, DaoError> {
+        let account = Account::from(self.runtime.borrow().get_caller());
+        let _guard = self
+            .locks
+            .try_acquire(LockKey::Claim(account))
+            .ok_or(DaoError::ClaimInProgress)?;
+
+        let pending = self.storage.borrow().get_pending_claim(account);
+        if let Some(pending) = pending {
+            return self.pay(account, pending).await;
+        }
+
+        let current_cycle = self.cycles.borrow().get_current_cycle()?.number;
+        let claims = self.storage.borrow().get_unpaid_claims(account, current_cycle);
+        let mut amount = Nat::from(0u8);
+        for (cycle_number, mut claim) in claims.clone() {
+            amount += self.reward(cycle_number, &claim);
+            claim.paid = true;
+            self.storage.borrow_mut().save_claim(account, cycle_number, claim);
+        }
+        let cycles: Vec<u64> = claims.into_iter().map(|(cycle_number, _)| cycle_number).collect();
+        if amount == 0u8 {
+            return Ok(StakingRewardsClaim { cycles, amount, block_index: None });
+        }
+
+        let now = self.runtime.borrow().get_time();
+        let pending = PendingClaim { payout: Payout::new(account, amount, &(account, &cycles), now), cycles };
+        self.storage.borrow_mut().save_pending_claim(account, pending.clone());
+
+        self.pay(account, pending).await
+    }
+
+    async fn pay(&self, account: Account, pending: PendingClaim) -> Result<StakingRewardsClaim, DaoError> {
+        let token = self.token.borrow().clone();
+        match pending.payout.send(&token, self.config.minting_subaccount).await {
+            PayoutOutcome::Paid(block_index) => {
+                let PendingClaim { cycles, payout } = pending;
+                self.storage.borrow_mut().remove_pending_claim(account);
+                self.events.record(DaoEvent::StakingRewardsClaimed { account, cycles: cycles.clone(), amount: payout.amount.clone() });
+
+                Ok(StakingRewardsClaim { cycles, amount: payout.amount, block_index: Some(block_index) })
+            }
+            PayoutOutcome::Rejected(reason) => {
+                let mut storage = self.storage.borrow_mut();
+                storage.remove_pending_claim(account);
+                for cycle_number in pending.cycles {
+                    if let Some(mut claim) = storage.get_claim(account, cycle_number) {
+                        claim.paid = false;
+                        storage.save_claim(account, cycle_number, claim);
+                    }
+                }
+                Err(DaoError::CallFailed { reason })
+            }
+            PayoutOutcome::Unknown(reason) => Err(DaoError::CallFailed { reason }),
+        }
+    }
+
+    fn reward(&self, cycle_number: u64, claim: &StakeClaim) -> Nat {
+        match self.storage.borrow().get_cycle_emission(cycle_number) {
+            Some(emission) if emission.total_score > 0u8 => emission.budget * claim.score.clone() / emission.total_score,
+            _ => Nat::from(0u8),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::cycles::CyclesConfig;
+    use crate::domain::mocks::{poll_once, CallContextMock, RuntimeMock};
+    use crate::domain::staking::StakingConfig;
+    use crate::icp::stable_storage::{CycleStorageStable, EmissionStorageStable, EventStorageStable};
+    use abstractions::token::{StakingLogEntry, StakingLogResult};
+    use candid::Principal;
+    use icrc_ledger_types::icrc1::transfer::TransferError;
+    use std::pin::pin;
+    use std::task::Poll;
+
+    const STAKERS: &str = "privia_stakers";
+    const STAKING_LOG: &str = "privia_staking_log";
+    const TRANSFER: &str = "icrc1_transfer";
+
+    fn respond_staking_log(calls: &CallContextMock, genesis: u64, amount: u32) {
+        let entry = StakingLogEntry {
+            previous_amount: Nat::from(0u8),
+            current_amount: Nat::from(amount),
+            timestamp: genesis + 1,
+        };
+        calls.respond(STAKING_LOG, StakingLogResult { from: 0, to: 0, log: vec![entry] });
+    }
+
+    struct Setup {
+        calls: CallContextMock,
+        runtime: Rc<RefCell<RuntimeMock>>,
+        service: EmissionService<CallContextMock>,
+        cycle_len: u64,
+    }
+
+    /// stakers 1 and 2 with scores 100 and 300 are counted for cycle 4
+    fn setup() -> Setup {
+        let cycles_config = CyclesConfig::default();
+        let genesis = cycles_config.genesis.unwrap();
+        let cycle_len = cycles_config.cycle_len_ns;
+        let runtime = Rc::new(RefCell::new(RuntimeMock { caller: Principal::from_slice(&[1]), time: genesis + 3 * cycle_len + 1 }));
+        let events = EventLog::new(Rc::new(RefCell::new(EventStorageStable::init())), runtime.clone());
+        let cycles = CycleService::new(cycles_config, Rc::new(RefCell::new(CycleStorageStable::init())), runtime.clone(), events.clone());
+        let cycles = Rc::new(RefCell::new(cycles));
+        let calls = CallContextMock::default();
+        let token = Rc::new(RefCell::new(TokenClient { runtime: Rc::new(RefCell::new(calls.clone())), canister_id: Principal::anonymous() }));
+        let staking = StakingService::new(StakingConfig::default(), token.clone(), cycles.clone());
+        let service = EmissionService::new(
+            EmissionConfig { budget_per_cycle: 1000, minting_subaccount: None },
+            cycles,
+            Rc::new(RefCell::new(staking)),
+            Rc::new(RefCell::new(EmissionStorageStable::init())),
+            token,
+            InFlightLocks::default(),
+            runtime.clone(),
+            events,
+        );
+
+        let stakers = vec![Account::from(Principal::from_slice(&[1])), Account::from(Principal::from_slice(&[2]))];
+        calls.respond(STAKERS, stakers);
+        respond_staking_log(&calls, genesis, 100);
+        respond_staking_log(&calls, genesis, 300);
+        assert!(matches!(poll_once(pin!(service.snapshot_scores())), Poll::Ready(Ok(2))));
+        assert!(matches!(poll_once(pin!(service.snapshot_scores())), Poll::Ready(Ok(0))));
+
+        Setup { calls, runtime, service, cycle_len }
+    }
+
+    fn claim(setup: &Setup, caller: u8) -> Result<StakingRewardsClaim, DaoError> {
+        setup.runtime.borrow_mut().caller = Principal::from_slice(&[caller]);
+        match poll_once(pin!(setup.service.claim_staking_rewards())) {
+            Poll::Ready(result) => result,
+            Poll::Pending => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn budget_is_split_pro_rata() {
+        let setup = setup();
+        assert_eq!(setup.service.get_cycle_emission(4).unwrap().participants, 2);
+        assert_eq!(claim(&setup, 2).unwrap().amount, Nat::from(0u8));
+
+        setup.runtime.borrow_mut().time += setup.cycle_len;
+        setup.calls.respond(TRANSFER, Err::<Nat, _>(TransferError::TemporarilyUnavailable));
+        assert!(matches!(claim(&setup, 2), Err(DaoError::CallFailed { .. })));
+
+        setup.calls.respond(TRANSFER, Ok::<Nat, TransferError>(Nat::from(7u8)));
+        let result = claim(&setup, 2).unwrap();
+        assert_eq!((result.cycles, result.amount, result.block_index), (vec![4], Nat::from(750u32), Some(Nat::from(7u8))));
+        assert_eq!(claim(&setup, 2).unwrap().amount, Nat::from(0u8));
+    }
+
+    #[test]
+    fn ambiguous_transfer_is_resent_unchanged() {
+        let setup = setup();
+        setup.runtime.borrow_mut().time += setup.cycle_len;
+
+        setup.calls.fail_unknown(TRANSFER, "timeout");
+        assert!(matches!(claim(&setup, 1), Err(DaoError::CallFailed { .. })));
+
+        // the ledger executed the first transfer and deduplicates the resent one
+        setup.runtime.borrow_mut().time += 1;
+        setup.calls.respond(TRANSFER, Err::<Nat, _>(TransferError::Duplicate { duplicate_of: Nat::from(3u8) }));
+        let result = claim(&setup, 1).unwrap();
+        assert_eq!((result.amount, result.block_index), (Nat::from(250u32), Some(Nat::from(3u8))));
+        assert_eq!(claim(&setup, 1).unwrap().amount, Nat::from(0u8));
+    }
+}
@@ -0,0 +1,87 @@
+use crate::domain::interfaces::storage::IEventStorage;
+use abstractions::dao::{DaoEvent, DaoEventFilter, DaoEventPage, DaoEventRecord};
+use abstractions::runtime::ICanisterRuntime;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Append-only history of the DAO, shared by the services which change its state
+#[derive(Clone)]
+pub struct EventLog {
+    storage: Rc<RefCell<dyn IEventStorage>>,
+    runtime: Rc<RefCell<dyn ICanisterRuntime>>,
+}
+
+impl EventLog {
+    const MAX_PAGE_SIZE: usize = 100;
+    /// events looked at by a single query, so that a selective filter cannot exhaust the instruction limit
+    const MAX_SCANNED: u64 = 5_000;
+
+    pub fn new(storage: Rc<RefCell<dyn IEventStorage>>, runtime: Rc<RefCell<dyn ICanisterRuntime>>) -> Self {
+        Self { storage, runtime }
+    }
+
+    pub fn record(&self, event: DaoEvent) {
+        let runtime = self.runtime.borrow();
+        let mut storage = self.storage.borrow_mut();
+        let record = DaoEventRecord {
+            id: storage.count_events(),
+            timestamp: runtime.get_time(),
+            caller: runtime.get_caller(),
+            event,
+        };
+        storage.append_event(&record);
+    }
+
+    /// events from `start` on matching the filter. Fewer than `limit` events can be returned
+    /// before the end of the log; reading continues from `next`
+    pub fn get_events(&self, start: u64, limit: Option<u32>, filter: Option<DaoEventFilter>) -> DaoEventPage {
+        let limit = limit.map_or(Self::MAX_PAGE_SIZE, |limit| (limit as usize).min(Self::MAX_PAGE_SIZE));
+        let filter = filter.unwrap_or_default();
+        let storage = self.storage.borrow();
+        let count = storage.count_events();
+        let end = count.min(start.saturating_add(Self::MAX_SCANNED));
+
+        let mut events = vec![];
+        let mut id = start;
+        while id < end && events.len() < limit {
+            let record = storage.get_event(id).unwrap();
+            if filter.matches(&record) {
+                events.push(record);
+            }
+            id += 1;
+        }
+
+        DaoEventPage {
+            events,
+            next: if id < count { Some(id) } else { None },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::mocks::RuntimeMock;
+    use crate::icp::stable_storage::EventStorageStable;
+    use abstractions::dao::DaoEventKind;
+    use candid::Principal;
+
+    #[test]
+    fn events_are_paged_and_filtered() {
+        let runtime = Rc::new(RefCell::new(RuntimeMock { caller: Principal::anonymous(), time: 1 }));
+        let events = EventLog::new(Rc::new(RefCell::new(EventStorageStable::init())), runtime.clone());
+        for merchant in 0..3u8 {
+            events.record(DaoEvent::MerchantRegistered { merchant_id: Principal::from_slice(&[merchant]), name: String::new() });
+            events.record(DaoEvent::ConfigUpdated);
+        }
+
+        let page = events.get_events(0, Some(4), None);
+        assert_eq!(page.events.iter().map(|record| record.id).collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+        assert_eq!(page.next, Some(4));
+
+        let filter = DaoEventFilter { kinds: Some(vec![DaoEventKind::ConfigUpdated]), caller: None };
+        let page = events.get_events(2, None, Some(filter));
+        assert_eq!(page.events.iter().map(|record| record.id).collect::<Vec<_>>(), vec![3, 5]);
+        assert_eq!(page.next, None);
+    }
+}
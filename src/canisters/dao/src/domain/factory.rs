@@ -0,0 +1,365 @@
+use crate::domain::events::EventLog;
+use crate::domain::governance;
+use crate::domain::hiving::HivingService;
+use crate::domain::interfaces::storage::IWasmStorage;
+use crate::domain::locks::{InFlightLocks, LockKey};
+use abstractions::dao::{DaoError, DaoEvent, HivingCanisterStatus, InstalledWasm, SpawnConfig, WasmKind, WasmModule};
+use abstractions::hiving::{HivingInitArgs, HivingPoolInitArgs};
+use abstractions::runtime::{ICanisterManager, ICanisterRuntime, InstallMode};
+use candid::{Encode, Principal};
+use num_traits::ToPrimitive;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Spawns hiving and hiving pool canisters running WASMs approved by governance. The DAO stays
+/// the only controller of the spawned canisters, so their code can only change through it
+pub struct FactoryService {
+    storage: Rc<RefCell<dyn IWasmStorage>>,
+    hiving: Rc<RefCell<HivingService>>,
+    manager: Rc<dyn ICanisterManager>,
+    locks: InFlightLocks,
+    runtime: Rc<RefCell<dyn ICanisterRuntime>>,
+    events: EventLog,
+}
+
+impl FactoryService {
+    /// largest chunk accepted by the management canister's chunk store
+    pub const MAX_CHUNK_SIZE: usize = 1024 * 1024;
+    const DEFAULT_CYCLES: u128 = 1_000_000_000_000;
+
+    pub fn new(
+        storage: Rc<RefCell<dyn IWasmStorage>>,
+        hiving: Rc<RefCell<HivingService>>,
+        manager: Rc<dyn ICanisterManager>,
+        locks: InFlightLocks,
+        runtime: Rc<RefCell<dyn ICanisterRuntime>>,
+        events: EventLog,
+    ) -> Self {
+        Self { storage, hiving, manager, locks, runtime, events }
+    }
+
+    /// stages the next chunk of a WASM and returns the number of staged chunks
+    pub fn upload_wasm_chunk(&self, kind: WasmKind, chunk: Vec<u8>) -> Result<u32, DaoError> {
+        self.ensure_governance()?;
+        if chunk.is_empty() || chunk.len() > Self::MAX_CHUNK_SIZE {
+            return Err(DaoError::InvalidArgument { reason: format!("Chunks must hold 1 to {} bytes", Self::MAX_CHUNK_SIZE) });
+        }
+
+        Ok(self.storage.borrow_mut().append_staged_chunk(kind, chunk))
+    }
+
+    pub fn clear_wasm_upload(&self, kind: WasmKind) -> Result<(), DaoError> {
+        self.ensure_governance()?;
+        self.storage.borrow_mut().clear_staged_chunks(kind);
+
+        Ok(())
+    }
+
+    /// approves the staged chunks as the next version of the kind, provided they make up the
+    /// module with the audited hash
+    pub fn approve_wasm(&self, kind: WasmKind, hash: Vec<u8>) -> Result<WasmModule, DaoError> {
+        self.ensure_governance()?;
+
+        let chunks = self.storage.borrow().get_staged_chunks(kind);
+        if chunks.is_empty() {
+            return Err(DaoError::InvalidArgument { reason: "No WASM chunks uploaded".to_string() });
+        }
+        let mut hasher = Sha256::new();
+        chunks.iter().for_each(|chunk| hasher.update(chunk));
+        if hasher.finalize().as_slice() != hash.as_slice() {
+            return Err(DaoError::InvalidArgument { reason: "Uploaded WASM does not match the hash".to_string() });
+        }
+
+        let version = self.storage.borrow().get_latest_module(kind).map_or(1, |module| module.version + 1);
+        let module = WasmModule {
+            kind,
+            version,
+            hash,
+            size: chunks.iter().map(|chunk| chunk.len() as u64).sum(),
+            chunk_count: chunks.len() as u32,
+            approved_on: self.runtime.borrow().get_time(),
+        };
+        self.storage.borrow_mut().commit_staged_chunks(module.clone());
+        self.events.record(DaoEvent::WasmApproved { kind, version });
+
+        Ok(module)
+    }
+
+    pub fn get_wasm_modules(&self, kind: WasmKind) -> Vec<WasmModule> {
+        self.storage.borrow().get_modules(kind)
+    }
+
+    /// lets the owner spawn a single canister for themselves, which the DAO pays the cycles of
+    pub fn allow_spawn(&self, owner: Principal) -> Result<(), DaoError> {
+        self.ensure_governance()?;
+        self.storage.borrow_mut().allow_spawn(owner);
+
+        Ok(())
+    }
+
+    /// Installs the latest approved WASM of the kind with `owner` as owner into a canister
+    /// controlled by the DAO and registers it bound to the owner. Governance spawns canisters for
+    /// anyone, owners it allowed to spawn a single one for themselves with the default cycles. A
+    /// canister whose install failed is kept and reused by the next spawn funded with the same
+    /// cycles, so its cycles are not lost
+    pub async fn spawn_hiving_canister(&self, owner: Principal, config: SpawnConfig) -> Result<Principal, DaoError> {
+        let is_governance = governance::is_governance(&*self.runtime.borrow());
+        let Some(_guard) = self.locks.try_acquire(LockKey::Spawn(owner)) else {
+            return Err(DaoError::InvalidArgument { reason: "A canister is already being spawned for the owner".to_string() });
+        };
+        if !is_governance {
+            self.ensure_owner_can_spawn(owner, &config)?;
+        }
+
+        let module = self
+            .storage
+            .borrow()
+            .get_latest_module(config.kind)
+            .ok_or(DaoError::InvalidArgument { reason: "No approved WASM".to_string() })?;
+        let arg = self.build_init_arg(owner, &config)?;
+        let cycles = match &config.cycles {
+            Some(cycles) => cycles.0.to_u128().ok_or(DaoError::InvalidArgument { reason: "Too many cycles".to_string() })?,
+            None => Self::DEFAULT_CYCLES,
+        };
+        let chunks = self.storage.borrow().get_module_chunks(config.kind, module.version);
+
+        let manager = self.manager.clone();
+        let spare = self.storage.borrow_mut().take_spare_canister(cycles);
+        let (canister_id, mode) = match spare {
+            // a failed install may still have left code behind
+            Some(canister_id) => (canister_id, InstallMode::Reinstall),
+            None => {
+                let dao = self.runtime.borrow().get_canister_id();
+                let canister_id = manager.create_canister(vec![dao], cycles).await.map_err(DaoError::call_failed)?;
+                (canister_id, InstallMode::Install)
+            }
+        };
+        if let Err(reason) = install_module(&*manager, canister_id, mode, chunks, module.hash.clone(), arg).await {
+            self.storage.borrow_mut().add_spare_canister(canister_id, cycles);
+            return Err(DaoError::CallFailed { reason: format!("Installing the code of {} failed: {}", canister_id, reason) });
+        }
+
+        let wasm = InstalledWasm { kind: config.kind, version: module.version };
+        let status = if is_governance { HivingCanisterStatus::Approved } else { HivingCanisterStatus::Pending };
+        let mut storage = self.storage.borrow_mut();
+        storage.save_spawned_canister(owner, canister_id);
+        storage.remove_spawn_allowance(owner);
+        drop(storage);
+        self.hiving.borrow().add_spawned_canister(canister_id, owner, config.metadata, wasm, status);
+
+        Ok(canister_id)
+    }
+
+    fn build_init_arg(&self, owner: Principal, config: &SpawnConfig) -> Result<Vec<u8>, DaoError> {
+        let dao_address = self.runtime.borrow().get_canister_id();
+        let owner = Some(owner);
+        let ckusdc_address = config
+            .ckusdc_address
+            .ok_or(DaoError::InvalidArgument { reason: "Hiving canisters require a ckUSDC address".to_string() })?;
+        let arg = match config.kind {
+            WasmKind::Hiving => Encode!(&HivingInitArgs { dao_address, ckusdc_address, owner }),
+            WasmKind::HivingPool => Encode!(&HivingPoolInitArgs { dao_address, ckusdc_address, owner }),
+        };
+
+        arg.map_err(|err| DaoError::InvalidArgument { reason: format!("Encoding the init args failed: {}", err) })
+    }
+
+    /// owners spawn a single canister for themselves once governance allowed it, funded with the
+    /// default cycles
+    fn ensure_owner_can_spawn(&self, owner: Principal, config: &SpawnConfig) -> Result<(), DaoError> {
+        if self.runtime.borrow().get_caller() != owner || owner == Principal::anonymous() {
+            return Err(DaoError::Unauthorized { reason: "Only governance can spawn canisters for others".to_string() });
+        }
+        if config.cycles.is_some() {
+            return Err(DaoError::Unauthorized { reason: "Only governance can choose the cycles of a spawned canister".to_string() });
+        }
+        if self.storage.borrow().get_spawned_canister(owner).is_some() {
+            return Err(DaoError::InvalidArgument { reason: "A canister was already spawned for the owner".to_string() });
+        }
+        if !self.storage.borrow().has_spawn_allowance(owner) {
+            return Err(DaoError::Unauthorized { reason: "Governance has not allowed the owner to spawn a canister".to_string() });
+        }
+
+        Ok(())
+    }
+
+    /// WASMs are approved and canisters spawned either by controllers or by the DAO itself
+    /// executing a proposal
+    fn ensure_governance(&self) -> Result<(), DaoError> {
+        governance::ensure_governance(&*self.runtime.borrow(), "manage the hiving canister factory")
+    }
+}
+
+/// uploads the module's chunks into the canister's chunk store and installs them from there
+pub(crate) async fn install_module(
+    manager: &dyn ICanisterManager,
+    canister_id: Principal,
+    mode: InstallMode,
+    chunks: Vec<Vec<u8>>,
+    module_hash: Vec<u8>,
+    arg: Vec<u8>,
+) -> Result<(), String> {
+    let mut chunk_hashes = Vec::with_capacity(chunks.len());
+    for chunk in chunks {
+        chunk_hashes.push(manager.upload_chunk(canister_id, chunk).await?);
+    }
+
+    manager.install_chunked_code(canister_id, mode, chunk_hashes, module_hash, arg).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::hiving::HivingConfig;
+    use crate::domain::mocks::{poll_once, CanisterManagerMock, HivingFixture, RuntimeMock};
+    use crate::icp::stable_storage::WasmStorageStable;
+    use abstractions::Account;
+    use candid::{Decode, Nat};
+    use std::pin::pin;
+    use std::task::Poll;
+
+    struct Setup {
+        runtime: Rc<RefCell<RuntimeMock>>,
+        hiving: Rc<RefCell<HivingService>>,
+        manager: CanisterManagerMock,
+        service: FactoryService,
+    }
+
+    impl Setup {
+        fn new() -> Self {
+            let HivingFixture { runtime, events, hiving } = HivingFixture::new(HivingConfig::default());
+            let manager = CanisterManagerMock::default();
+            let storage = Rc::new(RefCell::new(WasmStorageStable::init()));
+            let service = FactoryService::new(
+                storage,
+                hiving.clone(),
+                Rc::new(manager.clone()),
+                InFlightLocks::default(),
+                runtime.clone(),
+                events,
+            );
+
+            Self { runtime, hiving, manager, service }
+        }
+
+        fn approve(&self, kind: WasmKind, chunks: &[&[u8]]) -> WasmModule {
+            let mut hasher = Sha256::new();
+            for chunk in chunks {
+                self.service.upload_wasm_chunk(kind, chunk.to_vec()).unwrap();
+                hasher.update(chunk);
+            }
+            self.service.approve_wasm(kind, hasher.finalize().to_vec()).unwrap()
+        }
+
+        fn spawn(&self, owner: Principal, config: SpawnConfig) -> Result<Principal, DaoError> {
+            let spawn = pin!(self.service.spawn_hiving_canister(owner, config));
+            let Poll::Ready(result) = poll_once(spawn) else { panic!("spawn is pending") };
+            result
+        }
+    }
+
+    fn config(kind: WasmKind) -> SpawnConfig {
+        SpawnConfig { kind, ckusdc_address: Some(Principal::from_slice(&[3])), metadata: vec![], cycles: None }
+    }
+
+    #[test]
+    fn wasm_is_approved_only_with_its_hash() {
+        let setup = Setup::new();
+        setup.service.upload_wasm_chunk(WasmKind::Hiving, b"wasm".to_vec()).unwrap();
+        let result = setup.service.approve_wasm(WasmKind::Hiving, vec![0; 32]);
+        assert!(matches!(result, Err(DaoError::InvalidArgument { .. })));
+
+        setup.service.clear_wasm_upload(WasmKind::Hiving).unwrap();
+        assert_eq!(setup.approve(WasmKind::Hiving, &[b"first", b"module"]).version, 1);
+        assert_eq!(setup.approve(WasmKind::Hiving, &[b"second"]).version, 2);
+        assert!(setup.service.get_wasm_modules(WasmKind::HivingPool).is_empty());
+
+        setup.runtime.borrow_mut().caller = Principal::from_slice(&[9]);
+        let result = setup.service.upload_wasm_chunk(WasmKind::Hiving, b"wasm".to_vec());
+        assert!(matches!(result, Err(DaoError::Unauthorized { .. })));
+    }
+
+    #[test]
+    fn spawned_canister_runs_the_latest_wasm_and_is_registered() {
+        let setup = Setup::new();
+        let owner = Principal::from_slice(&[2]);
+        setup.approve(WasmKind::Hiving, &[b"old"]);
+        let module = setup.approve(WasmKind::Hiving, &[b"new", b"module"]);
+
+        let canister_id = setup.spawn(owner, config(WasmKind::Hiving)).unwrap();
+
+        let installs = setup.manager.installs();
+        assert_eq!(installs.len(), 1);
+        let (installed, mode, chunks, hash, arg) = &installs[0];
+        assert_eq!((*installed, *mode, chunks.len(), hash), (canister_id, InstallMode::Install, 2, &module.hash));
+        let init_args = Decode!(arg, HivingInitArgs).unwrap();
+        assert_eq!((init_args.owner, init_args.dao_address), (Some(owner), Principal::management_canister()));
+
+        let canister = setup.hiving.borrow().get_hiving_canister(canister_id).unwrap();
+        assert_eq!(canister.status, HivingCanisterStatus::Approved);
+        assert_eq!(canister.wasm, Some(InstalledWasm { kind: WasmKind::Hiving, version: 2 }));
+
+        setup.runtime.borrow_mut().caller = canister_id;
+        assert!(setup.hiving.borrow().ensure_can_mint_for(Account::from(owner)).is_ok());
+    }
+
+    #[test]
+    fn hiving_canisters_require_a_ckusdc_ledger() {
+        let setup = Setup::new();
+        setup.approve(WasmKind::Hiving, &[b"module"]);
+
+        let mut config = config(WasmKind::Hiving);
+        config.ckusdc_address = None;
+        assert!(matches!(setup.spawn(Principal::from_slice(&[2]), config), Err(DaoError::InvalidArgument { .. })));
+        assert!(setup.manager.installs().is_empty());
+    }
+
+    #[test]
+    fn canister_of_a_failed_install_is_reused_by_the_next_spawn() {
+        let setup = Setup::new();
+        let owner = Principal::from_slice(&[2]);
+        setup.approve(WasmKind::Hiving, &[b"module"]);
+        let created = Principal::from_slice(&[100, 1]);
+        setup.manager.fail_installs(created, "out of cycles");
+
+        assert!(matches!(setup.spawn(owner, config(WasmKind::Hiving)), Err(DaoError::CallFailed { .. })));
+        assert!(setup.hiving.borrow().get_hiving_canister(created).is_none());
+
+        // a spawn funded with other cycles does not take it
+        let mut funded = config(WasmKind::Hiving);
+        funded.cycles = Some(Nat::from(5_000_000_000_000u64));
+        let other = setup.spawn(Principal::from_slice(&[4]), funded).unwrap();
+        assert_ne!(other, created);
+
+        setup.manager.pass_installs(created);
+        assert_eq!(setup.spawn(owner, config(WasmKind::Hiving)), Ok(created));
+        let installs = setup.manager.installs();
+        assert_eq!((installs.len(), installs[1].1), (2, InstallMode::Reinstall));
+        assert_eq!(setup.spawn(owner, config(WasmKind::Hiving)), Ok(Principal::from_slice(&[100, 3])));
+    }
+
+    #[test]
+    fn owners_spawn_one_canister_which_waits_for_approval() {
+        let setup = Setup::new();
+        let owner = Principal::from_slice(&[2]);
+        setup.approve(WasmKind::Hiving, &[b"module"]);
+        setup.runtime.borrow_mut().caller = owner;
+        assert!(matches!(setup.spawn(owner, config(WasmKind::Hiving)), Err(DaoError::Unauthorized { .. })));
+        assert!(matches!(setup.service.allow_spawn(owner), Err(DaoError::Unauthorized { .. })));
+        setup.runtime.borrow_mut().caller = Principal::management_canister();
+        setup.service.allow_spawn(owner).unwrap();
+        setup.runtime.borrow_mut().caller = owner;
+
+        let result = setup.spawn(Principal::from_slice(&[3]), config(WasmKind::Hiving));
+        assert!(matches!(result, Err(DaoError::Unauthorized { .. })));
+        let mut funded = config(WasmKind::Hiving);
+        funded.cycles = Some(Nat::from(1u8));
+        assert!(matches!(setup.spawn(owner, funded), Err(DaoError::Unauthorized { .. })));
+
+        let canister_id = setup.spawn(owner, config(WasmKind::Hiving)).unwrap();
+        let canister = setup.hiving.borrow().get_hiving_canister(canister_id).unwrap();
+        assert_eq!((canister.owner, canister.status), (owner, HivingCanisterStatus::Pending));
+        assert!(matches!(setup.spawn(owner, config(WasmKind::Hiving)), Err(DaoError::InvalidArgument { .. })));
+    }
+}
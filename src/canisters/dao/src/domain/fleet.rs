@@ -0,0 +1,442 @@
+use crate::domain::events::EventLog;
+use crate::domain::factory::install_module;
+use crate::domain::governance;
+use crate::domain::hiving::HivingService;
+use crate::domain::interfaces::storage::{IFleetStorage, IWasmStorage};
+use crate::domain::locks::{InFlightLocks, LockKey};
+use abstractions::dao::{
+    CanisterUpgrade, CanisterUpgradeStatus, DaoError, DaoEvent, FleetUpgrade, FleetUpgradeStatus, HivingCanister, InstalledWasm, WasmKind,
+    WasmModule,
+};
+use abstractions::hiving::{HivingInitArgs, HivingPoolInitArgs};
+use abstractions::runtime::{ICanisterManager, ICanisterRuntime, InstallMode};
+use candid::{Encode, Principal};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Rolls approved WASM versions out to the canisters spawned by the DAO factory, a batch at a
+/// time. Canisters deployed by their owners are not part of a fleet since their code is unknown
+pub struct FleetService {
+    storage: Rc<RefCell<dyn IFleetStorage>>,
+    wasms: Rc<RefCell<dyn IWasmStorage>>,
+    hiving: Rc<RefCell<HivingService>>,
+    manager: Rc<dyn ICanisterManager>,
+    locks: InFlightLocks,
+    runtime: Rc<RefCell<dyn ICanisterRuntime>>,
+    events: EventLog,
+}
+
+impl FleetService {
+    pub const BATCH_SIZE: usize = 10;
+    const MAX_PAGE_SIZE: usize = 100;
+
+    pub fn new(
+        storage: Rc<RefCell<dyn IFleetStorage>>,
+        wasms: Rc<RefCell<dyn IWasmStorage>>,
+        hiving: Rc<RefCell<HivingService>>,
+        manager: Rc<dyn ICanisterManager>,
+        locks: InFlightLocks,
+        runtime: Rc<RefCell<dyn ICanisterRuntime>>,
+        events: EventLog,
+    ) -> Self {
+        Self { storage, wasms, hiving, manager, locks, runtime, events }
+    }
+
+    /// starts upgrading every canister of the kind to an approved version. Only executing an
+    /// approved fleet upgrade proposal starts a rollout. The version the previous rollout
+    /// installed is kept as the one to roll back to
+    pub fn start_upgrade(&self, kind: WasmKind, version: u32) -> Result<FleetUpgrade, DaoError> {
+        self.get_module(kind, version)?;
+
+        let current = self.storage.borrow().get_upgrade(kind);
+        let previous_version = match current {
+            Some(upgrade) if upgrade.status == FleetUpgradeStatus::Running => {
+                return Err(DaoError::InvalidArgument { reason: "A fleet upgrade is running, pause it first".to_string() });
+            }
+            Some(upgrade) => Some(upgrade.target_version),
+            None => self.get_fleet(kind).iter().filter_map(|canister| canister.wasm.as_ref().map(|wasm| wasm.version)).max(),
+        };
+
+        Ok(self.begin(kind, version, previous_version))
+    }
+
+    /// reinstalls the version the fleet ran before the current rollout, even while it is running.
+    /// Only executing an approved fleet rollback proposal rolls back
+    pub fn rollback_upgrade(&self, kind: WasmKind) -> Result<FleetUpgrade, DaoError> {
+        let current = self
+            .storage
+            .borrow()
+            .get_upgrade(kind)
+            .ok_or(DaoError::InvalidArgument { reason: "No fleet upgrade to roll back".to_string() })?;
+        let previous_version = current
+            .previous_version
+            .ok_or(DaoError::InvalidArgument { reason: "No previous version to roll back to".to_string() })?;
+
+        Ok(self.begin(kind, previous_version, Some(current.target_version)))
+    }
+
+    /// stops a running rollout right away, as an emergency action of governance
+    pub fn pause_upgrade(&self, kind: WasmKind) -> Result<FleetUpgrade, DaoError> {
+        self.ensure_governance()?;
+
+        let upgrade = self.get_upgrade(kind).filter(|upgrade| upgrade.status == FleetUpgradeStatus::Running);
+        let upgrade = upgrade.ok_or(DaoError::InvalidArgument { reason: "No fleet upgrade is running".to_string() })?;
+
+        Ok(self.set_status(upgrade, FleetUpgradeStatus::Paused))
+    }
+
+    /// continues a paused or completed rollout, retrying the canisters whose upgrade failed.
+    /// Only executing an approved fleet resume proposal resumes a rollout
+    pub fn resume_upgrade(&self, kind: WasmKind) -> Result<FleetUpgrade, DaoError> {
+        let upgrade = self.get_upgrade(kind).filter(|upgrade| upgrade.status != FleetUpgradeStatus::Running);
+        let upgrade = upgrade.ok_or(DaoError::InvalidArgument { reason: "No fleet upgrade to resume".to_string() })?;
+
+        let now = self.runtime.borrow().get_time();
+        let mut prev = None;
+        loop {
+            let page = self.storage.borrow().get_canister_upgrades(kind, prev, Self::MAX_PAGE_SIZE);
+            let Some(last) = page.last() else { break };
+            prev = Some(last.canister_id);
+
+            let mut storage = self.storage.borrow_mut();
+            for mut canister in page.into_iter().filter(|canister| matches!(canister.status, CanisterUpgradeStatus::Failed { .. })) {
+                canister.status = CanisterUpgradeStatus::Pending;
+                canister.updated_on = now;
+                storage.save_canister_upgrade(kind, canister);
+            }
+        }
+
+        Ok(self.set_status(upgrade, FleetUpgradeStatus::Running))
+    }
+
+    pub fn get_upgrade(&self, kind: WasmKind) -> Option<FleetUpgrade> {
+        self.storage.borrow().get_upgrade(kind)
+    }
+
+    pub fn get_canister_upgrades(&self, kind: WasmKind, prev: Option<Principal>, take: Option<u32>) -> Vec<CanisterUpgrade> {
+        let take = take.map_or(Self::MAX_PAGE_SIZE, |take| (take as usize).min(Self::MAX_PAGE_SIZE));
+        self.storage.borrow().get_canister_upgrades(kind, prev, take)
+    }
+
+    /// upgrades the next batch of pending canisters of every running rollout
+    pub async fn upgrade_next_batch(&self) {
+        for kind in [WasmKind::Hiving, WasmKind::HivingPool] {
+            self.upgrade_batch(kind).await;
+        }
+    }
+
+    async fn upgrade_batch(&self, kind: WasmKind) {
+        let Some(_guard) = self.locks.try_acquire(LockKey::FleetUpgrade(kind)) else { return };
+        if !self.is_running(kind) {
+            return;
+        }
+
+        let pending = self.storage.borrow().get_pending_upgrades(kind, Self::BATCH_SIZE);
+        for canister in pending {
+            // pauses and rollbacks issued while the batch awaits apply to its remaining canisters
+            let Some(upgrade) = self.get_upgrade(kind).filter(|upgrade| upgrade.status == FleetUpgradeStatus::Running) else { return };
+            let is_pending = self
+                .storage
+                .borrow()
+                .get_canister_upgrade(kind, canister.canister_id)
+                .is_some_and(|canister| canister.status == CanisterUpgradeStatus::Pending);
+            if !is_pending {
+                continue;
+            }
+
+            let status = self.upgrade_canister(kind, canister.canister_id, upgrade.target_version).await;
+            self.save_progress(kind, canister, &upgrade, status);
+        }
+
+        if let Some(upgrade) = self.get_upgrade(kind)
+            && upgrade.status == FleetUpgradeStatus::Running
+            && self.storage.borrow().get_pending_upgrades(kind, 1).is_empty()
+        {
+            self.set_status(upgrade, FleetUpgradeStatus::Completed);
+        }
+    }
+
+    async fn upgrade_canister(&self, kind: WasmKind, canister_id: Principal, version: u32) -> CanisterUpgradeStatus {
+        let code = self.get_module(kind, version).and_then(|module| Self::upgrade_arg(kind).map(|arg| (module, arg)));
+        let (module, arg) = match code {
+            Ok(code) => code,
+            Err(err) => return CanisterUpgradeStatus::Failed { reason: format!("{:?}", err) },
+        };
+        let chunks = self.wasms.borrow().get_module_chunks(kind, version);
+        let manager = self.manager.clone();
+        match self.is_controlled(canister_id).await {
+            Ok(true) => {}
+            Ok(false) => return CanisterUpgradeStatus::Skipped,
+            Err(reason) => return CanisterUpgradeStatus::Failed { reason },
+        }
+
+        match install_module(&*manager, canister_id, InstallMode::Upgrade, chunks, module.hash, arg).await {
+            Ok(()) => CanisterUpgradeStatus::Upgraded,
+            // the owner could have taken the canister over while it was being upgraded
+            Err(_) if self.is_controlled(canister_id).await == Ok(false) => CanisterUpgradeStatus::Skipped,
+            Err(reason) => CanisterUpgradeStatus::Failed { reason },
+        }
+    }
+
+    async fn is_controlled(&self, canister_id: Principal) -> Result<bool, String> {
+        let dao = self.runtime.borrow().get_canister_id();
+        let manager = self.manager.clone();
+        let controllers = manager.get_controllers(canister_id).await?;
+
+        Ok(controllers.contains(&dao))
+    }
+
+    fn get_module(&self, kind: WasmKind, version: u32) -> Result<WasmModule, DaoError> {
+        self.wasms
+            .borrow()
+            .get_module(kind, version)
+            .ok_or(DaoError::InvalidArgument { reason: format!("Version {} is not an approved WASM", version) })
+    }
+
+    /// canisters keep their configuration across upgrades, so no init args are passed
+    fn upgrade_arg(kind: WasmKind) -> Result<Vec<u8>, DaoError> {
+        let arg = match kind {
+            WasmKind::Hiving => Encode!(&None::<HivingInitArgs>),
+            WasmKind::HivingPool => Encode!(&None::<HivingPoolInitArgs>),
+        };
+
+        arg.map_err(|err| DaoError::InvalidArgument { reason: format!("Encoding the upgrade args failed: {}", err) })
+    }
+
+    /// records the outcome on the rollout that started the upgrade. If another rollout replaced it
+    /// in the meantime, only a successful install is carried over to the new one
+    fn save_progress(&self, kind: WasmKind, mut canister: CanisterUpgrade, upgrade: &FleetUpgrade, status: CanisterUpgradeStatus) {
+        let now = self.runtime.borrow().get_time();
+        let canister_id = canister.canister_id;
+
+        if status == CanisterUpgradeStatus::Upgraded {
+            canister.version = Some(upgrade.target_version);
+            let wasm = InstalledWasm { kind, version: upgrade.target_version };
+            // a canister leaving the DAO during its upgrade just drops out of the fleet
+            let _ = self.hiving.borrow().set_installed_wasm(canister_id, wasm);
+        }
+        if let CanisterUpgradeStatus::Failed { reason } = &status {
+            self.events.record(DaoEvent::HivingCanisterUpgradeFailed { canister_id, reason: reason.clone() });
+        }
+
+        let current = self.get_upgrade(kind);
+        canister.status = match current {
+            Some(current) if current.started_on == upgrade.started_on => status,
+            Some(current) if status == CanisterUpgradeStatus::Upgraded => {
+                // a canister which got the version abandoned by a rollback is queued again
+                if current.target_version == upgrade.target_version {
+                    CanisterUpgradeStatus::Upgraded
+                } else {
+                    CanisterUpgradeStatus::Pending
+                }
+            }
+            _ => return,
+        };
+        canister.updated_on = now;
+        self.storage.borrow_mut().save_canister_upgrade(kind, canister);
+    }
+
+    fn begin(&self, kind: WasmKind, target_version: u32, previous_version: Option<u32>) -> FleetUpgrade {
+        let now = self.runtime.borrow().get_time();
+        let fleet = self.get_fleet(kind);
+
+        let mut storage = self.storage.borrow_mut();
+        storage.clear_canister_upgrades(kind);
+        for canister in fleet {
+            let version = canister.wasm.map(|wasm| wasm.version);
+            if version != Some(target_version) {
+                let canister = CanisterUpgrade { canister_id: canister.canister_id, version, status: CanisterUpgradeStatus::Pending, updated_on: now };
+                storage.save_canister_upgrade(kind, canister);
+            }
+        }
+
+        let upgrade = FleetUpgrade { kind, target_version, previous_version, status: FleetUpgradeStatus::Running, started_on: now };
+        storage.save_upgrade(upgrade.clone());
+        self.events.record(DaoEvent::FleetUpgradeStarted { kind, target_version, previous_version });
+
+        upgrade
+    }
+
+    fn set_status(&self, mut upgrade: FleetUpgrade, status: FleetUpgradeStatus) -> FleetUpgrade {
+        upgrade.status = status;
+        self.storage.borrow_mut().save_upgrade(upgrade.clone());
+        self.events.record(DaoEvent::FleetUpgradeStatusChanged { kind: upgrade.kind, status });
+
+        upgrade
+    }
+
+    fn is_running(&self, kind: WasmKind) -> bool {
+        self.get_upgrade(kind).is_some_and(|upgrade| upgrade.status == FleetUpgradeStatus::Running)
+    }
+
+    /// registered canisters the factory installed code of the kind into
+    fn get_fleet(&self, kind: WasmKind) -> Vec<HivingCanister> {
+        let hiving = self.hiving.borrow();
+        let mut fleet = Vec::new();
+        let mut prev = None;
+        loop {
+            let page = hiving.get_registered_canisters(prev, Self::MAX_PAGE_SIZE);
+            let Some(last) = page.last() else { break };
+            prev = Some(last.canister_id);
+            fleet.extend(page.into_iter().filter(|canister| canister.wasm.as_ref().is_some_and(|wasm| wasm.kind == kind)));
+        }
+
+        fleet
+    }
+
+    /// started rollouts are steered either by controllers or by the DAO itself executing
+    /// a proposal
+    fn ensure_governance(&self) -> Result<(), DaoError> {
+        governance::ensure_governance(&*self.runtime.borrow(), "upgrade the hiving canister fleet")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::hiving::HivingConfig;
+    use crate::domain::mocks::{poll_once, CanisterManagerMock, HivingFixture, RuntimeMock};
+    use crate::icp::stable_storage::{FleetStorageStable, WasmStorageStable};
+    use abstractions::dao::HivingCanisterStatus;
+    use abstractions::hiving::RegisterHivingCanisterArgs;
+    use candid::Decode;
+    use std::collections::BTreeSet;
+    use std::pin::pin;
+
+    struct Setup {
+        runtime: Rc<RefCell<RuntimeMock>>,
+        hiving: Rc<RefCell<HivingService>>,
+        wasms: Rc<RefCell<WasmStorageStable>>,
+        manager: CanisterManagerMock,
+        service: FleetService,
+    }
+
+    impl Setup {
+        fn new() -> Self {
+            let HivingFixture { runtime, events, hiving } = HivingFixture::new(HivingConfig::default());
+            let wasms = Rc::new(RefCell::new(WasmStorageStable::init()));
+            let manager = CanisterManagerMock::default();
+            let service = FleetService::new(
+                Rc::new(RefCell::new(FleetStorageStable::init())),
+                wasms.clone(),
+                hiving.clone(),
+                Rc::new(manager.clone()),
+                InFlightLocks::default(),
+                runtime.clone(),
+                events,
+            );
+
+            Self { runtime, hiving, wasms, manager, service }
+        }
+
+        fn approve(&self, version: u32) {
+            let mut wasms = self.wasms.borrow_mut();
+            wasms.append_staged_chunk(WasmKind::Hiving, vec![version as u8]);
+            let module = WasmModule { kind: WasmKind::Hiving, version, hash: vec![version as u8], size: 1, chunk_count: 1, approved_on: 1 };
+            wasms.commit_staged_chunks(module);
+        }
+
+        fn spawn(&self, id: u8, version: u32) -> Principal {
+            let canister_id = Principal::from_slice(&[50, id]);
+            let wasm = InstalledWasm { kind: WasmKind::Hiving, version };
+            self.hiving.borrow().add_spawned_canister(canister_id, Principal::from_slice(&[2]), vec![], wasm, HivingCanisterStatus::Approved);
+            canister_id
+        }
+
+        fn run_batch(&self) {
+            self.runtime.borrow_mut().time += 1;
+            let batch = pin!(self.service.upgrade_next_batch());
+            assert!(poll_once(batch).is_ready());
+        }
+
+        fn version(&self, canister_id: Principal) -> Option<u32> {
+            self.hiving.borrow().get_hiving_canister(canister_id).unwrap().wasm.map(|wasm| wasm.version)
+        }
+
+        fn status(&self, canister_id: Principal) -> CanisterUpgradeStatus {
+            let upgrades = self.service.get_canister_upgrades(WasmKind::Hiving, None, None);
+            upgrades.into_iter().find(|upgrade| upgrade.canister_id == canister_id).unwrap().status
+        }
+    }
+
+    #[test]
+    fn fleet_is_upgraded_in_batches_where_the_dao_is_a_controller() {
+        let setup = Setup::new();
+        setup.approve(1);
+        setup.approve(2);
+        let fleet: Vec<_> = (0..12).map(|id| setup.spawn(id, 1)).collect();
+        let foreign = fleet[11];
+        setup.manager.set_controllers(foreign, vec![Principal::from_slice(&[2])]);
+        // canisters deployed by their owners are left alone
+        let own = Principal::from_slice(&[60]);
+        setup.runtime.borrow_mut().caller = own;
+        setup.hiving.borrow().authorize_canister(own);
+        setup.hiving.borrow().add_hiving_canister(RegisterHivingCanisterArgs { canister_id: own, owner: own, metadata: vec![] }).unwrap();
+        setup.runtime.borrow_mut().caller = Principal::management_canister();
+
+        let upgrade = setup.service.start_upgrade(WasmKind::Hiving, 2).unwrap();
+        assert_eq!((upgrade.previous_version, upgrade.status), (Some(1), FleetUpgradeStatus::Running));
+        assert_eq!(setup.service.get_canister_upgrades(WasmKind::Hiving, None, None).len(), 12);
+
+        setup.run_batch();
+        assert_eq!(setup.manager.installs().len(), FleetService::BATCH_SIZE);
+        assert_eq!(setup.service.get_upgrade(WasmKind::Hiving).unwrap().status, FleetUpgradeStatus::Running);
+
+        setup.run_batch();
+        assert_eq!(setup.service.get_upgrade(WasmKind::Hiving).unwrap().status, FleetUpgradeStatus::Completed);
+        let installs = setup.manager.installs();
+        let upgraded: BTreeSet<_> = installs.iter().map(|(canister_id, ..)| *canister_id).collect();
+        assert_eq!(upgraded, fleet[..11].iter().copied().collect());
+        let (_, mode, _, hash, arg) = &installs[0];
+        assert_eq!((*mode, hash), (InstallMode::Upgrade, &vec![2]));
+        assert!(Decode!(arg, Option<HivingInitArgs>).unwrap().is_none());
+
+        assert_eq!((setup.version(fleet[0]), setup.status(fleet[0])), (Some(2), CanisterUpgradeStatus::Upgraded));
+        assert_eq!((setup.version(foreign), setup.status(foreign)), (Some(1), CanisterUpgradeStatus::Skipped));
+        assert_eq!(setup.version(own), None);
+    }
+
+    #[test]
+    fn paused_rollout_waits_and_rollback_reinstalls_the_previous_version() {
+        let setup = Setup::new();
+        setup.approve(1);
+        setup.approve(2);
+        let first = setup.spawn(0, 1);
+        let second = setup.spawn(1, 1);
+        setup.manager.fail_installs(second, "out of cycles");
+
+        setup.service.start_upgrade(WasmKind::Hiving, 2).unwrap();
+        setup.service.pause_upgrade(WasmKind::Hiving).unwrap();
+        setup.run_batch();
+        assert!(setup.manager.installs().is_empty());
+
+        setup.service.resume_upgrade(WasmKind::Hiving).unwrap();
+        setup.run_batch();
+        assert_eq!(setup.service.get_upgrade(WasmKind::Hiving).unwrap().status, FleetUpgradeStatus::Completed);
+        assert_eq!(setup.status(second), CanisterUpgradeStatus::Failed { reason: "out of cycles".to_string() });
+
+        let rollback = setup.service.rollback_upgrade(WasmKind::Hiving).unwrap();
+        assert_eq!((rollback.target_version, rollback.previous_version), (1, Some(2)));
+        setup.run_batch();
+        assert_eq!((setup.version(first), setup.version(second)), (Some(1), Some(1)));
+        assert_eq!(setup.manager.installs().last().unwrap().3, vec![1]);
+        assert_eq!(setup.service.get_upgrade(WasmKind::Hiving).unwrap().status, FleetUpgradeStatus::Completed);
+    }
+
+    #[test]
+    fn rollouts_are_steered_by_governance_only() {
+        let setup = Setup::new();
+        setup.approve(1);
+        setup.spawn(0, 1);
+
+        let result = setup.service.start_upgrade(WasmKind::Hiving, 2);
+        assert!(matches!(result, Err(DaoError::InvalidArgument { .. })));
+        setup.approve(2);
+        setup.service.start_upgrade(WasmKind::Hiving, 2).unwrap();
+        let result = setup.service.start_upgrade(WasmKind::Hiving, 1);
+        assert!(matches!(result, Err(DaoError::InvalidArgument { .. })));
+
+        setup.runtime.borrow_mut().caller = Principal::from_slice(&[9]);
+        assert!(matches!(setup.service.pause_upgrade(WasmKind::Hiving), Err(DaoError::Unauthorized { .. })));
+    }
+}
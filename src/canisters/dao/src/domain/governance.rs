@@ -0,0 +1,18 @@
+use abstractions::dao::DaoError;
+use abstractions::runtime::ICanisterRuntime;
+
+/// Governance acts either through the canister's controllers or through the DAO itself
+/// executing a proposal
+pub fn is_governance(runtime: &dyn ICanisterRuntime) -> bool {
+    let caller = runtime.get_caller();
+    runtime.is_controller(&caller) || caller == runtime.get_canister_id()
+}
+
+/// rejects callers other than governance; `action` completes "Only governance can ..."
+pub fn ensure_governance(runtime: &dyn ICanisterRuntime, action: &str) -> Result<(), DaoError> {
+    if !is_governance(runtime) {
+        return Err(DaoError::Unauthorized { reason: format!("Only governance can {}", action) });
+    }
+
+    Ok(())
+}
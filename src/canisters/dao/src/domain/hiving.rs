@@ -1,30 +1,483 @@
 use std::cell::RefCell;
 use std::rc::Rc;
-use candid::Principal;
+use candid::{CandidType, Deserialize, Principal};
+use icrc_ledger_types::icrc1::account::Account;
+use serde::Serialize;
+use abstractions::dao::{DaoError, DaoEvent, HivingCanister, HivingCanisterStatus, InstalledWasm, MintDiscountError};
+use abstractions::hiving::{HivingHeartbeat, RegisterHivingCanisterArgs, HEARTBEAT_INTERVAL_NS};
 use abstractions::runtime::ICanisterRuntime;
+use abstractions::MetadataValue;
+use crate::domain::events::EventLog;
+use crate::domain::governance;
 use crate::domain::interfaces::storage::*;
 
+#[derive(Clone, Debug, Deserialize, Serialize, CandidType)]
+pub struct HivingConfig {
+    /// hiving canisters approved right away when joining; others wait for governance approval
+    pub allowlist: Vec<Principal>,
+    /// time between two heartbeats of a hiving canister
+    #[serde(default = "HivingConfig::default_heartbeat_interval_ns")]
+    pub heartbeat_interval_ns: u64,
+    /// approved canisters missing this many heartbeats in a row are marked stale, 0 never marks them
+    #[serde(default = "HivingConfig::default_max_missed_heartbeats")]
+    pub max_missed_heartbeats: u32,
+}
+
+impl HivingConfig {
+    fn default_heartbeat_interval_ns() -> u64 {
+        HEARTBEAT_INTERVAL_NS
+    }
+
+    fn default_max_missed_heartbeats() -> u32 {
+        3
+    }
+}
+
+impl Default for HivingConfig {
+    fn default() -> Self {
+        Self {
+            allowlist: Vec::new(),
+            heartbeat_interval_ns: Self::default_heartbeat_interval_ns(),
+            max_missed_heartbeats: Self::default_max_missed_heartbeats(),
+        }
+    }
+}
+
 pub struct HivingService {
+    config: HivingConfig,
     storage: Rc<RefCell<dyn IHivingStorage>>,
     runtime: Rc<RefCell<dyn ICanisterRuntime>>,
+    events: EventLog,
 }
 
 impl HivingService {
-    pub fn new(storage: Rc<RefCell<dyn IHivingStorage>>, runtime: Rc<RefCell<dyn ICanisterRuntime>>) -> Self {
-        Self { storage, runtime }
+    const MAX_PAGE_SIZE: usize = 100;
+
+    pub fn new(
+        config: HivingConfig,
+        storage: Rc<RefCell<dyn IHivingStorage>>,
+        runtime: Rc<RefCell<dyn ICanisterRuntime>>,
+        events: EventLog,
+    ) -> Self {
+        Self { config, storage, runtime, events }
     }
 
-    pub fn add_hiving_canister(&self) {
+    /// registers the calling canister. Its owner must have authorized it through `hiving_authorize`
+    /// first, and a canister joining again gets back the status it left with
+    pub fn add_hiving_canister(&self, args: RegisterHivingCanisterArgs) -> Result<(), DaoError> {
         let caller = self.runtime.borrow().get_caller();
-        self.storage.borrow_mut().add_hiving_canister(caller);
+        if caller != args.canister_id {
+            return Err(DaoError::Unauthorized { reason: "Hiving canister can only register itself".to_string() });
+        }
+        if self.storage.borrow().get_hiving_canister(&caller).is_some() {
+            return Err(DaoError::HivingCanisterAlreadyRegistered);
+        }
+        if !self.storage.borrow().has_hiver_binding(caller, Account::from(args.owner)) {
+            return Err(DaoError::Unauthorized { reason: "The owner has not authorized the hiving canister".to_string() });
+        }
+
+        let departed_status = self.storage.borrow_mut().take_departed_status(&caller);
+        let status = match departed_status {
+            Some(status) => status,
+            None if self.config.allowlist.contains(&caller) => HivingCanisterStatus::Approved,
+            None => HivingCanisterStatus::Pending,
+        };
+        let canister = HivingCanister {
+            canister_id: caller,
+            owner: args.owner,
+            metadata: args.metadata,
+            joined_on: self.runtime.borrow().get_time(),
+            status: status.clone(),
+            wasm: None,
+            last_heartbeat: None,
+            last_seen_on: None,
+        };
+        self.storage.borrow_mut().add_hiving_canister(canister);
+        self.events.record(DaoEvent::HivingCanisterJoined { canister_id: caller, status });
+
+        Ok(())
     }
 
-    pub fn remove_hiving_canister(&self) {
+    /// registers a canister spawned by the DAO factory and binds it to its owner. Canisters spawned
+    /// by governance are approved right away, the ones owners spawn for themselves wait for approval
+    pub fn add_spawned_canister(
+        &self,
+        canister_id: Principal,
+        owner: Principal,
+        metadata: Vec<(String, MetadataValue)>,
+        wasm: InstalledWasm,
+        status: HivingCanisterStatus,
+    ) {
+        let canister = HivingCanister {
+            canister_id,
+            owner,
+            metadata,
+            joined_on: self.runtime.borrow().get_time(),
+            status,
+            wasm: Some(wasm.clone()),
+            last_heartbeat: None,
+            last_seen_on: None,
+        };
+        let hiver = Account::from(owner);
+        let mut storage = self.storage.borrow_mut();
+        storage.add_hiving_canister(canister);
+        storage.add_hiver_binding(canister_id, hiver);
+
+        self.events.record(DaoEvent::HivingCanisterSpawned { canister_id, owner, wasm });
+        self.events.record(DaoEvent::HiverJoined { canister_id, hiver });
+    }
+
+    /// records the code a fleet upgrade installed into the canister
+    pub fn set_installed_wasm(&self, canister_id: Principal, wasm: InstalledWasm) -> Result<(), DaoError> {
+        let mut canister = self.get_registered(&canister_id)?;
+        canister.wasm = Some(wasm.clone());
+        self.storage.borrow_mut().update_hiving_canister(canister);
+        self.events.record(DaoEvent::HivingCanisterUpgraded { canister_id, wasm });
+
+        Ok(())
+    }
+
+    /// unregisters the calling canister and drops its hiver bindings, so it has to be
+    /// authorized again to mint after joining back
+    pub fn remove_hiving_canister(&self) -> Result<(), DaoError> {
         let caller = self.runtime.borrow().get_caller();
-        self.storage.borrow_mut().remove_hiving_canister(caller);
+        let mut storage = self.storage.borrow_mut();
+        let canister = storage.remove_hiving_canister(&caller).ok_or(DaoError::HivingCanisterNotRegistered)?;
+        storage.save_departed_status(caller, canister.status);
+        let hivers = storage.remove_hiver_bindings(caller);
+        drop(storage);
+
+        for hiver in hivers {
+            self.events.record(DaoEvent::HiverLeft { canister_id: caller, hiver });
+        }
+        self.events.record(DaoEvent::HivingCanisterLeft { canister_id: caller });
+
+        Ok(())
     }
 
-    pub fn get_hiving_canisters(&self) -> Vec<Principal>{
-        self.storage.borrow().get_hiving_canisters()
+    pub fn update_metadata(&self, metadata: Vec<(String, MetadataValue)>) -> Result<(), DaoError> {
+        let caller = self.runtime.borrow().get_caller();
+        let mut canister = self.get_registered(&caller)?;
+        canister.metadata = metadata;
+        self.storage.borrow_mut().update_hiving_canister(canister);
+
+        Ok(())
+    }
+
+    /// returns the interval the canister is expected to report at, which staleness is judged by.
+    /// A heartbeat from a stale canister approves it again
+    pub fn record_heartbeat(&self, heartbeat: HivingHeartbeat) -> Result<u64, DaoError> {
+        let caller = self.runtime.borrow().get_caller();
+        let mut canister = self.get_registered(&caller)?;
+        if heartbeat.owner != canister.owner {
+            return Err(DaoError::Unauthorized { reason: "Heartbeat owner does not match the registered owner".to_string() });
+        }
+        let reactivated = canister.status == HivingCanisterStatus::Stale;
+        if reactivated {
+            canister.status = HivingCanisterStatus::Approved;
+        }
+        canister.last_heartbeat = Some(heartbeat);
+        canister.last_seen_on = Some(self.runtime.borrow().get_time());
+        self.storage.borrow_mut().update_hiving_canister(canister);
+        if reactivated {
+            self.events.record(DaoEvent::HivingCanisterStatusChanged { canister_id: caller, status: HivingCanisterStatus::Approved });
+        }
+
+        Ok(self.config.heartbeat_interval_ns)
     }
-}
\ No newline at end of file
+
+    /// checks the next page of registered canisters, continuing where the previous run stopped,
+    /// marks the approved ones which missed too many heartbeats as stale and returns their number
+    pub fn evict_stale_canisters(&self) -> u32 {
+        if self.config.max_missed_heartbeats == 0 {
+            return 0;
+        }
+        let now = self.runtime.borrow().get_time();
+        let timeout = self.config.heartbeat_interval_ns.saturating_mul(self.config.max_missed_heartbeats as u64);
+
+        let cursor = self.storage.borrow().get_eviction_cursor();
+        let page = self.storage.borrow().get_hiving_canisters(None, cursor, Self::MAX_PAGE_SIZE);
+        let next = if page.len() < Self::MAX_PAGE_SIZE { None } else { page.last().map(|c| c.canister_id) };
+        self.storage.borrow_mut().set_eviction_cursor(next);
+
+        let mut evicted = 0;
+        for mut canister in page {
+            let last_seen_on = canister.last_seen_on.unwrap_or(canister.joined_on);
+            if canister.status != HivingCanisterStatus::Approved || now <= last_seen_on.saturating_add(timeout) {
+                continue;
+            }
+            let canister_id = canister.canister_id;
+            canister.status = HivingCanisterStatus::Stale;
+            self.storage.borrow_mut().update_hiving_canister(canister);
+            self.events.record(DaoEvent::HivingCanisterStatusChanged { canister_id, status: HivingCanisterStatus::Stale });
+            evicted += 1;
+        }
+
+        evicted
+    }
+
+    /// approves a stale canister again on behalf of governance, the canister itself comes back
+    /// by sending a heartbeat. It is given the full heartbeat timeout before it is marked stale once more
+    pub fn reactivate_hiving_canister(&self, canister_id: Principal) -> Result<(), DaoError> {
+        self.ensure_governance()?;
+        let mut canister = self.get_registered(&canister_id)?;
+        if canister.status != HivingCanisterStatus::Stale {
+            return Err(DaoError::InvalidArgument { reason: "Hiving canister is not stale".to_string() });
+        }
+
+        canister.status = HivingCanisterStatus::Approved;
+        canister.last_seen_on = Some(self.runtime.borrow().get_time());
+        self.storage.borrow_mut().update_hiving_canister(canister);
+        self.events.record(DaoEvent::HivingCanisterStatusChanged { canister_id, status: HivingCanisterStatus::Approved });
+
+        Ok(())
+    }
+
+    pub fn approve_hiving_canister(&self, canister_id: Principal) -> Result<(), DaoError> {
+        self.set_status(canister_id, HivingCanisterStatus::Approved)
+    }
+
+    pub fn suspend_hiving_canister(&self, canister_id: Principal) -> Result<(), DaoError> {
+        self.set_status(canister_id, HivingCanisterStatus::Suspended)
+    }
+
+    fn set_status(&self, canister_id: Principal, status: HivingCanisterStatus) -> Result<(), DaoError> {
+        self.ensure_governance()?;
+
+        let mut canister = self.get_registered(&canister_id)?;
+        canister.status = status.clone();
+        self.storage.borrow_mut().update_hiving_canister(canister);
+        self.events.record(DaoEvent::HivingCanisterStatusChanged { canister_id, status });
+
+        Ok(())
+    }
+
+    pub fn get_hiving_canister(&self, canister_id: Principal) -> Option<HivingCanister> {
+        self.storage.borrow().get_hiving_canister(&canister_id)
+    }
+
+    /// stale canisters are only listed when asked for by status
+    pub fn get_hiving_canisters(&self, status: Option<HivingCanisterStatus>, prev: Option<Principal>, take: Option<u32>) -> Vec<HivingCanister> {
+        let take = take.map_or(Self::MAX_PAGE_SIZE, |take| (take as usize).min(Self::MAX_PAGE_SIZE));
+        if status.is_some() {
+            return self.storage.borrow().get_hiving_canisters(status, prev, take);
+        }
+
+        let mut canisters = Vec::with_capacity(take);
+        let mut prev = prev;
+        while canisters.len() < take {
+            let page = self.get_registered_canisters(prev, take);
+            let Some(last) = page.last() else { break };
+            prev = Some(last.canister_id);
+            canisters.extend(page.into_iter().filter(|canister| canister.status != HivingCanisterStatus::Stale));
+        }
+        canisters.truncate(take);
+
+        canisters
+    }
+
+    /// every registered canister, stale ones included
+    pub fn get_registered_canisters(&self, prev: Option<Principal>, take: usize) -> Vec<HivingCanister> {
+        self.storage.borrow().get_hiving_canisters(None, prev, take.min(Self::MAX_PAGE_SIZE))
+    }
+
+    /// allows the hiving canister to mint discounts on behalf of the calling hiver
+    pub fn authorize_canister(&self, canister_id: Principal) {
+        let hiver = Account::from(self.runtime.borrow().get_caller());
+        self.storage.borrow_mut().add_hiver_binding(canister_id, hiver);
+        self.events.record(DaoEvent::HiverJoined { canister_id, hiver });
+    }
+
+    pub fn revoke_canister(&self, canister_id: Principal) {
+        let hiver = Account::from(self.runtime.borrow().get_caller());
+        self.storage.borrow_mut().remove_hiver_binding(canister_id, hiver);
+        self.events.record(DaoEvent::HiverLeft { canister_id, hiver });
+    }
+
+    /// checks that the caller is an approved hiving canister authorized by the hiver
+    pub fn ensure_can_mint_for(&self, hiver: Account) -> Result<(), MintDiscountError> {
+        let caller = self.runtime.borrow().get_caller();
+        let storage = self.storage.borrow();
+        match storage.get_hiving_canister(&caller) {
+            Some(canister) if canister.status == HivingCanisterStatus::Approved => {}
+            _ => return Err(MintDiscountError::NotHivingCanister),
+        }
+        if !storage.has_hiver_binding(caller, hiver) {
+            return Err(MintDiscountError::HiverNotAuthorized);
+        }
+
+        Ok(())
+    }
+
+    pub fn get_wallet_usage(&self, cycle_number: u64, wallet: Account) -> u32 {
+        self.storage.borrow().get_wallet_usage_per_cycle(cycle_number, Self::usage_key(wallet))
+    }
+
+    pub fn add_wallet_usage(&self, cycle_number: u64, wallet: Account) -> u32 {
+        self.storage.borrow_mut().add_wallet_usage_per_cycle(cycle_number, Self::usage_key(wallet))
+    }
+
+    pub fn remove_wallet_usage(&self, cycle_number: u64, wallet: Account) -> u32 {
+        self.storage.borrow_mut().remove_wallet_usage_per_cycle(cycle_number, Self::usage_key(wallet))
+    }
+
+    /// the per-wallet cap applies to the buyer principal, whichever subaccount receives the discount
+    fn usage_key(wallet: Account) -> Account {
+        Account::from(wallet.owner)
+    }
+
+    fn get_registered(&self, canister_id: &Principal) -> Result<HivingCanister, DaoError> {
+        self.storage
+            .borrow()
+            .get_hiving_canister(canister_id)
+            .ok_or(DaoError::HivingCanisterNotRegistered)
+    }
+
+    /// approvals are made either by controllers or by the DAO itself executing a proposal
+    fn ensure_governance(&self) -> Result<(), DaoError> {
+        governance::ensure_governance(&*self.runtime.borrow(), "change the status of hiving canisters")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::mocks::{HivingFixture, RuntimeMock};
+    use candid::Nat;
+
+    struct Setup {
+        runtime: Rc<RefCell<RuntimeMock>>,
+        hiving: Rc<RefCell<HivingService>>,
+    }
+
+    impl Setup {
+        fn new(max_missed_heartbeats: u32, canisters: &[Principal]) -> Self {
+            let config = HivingConfig { allowlist: canisters.to_vec(), heartbeat_interval_ns: 10, max_missed_heartbeats };
+            let HivingFixture { runtime, hiving, .. } = HivingFixture::new(config);
+            let setup = Self { runtime, hiving };
+            for canister_id in canisters {
+                setup.join(*canister_id).unwrap();
+            }
+
+            setup
+        }
+
+        /// the owner authorizes the canister, which then joins
+        fn join(&self, canister_id: Principal) -> Result<(), DaoError> {
+            self.call_as(owner());
+            self.hiving.borrow().authorize_canister(canister_id);
+            self.call_as(canister_id);
+            let args = RegisterHivingCanisterArgs { canister_id, owner: owner(), metadata: vec![] };
+            self.hiving.borrow().add_hiving_canister(args)
+        }
+
+        fn call_as(&self, caller: Principal) {
+            self.runtime.borrow_mut().caller = caller;
+        }
+
+        fn heartbeat(&self, canister_id: Principal, time: u64) -> Result<u64, DaoError> {
+            self.runtime.borrow_mut().time = time;
+            self.call_as(canister_id);
+            self.hiving.borrow().record_heartbeat(HivingHeartbeat { version: "0.1.0".to_string(), cycles: Nat::from(5u8), owner: owner() })
+        }
+
+        fn status(&self, canister_id: Principal) -> HivingCanisterStatus {
+            self.hiving.borrow().get_hiving_canister(canister_id).unwrap().status
+        }
+    }
+
+    fn owner() -> Principal {
+        Principal::from_slice(&[2])
+    }
+
+    #[test]
+    fn silent_canisters_go_stale_until_reactivated() {
+        let alive = Principal::from_slice(&[70]);
+        let silent = Principal::from_slice(&[71]);
+        let setup = Setup::new(3, &[alive, silent]);
+        setup.heartbeat(alive, 20).unwrap();
+
+        setup.runtime.borrow_mut().time = 35;
+        assert_eq!(setup.hiving.borrow().evict_stale_canisters(), 1);
+        assert_eq!((setup.status(alive), setup.status(silent)), (HivingCanisterStatus::Approved, HivingCanisterStatus::Stale));
+        let listed: Vec<_> = setup.hiving.borrow().get_hiving_canisters(None, None, None).iter().map(|c| c.canister_id).collect();
+        assert_eq!(listed, vec![alive]);
+        assert_eq!(setup.hiving.borrow().get_hiving_canisters(Some(HivingCanisterStatus::Stale), None, None).len(), 1);
+        setup.call_as(silent);
+        assert_eq!(setup.hiving.borrow().ensure_can_mint_for(Account::from(owner())), Err(MintDiscountError::NotHivingCanister));
+
+        // the owner cannot vouch for a canister which stays silent
+        setup.call_as(owner());
+        let result = setup.hiving.borrow().reactivate_hiving_canister(silent);
+        assert!(matches!(result, Err(DaoError::Unauthorized { .. })));
+        setup.heartbeat(silent, 35).unwrap();
+        assert_eq!(setup.status(silent), HivingCanisterStatus::Approved);
+        assert_eq!(setup.hiving.borrow().get_hiving_canister(silent).unwrap().last_seen_on, Some(35));
+        setup.call_as(Principal::management_canister());
+        assert!(matches!(setup.hiving.borrow().reactivate_hiving_canister(silent), Err(DaoError::InvalidArgument { .. })));
+
+        setup.runtime.borrow_mut().time = 60;
+        assert_eq!(setup.hiving.borrow().evict_stale_canisters(), 1);
+        assert_eq!((setup.status(alive), setup.status(silent)), (HivingCanisterStatus::Stale, HivingCanisterStatus::Approved));
+    }
+
+    #[test]
+    fn eviction_continues_where_the_previous_run_stopped() {
+        let canisters: Vec<_> = (0..=HivingService::MAX_PAGE_SIZE as u8).map(|id| Principal::from_slice(&[1, id])).collect();
+        let setup = Setup::new(1, &canisters);
+
+        setup.runtime.borrow_mut().time = 100;
+        assert_eq!(setup.hiving.borrow().evict_stale_canisters(), HivingService::MAX_PAGE_SIZE as u32);
+        assert_eq!(setup.hiving.borrow().evict_stale_canisters(), 1);
+        assert_eq!(setup.hiving.borrow().evict_stale_canisters(), 0);
+    }
+
+    #[test]
+    fn joining_needs_the_owner_authorization() {
+        let canister_id = Principal::from_slice(&[70]);
+        let setup = Setup::new(0, &[]);
+        setup.call_as(canister_id);
+        let args = RegisterHivingCanisterArgs { canister_id, owner: Principal::from_slice(&[9]), metadata: vec![] };
+        assert!(matches!(setup.hiving.borrow().add_hiving_canister(args), Err(DaoError::Unauthorized { .. })));
+
+        setup.join(canister_id).unwrap();
+        assert_eq!(setup.status(canister_id), HivingCanisterStatus::Pending);
+    }
+
+    #[test]
+    fn leaving_drops_the_bindings_and_rejoining_keeps_the_status() {
+        let canister_id = Principal::from_slice(&[70]);
+        let setup = Setup::new(0, &[canister_id]);
+        setup.call_as(Principal::management_canister());
+        setup.hiving.borrow().suspend_hiving_canister(canister_id).unwrap();
+
+        setup.call_as(canister_id);
+        setup.hiving.borrow().remove_hiving_canister().unwrap();
+        setup.call_as(canister_id);
+        let args = RegisterHivingCanisterArgs { canister_id, owner: owner(), metadata: vec![] };
+        assert!(matches!(setup.hiving.borrow().add_hiving_canister(args), Err(DaoError::Unauthorized { .. })));
+
+        // allowlisted, yet it comes back suspended
+        setup.join(canister_id).unwrap();
+        assert_eq!(setup.status(canister_id), HivingCanisterStatus::Suspended);
+    }
+
+    #[test]
+    fn heartbeats_are_kept_for_registered_canisters() {
+        let canister_id = Principal::from_slice(&[70]);
+        let setup = Setup::new(0, &[canister_id]);
+        assert!(matches!(setup.heartbeat(Principal::from_slice(&[71]), 5), Err(DaoError::HivingCanisterNotRegistered)));
+        let forged = HivingHeartbeat { version: "0.1.0".to_string(), cycles: Nat::from(5u8), owner: Principal::from_slice(&[9]) };
+        setup.call_as(canister_id);
+        assert!(matches!(setup.hiving.borrow().record_heartbeat(forged), Err(DaoError::Unauthorized { .. })));
+
+        assert_eq!(setup.heartbeat(canister_id, 5), Ok(10));
+        let canister = setup.hiving.borrow().get_hiving_canister(canister_id).unwrap();
+        assert_eq!((canister.last_seen_on, canister.last_heartbeat.unwrap().cycles), (Some(5), Nat::from(5u8)));
+
+        // eviction is turned off
+        setup.runtime.borrow_mut().time = 1_000;
+        assert_eq!(setup.hiving.borrow().evict_stale_canisters(), 0);
+        assert_eq!(setup.status(canister_id), HivingCanisterStatus::Approved);
+    }
+}
@@ -1,20 +1,104 @@
-use candid::Principal;
+use candid::{Nat, Principal};
 use icrc_ledger_types::icrc1::account::Account;
-use abstractions::dao::{Discount, Proposal, Vote};
+use abstractions::dao::{
+    CanisterUpgrade, CycleEmission, DaoEventRecord, VoterRewardAllocation, Discount, FleetUpgrade, HivingCanister, HivingCanisterStatus, Merchant, MintRecord,
+    MintStatus, Proposal, Vote, WasmKind, WasmModule,
+};
+use abstractions::Timestamp;
+use crate::domain::cycles::CycleEpoch;
+use crate::domain::emission::{PendingClaim, ScoreSnapshot, StakeClaim};
+use crate::domain::payouts::Payout;
+use crate::domain::reconciliation::ReconciliationRun;
 
 pub trait IDiscountStorage {
-    fn get_cycle_discounts_ids(&self, cycle_number: u64) -> Vec<u128>;
+    fn get_discounts_by_cycle(&self, cycle_number: u64, prev: Option<u128>, take: usize) -> Vec<Discount>;
+    fn get_discounts_by_owner(&self, owner: &Account, prev: Option<u128>, take: usize) -> Vec<Discount>;
+    fn get_discounts_by_hiver(&self, hiver: &Account, prev: Option<u128>, take: usize) -> Vec<Discount>;
     fn add_discount(&mut self, cycle_number: u64, data: Discount) -> u128;
+    fn get_discount(&self, id: u128) -> Option<Discount>;
+    fn get_discounts(&self, prev: Option<u128>, take: usize) -> Vec<Discount>;
+    fn count_discounts(&self) -> u64;
+    fn update_discount(&mut self, data: Discount);
+    /// discounts due for expiry by now, with the time they are due at in the expiry index
+    fn get_expired_discounts(&self, now: Timestamp, limit: usize) -> Vec<(Timestamp, Discount)>;
+    fn remove_from_expiry_index(&mut self, due: Timestamp, discount_id: u128);
+    fn reschedule_expiry(&mut self, due: Timestamp, discount_id: u128, retry_on: Timestamp);
     fn get_discount_index(&self, account: &Account, cycle_number: u64) -> u128;
     fn increase_discount_index(&mut self, account: Account, cycle_number: u64) -> u128;
+    fn decrease_discount_index(&mut self, account: Account, cycle_number: u64) -> u128;
+    /// indexes up to `limit` discounts stored before the cycle, owner and hiver indexes existed,
+    /// continuing where the previous call stopped. Returns whether every discount is indexed
+    fn backfill_indexes(&mut self, limit: usize) -> bool;
+
+    fn next_mint_key(&mut self) -> u64;
+    fn save_mint_record(&mut self, record: MintRecord);
+    fn remove_mint_record(&mut self, key: u64);
+    fn get_mint_record(&self, key: u64) -> Option<MintRecord>;
+    fn save_minted_key(&mut self, key: u64, discount_id: u128);
+    /// discount minted with the key, once its mint was confirmed
+    fn get_minted_key(&self, key: u64) -> Option<u128>;
+    fn get_mint_records(&self, status: MintStatus, limit: usize) -> Vec<MintRecord>;
+    fn count_mint_records(&self, status: MintStatus) -> u64;
+}
+
+pub trait IReconciliationStorage {
+    fn get_run(&self) -> Option<ReconciliationRun>;
+    fn set_run(&mut self, run: ReconciliationRun);
 }
 
 pub trait IHivingStorage {
-    fn add_hiving_canister(&mut self, canister_id: Principal);
-    fn remove_hiving_canister(&mut self, canister_id: Principal);
-    fn get_hiving_canisters(&self) -> Vec<Principal>;
+    fn add_hiving_canister(&mut self, canister: HivingCanister);
+    fn update_hiving_canister(&mut self, canister: HivingCanister);
+    fn remove_hiving_canister(&mut self, canister_id: &Principal) -> Option<HivingCanister>;
+    fn get_hiving_canister(&self, canister_id: &Principal) -> Option<HivingCanister>;
+    fn get_hiving_canisters(&self, status: Option<HivingCanisterStatus>, prev: Option<Principal>, take: usize) -> Vec<HivingCanister>;
+    fn add_hiver_binding(&mut self, canister_id: Principal, hiver: Account);
+    fn remove_hiver_binding(&mut self, canister_id: Principal, hiver: Account);
+    fn has_hiver_binding(&self, canister_id: Principal, hiver: Account) -> bool;
+    /// removes every binding of the canister and returns the hivers it was bound to
+    fn remove_hiver_bindings(&mut self, canister_id: Principal) -> Vec<Account>;
+    fn save_departed_status(&mut self, canister_id: Principal, status: HivingCanisterStatus);
+    fn take_departed_status(&mut self, canister_id: &Principal) -> Option<HivingCanisterStatus>;
+    /// last canister checked by the previous eviction run
+    fn get_eviction_cursor(&self) -> Option<Principal>;
+    fn set_eviction_cursor(&mut self, cursor: Option<Principal>);
     fn add_wallet_usage_per_cycle(&mut self, cycle_number: u64, wallet: Account) -> u32;
     fn get_wallet_usage_per_cycle(&self, cycle_number: u64, wallet: Account) -> u32;
+    fn remove_wallet_usage_per_cycle(&mut self, cycle_number: u64, wallet: Account) -> u32;
+}
+
+/// Chunks being uploaded are staged per kind until governance approves them as a new version
+pub trait IWasmStorage {
+    fn append_staged_chunk(&mut self, kind: WasmKind, chunk: Vec<u8>) -> u32;
+    fn get_staged_chunks(&self, kind: WasmKind) -> Vec<Vec<u8>>;
+    fn clear_staged_chunks(&mut self, kind: WasmKind);
+    /// makes the staged chunks the module's code
+    fn commit_staged_chunks(&mut self, module: WasmModule);
+    fn get_module(&self, kind: WasmKind, version: u32) -> Option<WasmModule>;
+    fn get_latest_module(&self, kind: WasmKind) -> Option<WasmModule>;
+    fn get_modules(&self, kind: WasmKind) -> Vec<WasmModule>;
+    fn get_module_chunks(&self, kind: WasmKind, version: u32) -> Vec<Vec<u8>>;
+    /// canisters the factory created but failed to install code into, kept for the next spawn
+    fn add_spare_canister(&mut self, canister_id: Principal, cycles: u128);
+    /// a spare canister created with exactly the given cycles
+    fn take_spare_canister(&mut self, cycles: u128) -> Option<Principal>;
+    fn get_spawned_canister(&self, owner: Principal) -> Option<Principal>;
+    fn save_spawned_canister(&mut self, owner: Principal, canister_id: Principal);
+    fn allow_spawn(&mut self, owner: Principal);
+    fn has_spawn_allowance(&self, owner: Principal) -> bool;
+    fn remove_spawn_allowance(&mut self, owner: Principal);
+}
+
+/// Current fleet upgrade of each kind and its progress per canister. Starting a new rollout
+/// clears the progress of the previous one
+pub trait IFleetStorage {
+    fn get_upgrade(&self, kind: WasmKind) -> Option<FleetUpgrade>;
+    fn save_upgrade(&mut self, upgrade: FleetUpgrade);
+    fn get_canister_upgrade(&self, kind: WasmKind, canister_id: Principal) -> Option<CanisterUpgrade>;
+    fn save_canister_upgrade(&mut self, kind: WasmKind, upgrade: CanisterUpgrade);
+    fn get_canister_upgrades(&self, kind: WasmKind, prev: Option<Principal>, take: usize) -> Vec<CanisterUpgrade>;
+    fn get_pending_upgrades(&self, kind: WasmKind, take: usize) -> Vec<CanisterUpgrade>;
+    fn clear_canister_upgrades(&mut self, kind: WasmKind);
 }
 
 pub trait IVotingStorage {
@@ -25,4 +109,49 @@ pub trait IVotingStorage {
     fn add_vote(&mut self, vote: Vote) -> u64;
     fn get_vote(&self, id: &u64) -> Option<Vote>;
     fn get_all_votes(&self, proposal_id: &u64) -> Vec<Vote>;
-}
\ No newline at end of file
+}
+
+pub trait IMerchantStorage {
+    fn add_merchant(&mut self, merchant: Merchant);
+    fn remove_merchant(&mut self, id: &Principal) -> Option<Merchant>;
+    fn get_merchant(&self, id: &Principal) -> Option<Merchant>;
+    fn get_merchants(&self) -> Vec<Merchant>;
+}
+
+pub trait ICycleStorage {
+    fn get_epochs(&self) -> Vec<CycleEpoch>;
+    fn set_epochs(&mut self, epochs: Vec<CycleEpoch>);
+}
+
+pub trait IEventStorage {
+    fn append_event(&mut self, record: &DaoEventRecord);
+    fn get_event(&self, id: u64) -> Option<DaoEventRecord>;
+    fn count_events(&self) -> u64;
+}
+
+pub trait IEmissionStorage {
+    fn get_cycle_emission(&self, cycle_number: u64) -> Option<CycleEmission>;
+    fn save_cycle_emission(&mut self, cycle_number: u64, emission: CycleEmission);
+    fn get_claim(&self, account: Account, cycle_number: u64) -> Option<StakeClaim>;
+    fn save_claim(&mut self, account: Account, cycle_number: u64, claim: StakeClaim);
+    /// claims not paid yet for cycles before `before_cycle`
+    fn get_unpaid_claims(&self, account: Account, before_cycle: u64) -> Vec<(u64, StakeClaim)>;
+    fn get_snapshot(&self) -> ScoreSnapshot;
+    fn save_snapshot(&mut self, snapshot: ScoreSnapshot);
+    fn get_pending_claim(&self, account: Account) -> Option<PendingClaim>;
+    fn save_pending_claim(&mut self, account: Account, claim: PendingClaim);
+    fn remove_pending_claim(&mut self, account: Account);
+}
+
+pub trait IVoterRewardsStorage {
+    /// first proposal whose voters have not been rewarded yet
+    fn get_next_proposal_id(&self) -> u64;
+    fn set_next_proposal_id(&mut self, proposal_id: u64);
+    fn get_balance(&self, voter: Principal) -> Nat;
+    fn set_balance(&mut self, voter: Principal, balance: Nat);
+    fn get_allocation(&self, cycle_number: u64) -> Option<VoterRewardAllocation>;
+    fn save_allocation(&mut self, cycle_number: u64, allocation: VoterRewardAllocation);
+    fn get_pending_payout(&self, voter: Principal) -> Option<Payout>;
+    fn save_pending_payout(&mut self, voter: Principal, payout: Payout);
+    fn remove_pending_payout(&mut self, voter: Principal);
+}
@@ -0,0 +1,80 @@
+use abstractions::dao::WasmKind;
+use candid::Principal;
+use icrc_ledger_types::icrc1::account::Account;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::rc::Rc;
+
+/// Operations which must not run concurrently for the same subject
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq)]
+pub enum LockKey {
+    Mint(Account),
+    Vote(u64, Principal),
+    Claim(Account),
+    VoterRewards(Principal),
+    FleetUpgrade(WasmKind),
+    Redemption(u128),
+    ScoreSnapshot,
+    Spawn(Principal),
+}
+
+/// Registry of operations which are currently awaiting inter-canister calls
+#[derive(Clone, Default)]
+pub struct InFlightLocks {
+    keys: Rc<RefCell<BTreeSet<LockKey>>>,
+}
+
+impl InFlightLocks {
+    pub fn new(keys: Rc<RefCell<BTreeSet<LockKey>>>) -> Self {
+        Self { keys }
+    }
+
+    /// returns None if an operation with the same key is already in flight
+    pub fn try_acquire(&self, key: LockKey) -> Option<InFlightGuard> {
+        if !self.keys.borrow_mut().insert(key.clone()) {
+            return None;
+        }
+
+        Some(InFlightGuard {
+            keys: Rc::clone(&self.keys),
+            key,
+        })
+    }
+
+    pub fn is_locked(&self, key: &LockKey) -> bool {
+        self.keys.borrow().contains(key)
+    }
+}
+
+/// Releases its key when dropped. If a callback traps, ic-cdk drops the pending future
+/// during cleanup, so the key is released in that case as well
+pub struct InFlightGuard {
+    keys: Rc<RefCell<BTreeSet<LockKey>>>,
+    key: LockKey,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.keys.borrow_mut().remove(&self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_is_released_on_drop() {
+        let locks = InFlightLocks::default();
+        let key = LockKey::Vote(1, Principal::anonymous());
+
+        let guard = locks.try_acquire(key.clone());
+        assert!(guard.is_some());
+        assert!(locks.try_acquire(key.clone()).is_none());
+        assert!(locks.try_acquire(LockKey::Vote(2, Principal::anonymous())).is_some());
+
+        drop(guard);
+        assert!(!locks.is_locked(&key));
+        assert!(locks.try_acquire(key).is_some());
+    }
+}
@@ -0,0 +1,60 @@
+use crate::domain::events::EventLog;
+use crate::domain::governance;
+use crate::domain::interfaces::storage::IMerchantStorage;
+use abstractions::dao::{DaoError, DaoEvent, Merchant};
+use abstractions::runtime::ICanisterRuntime;
+use candid::Principal;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub struct MerchantService {
+    storage: Rc<RefCell<dyn IMerchantStorage>>,
+    runtime: Rc<RefCell<dyn ICanisterRuntime>>,
+    events: EventLog,
+}
+
+impl MerchantService {
+    pub fn new(storage: Rc<RefCell<dyn IMerchantStorage>>, runtime: Rc<RefCell<dyn ICanisterRuntime>>, events: EventLog) -> Self {
+        Self { storage, runtime, events }
+    }
+
+    pub fn register_merchant(&self, merchant_id: Principal, name: String) -> Result<(), DaoError> {
+        self.ensure_governance()?;
+
+        let now = self.runtime.borrow().get_time();
+        let merchant = Merchant {
+            id: merchant_id,
+            name: name.clone(),
+            registered_on: now,
+        };
+        self.storage.borrow_mut().add_merchant(merchant);
+        self.events.record(DaoEvent::MerchantRegistered { merchant_id, name });
+
+        Ok(())
+    }
+
+    pub fn remove_merchant(&self, merchant_id: Principal) -> Result<(), DaoError> {
+        self.ensure_governance()?;
+
+        self.storage
+            .borrow_mut()
+            .remove_merchant(&merchant_id)
+            .ok_or(DaoError::MerchantNotRegistered)?;
+        self.events.record(DaoEvent::MerchantRemoved { merchant_id });
+
+        Ok(())
+    }
+
+    pub fn get_merchants(&self) -> Vec<Merchant> {
+        self.storage.borrow().get_merchants()
+    }
+
+    pub fn is_merchant(&self, principal: &Principal) -> bool {
+        self.storage.borrow().get_merchant(principal).is_some()
+    }
+
+    /// merchants are managed either by controllers or by the DAO itself executing a proposal
+    fn ensure_governance(&self) -> Result<(), DaoError> {
+        governance::ensure_governance(&*self.runtime.borrow(), "manage merchants")
+    }
+}
@@ -0,0 +1,217 @@
+use crate::domain::events::EventLog;
+use crate::domain::hiving::{HivingConfig, HivingService};
+use crate::icp::stable_storage::{EventStorageStable, HivingStorageStorable};
+use abstractions::runtime::{CallMode, ICallContext, ICanisterManager, ICanisterRuntime, InstallMode};
+use abstractions::Timestamp;
+use async_trait::async_trait;
+use candid::{CandidType, Principal};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+pub struct RuntimeMock {
+    pub caller: Principal,
+    pub time: Timestamp,
+}
+
+impl ICanisterRuntime for RuntimeMock {
+    fn get_caller(&self) -> Principal {
+        self.caller
+    }
+
+    fn get_time(&self) -> Timestamp {
+        self.time
+    }
+
+    fn is_controller(&self, _principal: &Principal) -> bool {
+        false
+    }
+
+    fn get_canister_id(&self) -> Principal {
+        Principal::management_canister()
+    }
+}
+
+/// Runtime, event log and hiving registry the services under test are wired to. The runtime
+/// calls as the DAO itself, so governance checks pass until the caller is changed
+pub struct HivingFixture {
+    pub runtime: Rc<RefCell<RuntimeMock>>,
+    pub events: EventLog,
+    pub hiving: Rc<RefCell<HivingService>>,
+}
+
+impl HivingFixture {
+    pub fn new(config: HivingConfig) -> Self {
+        let runtime = Rc::new(RefCell::new(RuntimeMock { caller: Principal::management_canister(), time: 1 }));
+        let events = EventLog::new(Rc::new(RefCell::new(EventStorageStable::init())), runtime.clone());
+        let storage = Rc::new(RefCell::new(HivingStorageStorable::init()));
+        let hiving = Rc::new(RefCell::new(HivingService::new(config, storage, runtime.clone(), events.clone())));
+
+        Self { runtime, events, hiving }
+    }
+}
+
+/// Answers inter-canister calls with pre-encoded candid responses. Calls to a held method
+/// stay pending until it is released, which lets tests interleave concurrent calls
+#[derive(Clone, Default)]
+pub struct CallContextMock {
+    state: Arc<Mutex<CallsState>>,
+}
+
+#[derive(Default)]
+struct CallsState {
+    responses: HashMap<String, VecDeque<Result<Vec<u8>, CallErrorMock>>>,
+    held: HashSet<String>,
+}
+
+/// a failed call; clean failures certainly had no effect on the callee
+#[derive(Clone, Debug)]
+pub struct CallErrorMock {
+    pub reason: String,
+    pub clean: bool,
+}
+
+impl CallContextMock {
+    pub fn respond<T: CandidType>(&self, method: &str, value: T) {
+        let bytes = candid::encode_one(value).unwrap();
+        self.push(method, Ok(bytes));
+    }
+
+    pub fn fail(&self, method: &str, reason: &str) {
+        self.push(method, Err(CallErrorMock { reason: reason.to_string(), clean: true }));
+    }
+
+    /// fails the call without telling whether the callee executed it
+    pub fn fail_unknown(&self, method: &str, reason: &str) {
+        self.push(method, Err(CallErrorMock { reason: reason.to_string(), clean: false }));
+    }
+
+    pub fn hold(&self, method: &str) {
+        self.state.lock().unwrap().held.insert(method.to_string());
+    }
+
+    pub fn release(&self, method: &str) {
+        self.state.lock().unwrap().held.remove(method);
+    }
+
+    fn push(&self, method: &str, response: Result<Vec<u8>, CallErrorMock>) {
+        let mut state = self.state.lock().unwrap();
+        state.responses.entry(method.to_string()).or_default().push_back(response);
+    }
+
+    fn poll_response(&self, method: &str) -> Poll<Result<Vec<u8>, CallErrorMock>> {
+        let mut state = self.state.lock().unwrap();
+        if state.held.contains(method) {
+            return Poll::Pending;
+        }
+        match state.responses.get_mut(method).and_then(|queue| queue.pop_front()) {
+            Some(response) => Poll::Ready(response),
+            None => panic!("No response prepared for {method}"),
+        }
+    }
+}
+
+#[async_trait]
+impl ICallContext for CallContextMock {
+    type Error = CallErrorMock;
+
+    async fn call<'a, Out>(
+        &self,
+        _id: Principal,
+        _mode: CallMode,
+        method: &str,
+        _args: &'a [u8],
+    ) -> Result<Out, Self::Error>
+    where
+        Out: CandidType + for<'de> Deserialize<'de>,
+    {
+        let bytes = poll_fn(|_| self.poll_response(method)).await?;
+        Ok(candid::decode_one(&bytes).unwrap())
+    }
+
+    fn is_clean_reject(error: &Self::Error) -> bool {
+        error.clean
+    }
+}
+
+/// Management canister creating canisters with consecutive ids and recording the installs.
+/// Canisters are controlled by the DAO unless told otherwise
+#[derive(Clone, Default)]
+pub struct CanisterManagerMock {
+    state: Arc<Mutex<ManagerState>>,
+}
+
+/// (canister, mode, chunk hashes, module hash, init arg)
+pub type InstallRecord = (Principal, InstallMode, Vec<Vec<u8>>, Vec<u8>, Vec<u8>);
+
+#[derive(Default)]
+struct ManagerState {
+    created: u8,
+    installs: Vec<InstallRecord>,
+    failing: HashMap<Principal, String>,
+    controllers: HashMap<Principal, Vec<Principal>>,
+}
+
+impl CanisterManagerMock {
+    pub fn installs(&self) -> Vec<InstallRecord> {
+        self.state.lock().unwrap().installs.clone()
+    }
+
+    pub fn fail_installs(&self, canister_id: Principal, reason: &str) {
+        self.state.lock().unwrap().failing.insert(canister_id, reason.to_string());
+    }
+
+    pub fn pass_installs(&self, canister_id: Principal) {
+        self.state.lock().unwrap().failing.remove(&canister_id);
+    }
+
+    pub fn set_controllers(&self, canister_id: Principal, controllers: Vec<Principal>) {
+        self.state.lock().unwrap().controllers.insert(canister_id, controllers);
+    }
+}
+
+#[async_trait]
+impl ICanisterManager for CanisterManagerMock {
+    async fn create_canister(&self, _controllers: Vec<Principal>, _cycles: u128) -> Result<Principal, String> {
+        let mut state = self.state.lock().unwrap();
+        state.created += 1;
+        Ok(Principal::from_slice(&[100, state.created]))
+    }
+
+    async fn upload_chunk(&self, _canister_id: Principal, chunk: Vec<u8>) -> Result<Vec<u8>, String> {
+        Ok(Sha256::digest(chunk).to_vec())
+    }
+
+    async fn install_chunked_code(
+        &self,
+        canister_id: Principal,
+        mode: InstallMode,
+        chunk_hashes: Vec<Vec<u8>>,
+        module_hash: Vec<u8>,
+        arg: Vec<u8>,
+    ) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(reason) = state.failing.get(&canister_id) {
+            return Err(reason.clone());
+        }
+        state.installs.push((canister_id, mode, chunk_hashes, module_hash, arg));
+        Ok(())
+    }
+
+    async fn get_controllers(&self, canister_id: Principal) -> Result<Vec<Principal>, String> {
+        let state = self.state.lock().unwrap();
+        Ok(state.controllers.get(&canister_id).cloned().unwrap_or(vec![Principal::management_canister()]))
+    }
+}
+
+/// polls the future once; pending calls are resumed by polling again after releasing them
+pub fn poll_once<F: Future>(future: Pin<&mut F>) -> Poll<F::Output> {
+    let mut cx = Context::from_waker(Waker::noop());
+    future.poll(&mut cx)
+}
@@ -2,5 +2,17 @@ pub mod hiving;
 pub mod voting;
 pub mod cycles;
 pub mod discounts;
+pub mod emission;
+pub mod events;
+pub mod factory;
+pub mod fleet;
+pub mod governance;
 pub mod interfaces;
+pub mod locks;
+pub mod merchants;
+#[cfg(test)]
+mod mocks;
+pub mod payouts;
+pub mod reconciliation;
 pub mod staking;
+pub mod voter_rewards;
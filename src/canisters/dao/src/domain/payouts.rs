@@ -0,0 +1,58 @@
+use abstractions::runtime::ICallContext;
+use abstractions::token::TokenClient;
+use abstractions::Timestamp;
+use candid::{CandidType, Deserialize, Nat};
+use icrc_ledger_types::icrc1::account::{Account, Subaccount};
+use icrc_ledger_types::icrc1::transfer::{Memo, TransferArg, TransferError};
+use sha2::{Digest, Sha256};
+use std::fmt::Debug;
+
+/// A ledger transfer paid by the DAO. It is stored before it is sent, and resent unchanged while
+/// its outcome is unknown: the ledger deduplicates transfers with the same created_at_time and memo
+#[derive(Clone, Debug, CandidType, Deserialize, PartialEq)]
+pub struct Payout {
+    pub to: Account,
+    pub amount: Nat,
+    pub memo: Vec<u8>,
+    pub created_at_time: Timestamp,
+}
+
+pub enum PayoutOutcome {
+    Paid(Nat),
+    /// the ledger certainly did not execute the transfer
+    Rejected(String),
+    /// the transfer may have been executed
+    Unknown(String),
+}
+
+impl Payout {
+    /// the memo is the hash of what the transfer pays for, e.g. an account and its cycles
+    pub fn new<T: CandidType>(to: Account, amount: Nat, paid_for: &T, now: Timestamp) -> Self {
+        let memo = Sha256::digest(candid::encode_one(paid_for).unwrap()).to_vec();
+        Self { to, amount, memo, created_at_time: now }
+    }
+
+    pub async fn send<R: ICallContext>(&self, token: &TokenClient<R>, from_subaccount: Option<Subaccount>) -> PayoutOutcome
+    where
+        R::Error: Debug,
+    {
+        let args = TransferArg {
+            from_subaccount,
+            to: self.to,
+            fee: None,
+            created_at_time: Some(self.created_at_time),
+            memo: Some(Memo::from(self.memo.clone())),
+            amount: self.amount.clone(),
+        };
+
+        match token.transfer(args).await {
+            Ok(Ok(block_index)) => PayoutOutcome::Paid(block_index),
+            Ok(Err(TransferError::Duplicate { duplicate_of })) => PayoutOutcome::Paid(duplicate_of),
+            // the ledger no longer deduplicates the transfer, so an earlier send may have been executed
+            Ok(Err(TransferError::TooOld)) => PayoutOutcome::Unknown(format!("{:?}", TransferError::TooOld)),
+            Ok(Err(err)) => PayoutOutcome::Rejected(format!("{:?}", err)),
+            Err(err) if R::is_clean_reject(&err) => PayoutOutcome::Rejected(format!("{:?}", err)),
+            Err(err) => PayoutOutcome::Unknown(format!("{:?}", err)),
+        }
+    }
+}
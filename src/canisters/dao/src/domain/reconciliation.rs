@@ -0,0 +1,248 @@
+use crate::domain::governance;
+use crate::domain::interfaces::storage::*;
+use abstractions::dao::{DaoError, Discount, DiscountDrift, ReconciliationReport};
+use abstractions::nft::NftClient;
+use abstractions::runtime::{ICallContext, ICanisterRuntime};
+use abstractions::{DiscountValue, MetadataValue, Timestamp};
+use candid::{CandidType, Deserialize};
+use icrc_ledger_types::icrc1::account::Account;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Reconciliation in progress: the report built so far and where to resume
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ReconciliationRun {
+    pub report: ReconciliationReport,
+    pub phase: ReconciliationPhase,
+}
+
+/// NFTs are compared with the DAO discounts first, then DAO discounts are checked for a missing NFT
+#[derive(Clone, Debug, CandidType, Deserialize, PartialEq)]
+pub enum ReconciliationPhase {
+    Tokens { prev: Option<u128> },
+    Discounts { prev: Option<u128> },
+    Done,
+}
+
+pub struct ReconciliationService<R: ICallContext> {
+    storage: Rc<RefCell<dyn IReconciliationStorage>>,
+    discounts: Rc<RefCell<dyn IDiscountStorage>>,
+    nft: Rc<RefCell<NftClient<R>>>,
+    runtime: Rc<RefCell<dyn ICanisterRuntime>>,
+}
+
+impl<R: ICallContext> ReconciliationService<R> {
+    const PAGE_SIZE: usize = 100;
+    const MAX_REPORTED_DRIFTS: usize = 500;
+    /// a new reconciliation starts on its own this long after the previous one started
+    const RUN_INTERVAL_NS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+    pub fn new(
+        storage: Rc<RefCell<dyn IReconciliationStorage>>,
+        discounts: Rc<RefCell<dyn IDiscountStorage>>,
+        nft: Rc<RefCell<NftClient<R>>>,
+        runtime: Rc<RefCell<dyn ICanisterRuntime>>,
+    ) -> Self {
+        Self { storage, discounts, nft, runtime }
+    }
+
+    pub fn get_report(&self) -> Option<ReconciliationReport> {
+        self.storage.borrow().get_run().map(|run| run.report)
+    }
+
+    /// starts a new reconciliation, abandoning the one in progress
+    pub fn start(&self) -> Result<(), DaoError> {
+        governance::ensure_governance(&*self.runtime.borrow(), "start a reconciliation")?;
+
+        let now = self.runtime.borrow().get_time();
+        self.storage.borrow_mut().set_run(Self::new_run(now));
+
+        Ok(())
+    }
+
+    /// processes the next page of the current reconciliation, starting a new one when the
+    /// previous one is due. Returns false when there was nothing to do
+    pub async fn reconcile_page(&self) -> bool {
+        let now = self.runtime.borrow().get_time();
+        let mut run = match self.storage.borrow().get_run() {
+            Some(run) if run.phase != ReconciliationPhase::Done => run,
+            Some(run) if now < run.report.started_on + Self::RUN_INTERVAL_NS => return false,
+            _ => Self::new_run(now),
+        };
+        let started_on = run.report.started_on;
+
+        let processed = match run.phase.clone() {
+            ReconciliationPhase::Tokens { prev } => self.check_tokens(&mut run, prev).await,
+            ReconciliationPhase::Discounts { prev } => self.check_discounts(&mut run, prev).await,
+            ReconciliationPhase::Done => Ok(()),
+        };
+        if processed.is_err() {
+            // the page is checked again on the next run of the job
+            return false;
+        }
+
+        // a reconciliation started while awaiting the NFT canister takes precedence
+        if let Some(current) = self.storage.borrow().get_run()
+            && current.report.started_on > started_on
+        {
+            return false;
+        }
+        self.storage.borrow_mut().set_run(run);
+
+        true
+    }
+
+    async fn check_tokens(&self, run: &mut ReconciliationRun, prev: Option<u128>) -> Result<(), R::Error> {
+        let nft = self.nft.borrow().clone();
+        let token_ids = nft.icrc7_tokens(prev, Some(Self::PAGE_SIZE as u128)).await?;
+        let Some(last) = token_ids.last().copied() else {
+            run.phase = ReconciliationPhase::Discounts { prev: None };
+            return Ok(());
+        };
+
+        let metadata = nft.icrc7_token_metadata(token_ids.clone()).await?;
+        let owners = nft.icrc7_owner_of(token_ids.clone()).await?;
+        for ((token_id, metadata), owner) in token_ids.into_iter().zip(metadata).zip(owners) {
+            let discount = self.discounts.borrow().get_discount(token_id);
+            for drift in Self::compare(token_id, discount, metadata, owner) {
+                Self::add_drift(&mut run.report, drift);
+            }
+            run.report.checked_tokens += 1;
+        }
+        run.phase = ReconciliationPhase::Tokens { prev: Some(last) };
+
+        Ok(())
+    }
+
+    async fn check_discounts(&self, run: &mut ReconciliationRun, prev: Option<u128>) -> Result<(), R::Error> {
+        let discounts = self.discounts.borrow().get_discounts(prev, Self::PAGE_SIZE);
+        let Some(last) = discounts.last().map(|d| d.id) else {
+            run.phase = ReconciliationPhase::Done;
+            run.report.finished_on = Some(self.runtime.borrow().get_time());
+            return Ok(());
+        };
+
+        let token_ids: Vec<u128> = discounts.iter().map(|d| d.id).collect();
+        let nft = self.nft.borrow().clone();
+        let owners = nft.icrc7_owner_of(token_ids.clone()).await?;
+        for (token_id, owner) in token_ids.into_iter().zip(owners) {
+            if owner.is_none() {
+                Self::add_drift(&mut run.report, DiscountDrift::Orphaned { token_id });
+            }
+            run.report.checked_discounts += 1;
+        }
+        run.phase = ReconciliationPhase::Discounts { prev: Some(last) };
+
+        Ok(())
+    }
+
+    fn compare(
+        token_id: u128,
+        discount: Option<Discount>,
+        metadata: Option<Vec<(String, MetadataValue)>>,
+        owner: Option<Account>,
+    ) -> Vec<DiscountDrift> {
+        let Some(discount) = discount else {
+            return vec![DiscountDrift::Missing { token_id }];
+        };
+
+        let mut drifts = vec![];
+        let nft_value = metadata.as_ref().and_then(|metadata| Self::parse_value(metadata));
+        if nft_value != Some(discount.value) {
+            drifts.push(DiscountDrift::ValueMismatch { token_id, dao_value: discount.value, nft_value });
+        }
+        if let Some(nft_owner) = owner
+            && nft_owner != discount.owner
+        {
+            drifts.push(DiscountDrift::OwnerChanged { token_id, dao_owner: discount.owner, nft_owner });
+        }
+
+        drifts
+    }
+
+    fn parse_value(metadata: &[(String, MetadataValue)]) -> Option<DiscountValue> {
+        metadata.iter().find_map(|(key, value)| match value {
+            MetadataValue::Text(text) if key == "value" => text.parse().ok(),
+            _ => None,
+        })
+    }
+
+    fn add_drift(report: &mut ReconciliationReport, drift: DiscountDrift) {
+        report.drift_count += 1;
+        if report.drifts.len() < Self::MAX_REPORTED_DRIFTS {
+            report.drifts.push(drift);
+        }
+    }
+
+    fn new_run(now: Timestamp) -> ReconciliationRun {
+        ReconciliationRun {
+            report: ReconciliationReport {
+                started_on: now,
+                finished_on: None,
+                checked_tokens: 0,
+                checked_discounts: 0,
+                drift_count: 0,
+                drifts: vec![],
+            },
+            phase: ReconciliationPhase::Tokens { prev: None },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::mocks::{poll_once, CallContextMock, RuntimeMock};
+    use crate::icp::stable_storage::{DiscountStorageStable, ReconciliationStorageStable};
+    use candid::Principal;
+    use std::pin::pin;
+    use std::task::Poll;
+
+    fn account(id: u8) -> Account {
+        Account::from(Principal::from_slice(&[id]))
+    }
+
+    fn nft_metadata(value: &str) -> Option<Vec<(String, MetadataValue)>> {
+        Some(vec![("value".to_string(), MetadataValue::Text(value.to_string()))])
+    }
+
+    #[test]
+    fn drifts_are_reported() {
+        let calls = CallContextMock::default();
+        let runtime = Rc::new(RefCell::new(RuntimeMock { caller: Principal::anonymous(), time: 1 }));
+        let discounts = Rc::new(RefCell::new(DiscountStorageStable::init()));
+        let nft = NftClient { runtime: Rc::new(RefCell::new(calls.clone())), canister_id: Principal::anonymous() };
+        let service = ReconciliationService::new(
+            Rc::new(RefCell::new(ReconciliationStorageStable::init())),
+            discounts.clone(),
+            Rc::new(RefCell::new(nft)),
+            runtime,
+        );
+        discounts.borrow_mut().add_discount(0, Discount::new(1, 10.0, account(1)));
+        discounts.borrow_mut().add_discount(0, Discount::new(2, 5.0, account(1)));
+        discounts.borrow_mut().add_discount(0, Discount::new(4, 5.0, account(1)));
+
+        calls.respond("icrc7_tokens", vec![1u128, 2, 3]);
+        calls.respond("icrc7_token_metadata", vec![nft_metadata("10"), nft_metadata("7.5"), nft_metadata("5")]);
+        calls.respond("icrc7_owner_of", vec![Some(account(2)), Some(account(1)), Some(account(1))]);
+        calls.respond("icrc7_tokens", Vec::<u128>::new());
+        calls.respond("icrc7_owner_of", vec![Some(account(2)), Some(account(1)), None::<Account>]);
+        for _ in 0..4 {
+            assert_eq!(poll_once(pin!(service.reconcile_page())), Poll::Ready(true));
+        }
+
+        let report = service.get_report().unwrap();
+        assert!(report.finished_on.is_some());
+        assert_eq!((report.checked_tokens, report.checked_discounts, report.drift_count), (3, 3, 4));
+        assert_eq!(
+            report.drifts,
+            vec![
+                DiscountDrift::OwnerChanged { token_id: 1, dao_owner: account(1), nft_owner: account(2) },
+                DiscountDrift::ValueMismatch { token_id: 2, dao_value: 5.0, nft_value: Some(7.5) },
+                DiscountDrift::Missing { token_id: 3 },
+                DiscountDrift::Orphaned { token_id: 4 },
+            ]
+        );
+        assert_eq!(poll_once(pin!(service.reconcile_page())), Poll::Ready(false));
+    }
+}
@@ -2,13 +2,15 @@ mod scorers;
 
 use crate::domain::cycles::CycleService;
 use abstractions::Timestamp;
+use abstractions::dao::DaoError;
 use abstractions::token::{StakingLogResult, TokenClient};
+use abstractions::runtime::ICallContext;
 use candid::{CandidType, Deserialize, Nat};
-use canister_runtime::CdkCallContext;
 use icrc_ledger_types::icrc1::account::Account;
 use scorers::LinearMinScorer;
 use serde::Serialize;
 use std::cell::RefCell;
+use std::fmt::Debug;
 use std::rc::Rc;
 
 #[derive(Clone, Debug, Deserialize, Serialize, CandidType)]
@@ -20,16 +22,29 @@ impl Default for StakingConfig {
     }
 }
 
-pub struct StakingService {
+pub struct StakingService<R: ICallContext> {
     config: StakingConfig,
-    tokens: Rc<RefCell<TokenClient<CdkCallContext>>>,
+    tokens: Rc<RefCell<TokenClient<R>>>,
     cycles: Rc<RefCell<CycleService>>,
 }
 
-impl StakingService {
+impl<R: ICallContext> Clone for StakingService<R> {
+    fn clone(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            tokens: Rc::clone(&self.tokens),
+            cycles: Rc::clone(&self.cycles),
+        }
+    }
+}
+
+impl<R: ICallContext> StakingService<R>
+where
+    R::Error: Debug,
+{
     pub fn new(
         config: StakingConfig,
-        tokens: Rc<RefCell<TokenClient<CdkCallContext>>>,
+        tokens: Rc<RefCell<TokenClient<R>>>,
         cycles: Rc<RefCell<CycleService>>,
     ) -> Self {
         Self {
@@ -44,28 +59,27 @@ impl StakingService {
         wallet: Account,
         start: Option<Timestamp>,
         end: Option<Timestamp>,
-    ) -> StakingLogResult {
-        self.tokens
-            .borrow()
+    ) -> Result<StakingLogResult, DaoError> {
+        let tokens = self.tokens.borrow().clone();
+        tokens
             .privia_staking_log(wallet, start, end)
             .await
-            .unwrap()
+            .map_err(DaoError::call_failed)
     }
 
-    pub async fn get_current_staking_score(&self, wallet: Account) -> Nat {
-        let current_cycle = self.cycles.borrow().get_current_cycle();
-        let log: StakingLogResult = self
-            .tokens
-            .borrow()
+    pub async fn get_current_staking_score(&self, wallet: Account) -> Result<Nat, DaoError> {
+        let current_cycle = self.cycles.borrow().get_current_cycle()?;
+        let tokens = self.tokens.borrow().clone();
+        let log: StakingLogResult = tokens
             .privia_staking_log(wallet, None, Some(current_cycle.start))
             .await
-            .unwrap();
+            .map_err(DaoError::call_failed)?;
 
         let score_calculator = LinearMinScorer::new(Rc::clone(&self.cycles));
         let score = score_calculator
             .calculate_score(&log.log, current_cycle.number)
             .await;
 
-        score
+        Ok(score)
     }
 }
\ No newline at end of file
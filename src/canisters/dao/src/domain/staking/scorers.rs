@@ -66,8 +66,9 @@ impl LinearMinScorer {
         };
 
         for entry in log.iter() {
-            let entry_cycle = self.cycles.borrow().resolve_cycle(entry.timestamp);
-            let current = CycleBalanceChange::new(entry_cycle.number, entry.current_amount.clone());
+            // stakes made before the calendar started count as cycle 0
+            let entry_cycle = self.cycles.borrow().resolve_cycle(entry.timestamp).map_or(0, |cycle| cycle.number);
+            let current = CycleBalanceChange::new(entry_cycle, entry.current_amount.clone());
 
             if current.cycle > last.cycle {
                 result.push(current.clone());
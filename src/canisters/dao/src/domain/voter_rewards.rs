@@ -0,0 +1,272 @@
+use crate::domain::cycles::CycleService;
+use crate::domain::events::EventLog;
+use crate::domain::interfaces::storage::{IVoterRewardsStorage, IVotingStorage};
+use crate::domain::locks::{InFlightLocks, LockKey};
+use crate::domain::payouts::{Payout, PayoutOutcome};
+use abstractions::dao::{DaoError, DaoEvent, Proposal, VoterRewardAllocation};
+use abstractions::runtime::{ICallContext, ICanisterRuntime};
+use abstractions::token::TokenClient;
+use candid::{CandidType, Deserialize, Nat, Principal};
+use icrc_ledger_types::icrc1::account::{Account, Subaccount};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::rc::Rc;
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, CandidType)]
+pub struct VoterRewardsConfig {
+    /// PVT shared by the voters of the proposals of a voting cycle, 0 disables the rewards
+    pub pool_per_cycle: u128,
+    /// subaccount of the DAO holding the treasury the rewards are paid from
+    pub treasury_subaccount: Option<Subaccount>,
+}
+
+pub struct VoterRewardsService<R: ICallContext> {
+    config: VoterRewardsConfig,
+    cycles: Rc<RefCell<CycleService>>,
+    voting: Rc<RefCell<dyn IVotingStorage>>,
+    storage: Rc<RefCell<dyn IVoterRewardsStorage>>,
+    token: Rc<RefCell<TokenClient<R>>>,
+    locks: InFlightLocks,
+    runtime: Rc<RefCell<dyn ICanisterRuntime>>,
+    events: EventLog,
+}
+
+impl<R: ICallContext> VoterRewardsService<R>
+where
+    R::Error: Debug,
+{
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        config: VoterRewardsConfig,
+        cycles: Rc<RefCell<CycleService>>,
+        voting: Rc<RefCell<dyn IVotingStorage>>,
+        storage: Rc<RefCell<dyn IVoterRewardsStorage>>,
+        token: Rc<RefCell<TokenClient<R>>>,
+        locks: InFlightLocks,
+        runtime: Rc<RefCell<dyn ICanisterRuntime>>,
+        events: EventLog,
+    ) -> Self {
+        Self { config, cycles, voting, storage, token, locks, runtime, events }
+    }
+
+    pub fn get_balance(&self, voter: Principal) -> Nat {
+        self.storage.borrow().get_balance(voter)
+    }
+
+    pub fn get_allocation(&self, cycle_number: u64) -> Option<VoterRewardAllocation> {
+        self.storage.borrow().get_allocation(cycle_number)
+    }
+
+    /// Credits the pool of the oldest voting cycle whose proposals are all finalized to the voters
+    /// of these proposals, in proportion to their vote weight. Proposals are numbered in the order
+    /// of their voting cycles, so the allocated ones are tracked with a single cursor.
+    /// Returns false when no voting cycle is ready
+    pub fn allocate_next_cycle(&self) -> Result<bool, DaoError> {
+        let now = self.runtime.borrow().get_time();
+        let first_id = self.storage.borrow().get_next_proposal_id();
+        let Some(first) = self.voting.borrow().get_proposal(&first_id) else {
+            return Ok(false);
+        };
+        if first.end >= now {
+            return Ok(false);
+        }
+
+        let cycle_number = self.cycles.borrow().resolve_cycle(first.end)?.number;
+        let mut proposals = vec![first];
+        while let Some(proposal) = self.voting.borrow().get_proposal(&(first_id + proposals.len() as u64))
+            && proposal.end == proposals[0].end
+        {
+            proposals.push(proposal);
+        }
+
+        let weights = self.collect_weights(&proposals);
+        let total_weight = weights.values().fold(Nat::from(0u8), |total, weight| total + weight.clone());
+        let pool = Nat::from(self.config.pool_per_cycle);
+        let mut storage = self.storage.borrow_mut();
+        if total_weight > 0u8 {
+            for (voter, weight) in weights.iter() {
+                let reward = pool.clone() * weight.clone() / total_weight.clone();
+                let balance = storage.get_balance(*voter) + reward;
+                storage.set_balance(*voter, balance);
+            }
+        }
+
+        let allocation = VoterRewardAllocation {
+            cycle_number,
+            pool,
+            total_weight,
+            proposals: proposals.len() as u64,
+            voters: weights.len() as u64,
+        };
+        storage.save_allocation(cycle_number, allocation.clone());
+        storage.set_next_proposal_id(first_id + proposals.len() as u64);
+        self.events.record(DaoEvent::VoterRewardsAllocated { allocation });
+
+        Ok(true)
+    }
+
+    /// weight of every principal which voted on the proposals; votes cast before votes were
+    /// weighted count as 0
+    fn collect_weights(&self, proposals: &[Proposal]) -> BTreeMap<Principal, Nat> {
+        let voting = self.voting.borrow();
+        let mut weights: BTreeMap<Principal, Nat> = BTreeMap::new();
+        for vote in proposals.iter().flat_map(|proposal| proposal.votes.iter()).filter_map(|id| voting.get_vote(id)) {
+            let weight = weights.entry(vote.created_by).or_insert(Nat::from(0u8));
+            *weight += vote.weight.unwrap_or(Nat::from(0u8));
+        }
+
+        weights
+    }
+
+    /// Pays the caller's balance from the treasury.
+    ///
+    /// A payout whose outcome is unknown is resent unchanged by the next claim, and its amount is
+    /// credited back to the balance only once the ledger rejected it
+    pub async fn claim_voter_rewards(&self) -> Result<Nat, DaoError> {
+        let voter = self.runtime.borrow().get_caller();
+        let _guard = self
+            .locks
+            .try_acquire(LockKey::VoterRewards(voter))
+            .ok_or(DaoError::ClaimInProgress)?;
+
+        let pending = self.storage.borrow().get_pending_payout(voter);
+        if let Some(payout) = pending {
+            return self.pay(voter, payout).await;
+        }
+
+        let amount = self.storage.borrow().get_balance(voter);
+        if amount == 0u8 {
+            return Ok(amount);
+        }
+        let now = self.runtime.borrow().get_time();
+        let payout = Payout::new(Account::from(voter), amount.clone(), &(voter, amount), now);
+        {
+            let mut storage = self.storage.borrow_mut();
+            storage.set_balance(voter, Nat::from(0u8));
+            storage.save_pending_payout(voter, payout.clone());
+        }
+
+        self.pay(voter, payout).await
+    }
+
+    async fn pay(&self, voter: Principal, payout: Payout) -> Result<Nat, DaoError> {
+        let token = self.token.borrow().clone();
+        match payout.send(&token, self.config.treasury_subaccount).await {
+            PayoutOutcome::Paid(_) => {
+                self.storage.borrow_mut().remove_pending_payout(voter);
+                self.events.record(DaoEvent::VoterRewardsClaimed { voter, amount: payout.amount.clone() });
+                Ok(payout.amount)
+            }
+            PayoutOutcome::Rejected(reason) => {
+                let mut storage = self.storage.borrow_mut();
+                storage.remove_pending_payout(voter);
+                let balance = storage.get_balance(voter) + payout.amount;
+                storage.set_balance(voter, balance);
+                Err(DaoError::CallFailed { reason })
+            }
+            PayoutOutcome::Unknown(reason) => Err(DaoError::CallFailed { reason }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::cycles::CyclesConfig;
+    use crate::domain::mocks::{poll_once, CallContextMock, RuntimeMock};
+    use crate::icp::stable_storage::{CycleStorageStable, EventStorageStable, VoterRewardsStorageStable, VotingStorageStable};
+    use abstractions::dao::{ProposalType, Vote, VoteOption};
+    use icrc_ledger_types::icrc1::transfer::TransferError;
+    use std::pin::pin;
+    use std::task::Poll;
+
+    const TRANSFER: &str = "icrc1_transfer";
+
+    struct Setup {
+        calls: CallContextMock,
+        runtime: Rc<RefCell<RuntimeMock>>,
+        voting: Rc<RefCell<dyn IVotingStorage>>,
+        storage: Rc<RefCell<dyn IVoterRewardsStorage>>,
+        service: VoterRewardsService<CallContextMock>,
+        genesis: u64,
+        cycle_len: u64,
+    }
+
+    fn setup() -> Setup {
+        let cycles_config = CyclesConfig::default();
+        let genesis = cycles_config.genesis.unwrap();
+        let cycle_len = cycles_config.cycle_len_ns;
+        let runtime = Rc::new(RefCell::new(RuntimeMock { caller: Principal::anonymous(), time: genesis + 10 * cycle_len }));
+        let events = EventLog::new(Rc::new(RefCell::new(EventStorageStable::init())), runtime.clone());
+        let cycles = CycleService::new(cycles_config, Rc::new(RefCell::new(CycleStorageStable::init())), runtime.clone(), events.clone());
+        let voting: Rc<RefCell<dyn IVotingStorage>> = Rc::new(RefCell::new(VotingStorageStable::init()));
+        let storage: Rc<RefCell<dyn IVoterRewardsStorage>> = Rc::new(RefCell::new(VoterRewardsStorageStable::init()));
+        let calls = CallContextMock::default();
+        let token = TokenClient { runtime: Rc::new(RefCell::new(calls.clone())), canister_id: Principal::anonymous() };
+        let service = VoterRewardsService::new(
+            VoterRewardsConfig { pool_per_cycle: 1000, treasury_subaccount: None },
+            Rc::new(RefCell::new(cycles)),
+            voting.clone(),
+            storage.clone(),
+            Rc::new(RefCell::new(token)),
+            InFlightLocks::default(),
+            runtime.clone(),
+            events,
+        );
+
+        Setup { calls, runtime, voting, storage, service, genesis, cycle_len }
+    }
+
+    #[test]
+    fn pool_is_split_by_vote_weight() {
+        let Setup { voting, service, genesis, cycle_len, .. } = setup();
+        let (first, second) = (Principal::from_slice(&[1]), Principal::from_slice(&[2]));
+        for (end_cycle, votes) in [(4, vec![(first, 10u32), (second, 30)]), (4, vec![(first, 40)]), (12, vec![(second, 10)])] {
+            let end = genesis + end_cycle * cycle_len;
+            let proposal_id = voting.borrow_mut().add_proposal(Proposal::new(0, first, ProposalType::Generic, String::new(), end - cycle_len, end));
+            let mut proposal = voting.borrow().get_proposal(&proposal_id).unwrap();
+            for (voter, weight) in votes {
+                let vote_id = voting.borrow_mut().add_vote(Vote::new(proposal_id, voter, end, VoteOption::Approve, Nat::from(weight)));
+                proposal.votes.push(vote_id);
+            }
+            voting.borrow_mut().update_proposal(proposal);
+        }
+
+        assert!(service.allocate_next_cycle().unwrap());
+        assert!(!service.allocate_next_cycle().unwrap());
+
+        assert_eq!(service.get_balance(first), Nat::from(625u32));
+        assert_eq!(service.get_balance(second), Nat::from(375u32));
+        let allocation = service.get_allocation(4).unwrap();
+        assert_eq!((allocation.proposals, allocation.voters), (2, 2));
+    }
+
+    #[test]
+    fn balance_is_credited_back_only_on_rejection() {
+        let setup = setup();
+        let voter = Principal::from_slice(&[1]);
+        setup.runtime.borrow_mut().caller = voter;
+        setup.storage.borrow_mut().set_balance(voter, Nat::from(100u8));
+        let claim = || match poll_once(pin!(setup.service.claim_voter_rewards())) {
+            Poll::Ready(result) => result,
+            Poll::Pending => unreachable!(),
+        };
+
+        setup.calls.fail_unknown(TRANSFER, "timeout");
+        assert!(matches!(claim(), Err(DaoError::CallFailed { .. })));
+        assert_eq!(setup.service.get_balance(voter), Nat::from(0u8));
+
+        setup.calls.fail(TRANSFER, "stopped");
+        assert!(matches!(claim(), Err(DaoError::CallFailed { .. })));
+        assert_eq!(setup.service.get_balance(voter), Nat::from(100u8));
+
+        setup.calls.fail_unknown(TRANSFER, "timeout");
+        assert!(claim().is_err());
+        setup.calls.respond(TRANSFER, Err::<Nat, _>(TransferError::Duplicate { duplicate_of: Nat::from(3u8) }));
+        assert_eq!(claim().unwrap(), Nat::from(100u8));
+        assert_eq!(setup.service.get_balance(voter), Nat::from(0u8));
+        assert_eq!(claim().unwrap(), Nat::from(0u8));
+    }
+}
@@ -1,101 +1,273 @@
 use super::{cycles::CycleService};
-use canister_runtime::CdkCallContext;
-use std::{cell::RefCell, rc::Rc};
-use candid::Nat;
+use super::events::EventLog;
+use super::locks::{InFlightLocks, LockKey};
+use std::{cell::RefCell, fmt::Debug, rc::Rc};
+use candid::{Nat, Principal};
 use icrc_ledger_types::icrc1::account::Account;
-use abstractions::dao::{Proposal, ProposalType, Vote, VoteOption};
-use abstractions::runtime::ICanisterRuntime;
+use abstractions::dao::{DaoError, DaoEvent, Proposal, ProposalType, Vote, VoteOption};
+use abstractions::runtime::{ICallContext, ICanisterRuntime};
 use abstractions::token::TokenClient;
 use super::interfaces::storage::IVotingStorage;
 
-pub struct VotingService {
+pub struct VotingService<R: ICallContext> {
     cycles: Rc<RefCell<CycleService>>,
     storage: Rc<RefCell<dyn IVotingStorage>>,
     runtime: Rc<RefCell<dyn ICanisterRuntime>>,
-    token: Rc<RefCell<TokenClient<CdkCallContext>>>
+    token: Rc<RefCell<TokenClient<R>>>,
+    locks: InFlightLocks,
+    events: EventLog,
 }
 
-impl VotingService {
+impl<R: ICallContext> VotingService<R>
+where
+    R::Error: Debug,
+{
     pub fn new(
         cycles: Rc<RefCell<CycleService>>,
         storage: Rc<RefCell<dyn IVotingStorage>>,
         runtime: Rc<RefCell<dyn ICanisterRuntime>>,
-        token: Rc<RefCell<TokenClient<CdkCallContext>>>
+        token: Rc<RefCell<TokenClient<R>>>,
+        locks: InFlightLocks,
+        events: EventLog,
     ) -> Self {
         Self {
             cycles,
             storage,
             runtime,
-            token
+            token,
+            locks,
+            events,
         }
     }
 
-    pub async fn create_proposal(&self, proposal_type: ProposalType, data: String) -> u64 {
+    pub async fn create_proposal(&self, proposal_type: ProposalType, data: String) -> Result<u64, DaoError> {
         let now = self.runtime.borrow().get_time();
         let caller = self.runtime.borrow().get_caller();
-        let voting_cycle = self.cycles.borrow().get_next_voting_cycle();
+        let voting_cycle = self.cycles.borrow().get_next_voting_cycle()?;
         let proposal = Proposal::new(
             now,
             caller,
-            proposal_type,
+            proposal_type.clone(),
             data,
             voting_cycle.start,
             voting_cycle.end,
         );
 
         let proposal_id = self.storage.borrow_mut().add_proposal(proposal);
+        self.events.record(DaoEvent::ProposalCreated { proposal_id, proposal_type });
 
-        proposal_id
+        Ok(proposal_id)
     }
 
     pub fn get_proposal(&self, proposal_id: &u64) -> Option<Proposal> {
         self.storage.borrow().get_proposal(proposal_id)
     }
 
-    pub async fn vote(&mut self, proposal_id: u64, vote: VoteOption) -> u64 {
-        let proposal = self.get_proposal(&proposal_id);
-        if proposal.is_none() {
-            panic!("Proposal does not exist!")
-        }
-        let mut proposal = proposal.unwrap();
+    pub async fn vote(&mut self, proposal_id: u64, vote: VoteOption) -> Result<u64, DaoError> {
+        let proposal = self.get_proposal(&proposal_id).ok_or(DaoError::ProposalNotFound)?;
 
         let now = self.runtime.borrow().get_time();
         let caller = self.runtime.borrow().get_caller();
 
         if now < proposal.start || now > proposal.end {
-            panic!("Voting on proposal is not active")
+            return Err(DaoError::VotingNotActive);
+        }
+
+        let _guard = self
+            .locks
+            .try_acquire(LockKey::Vote(proposal_id, caller))
+            .ok_or(DaoError::VoteInProgress)?;
+        if self.has_voted(&proposal, &caller) {
+            return Err(DaoError::AlreadyVoted);
         }
 
         let caller_acc = Account::from(caller);
-        let balance = self.token.borrow().balance_of(caller_acc).await.unwrap();
+        let token = self.token.borrow().clone();
+        let balance = token.balance_of(caller_acc).await.map_err(DaoError::call_failed)?;
         if balance <= Nat::from(0u32) {
-            panic!("Staking balance is zero! Only token holders are eligible to vote on proposals")
+            return Err(DaoError::NoStakingBalance);
         }
 
-        let vote = Vote::new(proposal_id, caller, now, vote);
+        // other voters could have been recorded while awaiting the balance
+        let mut proposal = self.get_proposal(&proposal_id).ok_or(DaoError::ProposalNotFound)?;
+        let vote = Vote::new(proposal_id, caller, now, vote, balance);
 
-        let vote_id = self.storage.borrow_mut().add_vote(vote);
+        let vote_id = self.storage.borrow_mut().add_vote(vote.clone());
         proposal.votes.push(vote_id);
         self.storage.borrow_mut().update_proposal(proposal);
+        self.events.record(DaoEvent::VoteCast { proposal_id, vote_id, vote: vote.result });
 
-        vote_id
+        Ok(vote_id)
+    }
+
+    fn has_voted(&self, proposal: &Proposal, voter: &Principal) -> bool {
+        proposal
+            .votes
+            .iter()
+            .filter_map(|vote_id| self.get_vote(vote_id))
+            .any(|vote| &vote.created_by == voter)
     }
 
     pub fn get_vote(&self, vote_id: &u64) -> Option<Vote> {
         self.storage.borrow().get_vote(vote_id)
     }
 
-    pub fn get_all_votes(&self, proposal_id: &u64) -> Vec<Vote> {
-        let proposal = self
-            .get_proposal(proposal_id)
-            .expect("Proposal does not exit");
+    pub fn get_all_votes(&self, proposal_id: &u64) -> Result<Vec<Vote>, DaoError> {
+        let proposal = self.get_proposal(proposal_id).ok_or(DaoError::ProposalNotFound)?;
+
+        let result = proposal
+            .votes
+            .iter()
+            .filter_map(|vote_id| self.get_vote(vote_id))
+            .collect();
+
+        Ok(result)
+    }
+
+    /// an ended proposal is approved when the weight voting for it beats the weight against it.
+    /// Approved proposals are executed once
+    pub fn get_executable_proposal(&self, proposal_id: u64) -> Result<Proposal, DaoError> {
+        let proposal = self.get_proposal(&proposal_id).ok_or(DaoError::ProposalNotFound)?;
+        if self.runtime.borrow().get_time() <= proposal.end {
+            return Err(DaoError::InvalidArgument { reason: "Voting on the proposal has not ended".to_string() });
+        }
+        if proposal.executed_on.is_some() {
+            return Err(DaoError::InvalidArgument { reason: "The proposal was already executed".to_string() });
+        }
+
+        let (approve, decline) = self.get_all_votes(&proposal_id)?.into_iter().fold(
+            (Nat::from(0u32), Nat::from(0u32)),
+            |(approve, decline), vote| {
+                let weight = vote.weight.unwrap_or_default();
+                match vote.result {
+                    VoteOption::Approve => (approve + weight, decline),
+                    VoteOption::Decline => (approve, decline + weight),
+                }
+            },
+        );
+        if approve <= decline {
+            return Err(DaoError::InvalidArgument { reason: "The proposal was not approved".to_string() });
+        }
+
+        Ok(proposal)
+    }
+
+    pub fn mark_executed(&self, mut proposal: Proposal) {
+        let proposal_id = proposal.id;
+        proposal.executed_on = Some(self.runtime.borrow().get_time());
+        self.storage.borrow_mut().update_proposal(proposal);
+        self.events.record(DaoEvent::ProposalExecuted { proposal_id });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::cycles::CyclesConfig;
+    use crate::domain::mocks::{poll_once, CallContextMock, RuntimeMock};
+    use crate::icp::stable_storage::{CycleStorageStable, EventStorageStable, VotingStorageStable};
+    use abstractions::dao::WasmKind;
+    use std::pin::pin;
+    use std::task::Poll;
+
+    const BALANCE_OF: &str = "icrc1_balance_of";
+
+    struct Setup {
+        runtime: Rc<RefCell<RuntimeMock>>,
+        cycles: Rc<RefCell<CycleService>>,
+        storage: Rc<RefCell<dyn IVotingStorage>>,
+        calls: CallContextMock,
+        locks: InFlightLocks,
+        events: EventLog,
+    }
+
+    impl Setup {
+        fn new() -> Self {
+            let cycles_config = CyclesConfig::default();
+            let genesis = cycles_config.genesis.unwrap();
+            let runtime = Rc::new(RefCell::new(RuntimeMock {
+                caller: Principal::from_slice(&[1]),
+                time: genesis + 1,
+            }));
+            let events = EventLog::new(Rc::new(RefCell::new(EventStorageStable::init())), runtime.clone());
+            let cycles = CycleService::new(cycles_config, Rc::new(RefCell::new(CycleStorageStable::init())), runtime.clone(), events.clone());
+            let cycles = Rc::new(RefCell::new(cycles));
+            let storage: Rc<RefCell<dyn IVotingStorage>> = Rc::new(RefCell::new(VotingStorageStable::init()));
+
+            Self { runtime, cycles, storage, calls: CallContextMock::default(), locks: InFlightLocks::default(), events }
+        }
+
+        fn service(&self) -> VotingService<CallContextMock> {
+            let token = TokenClient { runtime: Rc::new(RefCell::new(self.calls.clone())), canister_id: Principal::anonymous() };
+            let token = Rc::new(RefCell::new(token));
+            VotingService::new(self.cycles.clone(), self.storage.clone(), self.runtime.clone(), token, self.locks.clone(), self.events.clone())
+        }
+
+        fn propose(&self, proposal_type: ProposalType) -> u64 {
+            match poll_once(pin!(self.service().create_proposal(proposal_type, String::new()))) {
+                Poll::Ready(id) => id.unwrap(),
+                Poll::Pending => unreachable!(),
+            }
+        }
+
+        fn open_voting(&self, proposal_id: u64) {
+            self.runtime.borrow_mut().time = self.service().get_proposal(&proposal_id).unwrap().start + 1;
+        }
 
-        let mut result: Vec<Vote> = Vec::new();
-        for vote_id in proposal.votes {
-            let vote = self.get_vote(&vote_id).unwrap();
-            result.push(vote);
+        fn vote(&self, voter: u8, proposal_id: u64, vote: VoteOption, balance: u8) {
+            self.runtime.borrow_mut().caller = Principal::from_slice(&[voter]);
+            self.calls.respond(BALANCE_OF, Nat::from(balance));
+            let mut service = self.service();
+            assert!(matches!(poll_once(pin!(service.vote(proposal_id, vote))), Poll::Ready(Ok(_))));
         }
+    }
+
+    #[test]
+    fn concurrent_votes_are_all_recorded() {
+        let setup = Setup::new();
+        let proposal_id = setup.propose(ProposalType::Generic);
+        setup.open_voting(proposal_id);
+        setup.calls.respond(BALANCE_OF, Nat::from(10u8));
+        setup.calls.respond(BALANCE_OF, Nat::from(10u8));
+        setup.calls.hold(BALANCE_OF);
+
+        let (mut first, mut second) = (setup.service(), setup.service());
+        let mut first_vote = pin!(first.vote(proposal_id, VoteOption::Approve));
+        assert!(poll_once(first_vote.as_mut()).is_pending());
+        assert!(setup.locks.is_locked(&LockKey::Vote(proposal_id, Principal::from_slice(&[1]))));
+
+        setup.runtime.borrow_mut().caller = Principal::from_slice(&[2]);
+        let mut second_vote = pin!(second.vote(proposal_id, VoteOption::Decline));
+        assert!(poll_once(second_vote.as_mut()).is_pending());
+
+        setup.calls.release(BALANCE_OF);
+        assert!(matches!(poll_once(first_vote), Poll::Ready(Ok(_))));
+        assert!(matches!(poll_once(second_vote), Poll::Ready(Ok(_))));
+
+        assert_eq!(setup.service().get_all_votes(&proposal_id).unwrap().len(), 2);
+        assert!(!setup.locks.is_locked(&LockKey::Vote(proposal_id, Principal::from_slice(&[1]))));
+    }
+
+    #[test]
+    fn ended_proposals_approved_by_weight_are_executed_once() {
+        let setup = Setup::new();
+        let approved = setup.propose(ProposalType::FleetUpgrade { kind: WasmKind::Hiving, version: 2 });
+        let declined = setup.propose(ProposalType::Generic);
+        setup.open_voting(approved);
+        setup.vote(1, approved, VoteOption::Approve, 10);
+        setup.vote(2, approved, VoteOption::Decline, 4);
+        setup.vote(3, approved, VoteOption::Decline, 5);
+        setup.vote(1, declined, VoteOption::Approve, 10);
+        setup.vote(2, declined, VoteOption::Decline, 10);
+
+        let service = setup.service();
+        assert!(matches!(service.get_executable_proposal(approved), Err(DaoError::InvalidArgument { .. })));
+        setup.runtime.borrow_mut().time = service.get_proposal(&approved).unwrap().end + 1;
+        assert!(matches!(service.get_executable_proposal(declined), Err(DaoError::InvalidArgument { .. })));
 
-        result
+        let proposal = service.get_executable_proposal(approved).unwrap();
+        service.mark_executed(proposal);
+        assert!(service.get_proposal(&approved).unwrap().executed_on.is_some());
+        assert!(matches!(service.get_executable_proposal(approved), Err(DaoError::InvalidArgument { .. })));
     }
 }
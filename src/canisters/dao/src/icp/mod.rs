@@ -1,3 +1,3 @@
 pub mod service_builder_icp;
 
-mod stable_storage;
+pub(crate) mod stable_storage;
@@ -1,27 +1,51 @@
-use super::stable_storage::{ConfigStorageStable, DiscountStorageStable, HivingStorageStorable, VotingStorageStable};
+use super::stable_storage::{
+    ConfigStorageStable, CycleStorageStable, DiscountStorageStable, EmissionStorageStable, EventStorageStable, FleetStorageStable,
+    HivingStorageStorable, MerchantStorageStable, ReconciliationStorageStable, VoterRewardsStorageStable, VotingStorageStable, WasmStorageStable,
+};
 use crate::app::IConfigStorage;
 use crate::domain::interfaces::storage::*;
+use crate::domain::locks::{InFlightLocks, LockKey};
 use abstractions::nft::NftClient;
-use abstractions::runtime::ICanisterRuntime;
+use abstractions::runtime::{ICanisterManager, ICanisterRuntime};
 use abstractions::token::TokenClient;
 use candid::Principal;
-use canister_runtime::{CdkCallContext, RuntimeIcp};
+use canister_runtime::{CdkCallContext, CdkCanisterManager, RuntimeIcp};
 use std::cell::RefCell;
+use std::collections::BTreeSet;
 use std::rc::Rc;
 
 thread_local! {
     static RUNTIME: Rc<RefCell<dyn ICanisterRuntime>> = Rc::new(RefCell::new(RuntimeIcp::new()));
+    static CANISTER_MANAGER: Rc<dyn ICanisterManager> = Rc::new(CdkCanisterManager);
 
     static VOTING_STORAGE: Rc<RefCell<dyn IVotingStorage>> = Rc::new(RefCell::new(VotingStorageStable::init()));
     static CONFIG_STORAGE: Rc<RefCell<dyn IConfigStorage>> = Rc::new(RefCell::new(ConfigStorageStable::init()));
     static DISCOUNT_STORAGE: Rc<RefCell<dyn IDiscountStorage>> = Rc::new(RefCell::new(DiscountStorageStable::init()));
     static HIVING_STORAGE: Rc<RefCell<dyn IHivingStorage>> = Rc::new(RefCell::new(HivingStorageStorable::init()));
+    static MERCHANT_STORAGE: Rc<RefCell<dyn IMerchantStorage>> = Rc::new(RefCell::new(MerchantStorageStable::init()));
+    static CYCLE_STORAGE: Rc<RefCell<dyn ICycleStorage>> = Rc::new(RefCell::new(CycleStorageStable::init()));
+    static EMISSION_STORAGE: Rc<RefCell<dyn IEmissionStorage>> = Rc::new(RefCell::new(EmissionStorageStable::init()));
+    static EVENT_STORAGE: Rc<RefCell<dyn IEventStorage>> = Rc::new(RefCell::new(EventStorageStable::init()));
+    static VOTER_REWARDS_STORAGE: Rc<RefCell<dyn IVoterRewardsStorage>> = Rc::new(RefCell::new(VoterRewardsStorageStable::init()));
+    static RECONCILIATION_STORAGE: Rc<RefCell<dyn IReconciliationStorage>> = Rc::new(RefCell::new(ReconciliationStorageStable::init()));
+    static WASM_STORAGE: Rc<RefCell<dyn IWasmStorage>> = Rc::new(RefCell::new(WasmStorageStable::init()));
+    static FLEET_STORAGE: Rc<RefCell<dyn IFleetStorage>> = Rc::new(RefCell::new(FleetStorageStable::init()));
+
+    static IN_FLIGHT_LOCKS: Rc<RefCell<BTreeSet<LockKey>>> = Rc::new(RefCell::new(BTreeSet::new()));
 }
 
 pub fn build_runtime() -> Rc<RefCell<dyn ICanisterRuntime>> {
     RUNTIME.with(|rc| rc.clone())
 }
 
+pub fn build_canister_manager() -> Rc<dyn ICanisterManager> {
+    CANISTER_MANAGER.with(|rc| rc.clone())
+}
+
+pub fn build_in_flight_locks() -> InFlightLocks {
+    IN_FLIGHT_LOCKS.with(|rc| InFlightLocks::new(rc.clone()))
+}
+
 pub fn build_voting_storage() -> Rc<RefCell<dyn IVotingStorage>> {
     VOTING_STORAGE.with(|rc| rc.clone())
 }
@@ -38,6 +62,38 @@ pub fn build_hiving_storage() -> Rc<RefCell<dyn IHivingStorage>> {
     HIVING_STORAGE.with(|rc| rc.clone())
 }
 
+pub fn build_merchant_storage() -> Rc<RefCell<dyn IMerchantStorage>> {
+    MERCHANT_STORAGE.with(|rc| rc.clone())
+}
+
+pub fn build_reconciliation_storage() -> Rc<RefCell<dyn IReconciliationStorage>> {
+    RECONCILIATION_STORAGE.with(|rc| rc.clone())
+}
+
+pub fn build_cycle_storage() -> Rc<RefCell<dyn ICycleStorage>> {
+    CYCLE_STORAGE.with(|rc| rc.clone())
+}
+
+pub fn build_emission_storage() -> Rc<RefCell<dyn IEmissionStorage>> {
+    EMISSION_STORAGE.with(|rc| rc.clone())
+}
+
+pub fn build_event_storage() -> Rc<RefCell<dyn IEventStorage>> {
+    EVENT_STORAGE.with(|rc| rc.clone())
+}
+
+pub fn build_voter_rewards_storage() -> Rc<RefCell<dyn IVoterRewardsStorage>> {
+    VOTER_REWARDS_STORAGE.with(|rc| rc.clone())
+}
+
+pub fn build_wasm_storage() -> Rc<RefCell<dyn IWasmStorage>> {
+    WASM_STORAGE.with(|rc| rc.clone())
+}
+
+pub fn build_fleet_storage() -> Rc<RefCell<dyn IFleetStorage>> {
+    FLEET_STORAGE.with(|rc| rc.clone())
+}
+
 pub fn build_token_service(canister_id: Principal) -> Rc<RefCell<TokenClient<CdkCallContext>>> {
     let runtime = CdkCallContext {};
     let client = TokenClient {
@@ -0,0 +1,63 @@
+use candid::Principal;
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::Storable;
+use icrc_ledger_types::icrc1::account::Account;
+use std::borrow::Cow;
+
+/// Account with a fixed-size encoding, usable as an element of composite stable keys
+#[derive(Clone, Ord, PartialOrd, PartialEq, Eq)]
+pub struct BoundedAccount(pub Account);
+
+impl Storable for BoundedAccount {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let Account { owner, subaccount } = &self.0;
+
+        let owner_bytes = owner.as_slice();
+        let mut buf = Vec::with_capacity(100);
+
+        // Encode Principal with fixed length: [len (1 byte)] + [29 bytes max]
+        buf.push(owner_bytes.len() as u8);
+        buf.extend_from_slice(owner_bytes);
+        buf.resize(1 + 29, 0); // pad to 30 total bytes
+
+        // Encode Option<[u8; 32]> as [present (1 byte)] + [32 bytes]
+        match subaccount {
+            Some(sa) => {
+                buf.push(1);
+                buf.extend_from_slice(sa);
+            }
+            None => {
+                buf.push(0);
+                buf.extend_from_slice(&[0u8; 32]);
+            }
+        }
+
+        // Pad remaining to reach 100 bytes if needed
+        buf.resize(100, 0);
+
+        Cow::Owned(buf)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let bytes = bytes.as_ref();
+
+        let owner_len = bytes[0] as usize;
+        let owner = Principal::from_slice(&bytes[1..1 + owner_len]);
+
+        let sub_present = bytes[30] == 1;
+        let mut sub = [0u8; 32];
+        sub.copy_from_slice(&bytes[31..63]);
+
+        let account = Account {
+            owner,
+            subaccount: if sub_present { Some(sub) } else { None },
+        };
+
+        BoundedAccount(account)
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 100,
+        is_fixed_size: true,
+    };
+}
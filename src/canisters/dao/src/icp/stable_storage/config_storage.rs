@@ -1,41 +1,76 @@
 use super::{get_config_memory, IcpMemory};
 use ic_stable_structures::storable::Bound;
 use ic_stable_structures::{StableCell, Storable};
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use crate::app::{AppConfig, IConfigStorage};
 
 pub struct ConfigStorageStable {
-    config: StableCell<AppConfig, IcpMemory>,
+    config: StableCell<VersionedConfig, IcpMemory>,
 }
 
 impl ConfigStorageStable {
     pub fn init() -> Self {
         Self {
-            config: StableCell::init(get_config_memory(), AppConfig::default()).unwrap(),
+            config: StableCell::init(get_config_memory(), VersionedConfig::V1(AppConfig::default())).unwrap(),
         }
     }
 }
 
 impl IConfigStorage for ConfigStorageStable {
     fn set_config(&mut self, config: AppConfig) {
-        self.config.set(config).unwrap();
+        self.config.set(VersionedConfig::V1(config)).unwrap();
     }
 
     fn get_config(&self) -> AppConfig {
-        self.config.get().clone()
+        self.config.get().clone().migrate()
     }
 }
 
-impl Storable for AppConfig {
+/// Stored config layouts. A new variant is added whenever a change of `AppConfig` cannot be
+/// covered by serde defaults, together with the migration from the previous one
+#[derive(Clone, Serialize, Deserialize)]
+enum VersionedConfig {
+    V1(AppConfig),
+}
+
+impl VersionedConfig {
+    fn migrate(self) -> AppConfig {
+        match self {
+            VersionedConfig::V1(config) => config,
+        }
+    }
+}
+
+impl Storable for VersionedConfig {
     fn to_bytes(&self) -> Cow<'_, [u8]> {
         let mut buf = vec![];
         ciborium::ser::into_writer(&self, &mut buf).unwrap();
         Cow::Owned(buf)
     }
 
+    /// configs stored before versioning were a bare `AppConfig`, read as the first version
     fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        ciborium::de::from_reader(bytes.as_ref()).unwrap()
+        ciborium::de::from_reader(bytes.as_ref())
+            .or_else(|_| ciborium::de::from_reader(bytes.as_ref()).map(VersionedConfig::V1))
+            .unwrap()
     }
 
     const BOUND: Bound = Bound::Unbounded;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unversioned_config_is_read_as_first_version() {
+        let mut config = AppConfig::default();
+        config.discounts.discounts_per_cycle = 42;
+        let mut legacy = vec![];
+        ciborium::ser::into_writer(&config, &mut legacy).unwrap();
+
+        let stored = VersionedConfig::from_bytes(Cow::Owned(legacy));
+        assert_eq!(stored.migrate().discounts.discounts_per_cycle, 42);
+    }
+}
@@ -0,0 +1,43 @@
+use crate::domain::cycles::CycleEpoch;
+use crate::domain::interfaces::storage::ICycleStorage;
+use crate::icp::stable_storage::{get_cycle_calendar_memory, IcpMemory};
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{StableCell, Storable};
+use std::borrow::Cow;
+
+pub struct CycleStorageStable {
+    epochs: StableCell<StorableEpochs, IcpMemory>,
+}
+
+impl CycleStorageStable {
+    pub fn init() -> Self {
+        Self {
+            epochs: StableCell::init(get_cycle_calendar_memory(), StorableEpochs(vec![])).unwrap(),
+        }
+    }
+}
+
+impl ICycleStorage for CycleStorageStable {
+    fn get_epochs(&self) -> Vec<CycleEpoch> {
+        self.epochs.get().0.clone()
+    }
+
+    fn set_epochs(&mut self, epochs: Vec<CycleEpoch>) {
+        self.epochs.set(StorableEpochs(epochs)).unwrap();
+    }
+}
+
+struct StorableEpochs(Vec<CycleEpoch>);
+
+impl Storable for StorableEpochs {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let inner: Vec<CycleEpoch> = candid::decode_one(&bytes).unwrap();
+        StorableEpochs(inner)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
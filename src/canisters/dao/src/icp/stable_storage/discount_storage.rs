@@ -1,57 +1,207 @@
 use std::borrow::Cow;
-use ic_stable_structures::{StableBTreeMap, Storable};
+use candid::{CandidType, Deserialize};
+use ic_stable_structures::{StableBTreeMap, StableCell, Storable};
 use ic_stable_structures::storable::Bound;
-use abstractions::dao::Discount;
+use abstractions::dao::{Discount, MintRecord, MintStatus};
+use abstractions::Timestamp;
 use crate::domain::interfaces::storage::*;
+use super::bounded_account::BoundedAccount;
 use super::IcpMemory;
 
 pub struct DiscountStorageStable {
-    cycle_discounts_index: StableBTreeMap<u64, String, IcpMemory>,
+    legacy_cycle_discounts_index: StableBTreeMap<u64, String, IcpMemory>,
+    cycle_discounts_index: StableBTreeMap<(u64, u128), (), IcpMemory>,
+    owner_discounts_index: StableBTreeMap<(BoundedAccount, u128), (), IcpMemory>,
+    hiver_discounts_index: StableBTreeMap<(BoundedAccount, u128), (), IcpMemory>,
     discounts: StableBTreeMap<u128, StorableDiscount, IcpMemory>,
-    account_cycle_index: StableBTreeMap<(BoundedAccount, u64), u128, IcpMemory>
+    account_cycle_index: StableBTreeMap<(BoundedAccount, u64), u128, IcpMemory>,
+    expiry_index: StableBTreeMap<(u64, u128), (), IcpMemory>,
+    mint_records: StableBTreeMap<u64, StorableMintRecord, IcpMemory>,
+    last_mint_key: StableCell<u64, IcpMemory>,
+    /// discount minted for each confirmed mint key
+    minted_keys: StableBTreeMap<u64, u128, IcpMemory>,
+    index_backfill: StableCell<StorableIndexBackfill, IcpMemory>,
 }
 
 impl IDiscountStorage for DiscountStorageStable {
-    fn get_cycle_discounts_ids(&self, cycle_number: u64) -> Vec<u128> {
-        let ids_str = self
-            .cycle_discounts_index
-            .get(&cycle_number)
-            .unwrap_or(String::new());
-
-        if ids_str.is_empty() {
-            return vec![];
+    fn get_discounts_by_cycle(&self, cycle_number: u64, prev: Option<u128>, take: usize) -> Vec<Discount> {
+        self.get_page(&self.cycle_discounts_index, cycle_number, prev, take)
+    }
+
+    fn get_discounts_by_owner(&self, owner: &Account, prev: Option<u128>, take: usize) -> Vec<Discount> {
+        if !self.index_backfill.get().0.done {
+            return self.scan(prev, take, |discount| discount.owner == *owner);
         }
+        self.get_page(&self.owner_discounts_index, BoundedAccount(*owner), prev, take)
+    }
 
-        let result = Self::parse_string_with_ids(ids_str);
-        result
+    fn get_discounts_by_hiver(&self, hiver: &Account, prev: Option<u128>, take: usize) -> Vec<Discount> {
+        if !self.index_backfill.get().0.done {
+            return self.scan(prev, take, |discount| discount.hiver == Some(*hiver));
+        }
+        self.get_page(&self.hiver_discounts_index, BoundedAccount(*hiver), prev, take)
     }
 
     fn add_discount(&mut self, cycle_number: u64, discount: Discount) -> u128 {
-        let id = self.discounts.len() as u128;
+        let id = discount.id;
+        if let Some(expires_on) = discount.expires_on {
+            self.expiry_index.insert((expires_on, id), ());
+        }
+        self.cycle_discounts_index.insert((cycle_number, id), ());
+        self.owner_discounts_index.insert((BoundedAccount(discount.owner), id), ());
+        if let Some(hiver) = discount.hiver {
+            self.hiver_discounts_index.insert((BoundedAccount(hiver), id), ());
+        }
         self.discounts.insert(id, StorableDiscount(discount));
 
-        let old_index = self.cycle_discounts_index.get(&cycle_number);
-        let new_index = match old_index {
-            Some(value) => value.add(&format!(",{id}")),
-            None => id.to_string(),
+        id
+    }
+
+    fn get_discount(&self, id: u128) -> Option<Discount> {
+        self.discounts.get(&id).map(|d| d.0)
+    }
+
+    fn get_discounts(&self, prev: Option<u128>, take: usize) -> Vec<Discount> {
+        let start = match prev {
+            Some(prev) => RangeBound::Excluded(prev),
+            None => RangeBound::Unbounded,
         };
-        self.cycle_discounts_index.insert(cycle_number, new_index);
 
-        id
+        self.discounts
+            .range((start, RangeBound::Unbounded))
+            .take(take)
+            .map(|(_, d)| d.0)
+            .collect()
     }
-    
+
+    fn count_discounts(&self) -> u64 {
+        self.discounts.len()
+    }
+
+    fn update_discount(&mut self, discount: Discount) {
+        let id = discount.id;
+        if let Some(old) = self.discounts.get(&id)
+            && old.owner != discount.owner
+        {
+            self.owner_discounts_index.remove(&(BoundedAccount(old.owner), id));
+            self.owner_discounts_index.insert((BoundedAccount(discount.owner), id), ());
+        }
+        self.discounts.insert(id, StorableDiscount(discount));
+    }
+
+    fn get_expired_discounts(&self, now: Timestamp, limit: usize) -> Vec<(Timestamp, Discount)> {
+        self.expiry_index
+            .range(..=(now, u128::MAX))
+            .take(limit)
+            .filter_map(|((due, id), _)| self.get_discount(id).map(|discount| (due, discount)))
+            .collect()
+    }
+
+    fn remove_from_expiry_index(&mut self, due: Timestamp, discount_id: u128) {
+        self.expiry_index.remove(&(due, discount_id));
+    }
+
+    fn reschedule_expiry(&mut self, due: Timestamp, discount_id: u128, retry_on: Timestamp) {
+        if self.expiry_index.remove(&(due, discount_id)).is_some() {
+            self.expiry_index.insert((retry_on, discount_id), ());
+        }
+    }
+
     fn get_discount_index(&self, account: &Account, cycle_number: u64) -> u128 {
-        let pk = (BoundedAccount(account.clone()), cycle_number);
+        let pk = (BoundedAccount(*account), cycle_number);
         self.account_cycle_index.get(&pk).unwrap_or(0)
     }
 
     fn increase_discount_index(&mut self, account: Account, cycle_number: u64) -> u128 {
-        let pk = (BoundedAccount(account.clone()), cycle_number);
+        let pk = (BoundedAccount(account), cycle_number);
         let current_count = self.account_cycle_index.get(&pk).unwrap_or(0);
         let new_count = current_count + 1;
         self.account_cycle_index.insert(pk, current_count + 1);
         new_count
     }
+
+    fn decrease_discount_index(&mut self, account: Account, cycle_number: u64) -> u128 {
+        let pk = (BoundedAccount(account), cycle_number);
+        let current_count = self.account_cycle_index.get(&pk).unwrap_or(0);
+        let new_count = current_count.saturating_sub(1);
+        self.account_cycle_index.insert(pk, new_count);
+        new_count
+    }
+
+    fn backfill_indexes(&mut self, limit: usize) -> bool {
+        let mut backfill = self.index_backfill.get().0.clone();
+        if backfill.done {
+            return true;
+        }
+
+        // the legacy cycle index is moved over first, a cycle at a time
+        let mut budget = limit;
+        while budget > 0
+            && let Some((cycle_number, ids)) = self.legacy_cycle_discounts_index.pop_first()
+        {
+            for id in ids.split(',').filter_map(|id| id.parse::<u128>().ok()) {
+                self.cycle_discounts_index.insert((cycle_number, id), ());
+            }
+            budget -= 1;
+        }
+
+        let start = match backfill.last_indexed {
+            Some(prev) => RangeBound::Excluded(prev),
+            None => RangeBound::Unbounded,
+        };
+        let page: Vec<(u128, Discount)> = self.discounts.range((start, RangeBound::Unbounded)).take(budget).map(|(id, d)| (id, d.0)).collect();
+        for (id, discount) in page.iter() {
+            self.owner_discounts_index.insert((BoundedAccount(discount.owner), *id), ());
+            if let Some(hiver) = discount.hiver {
+                self.hiver_discounts_index.insert((BoundedAccount(hiver), *id), ());
+            }
+        }
+
+        backfill.done = page.len() < budget;
+        backfill.last_indexed = page.last().map(|(id, _)| *id).or(backfill.last_indexed);
+        self.index_backfill.set(StorableIndexBackfill(backfill.clone())).unwrap();
+
+        backfill.done
+    }
+
+    fn next_mint_key(&mut self) -> u64 {
+        let key = self.last_mint_key.get() + 1;
+        self.last_mint_key.set(key).unwrap();
+        key
+    }
+
+    fn save_mint_record(&mut self, record: MintRecord) {
+        self.mint_records.insert(record.key, StorableMintRecord(record));
+    }
+
+    fn remove_mint_record(&mut self, key: u64) {
+        self.mint_records.remove(&key);
+    }
+
+    fn get_mint_record(&self, key: u64) -> Option<MintRecord> {
+        self.mint_records.get(&key).map(|r| r.0)
+    }
+
+    fn save_minted_key(&mut self, key: u64, discount_id: u128) {
+        self.minted_keys.insert(key, discount_id);
+    }
+
+    fn get_minted_key(&self, key: u64) -> Option<u128> {
+        self.minted_keys.get(&key)
+    }
+
+    fn get_mint_records(&self, status: MintStatus, limit: usize) -> Vec<MintRecord> {
+        self.mint_records
+            .values()
+            .map(|r| r.0)
+            .filter(|r| r.status == status)
+            .take(limit)
+            .collect()
+    }
+
+    fn count_mint_records(&self, status: MintStatus) -> u64 {
+        self.mint_records.values().filter(|r| r.0.status == status).count() as u64
+    }
 }
 
 pub struct StorableDiscount(pub Discount);
@@ -69,30 +219,73 @@ impl Storable for StorableDiscount {
     const BOUND: Bound = Bound::Unbounded;
 }
 
+struct StorableMintRecord(pub MintRecord);
+
+impl Storable for StorableMintRecord {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let inner: MintRecord = candid::decode_one(&bytes).unwrap();
+        StorableMintRecord(inner)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
 impl DiscountStorageStable {
     pub fn init() -> Self {
         Self {
+            legacy_cycle_discounts_index: StableBTreeMap::init(super::get_legacy_cycle_discounts_index_memory()),
             cycle_discounts_index: StableBTreeMap::init(super::get_cycle_discounts_index_memory()),
+            owner_discounts_index: StableBTreeMap::init(super::get_owner_discounts_index_memory()),
+            hiver_discounts_index: StableBTreeMap::init(super::get_hiver_discounts_index_memory()),
             discounts: StableBTreeMap::init(super::get_discounts_memory()),
-            account_cycle_index: StableBTreeMap::init(super::get_account_cycles_index_memory())
+            account_cycle_index: StableBTreeMap::init(super::get_account_cycles_index_memory()),
+            expiry_index: StableBTreeMap::init(super::get_discounts_expiry_index_memory()),
+            mint_records: StableBTreeMap::init(super::get_mint_records_memory()),
+            last_mint_key: StableCell::init(super::get_mint_key_memory(), 0).unwrap(),
+            minted_keys: StableBTreeMap::init(super::get_minted_keys_memory()),
+            index_backfill: StableCell::init(super::get_index_backfill_memory(), StorableIndexBackfill::default()).unwrap(),
         }
     }
 
-    fn parse_string_with_ids(ids_str: String) -> Vec<u128> {
-        let mut result = Vec::new();
+    /// reads a page of the discounts matching the filter, starting after prev, without an index
+    fn scan(&self, prev: Option<u128>, take: usize, filter: impl Fn(&Discount) -> bool) -> Vec<Discount> {
+        let start = match prev {
+            Some(prev) => RangeBound::Excluded(prev),
+            None => RangeBound::Unbounded,
+        };
+
+        self.discounts
+            .range((start, RangeBound::Unbounded))
+            .map(|(_, d)| d.0)
+            .filter(|discount| filter(discount))
+            .take(take)
+            .collect()
+    }
 
-        let split =  ids_str.split(",");
-        for id_str in split.into_iter() {
-            let id = u128::from_str_radix(id_str, 10).unwrap();
-            result.push(id);
-        }
+    /// reads a page of discounts from an index keyed by (prefix, discount id), starting after prev
+    fn get_page<P>(&self, index: &StableBTreeMap<(P, u128), (), IcpMemory>, prefix: P, prev: Option<u128>, take: usize) -> Vec<Discount>
+    where
+        P: Storable + Ord + Clone,
+    {
+        let start = match prev {
+            Some(prev) => RangeBound::Excluded((prefix.clone(), prev)),
+            None => RangeBound::Included((prefix.clone(), 0)),
+        };
+        let end = RangeBound::Included((prefix, u128::MAX));
 
-        result
+        index
+            .range((start, end))
+            .take(take)
+            .filter_map(|((_, id), _)| self.get_discount(id))
+            .collect()
     }
 }
 
-use std::ops::{Add, Deref, DerefMut};
-use candid::Principal;
+use std::ops::{Bound as RangeBound, Deref, DerefMut};
 use icrc_ledger_types::icrc1::account::Account;
 
 impl Deref for StorableDiscount {
@@ -109,59 +302,89 @@ impl DerefMut for StorableDiscount {
     }
 }
 
-#[derive(Clone, Ord, PartialOrd, PartialEq, Eq)]
-struct BoundedAccount(pub Account);
-
-impl Storable for BoundedAccount {
-    fn to_bytes(&self) -> Cow<'_, [u8]> {
-        let Account { owner, subaccount } = &self.0;
-
-        let owner_bytes = owner.as_slice();
-        let mut buf = Vec::with_capacity(100);
-
-        // Encode Principal with fixed length: [len (1 byte)] + [29 bytes max]
-        buf.push(owner_bytes.len() as u8);
-        buf.extend_from_slice(owner_bytes);
-        buf.resize(1 + 29, 0); // pad to 30 total bytes
-
-        // Encode Option<[u8; 32]> as [present (1 byte)] + [32 bytes]
-        match subaccount {
-            Some(sa) => {
-                buf.push(1);
-                buf.extend_from_slice(sa);
-            }
-            None => {
-                buf.push(0);
-                buf.extend_from_slice(&[0u8; 32]);
-            }
-        }
+/// progress of indexing the discounts stored before the composite-key indexes existed
+#[derive(Clone, Default, CandidType, Deserialize)]
+struct IndexBackfill {
+    last_indexed: Option<u128>,
+    done: bool,
+}
 
-        // Pad remaining to reach 100 bytes if needed
-        buf.resize(100, 0);
+#[derive(Default)]
+struct StorableIndexBackfill(IndexBackfill);
 
-        Cow::Owned(buf)
+impl Storable for StorableIndexBackfill {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
     }
 
     fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        let bytes = bytes.as_ref();
+        StorableIndexBackfill(candid::decode_one(&bytes).unwrap())
+    }
 
-        let owner_len = bytes[0] as usize;
-        let owner = Principal::from_slice(&bytes[1..1 + owner_len]);
+    const BOUND: Bound = Bound::Unbounded;
+}
 
-        let sub_present = bytes[30] == 1;
-        let mut sub = [0u8; 32];
-        sub.copy_from_slice(&bytes[31..63]);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Principal;
 
-        let account = Account {
-            owner,
-            subaccount: if sub_present { Some(sub) } else { None },
-        };
+    fn discount(id: u128, owner: Account, hiver: Account) -> Discount {
+        let mut discount = Discount::new(id, 10.0, owner);
+        discount.hiver = Some(hiver);
+        discount
+    }
 
-        BoundedAccount(account)
+    fn ids(discounts: Vec<Discount>) -> Vec<u128> {
+        discounts.iter().map(|d| d.id).collect()
     }
 
-    const BOUND: Bound = Bound::Bounded {
-        max_size: 100,
-        is_fixed_size: true,
-    };
+    #[test]
+    fn indexes_are_paginated_per_prefix() {
+        let alice = Account::from(Principal::from_slice(&[1]));
+        let bob = Account::from(Principal::from_slice(&[2]));
+        let hiver = Account::from(Principal::from_slice(&[3]));
+
+        let mut storage = DiscountStorageStable::init();
+        storage.add_discount(1, discount(10, alice, hiver));
+        storage.add_discount(1, discount(11, bob, hiver));
+        storage.add_discount(2, discount(12, alice, hiver));
+        storage.add_discount(2, discount(13, alice, bob));
+
+        assert_eq!(ids(storage.get_discounts_by_cycle(1, None, 10)), vec![10, 11]);
+        assert_eq!(ids(storage.get_discounts_by_cycle(2, None, 1)), vec![12]);
+        assert_eq!(ids(storage.get_discounts_by_cycle(2, Some(12), 1)), vec![13]);
+        assert_eq!(ids(storage.get_discounts_by_owner(&alice, None, 10)), vec![10, 12, 13]);
+        assert_eq!(ids(storage.get_discounts_by_hiver(&hiver, Some(10), 10)), vec![11, 12]);
+
+        let mut transferred = storage.get_discount(10).unwrap();
+        transferred.owner = bob;
+        storage.update_discount(transferred);
+
+        assert_eq!(ids(storage.get_discounts_by_owner(&alice, None, 10)), vec![12, 13]);
+        assert_eq!(ids(storage.get_discounts_by_owner(&bob, None, 10)), vec![10, 11]);
+    }
+
+    #[test]
+    fn discounts_stored_before_the_indexes_are_backfilled() {
+        let alice = Account::from(Principal::from_slice(&[1]));
+        let hiver = Account::from(Principal::from_slice(&[3]));
+
+        let mut storage = DiscountStorageStable::init();
+        storage.discounts.insert(20, StorableDiscount(discount(20, alice, hiver)));
+        storage.discounts.insert(21, StorableDiscount(Discount::new(21, 10.0, alice)));
+        storage.legacy_cycle_discounts_index.insert(5, "20,21".to_string());
+
+        // listings scan the discounts until the backfill is done
+        assert_eq!(ids(storage.get_discounts_by_owner(&alice, Some(20), 10)), vec![21]);
+        assert_eq!(ids(storage.get_discounts_by_hiver(&hiver, None, 10)), vec![20]);
+
+        let runs = std::iter::repeat_with(|| storage.backfill_indexes(1)).take_while(|done| !done).count();
+        assert_eq!(runs, 3);
+        assert!(storage.owner_discounts_index.contains_key(&(BoundedAccount(alice), 21)));
+        assert_eq!(ids(storage.get_discounts_by_cycle(5, None, 10)), vec![20, 21]);
+        assert_eq!(ids(storage.get_discounts_by_owner(&alice, None, 10)), vec![20, 21]);
+        assert_eq!(ids(storage.get_discounts_by_hiver(&hiver, None, 10)), vec![20]);
+        assert!(storage.legacy_cycle_discounts_index.is_empty());
+    }
 }
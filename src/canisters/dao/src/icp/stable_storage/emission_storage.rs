@@ -0,0 +1,136 @@
+use super::bounded_account::BoundedAccount;
+use crate::domain::emission::{PendingClaim, ScoreSnapshot, StakeClaim};
+use crate::domain::interfaces::storage::IEmissionStorage;
+use crate::icp::stable_storage::{
+    get_cycle_emissions_memory, get_pending_claims_memory, get_score_snapshot_memory, get_stake_claims_memory, IcpMemory,
+};
+use abstractions::dao::CycleEmission;
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{StableBTreeMap, StableCell, Storable};
+use icrc_ledger_types::icrc1::account::Account;
+use std::borrow::Cow;
+
+pub struct EmissionStorageStable {
+    emissions: StableBTreeMap<u64, StorableCycleEmission, IcpMemory>,
+    claims: StableBTreeMap<(BoundedAccount, u64), StorableStakeClaim, IcpMemory>,
+    snapshot: StableCell<StorableScoreSnapshot, IcpMemory>,
+    pending_claims: StableBTreeMap<BoundedAccount, StorablePendingClaim, IcpMemory>,
+}
+
+impl EmissionStorageStable {
+    pub fn init() -> Self {
+        Self {
+            emissions: StableBTreeMap::init(get_cycle_emissions_memory()),
+            claims: StableBTreeMap::init(get_stake_claims_memory()),
+            snapshot: StableCell::init(get_score_snapshot_memory(), StorableScoreSnapshot(ScoreSnapshot::default())).unwrap(),
+            pending_claims: StableBTreeMap::init(get_pending_claims_memory()),
+        }
+    }
+}
+
+impl IEmissionStorage for EmissionStorageStable {
+    fn get_cycle_emission(&self, cycle_number: u64) -> Option<CycleEmission> {
+        self.emissions.get(&cycle_number).map(|e| e.0)
+    }
+
+    fn save_cycle_emission(&mut self, cycle_number: u64, emission: CycleEmission) {
+        self.emissions.insert(cycle_number, StorableCycleEmission(emission));
+    }
+
+    fn get_claim(&self, account: Account, cycle_number: u64) -> Option<StakeClaim> {
+        self.claims.get(&(BoundedAccount(account), cycle_number)).map(|c| c.0)
+    }
+
+    fn save_claim(&mut self, account: Account, cycle_number: u64, claim: StakeClaim) {
+        self.claims.insert((BoundedAccount(account), cycle_number), StorableStakeClaim(claim));
+    }
+
+    fn get_unpaid_claims(&self, account: Account, before_cycle: u64) -> Vec<(u64, StakeClaim)> {
+        let account = BoundedAccount(account);
+        self.claims
+            .range((account.clone(), 0)..(account, before_cycle))
+            .map(|((_, cycle_number), claim)| (cycle_number, claim.0))
+            .filter(|(_, claim)| !claim.paid)
+            .collect()
+    }
+
+    fn get_snapshot(&self) -> ScoreSnapshot {
+        self.snapshot.get().0.clone()
+    }
+
+    fn save_snapshot(&mut self, snapshot: ScoreSnapshot) {
+        self.snapshot.set(StorableScoreSnapshot(snapshot)).unwrap();
+    }
+
+    fn get_pending_claim(&self, account: Account) -> Option<PendingClaim> {
+        self.pending_claims.get(&BoundedAccount(account)).map(|c| c.0)
+    }
+
+    fn save_pending_claim(&mut self, account: Account, claim: PendingClaim) {
+        self.pending_claims.insert(BoundedAccount(account), StorablePendingClaim(claim));
+    }
+
+    fn remove_pending_claim(&mut self, account: Account) {
+        self.pending_claims.remove(&BoundedAccount(account));
+    }
+}
+
+struct StorableCycleEmission(CycleEmission);
+
+impl Storable for StorableCycleEmission {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let inner: CycleEmission = candid::decode_one(&bytes).unwrap();
+        StorableCycleEmission(inner)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+struct StorableStakeClaim(StakeClaim);
+
+impl Storable for StorableStakeClaim {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let inner: StakeClaim = candid::decode_one(&bytes).unwrap();
+        StorableStakeClaim(inner)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+struct StorableScoreSnapshot(ScoreSnapshot);
+
+impl Storable for StorableScoreSnapshot {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let inner: ScoreSnapshot = candid::decode_one(&bytes).unwrap();
+        StorableScoreSnapshot(inner)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+struct StorablePendingClaim(PendingClaim);
+
+impl Storable for StorablePendingClaim {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let inner: PendingClaim = candid::decode_one(&bytes).unwrap();
+        StorablePendingClaim(inner)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
@@ -0,0 +1,47 @@
+use crate::domain::interfaces::storage::IEventStorage;
+use crate::icp::stable_storage::{get_events_data_memory, get_events_index_memory, IcpMemory};
+use abstractions::dao::DaoEventRecord;
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{StableLog, Storable};
+use std::borrow::Cow;
+
+pub struct EventStorageStable {
+    log: StableLog<DaoEventRecordStorable, IcpMemory, IcpMemory>,
+}
+
+impl EventStorageStable {
+    pub fn init() -> Self {
+        Self {
+            log: StableLog::init(get_events_index_memory(), get_events_data_memory()).expect("log initialization failed"),
+        }
+    }
+}
+
+impl IEventStorage for EventStorageStable {
+    fn append_event(&mut self, record: &DaoEventRecord) {
+        self.log.append(&DaoEventRecordStorable(record.clone())).expect("log append failed");
+    }
+
+    fn get_event(&self, id: u64) -> Option<DaoEventRecord> {
+        self.log.get(id).map(|record| record.0)
+    }
+
+    fn count_events(&self) -> u64 {
+        self.log.len()
+    }
+}
+
+struct DaoEventRecordStorable(DaoEventRecord);
+
+impl Storable for DaoEventRecordStorable {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let inner: DaoEventRecord = candid::decode_one(&bytes).unwrap();
+        DaoEventRecordStorable(inner)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
@@ -0,0 +1,100 @@
+use super::wasm_storage::kind_key;
+use crate::domain::interfaces::storage::IFleetStorage;
+use crate::icp::stable_storage::{get_canister_upgrades_memory, get_fleet_upgrades_memory, IcpMemory};
+use abstractions::dao::{CanisterUpgrade, CanisterUpgradeStatus, FleetUpgrade, WasmKind};
+use candid::Principal;
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{StableBTreeMap, Storable};
+use std::borrow::Cow;
+use std::ops::Bound as RangeBound;
+
+pub struct FleetStorageStable {
+    upgrades: StableBTreeMap<u8, StorableFleetUpgrade, IcpMemory>,
+    canister_upgrades: StableBTreeMap<(u8, Principal), StorableCanisterUpgrade, IcpMemory>,
+}
+
+impl FleetStorageStable {
+    pub fn init() -> Self {
+        Self {
+            upgrades: StableBTreeMap::init(get_fleet_upgrades_memory()),
+            canister_upgrades: StableBTreeMap::init(get_canister_upgrades_memory()),
+        }
+    }
+
+    fn kind_range(&self, kind: WasmKind, prev: Option<Principal>) -> impl Iterator<Item = CanisterUpgrade> + '_ {
+        let kind = kind_key(kind);
+        let start = match prev {
+            Some(prev) => RangeBound::Excluded((kind, prev)),
+            None => RangeBound::Included((kind, Principal::from_slice(&[]))),
+        };
+
+        self.canister_upgrades
+            .range((start, RangeBound::Unbounded))
+            .take_while(move |((key_kind, _), _)| *key_kind == kind)
+            .map(|(_, upgrade)| upgrade.0)
+    }
+}
+
+impl IFleetStorage for FleetStorageStable {
+    fn get_upgrade(&self, kind: WasmKind) -> Option<FleetUpgrade> {
+        self.upgrades.get(&kind_key(kind)).map(|u| u.0)
+    }
+
+    fn save_upgrade(&mut self, upgrade: FleetUpgrade) {
+        self.upgrades.insert(kind_key(upgrade.kind), StorableFleetUpgrade(upgrade));
+    }
+
+    fn get_canister_upgrade(&self, kind: WasmKind, canister_id: Principal) -> Option<CanisterUpgrade> {
+        self.canister_upgrades.get(&(kind_key(kind), canister_id)).map(|u| u.0)
+    }
+
+    fn save_canister_upgrade(&mut self, kind: WasmKind, upgrade: CanisterUpgrade) {
+        self.canister_upgrades.insert((kind_key(kind), upgrade.canister_id), StorableCanisterUpgrade(upgrade));
+    }
+
+    fn get_canister_upgrades(&self, kind: WasmKind, prev: Option<Principal>, take: usize) -> Vec<CanisterUpgrade> {
+        self.kind_range(kind, prev).take(take).collect()
+    }
+
+    fn get_pending_upgrades(&self, kind: WasmKind, take: usize) -> Vec<CanisterUpgrade> {
+        self.kind_range(kind, None)
+            .filter(|upgrade| upgrade.status == CanisterUpgradeStatus::Pending)
+            .take(take)
+            .collect()
+    }
+
+    fn clear_canister_upgrades(&mut self, kind: WasmKind) {
+        let keys: Vec<_> = self.kind_range(kind, None).map(|upgrade| (kind_key(kind), upgrade.canister_id)).collect();
+        for key in keys {
+            self.canister_upgrades.remove(&key);
+        }
+    }
+}
+
+struct StorableFleetUpgrade(FleetUpgrade);
+
+impl Storable for StorableFleetUpgrade {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        StorableFleetUpgrade(candid::decode_one(&bytes).unwrap())
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+struct StorableCanisterUpgrade(CanisterUpgrade);
+
+impl Storable for StorableCanisterUpgrade {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        StorableCanisterUpgrade(candid::decode_one(&bytes).unwrap())
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
@@ -1,36 +1,114 @@
+use super::bounded_account::BoundedAccount;
 use crate::domain::interfaces::storage::*;
-use crate::icp::stable_storage::{get_wallet_usages_memory, IcpMemory};
+use crate::icp::stable_storage::{
+    get_departed_canisters_memory, get_eviction_cursor_memory, get_hiver_bindings_memory, get_hiving_canisters_memory, get_wallet_usages_memory, IcpMemory,
+};
+use abstractions::dao::{HivingCanister, HivingCanisterStatus};
 use candid::Principal;
-use ic_stable_structures::{StableBTreeMap};
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{StableBTreeMap, StableCell, Storable};
 use icrc_ledger_types::icrc1::account::Account;
+use std::borrow::Cow;
+use std::ops::Bound as RangeBound;
 
 pub struct HivingStorageStorable {
-    wallet_usages: StableBTreeMap<(u64, Account), u32, IcpMemory>,
-    hiving_canisters: StableBTreeMap<Principal, (), IcpMemory>,
+    wallet_usages: StableBTreeMap<(u64, BoundedAccount), u32, IcpMemory>,
+    hiving_canisters: StableBTreeMap<Principal, StorableHivingCanister, IcpMemory>,
+    hiver_bindings: StableBTreeMap<(Principal, BoundedAccount), (), IcpMemory>,
+    /// status of the canisters which left, restored when they join again
+    departed_canisters: StableBTreeMap<Principal, StorableStatus, IcpMemory>,
+    eviction_cursor: StableCell<StorableCursor, IcpMemory>,
 }
 
 impl IHivingStorage for HivingStorageStorable {
-    fn add_hiving_canister(&mut self, canister_id: Principal) {
-        self.hiving_canisters.insert(canister_id, ());
+    fn add_hiving_canister(&mut self, canister: HivingCanister) {
+        self.hiving_canisters.insert(canister.canister_id, StorableHivingCanister(canister));
     }
 
-    fn remove_hiving_canister(&mut self, canister_id: Principal) {
-        self.hiving_canisters.remove(&canister_id).unwrap();
+    fn update_hiving_canister(&mut self, canister: HivingCanister) {
+        self.hiving_canisters.insert(canister.canister_id, StorableHivingCanister(canister));
     }
 
-    fn get_hiving_canisters(&self) -> Vec<Principal> {
-        todo!()
+    fn remove_hiving_canister(&mut self, canister_id: &Principal) -> Option<HivingCanister> {
+        self.hiving_canisters.remove(canister_id).map(|c| c.0)
+    }
+
+    fn get_hiving_canister(&self, canister_id: &Principal) -> Option<HivingCanister> {
+        self.hiving_canisters.get(canister_id).map(|c| c.0)
+    }
+
+    fn get_hiving_canisters(&self, status: Option<HivingCanisterStatus>, prev: Option<Principal>, take: usize) -> Vec<HivingCanister> {
+        let start = match prev {
+            Some(prev) => RangeBound::Excluded(prev),
+            None => RangeBound::Unbounded,
+        };
+
+        self.hiving_canisters
+            .range((start, RangeBound::Unbounded))
+            .map(|(_, c)| c.0)
+            .filter(|c| status.as_ref().is_none_or(|status| &c.status == status))
+            .take(take)
+            .collect()
+    }
+
+    fn add_hiver_binding(&mut self, canister_id: Principal, hiver: Account) {
+        self.hiver_bindings.insert((canister_id, BoundedAccount(hiver)), ());
+    }
+
+    fn remove_hiver_binding(&mut self, canister_id: Principal, hiver: Account) {
+        self.hiver_bindings.remove(&(canister_id, BoundedAccount(hiver)));
+    }
+
+    fn has_hiver_binding(&self, canister_id: Principal, hiver: Account) -> bool {
+        self.hiver_bindings.contains_key(&(canister_id, BoundedAccount(hiver)))
+    }
+
+    fn remove_hiver_bindings(&mut self, canister_id: Principal) -> Vec<Account> {
+        let first = BoundedAccount(Account { owner: Principal::from_slice(&[]), subaccount: None });
+        let hivers: Vec<Account> = self
+            .hiver_bindings
+            .range((canister_id, first)..)
+            .take_while(|((id, _), _)| *id == canister_id)
+            .map(|((_, hiver), _)| hiver.0)
+            .collect();
+        for hiver in hivers.iter() {
+            self.hiver_bindings.remove(&(canister_id, BoundedAccount(*hiver)));
+        }
+
+        hivers
+    }
+
+    fn save_departed_status(&mut self, canister_id: Principal, status: HivingCanisterStatus) {
+        self.departed_canisters.insert(canister_id, StorableStatus(status));
+    }
+
+    fn take_departed_status(&mut self, canister_id: &Principal) -> Option<HivingCanisterStatus> {
+        self.departed_canisters.remove(canister_id).map(|s| s.0)
+    }
+
+    fn get_eviction_cursor(&self) -> Option<Principal> {
+        self.eviction_cursor.get().0
+    }
+
+    fn set_eviction_cursor(&mut self, cursor: Option<Principal>) {
+        self.eviction_cursor.set(StorableCursor(cursor)).unwrap();
     }
 
     fn add_wallet_usage_per_cycle(&mut self, cycle_number: u64, wallet: Account) -> u32 {
         let current_usage = self.get_wallet_usage_per_cycle(cycle_number, wallet);
         let new_usage = current_usage + 1;
-        self.wallet_usages.insert((cycle_number, wallet), current_usage + 1);
+        self.wallet_usages.insert((cycle_number, BoundedAccount(wallet)), current_usage + 1);
         new_usage
     }
 
     fn get_wallet_usage_per_cycle(&self, cycle_number: u64, wallet: Account) -> u32 {
-        self.wallet_usages.get(&(cycle_number, wallet)).unwrap_or(0)
+        self.wallet_usages.get(&(cycle_number, BoundedAccount(wallet))).unwrap_or(0)
+    }
+
+    fn remove_wallet_usage_per_cycle(&mut self, cycle_number: u64, wallet: Account) -> u32 {
+        let new_usage = self.get_wallet_usage_per_cycle(cycle_number, wallet).saturating_sub(1);
+        self.wallet_usages.insert((cycle_number, BoundedAccount(wallet)), new_usage);
+        new_usage
     }
 }
 
@@ -38,7 +116,53 @@ impl HivingStorageStorable {
     pub fn init() -> Self {
         Self {
             wallet_usages: StableBTreeMap::init(get_wallet_usages_memory()),
-            hiving_canisters: StableBTreeMap::init(get_wallet_usages_memory()),
+            hiving_canisters: StableBTreeMap::init(get_hiving_canisters_memory()),
+            hiver_bindings: StableBTreeMap::init(get_hiver_bindings_memory()),
+            departed_canisters: StableBTreeMap::init(get_departed_canisters_memory()),
+            eviction_cursor: StableCell::init(get_eviction_cursor_memory(), StorableCursor(None)).unwrap(),
         }
     }
 }
+
+struct StorableHivingCanister(pub HivingCanister);
+
+impl Storable for StorableHivingCanister {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let inner: HivingCanister = candid::decode_one(&bytes).unwrap();
+        StorableHivingCanister(inner)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+struct StorableStatus(pub HivingCanisterStatus);
+
+impl Storable for StorableStatus {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        StorableStatus(candid::decode_one(&bytes).unwrap())
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+struct StorableCursor(pub Option<Principal>);
+
+impl Storable for StorableCursor {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        StorableCursor(candid::decode_one(&bytes).unwrap())
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
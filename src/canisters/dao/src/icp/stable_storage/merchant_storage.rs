@@ -0,0 +1,52 @@
+use crate::domain::interfaces::storage::IMerchantStorage;
+use crate::icp::stable_storage::{get_merchants_memory, IcpMemory};
+use abstractions::dao::Merchant;
+use candid::Principal;
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{StableBTreeMap, Storable};
+use std::borrow::Cow;
+
+pub struct MerchantStorageStable {
+    merchants: StableBTreeMap<Principal, StorableMerchant, IcpMemory>,
+}
+
+impl MerchantStorageStable {
+    pub fn init() -> Self {
+        Self {
+            merchants: StableBTreeMap::init(get_merchants_memory()),
+        }
+    }
+}
+
+impl IMerchantStorage for MerchantStorageStable {
+    fn add_merchant(&mut self, merchant: Merchant) {
+        self.merchants.insert(merchant.id, StorableMerchant(merchant));
+    }
+
+    fn remove_merchant(&mut self, id: &Principal) -> Option<Merchant> {
+        self.merchants.remove(id).map(|m| m.0)
+    }
+
+    fn get_merchant(&self, id: &Principal) -> Option<Merchant> {
+        self.merchants.get(id).map(|m| m.0)
+    }
+
+    fn get_merchants(&self) -> Vec<Merchant> {
+        self.merchants.values().map(|m| m.0).collect()
+    }
+}
+
+struct StorableMerchant(pub Merchant);
+
+impl Storable for StorableMerchant {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let inner: Merchant = candid::decode_one(&bytes).unwrap();
+        StorableMerchant(inner)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
@@ -1,12 +1,29 @@
+mod bounded_account;
 mod config_storage;
+mod cycle_storage;
 mod discount_storage;
+mod emission_storage;
+mod event_storage;
+mod fleet_storage;
 mod hiving_storage;
+mod merchant_storage;
+mod reconciliation_storage;
+mod voter_rewards_storage;
 mod voting_storage;
+mod wasm_storage;
 
 pub use config_storage::ConfigStorageStable;
+pub use cycle_storage::CycleStorageStable;
 pub use discount_storage::DiscountStorageStable;
+pub use emission_storage::EmissionStorageStable;
+pub use event_storage::EventStorageStable;
+pub use fleet_storage::FleetStorageStable;
 pub use voting_storage::VotingStorageStable;
 pub use hiving_storage::HivingStorageStorable;
+pub use merchant_storage::MerchantStorageStable;
+pub use reconciliation_storage::ReconciliationStorageStable;
+pub use voter_rewards_storage::VoterRewardsStorageStable;
+pub use wasm_storage::WasmStorageStable;
 
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
 use ic_stable_structures::DefaultMemoryImpl;
@@ -18,10 +35,42 @@ const PROPOSALS_MEMORY_ID: MemoryId = MemoryId::new(1);
 const VOTES_MEMORY_ID: MemoryId = MemoryId::new(2);
 const CONFIG_MEMORY_ID: MemoryId = MemoryId::new(3);
 const WALLET_USAGES_MEMORY_ID: MemoryId = MemoryId::new(5);
-const CYCLE_DISCOUNTS_INDEX_MEMORY_ID: MemoryId = MemoryId::new(6);
+// 6 holds the former comma-joined cycle index, which is only read to backfill the composite-key indexes
+const LEGACY_CYCLE_DISCOUNTS_INDEX_MEMORY_ID: MemoryId = MemoryId::new(6);
 const DISCOUNTS_MEMORY_ID: MemoryId = MemoryId::new(7);
 const ACCOUNT_CYCLE_INDEX_MEMORY_ID: MemoryId = MemoryId::new(8);
 const HIVING_CANISTERS_MEMORY_ID: MemoryId = MemoryId::new(9);
+const MERCHANTS_MEMORY_ID: MemoryId = MemoryId::new(10);
+const DISCOUNTS_EXPIRY_INDEX_MEMORY_ID: MemoryId = MemoryId::new(11);
+const HIVER_BINDINGS_MEMORY_ID: MemoryId = MemoryId::new(12);
+const OWNER_DISCOUNTS_INDEX_MEMORY_ID: MemoryId = MemoryId::new(13);
+const HIVER_DISCOUNTS_INDEX_MEMORY_ID: MemoryId = MemoryId::new(14);
+const CYCLE_DISCOUNTS_INDEX_MEMORY_ID: MemoryId = MemoryId::new(15);
+const MINT_RECORDS_MEMORY_ID: MemoryId = MemoryId::new(16);
+const MINT_KEY_MEMORY_ID: MemoryId = MemoryId::new(17);
+const RECONCILIATION_MEMORY_ID: MemoryId = MemoryId::new(18);
+const CYCLE_CALENDAR_MEMORY_ID: MemoryId = MemoryId::new(19);
+const EVENTS_INDEX_MEMORY_ID: MemoryId = MemoryId::new(20);
+const EVENTS_DATA_MEMORY_ID: MemoryId = MemoryId::new(21);
+const CYCLE_EMISSIONS_MEMORY_ID: MemoryId = MemoryId::new(22);
+const STAKE_CLAIMS_MEMORY_ID: MemoryId = MemoryId::new(23);
+const VOTER_REWARDS_CURSOR_MEMORY_ID: MemoryId = MemoryId::new(24);
+const VOTER_BALANCES_MEMORY_ID: MemoryId = MemoryId::new(25);
+const VOTER_REWARD_ALLOCATIONS_MEMORY_ID: MemoryId = MemoryId::new(26);
+const WASM_CHUNKS_MEMORY_ID: MemoryId = MemoryId::new(27);
+const WASM_MODULES_MEMORY_ID: MemoryId = MemoryId::new(28);
+const FLEET_UPGRADES_MEMORY_ID: MemoryId = MemoryId::new(29);
+const CANISTER_UPGRADES_MEMORY_ID: MemoryId = MemoryId::new(30);
+const SCORE_SNAPSHOT_MEMORY_ID: MemoryId = MemoryId::new(31);
+const PENDING_CLAIMS_MEMORY_ID: MemoryId = MemoryId::new(32);
+const VOTER_PAYOUTS_MEMORY_ID: MemoryId = MemoryId::new(33);
+const MINTED_KEYS_MEMORY_ID: MemoryId = MemoryId::new(34);
+const SPARE_CANISTERS_MEMORY_ID: MemoryId = MemoryId::new(35);
+const SPAWNED_CANISTERS_MEMORY_ID: MemoryId = MemoryId::new(36);
+const DEPARTED_CANISTERS_MEMORY_ID: MemoryId = MemoryId::new(37);
+const EVICTION_CURSOR_MEMORY_ID: MemoryId = MemoryId::new(38);
+const INDEX_BACKFILL_MEMORY_ID: MemoryId = MemoryId::new(39);
+const SPAWN_ALLOWANCES_MEMORY_ID: MemoryId = MemoryId::new(40);
 
 thread_local! {
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
@@ -46,13 +95,89 @@ fn get_wallet_usages_memory() -> IcpMemory {
 }
 
 fn get_hiving_canisters_memory() -> IcpMemory {
-    MEMORY_MANAGER.with(|m| m.borrow().get(WALLET_USAGES_MEMORY_ID))
+    MEMORY_MANAGER.with(|m| m.borrow().get(HIVING_CANISTERS_MEMORY_ID))
+}
+
+fn get_hiver_bindings_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(HIVER_BINDINGS_MEMORY_ID))
 }
 
 fn get_cycle_discounts_index_memory() -> IcpMemory {
     MEMORY_MANAGER.with(|m| m.borrow().get(CYCLE_DISCOUNTS_INDEX_MEMORY_ID))
 }
 
+fn get_legacy_cycle_discounts_index_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(LEGACY_CYCLE_DISCOUNTS_INDEX_MEMORY_ID))
+}
+
+fn get_owner_discounts_index_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(OWNER_DISCOUNTS_INDEX_MEMORY_ID))
+}
+
+fn get_hiver_discounts_index_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(HIVER_DISCOUNTS_INDEX_MEMORY_ID))
+}
+
+fn get_reconciliation_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(RECONCILIATION_MEMORY_ID))
+}
+
+fn get_cycle_calendar_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(CYCLE_CALENDAR_MEMORY_ID))
+}
+
+fn get_events_index_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(EVENTS_INDEX_MEMORY_ID))
+}
+
+fn get_events_data_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(EVENTS_DATA_MEMORY_ID))
+}
+
+fn get_cycle_emissions_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(CYCLE_EMISSIONS_MEMORY_ID))
+}
+
+fn get_stake_claims_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(STAKE_CLAIMS_MEMORY_ID))
+}
+
+fn get_score_snapshot_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(SCORE_SNAPSHOT_MEMORY_ID))
+}
+
+fn get_pending_claims_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(PENDING_CLAIMS_MEMORY_ID))
+}
+
+fn get_voter_rewards_cursor_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(VOTER_REWARDS_CURSOR_MEMORY_ID))
+}
+
+fn get_voter_balances_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(VOTER_BALANCES_MEMORY_ID))
+}
+
+fn get_voter_payouts_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(VOTER_PAYOUTS_MEMORY_ID))
+}
+
+fn get_voter_reward_allocations_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(VOTER_REWARD_ALLOCATIONS_MEMORY_ID))
+}
+
+fn get_mint_records_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(MINT_RECORDS_MEMORY_ID))
+}
+
+fn get_mint_key_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(MINT_KEY_MEMORY_ID))
+}
+
+fn get_minted_keys_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(MINTED_KEYS_MEMORY_ID))
+}
+
 fn get_discounts_memory() -> IcpMemory {
     MEMORY_MANAGER.with(|m| m.borrow().get(DISCOUNTS_MEMORY_ID))
 }
@@ -60,3 +185,51 @@ fn get_discounts_memory() -> IcpMemory {
 fn get_account_cycles_index_memory() -> IcpMemory {
     MEMORY_MANAGER.with(|m| m.borrow().get(ACCOUNT_CYCLE_INDEX_MEMORY_ID))
 }
+
+fn get_merchants_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(MERCHANTS_MEMORY_ID))
+}
+
+fn get_discounts_expiry_index_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(DISCOUNTS_EXPIRY_INDEX_MEMORY_ID))
+}
+
+fn get_wasm_chunks_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(WASM_CHUNKS_MEMORY_ID))
+}
+
+fn get_wasm_modules_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(WASM_MODULES_MEMORY_ID))
+}
+
+fn get_spare_canisters_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(SPARE_CANISTERS_MEMORY_ID))
+}
+
+fn get_spawned_canisters_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(SPAWNED_CANISTERS_MEMORY_ID))
+}
+
+fn get_departed_canisters_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(DEPARTED_CANISTERS_MEMORY_ID))
+}
+
+fn get_eviction_cursor_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(EVICTION_CURSOR_MEMORY_ID))
+}
+
+fn get_index_backfill_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(INDEX_BACKFILL_MEMORY_ID))
+}
+
+fn get_spawn_allowances_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(SPAWN_ALLOWANCES_MEMORY_ID))
+}
+
+fn get_fleet_upgrades_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(FLEET_UPGRADES_MEMORY_ID))
+}
+
+fn get_canister_upgrades_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(CANISTER_UPGRADES_MEMORY_ID))
+}
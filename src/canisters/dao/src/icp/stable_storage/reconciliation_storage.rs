@@ -0,0 +1,43 @@
+use crate::domain::interfaces::storage::IReconciliationStorage;
+use crate::domain::reconciliation::ReconciliationRun;
+use crate::icp::stable_storage::{get_reconciliation_memory, IcpMemory};
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{StableCell, Storable};
+use std::borrow::Cow;
+
+pub struct ReconciliationStorageStable {
+    run: StableCell<StorableRun, IcpMemory>,
+}
+
+impl ReconciliationStorageStable {
+    pub fn init() -> Self {
+        Self {
+            run: StableCell::init(get_reconciliation_memory(), StorableRun(None)).unwrap(),
+        }
+    }
+}
+
+impl IReconciliationStorage for ReconciliationStorageStable {
+    fn get_run(&self) -> Option<ReconciliationRun> {
+        self.run.get().0.clone()
+    }
+
+    fn set_run(&mut self, run: ReconciliationRun) {
+        self.run.set(StorableRun(Some(run))).unwrap();
+    }
+}
+
+struct StorableRun(Option<ReconciliationRun>);
+
+impl Storable for StorableRun {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let inner: Option<ReconciliationRun> = candid::decode_one(&bytes).unwrap();
+        StorableRun(inner)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
@@ -0,0 +1,116 @@
+use crate::domain::interfaces::storage::IVoterRewardsStorage;
+use crate::domain::payouts::Payout;
+use crate::icp::stable_storage::{
+    get_voter_balances_memory, get_voter_payouts_memory, get_voter_reward_allocations_memory, get_voter_rewards_cursor_memory,
+    IcpMemory,
+};
+use abstractions::dao::VoterRewardAllocation;
+use candid::{Nat, Principal};
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{StableBTreeMap, StableCell, Storable};
+use std::borrow::Cow;
+
+pub struct VoterRewardsStorageStable {
+    next_proposal_id: StableCell<u64, IcpMemory>,
+    balances: StableBTreeMap<Principal, StorableNat, IcpMemory>,
+    allocations: StableBTreeMap<u64, StorableAllocation, IcpMemory>,
+    payouts: StableBTreeMap<Principal, StorablePayout, IcpMemory>,
+}
+
+impl VoterRewardsStorageStable {
+    pub fn init() -> Self {
+        Self {
+            next_proposal_id: StableCell::init(get_voter_rewards_cursor_memory(), 0).unwrap(),
+            balances: StableBTreeMap::init(get_voter_balances_memory()),
+            allocations: StableBTreeMap::init(get_voter_reward_allocations_memory()),
+            payouts: StableBTreeMap::init(get_voter_payouts_memory()),
+        }
+    }
+}
+
+impl IVoterRewardsStorage for VoterRewardsStorageStable {
+    fn get_next_proposal_id(&self) -> u64 {
+        *self.next_proposal_id.get()
+    }
+
+    fn set_next_proposal_id(&mut self, proposal_id: u64) {
+        self.next_proposal_id.set(proposal_id).unwrap();
+    }
+
+    fn get_balance(&self, voter: Principal) -> Nat {
+        self.balances.get(&voter).map_or(Nat::from(0u8), |balance| balance.0)
+    }
+
+    fn set_balance(&mut self, voter: Principal, balance: Nat) {
+        if balance == 0u8 {
+            self.balances.remove(&voter);
+        } else {
+            self.balances.insert(voter, StorableNat(balance));
+        }
+    }
+
+    fn get_allocation(&self, cycle_number: u64) -> Option<VoterRewardAllocation> {
+        self.allocations.get(&cycle_number).map(|a| a.0)
+    }
+
+    fn save_allocation(&mut self, cycle_number: u64, allocation: VoterRewardAllocation) {
+        self.allocations.insert(cycle_number, StorableAllocation(allocation));
+    }
+
+    fn get_pending_payout(&self, voter: Principal) -> Option<Payout> {
+        self.payouts.get(&voter).map(|p| p.0)
+    }
+
+    fn save_pending_payout(&mut self, voter: Principal, payout: Payout) {
+        self.payouts.insert(voter, StorablePayout(payout));
+    }
+
+    fn remove_pending_payout(&mut self, voter: Principal) {
+        self.payouts.remove(&voter);
+    }
+}
+
+struct StorableNat(Nat);
+
+impl Storable for StorableNat {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let inner: Nat = candid::decode_one(&bytes).unwrap();
+        StorableNat(inner)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+struct StorableAllocation(VoterRewardAllocation);
+
+impl Storable for StorableAllocation {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let inner: VoterRewardAllocation = candid::decode_one(&bytes).unwrap();
+        StorableAllocation(inner)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+struct StorablePayout(Payout);
+
+impl Storable for StorablePayout {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let inner: Payout = candid::decode_one(&bytes).unwrap();
+        StorablePayout(inner)
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
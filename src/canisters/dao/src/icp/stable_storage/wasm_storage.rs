@@ -0,0 +1,144 @@
+use crate::domain::interfaces::storage::IWasmStorage;
+use crate::icp::stable_storage::{
+    get_spare_canisters_memory, get_spawn_allowances_memory, get_spawned_canisters_memory, get_wasm_chunks_memory, get_wasm_modules_memory, IcpMemory,
+};
+use abstractions::dao::{WasmKind, WasmModule};
+use candid::Principal;
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{StableBTreeMap, Storable};
+use std::borrow::Cow;
+
+/// chunks of version 0 are the staged ones
+const STAGED: u32 = 0;
+
+pub struct WasmStorageStable {
+    /// (kind, version, index) -> chunk
+    chunks: StableBTreeMap<(u8, u32, u32), Vec<u8>, IcpMemory>,
+    modules: StableBTreeMap<(u8, u32), StorableWasmModule, IcpMemory>,
+    /// (cycles the canister was created with, canister)
+    spare_canisters: StableBTreeMap<(u128, Principal), (), IcpMemory>,
+    /// owner -> canister spawned for them
+    spawned_canisters: StableBTreeMap<Principal, Principal, IcpMemory>,
+    /// owners governance allowed to spawn a canister for themselves
+    spawn_allowances: StableBTreeMap<Principal, (), IcpMemory>,
+}
+
+impl WasmStorageStable {
+    pub fn init() -> Self {
+        Self {
+            chunks: StableBTreeMap::init(get_wasm_chunks_memory()),
+            modules: StableBTreeMap::init(get_wasm_modules_memory()),
+            spare_canisters: StableBTreeMap::init(get_spare_canisters_memory()),
+            spawned_canisters: StableBTreeMap::init(get_spawned_canisters_memory()),
+            spawn_allowances: StableBTreeMap::init(get_spawn_allowances_memory()),
+        }
+    }
+
+    fn chunk_keys(&self, kind: WasmKind, version: u32) -> Vec<(u8, u32, u32)> {
+        let kind = kind_key(kind);
+        self.chunks.range((kind, version, 0)..=(kind, version, u32::MAX)).map(|(key, _)| key).collect()
+    }
+}
+
+impl IWasmStorage for WasmStorageStable {
+    fn append_staged_chunk(&mut self, kind: WasmKind, chunk: Vec<u8>) -> u32 {
+        let index = self.chunk_keys(kind, STAGED).len() as u32;
+        self.chunks.insert((kind_key(kind), STAGED, index), chunk);
+        index + 1
+    }
+
+    fn get_staged_chunks(&self, kind: WasmKind) -> Vec<Vec<u8>> {
+        self.get_module_chunks(kind, STAGED)
+    }
+
+    fn clear_staged_chunks(&mut self, kind: WasmKind) {
+        for key in self.chunk_keys(kind, STAGED) {
+            self.chunks.remove(&key);
+        }
+    }
+
+    fn commit_staged_chunks(&mut self, module: WasmModule) {
+        let kind = kind_key(module.kind);
+        for (_, _, index) in self.chunk_keys(module.kind, STAGED) {
+            let chunk = self.chunks.remove(&(kind, STAGED, index)).unwrap();
+            self.chunks.insert((kind, module.version, index), chunk);
+        }
+        self.modules.insert((kind, module.version), StorableWasmModule(module));
+    }
+
+    fn get_module(&self, kind: WasmKind, version: u32) -> Option<WasmModule> {
+        self.modules.get(&(kind_key(kind), version)).map(|m| m.0)
+    }
+
+    fn get_latest_module(&self, kind: WasmKind) -> Option<WasmModule> {
+        let kind = kind_key(kind);
+        self.modules.range((kind, 0)..=(kind, u32::MAX)).last().map(|(_, m)| m.0)
+    }
+
+    fn get_modules(&self, kind: WasmKind) -> Vec<WasmModule> {
+        let kind = kind_key(kind);
+        self.modules.range((kind, 0)..=(kind, u32::MAX)).map(|(_, m)| m.0).collect()
+    }
+
+    fn get_module_chunks(&self, kind: WasmKind, version: u32) -> Vec<Vec<u8>> {
+        let kind = kind_key(kind);
+        self.chunks.range((kind, version, 0)..=(kind, version, u32::MAX)).map(|(_, chunk)| chunk).collect()
+    }
+
+    fn add_spare_canister(&mut self, canister_id: Principal, cycles: u128) {
+        self.spare_canisters.insert((cycles, canister_id), ());
+    }
+
+    fn take_spare_canister(&mut self, cycles: u128) -> Option<Principal> {
+        let key = self
+            .spare_canisters
+            .range((cycles, Principal::from_slice(&[]))..)
+            .map(|(key, _)| key)
+            .next()
+            .filter(|(funded, _)| *funded == cycles)?;
+        self.spare_canisters.remove(&key);
+
+        Some(key.1)
+    }
+
+    fn get_spawned_canister(&self, owner: Principal) -> Option<Principal> {
+        self.spawned_canisters.get(&owner)
+    }
+
+    fn save_spawned_canister(&mut self, owner: Principal, canister_id: Principal) {
+        self.spawned_canisters.insert(owner, canister_id);
+    }
+
+    fn allow_spawn(&mut self, owner: Principal) {
+        self.spawn_allowances.insert(owner, ());
+    }
+
+    fn has_spawn_allowance(&self, owner: Principal) -> bool {
+        self.spawn_allowances.contains_key(&owner)
+    }
+
+    fn remove_spawn_allowance(&mut self, owner: Principal) {
+        self.spawn_allowances.remove(&owner);
+    }
+}
+
+pub(super) fn kind_key(kind: WasmKind) -> u8 {
+    match kind {
+        WasmKind::Hiving => 0,
+        WasmKind::HivingPool => 1,
+    }
+}
+
+struct StorableWasmModule(WasmModule);
+
+impl Storable for StorableWasmModule {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        StorableWasmModule(candid::decode_one(&bytes).unwrap())
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
@@ -1,28 +1,139 @@
-use crate::services::InitArgs;
-use crate::{services, DiscountQuote};
-use ic_cdk::{init, query, update};
+use crate::services;
+use crate::services::{InitArgs, QuoteDiscountArgs, RegisterHiverArgs};
+use crate::state::{AccessPolicy, BuyerFilter, PurchaseError};
+use abstractions::hiving::{ContractId, ContractTransition, DiscountContract, HiverId, HiverRegistration, PricingPolicy, QuoteId, QuoteReservation, TimeUnits};
+use abstractions::Account;
+use candid::Principal;
+use ic_cdk::{init, post_upgrade, pre_upgrade, query, update};
 
 #[init]
 fn init(init_args: InitArgs) {
     services::init(init_args);
+    services::start_heartbeat();
+}
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    services::pre_upgrade();
+}
+
+#[post_upgrade]
+fn post_upgrade(init_args: Option<InitArgs>) {
+    services::post_upgrade(init_args);
+    services::start_heartbeat();
+}
+
+#[update]
+async fn join_dao() -> Result<(), String> {
+    services::join_dao().await
+}
+
+#[update]
+async fn leave_dao() -> Result<(), String> {
+    services::leave_dao().await
+}
+
+#[update]
+fn register_hiver(args: RegisterHiverArgs) -> Result<HiverId, String> {
+    services::register_hiver(args)
+}
+
+#[query]
+fn get_hiver(hiver_id: HiverId) -> Option<HiverRegistration> {
+    services::get_hiver(hiver_id)
+}
+
+#[query]
+fn get_available_time(hiver_id: HiverId) -> Option<TimeUnits> {
+    services::get_available_time(hiver_id)
+}
+
+#[update]
+fn set_pricing_policy(policy: PricingPolicy) -> Result<(), String> {
+    services::set_pricing_policy(policy)
+}
+
+#[update]
+async fn quote_discount(args: QuoteDiscountArgs) -> Result<QuoteReservation, String> {
+    services::quote_discount(args).await
+}
+
+#[update]
+async fn buy_discount(quote_id: QuoteId) -> Result<ContractId, PurchaseError> {
+    services::buy_discount(quote_id).await
+}
+
+#[query]
+fn get_contract(contract_id: ContractId) -> Option<DiscountContract> {
+    services::get_contract(contract_id)
+}
+
+#[query]
+fn get_contract_transitions(contract_id: ContractId) -> Option<Vec<ContractTransition>> {
+    services::get_contract_transitions(contract_id)
+}
+
+#[update]
+async fn settle_contract(contract_id: ContractId) -> Result<DiscountContract, String> {
+    services::settle_contract(contract_id).await
+}
+
+#[update]
+async fn cancel_contract(contract_id: ContractId) -> Result<DiscountContract, String> {
+    services::cancel_contract(contract_id).await
 }
 
 #[update]
-async fn join_dao() {
-    services::join_dao().await;
+async fn sync_contract_redemption(contract_id: ContractId) -> Result<DiscountContract, String> {
+    services::sync_contract_redemption(contract_id).await
+}
+
+#[query]
+fn list_contracts(buyer: Option<Account>, prev: Option<ContractId>, take: Option<u32>) -> Vec<DiscountContract> {
+    services::list_contracts(buyer, prev, take)
+}
+
+#[query]
+fn get_access_policy() -> AccessPolicy {
+    services::get_access_policy()
 }
 
 #[update]
-async fn leave_dao() {
-    services::leave_dao().await;
+fn add_operator(operator: Principal) -> Result<(), String> {
+    services::add_operator(operator)
 }
 
 #[update]
-async fn quote_discount(price: u128) -> DiscountQuote {
-    services::quote_discount(price).await
+fn remove_operator(operator: Principal) -> Result<(), String> {
+    services::remove_operator(operator)
+}
+
+#[update]
+fn transfer_ownership(new_owner: Principal) -> Result<(), String> {
+    services::transfer_ownership(new_owner)
+}
+
+#[update]
+fn accept_ownership() -> Result<(), String> {
+    services::accept_ownership()
+}
+
+#[update]
+fn set_buyer_filter(buyer_filter: BuyerFilter) -> Result<(), String> {
+    services::set_buyer_filter(buyer_filter)
+}
+
+#[update]
+fn set_buyers_listed(buyers: Vec<Principal>, listed: bool) -> Result<(), String> {
+    services::set_buyers_listed(buyers, listed)
+}
+
+#[query]
+fn list_buyers(prev: Option<Principal>, take: Option<u32>) -> Vec<Principal> {
+    services::list_buyers(prev, take)
 }
 
 #[update]
-async fn buy_discount(price: u128) -> u128 {
-    services::buy_discount(price).await
+fn set_purchase_limit(max_purchases_per_cycle: Option<u32>) -> Result<(), String> {
+    services::set_purchase_limit(max_purchases_per_cycle)
 }
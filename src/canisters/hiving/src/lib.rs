@@ -1,14 +1,6 @@
+#![allow(dead_code)]
+
 mod api;
 mod services;
-
-use candid::CandidType;
-use serde::Serialize;
-use abstractions::DiscountValue;
-
-
-
-#[derive(Clone, Debug, Serialize, CandidType)]
-struct DiscountQuote {
-    discount_value: DiscountValue,
-    price: u128
-}
\ No newline at end of file
+mod stable_storage;
+mod state;
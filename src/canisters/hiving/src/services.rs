@@ -1,107 +1,627 @@
-use crate::DiscountQuote;
-use abstractions::dao::{DaoClient, DiscountRequest};
-use abstractions::{Account, DiscountValue};
-use candid::{CandidType, Deserialize, Principal};
+use crate::state::{with_state, AccessPolicy, BuyerFilter, EscrowTransfer, HivingConfig, PurchaseError};
+use abstractions::ckusdc::CkUsdcClient;
+use abstractions::dao::{Cycle, DaoClient, DaoError, DiscountMintState, DiscountRequest, MintDiscountError};
+use abstractions::hiving::{
+    ContractId, ContractStatus, ContractTransition, DiscountContract, HiverId, HiverRegistration, HivingHeartbeat, PriceQuote, PricingPolicy,
+    QuoteId, QuoteReservation, RegisterHivingCanisterArgs, TimeUnits, HEARTBEAT_INTERVAL_NS,
+};
+use abstractions::runtime::ICallContext;
+use abstractions::{Account, MetadataValue};
+use candid::{CandidType, Deserialize, Nat, Principal};
 use canister_runtime::CdkCallContext;
-use ic_cdk::api::msg_caller;
-use std::cell::RefCell;
+use icrc_ledger_types::icrc1::transfer::{Memo, TransferArg, TransferError};
+use icrc_ledger_types::icrc2::transfer_from::{TransferFromArgs, TransferFromError};
+use ic_cdk::api::{canister_cycle_balance, canister_self, msg_caller, time};
+use ic_cdk::futures::spawn;
+use ic_cdk_timers::{clear_timer, set_timer, set_timer_interval, TimerId};
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
+use std::time::Duration;
 
-thread_local! {
-    static CONFIG_STORAGE: RefCell<Option<CanisterConfig>> = RefCell::new(None);
+const MAX_PAGE_SIZE: usize = 100;
+/// paid contracts left undelivered this long can be cancelled by anyone
+const CONTRACT_TIMEOUT_NS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+pub use abstractions::hiving::HivingInitArgs as InitArgs;
+
+#[derive(Clone, Debug, Deserialize, CandidType)]
+pub struct RegisterHiverArgs {
+    pub metadata: Vec<(String, MetadataValue)>,
+    pub price_per_time_unit: Nat,
+    /// total time units the hiver offers for sale
+    pub max_time_units: TimeUnits,
+    /// time units that can be sold within a single DAO cycle
+    pub cycle_cap: TimeUnits,
+    /// defaults to the hiver's default account
+    pub payout_account: Option<Account>,
+    /// defaults to `PricingPolicy::PerTimeUnit`
+    pub pricing: Option<PricingPolicy>,
 }
 
 #[derive(Clone, Debug, Deserialize, CandidType)]
-pub struct InitArgs {
-    pub dao_address: Principal,
+pub struct QuoteDiscountArgs {
+    pub hiver_id: HiverId,
+    pub time_units: TimeUnits,
+    /// price of the product the discount is quoted for
+    pub product_price: u128,
 }
 
-struct ConfigStorage {}
+pub fn init(init_args: InitArgs) {
+    let config = HivingConfig {
+        owner: init_args.owner.unwrap_or(msg_caller()),
+        dao_canister_id: init_args.dao_address,
+        ckusdc_canister_id: init_args.ckusdc_address,
+    };
+    with_state(|state| {
+        state.migrate();
+        state.set_config(config);
+    });
+}
 
-impl ConfigStorage {
-    pub fn set_config(&mut self, config: CanisterConfig) {
-        CONFIG_STORAGE.with(|cell| {
-            *cell.borrow_mut() = Some(config);
-        });
+/// The in-flight purchases are only kept on the heap, so an upgrade mid-purchase would lose track
+/// of escrows waiting to be released or refunded.
+pub fn pre_upgrade() {
+    if with_state(|state| !state.purchases_in_flight.is_empty()) {
+        ic_cdk::trap("Purchases are in flight, stop the canister before upgrading");
     }
+}
 
-    pub fn get_config(&self) -> CanisterConfig {
-        CONFIG_STORAGE.with(|cell| {
-            cell.borrow()
-                .clone()
-                .expect("ConfigStorage is not initialized")
-        })
+/// Migrates the stored state. Canisters upgraded from a release that kept its state on the heap
+/// lost their config and need the init arguments again, with the upgrading controller as default
+/// owner.
+pub fn post_upgrade(init_args: Option<InitArgs>) {
+    let found_version = with_state(|state| state.migrate());
+    let mut config = get_config();
+    let owner = match init_args {
+        Some(init_args) => {
+            config.dao_canister_id = init_args.dao_address;
+            config.ckusdc_canister_id = init_args.ckusdc_address;
+            init_args.owner
+        }
+        None if found_version == 0 => ic_cdk::trap("Upgrading a canister without stable state requires init arguments"),
+        None => return,
+    };
+    if let Some(owner) = owner {
+        config.owner = owner;
+    } else if found_version == 0 {
+        config.owner = msg_caller();
     }
+
+    with_state(|state| state.set_config(config));
 }
 
-#[derive(Clone, Debug)]
-pub struct CanisterConfig {
-    pub owner: Principal,
-    pub dao_address: Principal,
+pub fn get_access_policy() -> AccessPolicy {
+    with_state(|state| state.get_access_policy())
 }
 
-pub fn init(init_args: InitArgs) {
+pub fn add_operator(operator: Principal) -> Result<(), String> {
+    ensure_owner()?;
+    update_access_policy(|policy| {
+        if !policy.operators.contains(&operator) {
+            policy.operators.push(operator);
+        }
+    });
+
+    Ok(())
+}
+
+pub fn remove_operator(operator: Principal) -> Result<(), String> {
+    ensure_owner()?;
+    update_access_policy(|policy| policy.operators.retain(|p| p != &operator));
+
+    Ok(())
+}
+
+/// First step of an ownership transfer; the new owner takes over once they accept
+pub fn transfer_ownership(new_owner: Principal) -> Result<(), String> {
+    ensure_owner()?;
+    update_access_policy(|policy| policy.pending_owner = Some(new_owner));
+
+    Ok(())
+}
+
+pub fn accept_ownership() -> Result<(), String> {
     let caller = msg_caller();
-    let config = CanisterConfig {
-        owner: caller,
-        dao_address: init_args.dao_address,
-    };
-    let mut storage = ConfigStorage {};
-    storage.set_config(config)
+    if get_access_policy().pending_owner != Some(caller) {
+        return Err("Caller is not the pending owner".to_string());
+    }
+
+    with_state(|state| {
+        let mut config = state.get_config();
+        config.owner = caller;
+        state.set_config(config);
+    });
+    update_access_policy(|policy| policy.pending_owner = None);
+
+    Ok(())
+}
+
+pub fn set_buyer_filter(buyer_filter: BuyerFilter) -> Result<(), String> {
+    ensure_admin()?;
+    update_access_policy(|policy| policy.buyer_filter = buyer_filter);
+
+    Ok(())
+}
+
+/// Adds or removes buyers from the list the filter applies to
+pub fn set_buyers_listed(buyers: Vec<Principal>, listed: bool) -> Result<(), String> {
+    ensure_admin()?;
+    with_state(|state| buyers.into_iter().for_each(|buyer| state.set_buyer_listed(buyer, listed)));
+
+    Ok(())
 }
 
-fn get_config() -> CanisterConfig {
-    let storage = ConfigStorage {};
-    storage.get_config()
+pub fn list_buyers(prev: Option<Principal>, take: Option<u32>) -> Vec<Principal> {
+    let take = take.map_or(MAX_PAGE_SIZE, |take| (take as usize).min(MAX_PAGE_SIZE));
+    with_state(|state| state.list_buyers(prev, take))
+}
+
+pub fn set_purchase_limit(max_purchases_per_cycle: Option<u32>) -> Result<(), String> {
+    ensure_admin()?;
+    update_access_policy(|policy| policy.max_purchases_per_cycle = max_purchases_per_cycle);
+
+    Ok(())
 }
 
-pub async fn join_dao() {
+fn update_access_policy(update: impl FnOnce(&mut AccessPolicy)) {
+    with_state(|state| {
+        let mut policy = state.get_access_policy();
+        update(&mut policy);
+        state.set_access_policy(policy);
+    });
+}
+
+fn ensure_owner() -> Result<(), String> {
+    if msg_caller() != get_config().owner {
+        return Err("Only the owner can call this method".to_string());
+    }
+
+    Ok(())
+}
+
+fn ensure_admin() -> Result<(), String> {
+    let caller = msg_caller();
+    if caller != get_config().owner && !get_access_policy().operators.contains(&caller) {
+        return Err("Only the owner or an operator can call this method".to_string());
+    }
+
+    Ok(())
+}
+
+fn get_config() -> HivingConfig {
+    with_state(|state| state.get_config())
+}
+
+pub async fn join_dao() -> Result<(), String> {
+    ensure_owner()?;
     let dao = build_dao_service();
-    dao.borrow().hiving_join().await.unwrap();
+    let config = get_config();
+    let args = RegisterHivingCanisterArgs {
+        canister_id: canister_self(),
+        owner: config.owner,
+        metadata: Vec::new(),
+    };
+    match dao.hiving_join(args).await {
+        // joining again, e.g. after a reinstall, keeps the existing registration
+        Ok(Ok(())) | Ok(Err(DaoError::HivingCanisterAlreadyRegistered)) => Ok(()),
+        result => Err(format!("Failed to join the DAO: {:?}", result)),
+    }
 }
 
-pub async fn leave_dao() {
+pub async fn leave_dao() -> Result<(), String> {
+    ensure_owner()?;
     let dao = build_dao_service();
-    dao.borrow().hiving_leave().await.unwrap()
+    match dao.hiving_leave().await {
+        Ok(Ok(())) | Ok(Err(DaoError::HivingCanisterNotRegistered)) => Ok(()),
+        result => Err(format!("Failed to leave the DAO: {:?}", result)),
+    }
+}
+
+thread_local! {
+    /// timer reporting to the DAO, replaced when the DAO asks for another interval
+    static HEARTBEAT_TIMER: Cell<Option<TimerId>> = const { Cell::new(None) };
+    /// interval the DAO judges staleness by, known once it answered a report
+    static HEARTBEAT_INTERVAL: Cell<u64> = const { Cell::new(HEARTBEAT_INTERVAL_NS) };
+}
+
+/// Reports to the DAO right away and then once every interval it asks for, `HEARTBEAT_INTERVAL_NS`
+/// until it answered; the DAO marks canisters which stop reporting as stale. Timers do not survive
+/// upgrades, so this is called on init and post_upgrade
+pub fn start_heartbeat() {
+    set_timer(Duration::ZERO, || spawn(send_heartbeat()));
+    schedule_heartbeat(HEARTBEAT_INTERVAL_NS);
 }
 
-pub async fn quote_discount(product_price: u128) -> DiscountQuote {
+fn schedule_heartbeat(interval_ns: u64) {
+    HEARTBEAT_INTERVAL.with(|interval| interval.set(interval_ns));
+    if let Some(timer) = HEARTBEAT_TIMER.with(|timer| timer.take()) {
+        clear_timer(timer);
+    }
+    let timer = set_timer_interval(Duration::from_nanos(interval_ns), || spawn(send_heartbeat()));
+    HEARTBEAT_TIMER.with(|current| current.set(Some(timer)));
+}
+
+/// a failed report is only repeated with the next one
+async fn send_heartbeat() {
+
+    let heartbeat = HivingHeartbeat {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        cycles: Nat::from(canister_cycle_balance()),
+        owner: get_config().owner,
+    };
     let dao = build_dao_service();
-    let config = get_config();
-    let account = Account::from(config.owner);
+    // the DAO refuses reports of canisters which have not joined yet
+    if let Ok(Ok(interval)) = dao.hiving_heartbeat(heartbeat).await
+        && interval > 0
+        && interval != HEARTBEAT_INTERVAL.with(|current| current.get())
+    {
+        schedule_heartbeat(interval);
+    }
+}
+
+/// Hivers sell their DAO staking score through this canister, so only its administrators can
+/// register, each for themselves.
+pub fn register_hiver(args: RegisterHiverArgs) -> Result<HiverId, String> {
+    ensure_admin()?;
+    let caller = msg_caller();
+    with_state(|state| {
+        if state.find_hiver(&caller).is_some() {
+            return Err("Hiver already registered".to_string());
+        }
+
+        let registration = HiverRegistration {
+            principal: caller,
+            metadata: args.metadata,
+            price_per_time_unit: args.price_per_time_unit,
+            max_time_units: args.max_time_units,
+            cycle_cap: args.cycle_cap,
+            payout_account: args.payout_account.unwrap_or(Account::from(caller)),
+            pricing: args.pricing,
+        };
+        Ok(state.add_hiver(registration))
+    })
+}
+
+pub fn get_hiver(hiver_id: HiverId) -> Option<HiverRegistration> {
+    with_state(|state| state.get_hiver(hiver_id))
+}
+
+pub fn get_available_time(hiver_id: HiverId) -> Option<TimeUnits> {
+    with_state(|state| state.get_available_time(hiver_id))
+}
+
+/// Lets a registered hiver change how their time is priced, for quotes made from now on.
+pub fn set_pricing_policy(policy: PricingPolicy) -> Result<(), String> {
+    ensure_admin()?;
+    let caller = msg_caller();
+    with_state(|state| {
+        let hiver_id = state.find_hiver(&caller).ok_or("Hiver not registered")?;
+        state.set_pricing(hiver_id, policy)
+    })
+}
+
+/// Prices the discount and holds the quote for the caller, who can buy it with `buy_discount`
+/// until it expires.
+pub async fn quote_discount(args: QuoteDiscountArgs) -> Result<QuoteReservation, String> {
+    let (quote, cycle) = price_discount(&args).await?;
+    let buyer = msg_caller();
 
-    let discount_value = dao.borrow().calculate_max_discount(&account, &product_price).await.unwrap();
-    let discount_price = calculate_discount_price(product_price, discount_value);
+    Ok(with_state(|state| state.reserve_quote(buyer, args.hiver_id, args.product_price, quote, cycle.number, time())))
+}
 
-    DiscountQuote {
-        discount_value,
-        price: discount_price,
+/// Prices a purchase with the hiver's policy, using the discount the DAO would grant on the
+/// product and the hiver's sales in the current cycle.
+async fn price_discount(args: &QuoteDiscountArgs) -> Result<(PriceQuote, Cycle), String> {
+    if args.time_units == 0 {
+        return Err("Time units must be positive".to_string());
     }
+    let hiver = with_state(|state| state.get_hiver(args.hiver_id)).ok_or("Hiver not found")?;
+
+    let dao = build_dao_service();
+    let cycle = match dao.get_current_cycle().await {
+        Ok(Ok(cycle)) => cycle,
+        result => return Err(format!("Failed to get the current cycle: {:?}", result)),
+    };
+    let discount_value = match dao.calculate_max_discount(&Account::from(hiver.principal), &args.product_price).await {
+        Ok(Ok(discount_value)) => discount_value,
+        result => return Err(format!("Failed to calculate the discount: {:?}", result)),
+    };
+
+    let quote = with_state(|state| state.quote(args.hiver_id, args.time_units, args.product_price, discount_value, cycle.number))
+        .ok_or("Hiver not found")?;
+    Ok((quote, cycle))
 }
 
-fn calculate_discount_price(product_price: u128, discount_value: DiscountValue) -> u128 {
-    1_000_000
+/// Buys a quote of the caller at exactly its price and discount value. The quote is consumed
+/// whatever the outcome. The ckUSDC price is collected from the buyer through an ICRC-2 allowance
+/// into the contract's escrow subaccount, then the DAO is asked to mint the discount. The escrow
+/// is released to the hiver's payout account once the discount is delivered, or refunded to the
+/// buyer otherwise.
+pub async fn buy_discount(quote_id: QuoteId) -> Result<ContractId, PurchaseError> {
+    let buyer = Account::from(msg_caller());
+    if !with_state(|state| state.is_buyer_allowed(&buyer.owner)) {
+        return Err("Buyer is not allowed to purchase discounts".to_string().into());
+    }
+    let reservation = with_state(|state| state.take_quote(quote_id, buyer.owner, time()))?;
+    let (hiver_id, quote, cycle_number) = (reservation.hiver_id, reservation.quote, reservation.cycle_number);
+
+    let ckusdc = build_ckusdc_client();
+    let fee = ckusdc.inner().fee().await.map_err(|err| format!("Failed to get the ckUSDC fee: {:?}", err))?;
+    if quote.ckusdc_cost <= fee {
+        return Err("Price does not cover the ckUSDC transfer fee".to_string().into());
+    }
+
+    // caps and availability are checked after the call above so concurrent purchases can't oversell
+    let (contract, hiver) = with_state(|state| {
+        let hiver = state.get_hiver(hiver_id).ok_or("Hiver not found")?;
+        state.check_cycle_cap(hiver_id, cycle_number, quote.time_units)?;
+        state.reserve_time(hiver_id, quote.time_units)?;
+        state.mark_cycle_usage(hiver_id, cycle_number, quote.time_units);
+        if let Err(err) = state.count_purchase(cycle_number, buyer.owner) {
+            state.release_time(hiver_id, quote.time_units);
+            state.release_cycle_usage(hiver_id, cycle_number, quote.time_units);
+            return Err(err);
+        }
+
+        let contract = state.open_contract(buyer, hiver.principal, quote.clone(), cycle_number, time());
+        state.purchases_in_flight.insert(contract.id);
+        Ok::<_, String>((contract, hiver))
+    })?;
+
+    let result = complete_purchase(&contract, hiver, reservation.product_price).await;
+    with_state(|state| state.purchases_in_flight.remove(&contract.id));
+
+    result.map(|_| contract.id).map_err(PurchaseError::from)
 }
 
-pub async fn buy_discount(price: u128) -> u128 {
+/// Ambiguous outcomes leave the contract as it is: an Open contract whose payment may have reached
+/// the escrow is refunded by `cancel_contract`, and a Paid contract whose discount mint is pending
+/// is delivered or refunded by `settle_contract` once the DAO resolved the mint.
+async fn complete_purchase(contract: &DiscountContract, hiver: HiverRegistration, product_price: u128) -> Result<(), String> {
+    let payment = TransferFromArgs {
+        spender_subaccount: None,
+        from: contract.buyer,
+        to: escrow_account(contract),
+        amount: contract.price.clone(),
+        fee: None,
+        memo: Some(transfer_memo(contract.id)),
+        created_at_time: Some(time()),
+    };
+    match build_ckusdc_client().inner().transfer_from(payment).await {
+        Ok(Ok(block_index)) | Ok(Err(TransferFromError::Duplicate { duplicate_of: block_index })) => {
+            transition(contract.id, ContractStatus::Paid, Some(block_index))?
+        }
+        Ok(Err(err)) if err != TransferFromError::TooOld => {
+            transition(contract.id, ContractStatus::Cancelled, None)?;
+            return Err(format!("Failed to collect the payment: {:?}", err));
+        }
+        Err(err) if CdkCallContext::is_clean_reject(&err) => {
+            transition(contract.id, ContractStatus::Cancelled, None)?;
+            return Err(format!("Failed to collect the payment: {:?}", err));
+        }
+        result => return Err(format!("Payment outcome unknown, the contract stays open: {:?}", result)),
+    };
+
+    let mut request = DiscountRequest::new(product_price, contract.buyer);
+    request.value = Some(contract.discount_value);
     let dao = build_dao_service();
-    let config = get_config();
-    let hiver = Account::from(config.owner);
+    match dao.mint_discount(Account::from(hiver.principal), request).await {
+        Ok(Ok(discount_id)) => with_state(|state| state.set_discount(contract.id, discount_id)),
+        Ok(Err(DaoError::Mint(MintDiscountError::NftMintPending { mint_key }))) => {
+            with_state(|state| state.set_pending_mint(contract.id, mint_key));
+            return Err("Discount mint pending, settle the contract once the DAO resolved it".to_string());
+        }
+        Err(err) if !CdkCallContext::is_clean_reject(&err) => {
+            return Err(format!("Discount mint outcome unknown, the contract stays paid: {:?}", err));
+        }
+        result => {
+            let refund = refund(contract.id).await;
+            return Err(format!("Failed to mint the discount: {:?}, refund: {:?}", result, refund));
+        }
+    }
+
+    // a failed release leaves the contract paid with the discount delivered, to be settled later
+    let _ = release(contract.id).await;
 
-    let discount_value = dao.borrow().calculate_max_discount(&hiver, &price).await.unwrap();
+    Ok(())
+}
+
+/// Releases the escrow of a paid contract whose discount was delivered to the hiver. A contract
+/// whose mint was pending is delivered once the DAO minted the discount, or refunded once it gave
+/// the mint up.
+pub async fn settle_contract(contract_id: ContractId) -> Result<DiscountContract, String> {
+    let contract = get_contract(contract_id).ok_or("Contract not found")?;
+    if contract.discount_id.is_none() {
+        let mint_key = with_state(|state| state.get_pending_mint(contract_id)).ok_or("Discount not delivered yet")?;
+        let dao = build_dao_service();
+        let mint = dao.get_discount_mint(mint_key).await.map_err(|err| format!("Failed to get the discount mint: {:?}", err))?;
+        match mint {
+            Ok(Some(DiscountMintState::Minted { discount_id })) => with_state(|state| {
+                state.set_discount(contract_id, discount_id);
+                state.remove_pending_mint(contract_id);
+            }),
+            Ok(Some(DiscountMintState::Compensated)) => {
+                with_state(|state| state.remove_pending_mint(contract_id));
+                return refund(contract_id).await;
+            }
+            mint => return Err(format!("Discount mint not resolved yet: {:?}", mint)),
+        }
+    }
+
+    release(contract_id).await
+}
+
+/// Cancels a contract whose discount wasn't delivered, refunding the escrow if it was paid.
+/// The hiver can cancel at any time, anyone else only once the contract timed out.
+pub async fn cancel_contract(contract_id: ContractId) -> Result<DiscountContract, String> {
     let caller = msg_caller();
-    let discount_request = DiscountRequest::new(discount_value, Account::from(caller));
-    let nft = dao.borrow().mint_discount(hiver, discount_request).await.unwrap();
+    let (contract, last_change, in_flight, mint_pending) = with_state(|state| {
+        let contract = state.get_contract(contract_id).ok_or("Contract not found")?;
+        let last_change = state.get_transitions(contract_id).and_then(|transitions| transitions.last().map(|t| t.timestamp)).unwrap_or(0);
+        let mint_pending = state.get_pending_mint(contract_id).is_some();
+        Ok::<_, String>((contract, last_change, state.purchases_in_flight.contains(&contract_id), mint_pending))
+    })?;
+    if in_flight || contract.discount_id.is_some() || mint_pending {
+        return Err("Discount is being delivered or already delivered".to_string());
+    }
+    if caller != contract.seller && time() < last_change.saturating_add(CONTRACT_TIMEOUT_NS) {
+        return Err("Only the hiver can cancel the contract before it times out".to_string());
+    }
 
-    nft
+    match contract.status {
+        ContractStatus::Paid => refund(contract_id).await,
+        ContractStatus::Open if escrow_is_funded(&contract).await? => {
+            // the payment reached the escrow although its outcome was unknown to the purchase
+            transition(contract_id, ContractStatus::Paid, None)?;
+            refund(contract_id).await
+        }
+        _ => transition(contract_id, ContractStatus::Cancelled, None),
+    }
 }
 
-fn build_dao_service() -> Rc<RefCell<DaoClient<CdkCallContext>>> {
+/// Moves a fulfilled contract to Redeemed once a merchant redeemed its discount at the DAO.
+pub async fn sync_contract_redemption(contract_id: ContractId) -> Result<DiscountContract, String> {
+    let contract = get_contract(contract_id).ok_or("Contract not found")?;
+    let discount_id = match (&contract.status, contract.discount_id) {
+        (ContractStatus::Fulfilled, Some(discount_id)) => discount_id,
+        _ => return Err(format!("Contract is {:?}, not fulfilled", contract.status)),
+    };
+
+    let dao = build_dao_service();
+    let discount = match dao.get_discount(discount_id).await {
+        Ok(Ok(discount)) => discount,
+        result => return Err(format!("Failed to get the discount: {:?}", result)),
+    };
+    if discount.redemption.is_none() {
+        return Err("Discount not redeemed yet".to_string());
+    }
+
+    transition(contract_id, ContractStatus::Redeemed, None)
+}
+
+async fn escrow_is_funded(contract: &DiscountContract) -> Result<bool, String> {
+    let balance = build_ckusdc_client()
+        .inner()
+        .balance_of(escrow_account(contract))
+        .await
+        .map_err(|err| format!("Failed to get the escrow balance: {:?}", err))?;
+
+    Ok(balance >= contract.price)
+}
+
+async fn release(contract_id: ContractId) -> Result<DiscountContract, String> {
+    let contract = get_contract(contract_id).ok_or("Contract not found")?;
+    let payout_account = with_state(|state| {
+        let hiver_id = state.find_hiver(&contract.seller)?;
+        state.get_hiver(hiver_id).map(|hiver| hiver.payout_account)
+    })
+    .ok_or("Hiver not found")?;
+
+    let block_index = transfer_escrow(&contract, payout_account).await?;
+    transition(contract_id, ContractStatus::Fulfilled, block_index)
+}
+
+async fn refund(contract_id: ContractId) -> Result<DiscountContract, String> {
+    let contract = get_contract(contract_id).ok_or("Contract not found")?;
+    let block_index = transfer_escrow(&contract, contract.buyer).await?;
+    transition(contract_id, ContractStatus::Cancelled, block_index)
+}
+
+/// Moves the escrowed payment less the ledger fee. The transfer drains the escrow, so a contract
+/// can't be both released and refunded. A transfer with an unknown outcome is recorded and resent
+/// unchanged, so the ledger deduplicates it; once it is too old to be deduplicated, the escrow
+/// balance tells whether it was executed, in which case its block index is unknown.
+async fn transfer_escrow(contract: &DiscountContract, to: Account) -> Result<Option<Nat>, String> {
+    if contract.status != ContractStatus::Paid {
+        return Err(format!("Contract is {:?}, not paid", contract.status));
+    }
+
+    let ckusdc = build_ckusdc_client();
+    let fee = ckusdc.inner().fee().await.map_err(|err| format!("Failed to get the ckUSDC fee: {:?}", err))?;
+    if contract.price <= fee {
+        return Err("Escrow does not cover the ckUSDC transfer fee".to_string());
+    }
+
+    let transfer = with_state(|state| match state.get_escrow_transfer(contract.id) {
+        Some(transfer) if transfer.to != to => Err("Escrow is being transferred to another account".to_string()),
+        Some(transfer) => Ok(transfer),
+        None => {
+            let transfer = EscrowTransfer { to, created_at_time: time() };
+            state.set_escrow_transfer(contract.id, transfer.clone());
+            Ok(transfer)
+        }
+    })?;
+    let args = TransferArg {
+        from_subaccount: Some(contract.escrow_subaccount()),
+        to,
+        fee: None,
+        created_at_time: Some(transfer.created_at_time),
+        memo: Some(transfer_memo(contract.id)),
+        amount: contract.price.clone() - fee,
+    };
+    let result = ckusdc.inner().transfer(args).await;
+    let outcome = match result {
+        Ok(Ok(block_index)) | Ok(Err(TransferError::Duplicate { duplicate_of: block_index })) => Ok(Some(block_index)),
+        Ok(Err(TransferError::TooOld)) => match escrow_is_funded(contract).await {
+            Ok(true) => Err("Escrow transfer expired, retry".to_string()),
+            Ok(false) => Ok(None),
+            Err(err) => return Err(err),
+        },
+        Err(err) if !CdkCallContext::is_clean_reject(&err) => {
+            return Err(format!("Escrow transfer outcome unknown, retry: {:?}", err));
+        }
+        result => Err(format!("Failed to transfer the escrow: {:?}", result)),
+    };
+    with_state(|state| state.remove_escrow_transfer(contract.id));
+
+    outcome
+}
+
+/// memo of the payment and escrow transfers of a contract, which the ledger deduplicates with
+fn transfer_memo(contract_id: ContractId) -> Memo {
+    Memo::from(contract_id.to_be_bytes().to_vec())
+}
+
+fn transition(contract_id: ContractId, status: ContractStatus, block_index: Option<Nat>) -> Result<DiscountContract, String> {
+    let transition = ContractTransition {
+        status,
+        timestamp: time(),
+        caller: msg_caller(),
+        block_index,
+    };
+    with_state(|state| state.transition_contract(contract_id, transition))
+}
+
+fn escrow_account(contract: &DiscountContract) -> Account {
+    Account {
+        owner: canister_self(),
+        subaccount: Some(contract.escrow_subaccount()),
+    }
+}
+
+pub fn get_contract(contract_id: ContractId) -> Option<DiscountContract> {
+    with_state(|state| state.get_contract(contract_id))
+}
+
+pub fn get_contract_transitions(contract_id: ContractId) -> Option<Vec<ContractTransition>> {
+    with_state(|state| state.get_transitions(contract_id))
+}
+
+pub fn list_contracts(buyer: Option<Account>, prev: Option<ContractId>, take: Option<u32>) -> Vec<DiscountContract> {
+    let take = take.map_or(MAX_PAGE_SIZE, |take| (take as usize).min(MAX_PAGE_SIZE));
+    with_state(|state| state.list_contracts(buyer, prev, take))
+}
+
+fn build_ckusdc_client() -> CkUsdcClient<CdkCallContext> {
+    let canister_id = get_config().ckusdc_canister_id;
+    CkUsdcClient::new(Rc::new(RefCell::new(CdkCallContext {})), canister_id)
+}
+
+fn build_dao_service() -> DaoClient<CdkCallContext> {
     let config = get_config();
     let runtime = CdkCallContext {};
-    let client = DaoClient {
+    DaoClient {
         runtime: Rc::new(RefCell::new(runtime)),
-        canister_id: config.dao_address,
-    };
-    Rc::new(RefCell::new(client))
+        canister_id: config.dao_canister_id,
+    }
 }
@@ -0,0 +1,194 @@
+use crate::state::{AccessPolicy, EscrowTransfer, HivingConfig};
+use abstractions::hiving::{ContractTransition, DiscountContract, HiverRegistration, QuoteReservation};
+use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{DefaultMemoryImpl, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+
+pub type IcpMemory = VirtualMemory<DefaultMemoryImpl>;
+
+const LAYOUT_VERSION_MEMORY_ID: MemoryId = MemoryId::new(1);
+const CONFIG_MEMORY_ID: MemoryId = MemoryId::new(2);
+const HIVERS_MEMORY_ID: MemoryId = MemoryId::new(3);
+const AVAILABLE_TIME_MEMORY_ID: MemoryId = MemoryId::new(4);
+const CONTRACTS_MEMORY_ID: MemoryId = MemoryId::new(5);
+const TRANSITIONS_MEMORY_ID: MemoryId = MemoryId::new(6);
+const CYCLE_USAGE_MEMORY_ID: MemoryId = MemoryId::new(7);
+const ACCESS_POLICY_MEMORY_ID: MemoryId = MemoryId::new(8);
+const LISTED_BUYERS_MEMORY_ID: MemoryId = MemoryId::new(9);
+const BUYER_PURCHASES_MEMORY_ID: MemoryId = MemoryId::new(10);
+const QUOTES_MEMORY_ID: MemoryId = MemoryId::new(11);
+const NEXT_QUOTE_ID_MEMORY_ID: MemoryId = MemoryId::new(12);
+const PENDING_MINTS_MEMORY_ID: MemoryId = MemoryId::new(13);
+const ESCROW_TRANSFERS_MEMORY_ID: MemoryId = MemoryId::new(14);
+
+thread_local! {
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
+        MemoryManager::init(DefaultMemoryImpl::default())
+    );
+}
+
+pub fn get_layout_version_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(LAYOUT_VERSION_MEMORY_ID))
+}
+
+pub fn get_config_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(CONFIG_MEMORY_ID))
+}
+
+pub fn get_hivers_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(HIVERS_MEMORY_ID))
+}
+
+pub fn get_available_time_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(AVAILABLE_TIME_MEMORY_ID))
+}
+
+pub fn get_contracts_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(CONTRACTS_MEMORY_ID))
+}
+
+pub fn get_transitions_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(TRANSITIONS_MEMORY_ID))
+}
+
+pub fn get_cycle_usage_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(CYCLE_USAGE_MEMORY_ID))
+}
+
+pub fn get_access_policy_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(ACCESS_POLICY_MEMORY_ID))
+}
+
+pub fn get_listed_buyers_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(LISTED_BUYERS_MEMORY_ID))
+}
+
+pub fn get_buyer_purchases_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(BUYER_PURCHASES_MEMORY_ID))
+}
+
+pub fn get_quotes_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(QUOTES_MEMORY_ID))
+}
+
+pub fn get_next_quote_id_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(NEXT_QUOTE_ID_MEMORY_ID))
+}
+
+pub fn get_pending_mints_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(PENDING_MINTS_MEMORY_ID))
+}
+
+pub fn get_escrow_transfers_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(ESCROW_TRANSFERS_MEMORY_ID))
+}
+
+/// Stored config layouts. A new variant is added whenever a change of `HivingConfig` cannot be
+/// covered by candid optional fields, together with the migration from the previous one
+#[derive(Clone, Deserialize, Serialize, candid::CandidType)]
+pub enum VersionedConfig {
+    V1(HivingConfig),
+}
+
+impl VersionedConfig {
+    pub fn migrate(self) -> HivingConfig {
+        match self {
+            VersionedConfig::V1(config) => config,
+        }
+    }
+}
+
+impl Storable for VersionedConfig {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+pub struct StorableHiver(pub HiverRegistration);
+
+impl Storable for StorableHiver {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        StorableHiver(candid::decode_one(&bytes).unwrap())
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+pub struct StorableContract(pub DiscountContract);
+
+impl Storable for StorableContract {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        StorableContract(candid::decode_one(&bytes).unwrap())
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+pub struct StorableTransitions(pub Vec<ContractTransition>);
+
+impl Storable for StorableTransitions {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        StorableTransitions(candid::decode_one(&bytes).unwrap())
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+pub struct StorableQuote(pub QuoteReservation);
+
+impl Storable for StorableQuote {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        StorableQuote(candid::decode_one(&bytes).unwrap())
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl Storable for AccessPolicy {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl Storable for EscrowTransfer {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
@@ -1,76 +1,261 @@
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use crate::stable_storage::*;
 use abstractions::hiving::{
-    ContractId, ContractStatus, DiscountContract, HiverId, HiverRegistration, PriceQuote,
-    TimeUnits,
+    ContractId, ContractStatus, ContractTransition, DiscountContract, HiverId, HiverRegistration, PriceQuote,
+    PricingInput, PricingPolicy, QuoteId, QuoteReservation, TimeUnits,
 };
-use candid::Nat;
-use std::collections::BTreeMap;
+use abstractions::{DiscountValue, Timestamp};
+use ic_stable_structures::{StableBTreeMap, StableCell};
+use std::collections::BTreeSet;
+use std::ops::Bound as RangeBound;
 use icrc_ledger_types::icrc1::account::Account;
 use serde::{Deserialize, Serialize};
 use candid::Principal;
-use abstractions::MetadataValue;
 use abstractions::dao::Discount;
 
-#[derive(Default, Clone, Deserialize, Serialize, candid::CandidType)]
+/// version of the stable memory layout written by this code
+pub const LAYOUT_VERSION: u32 = 1;
+/// how long a quote can be bought at its price
+pub const QUOTE_TTL_NS: u64 = 5 * 60 * 1_000_000_000;
+
 pub struct HivingState {
-    pub config: HivingConfig,
-    pub hivers: Vec<HiverRegistration>,
-    pub available_time: Vec<TimeUnits>,
-    pub contracts: Vec<DiscountContract>,
-    pub cycle_usage: BTreeMap<(HiverId, u64), TimeUnits>,
+    layout_version: StableCell<u32, IcpMemory>,
+    config: StableCell<VersionedConfig, IcpMemory>,
+    hivers: StableBTreeMap<HiverId, StorableHiver, IcpMemory>,
+    available_time: StableBTreeMap<HiverId, TimeUnits, IcpMemory>,
+    contracts: StableBTreeMap<ContractId, StorableContract, IcpMemory>,
+    /// status history of each contract
+    transitions: StableBTreeMap<ContractId, StorableTransitions, IcpMemory>,
+    cycle_usage: StableBTreeMap<(HiverId, u64), TimeUnits, IcpMemory>,
+    access_policy: StableCell<AccessPolicy, IcpMemory>,
+    /// buyers on the allowlist or blocklist, depending on the policy's filter
+    listed_buyers: StableBTreeMap<Principal, (), IcpMemory>,
+    /// contracts opened per buyer within a cycle
+    buyer_purchases: StableBTreeMap<(u64, Principal), u32, IcpMemory>,
+    /// quotes waiting to be bought, by id and thus by expiry
+    quotes: StableBTreeMap<QuoteId, StorableQuote, IcpMemory>,
+    next_quote_id: StableCell<QuoteId, IcpMemory>,
+    /// DAO mint key of paid contracts whose discount mint had an unknown outcome
+    pending_mints: StableBTreeMap<ContractId, u64, IcpMemory>,
+    /// escrow transfers sent without a known outcome, resent unchanged until they resolve
+    escrow_transfers: StableBTreeMap<ContractId, EscrowTransfer, IcpMemory>,
+    /// contracts whose payment and mint are being processed by `buy_discount`
+    pub purchases_in_flight: BTreeSet<ContractId>,
 }
 
 impl HivingState {
+    pub fn init() -> Self {
+        Self {
+            layout_version: StableCell::init(get_layout_version_memory(), 0).unwrap(),
+            config: StableCell::init(get_config_memory(), VersionedConfig::V1(HivingConfig::default())).unwrap(),
+            hivers: StableBTreeMap::init(get_hivers_memory()),
+            available_time: StableBTreeMap::init(get_available_time_memory()),
+            contracts: StableBTreeMap::init(get_contracts_memory()),
+            transitions: StableBTreeMap::init(get_transitions_memory()),
+            cycle_usage: StableBTreeMap::init(get_cycle_usage_memory()),
+            access_policy: StableCell::init(get_access_policy_memory(), AccessPolicy::default()).unwrap(),
+            listed_buyers: StableBTreeMap::init(get_listed_buyers_memory()),
+            buyer_purchases: StableBTreeMap::init(get_buyer_purchases_memory()),
+            quotes: StableBTreeMap::init(get_quotes_memory()),
+            next_quote_id: StableCell::init(get_next_quote_id_memory(), 0).unwrap(),
+            pending_mints: StableBTreeMap::init(get_pending_mints_memory()),
+            escrow_transfers: StableBTreeMap::init(get_escrow_transfers_memory()),
+            purchases_in_flight: BTreeSet::new(),
+        }
+    }
+
+    /// Brings the stored layout up to `LAYOUT_VERSION` and returns the version found. Version 0
+    /// is a fresh memory, either a new canister or one upgraded from a release that kept its
+    /// state on the heap, which the upgrade wiped.
+    pub fn migrate(&mut self) -> u32 {
+        let stored = *self.layout_version.get();
+        if stored < LAYOUT_VERSION {
+            self.layout_version.set(LAYOUT_VERSION).unwrap();
+        }
+
+        stored
+    }
+
     pub fn set_config(&mut self, cfg: HivingConfig) {
-        self.config = cfg;
+        self.config.set(VersionedConfig::V1(cfg)).unwrap();
     }
 
     pub fn get_config(&self) -> HivingConfig {
-        self.config.clone()
+        self.config.get().clone().migrate()
+    }
+
+    pub fn get_access_policy(&self) -> AccessPolicy {
+        self.access_policy.get().clone()
+    }
+
+    pub fn set_access_policy(&mut self, policy: AccessPolicy) {
+        self.access_policy.set(policy).unwrap();
+    }
+
+    pub fn set_buyer_listed(&mut self, buyer: Principal, listed: bool) {
+        if listed {
+            self.listed_buyers.insert(buyer, ());
+        } else {
+            self.listed_buyers.remove(&buyer);
+        }
+    }
+
+    pub fn list_buyers(&self, prev: Option<Principal>, take: usize) -> Vec<Principal> {
+        let start = match prev {
+            Some(prev) => RangeBound::Excluded(prev),
+            None => RangeBound::Unbounded,
+        };
+
+        self.listed_buyers.range((start, RangeBound::Unbounded)).map(|(buyer, _)| buyer).take(take).collect()
+    }
+
+    pub fn is_buyer_allowed(&self, buyer: &Principal) -> bool {
+        self.get_access_policy().buyer_filter.allows(self.listed_buyers.contains_key(buyer))
+    }
+
+    /// counts a purchase of the buyer in the cycle, unless it exceeds the policy's limit
+    pub fn count_purchase(&mut self, cycle_number: u64, buyer: Principal) -> Result<(), String> {
+        let purchases = self.buyer_purchases.get(&(cycle_number, buyer)).unwrap_or(0);
+        if let Some(limit) = self.get_access_policy().max_purchases_per_cycle
+            && purchases >= limit
+        {
+            return Err("Purchase limit for this cycle reached".to_string());
+        }
+        self.buyer_purchases.insert((cycle_number, buyer), purchases + 1);
+
+        Ok(())
+    }
+
+    /// gives back a purchase of the buyer in the cycle, e.g. when its contract is cancelled
+    pub fn release_purchase(&mut self, cycle_number: u64, buyer: Principal) {
+        match self.buyer_purchases.get(&(cycle_number, buyer)) {
+            Some(purchases) if purchases > 1 => {
+                self.buyer_purchases.insert((cycle_number, buyer), purchases - 1);
+            }
+            Some(_) => {
+                self.buyer_purchases.remove(&(cycle_number, buyer));
+            }
+            None => {}
+        }
     }
 
     pub fn add_hiver(&mut self, reg: HiverRegistration) -> HiverId {
         let id = self.hivers.len() as HiverId;
-        self.hivers.push(reg);
-        self.available_time.push(self.hivers[id as usize].max_time_units);
+        self.available_time.insert(id, reg.max_time_units);
+        self.hivers.insert(id, StorableHiver(reg));
         id
     }
 
-    pub fn get_hiver(&self, id: HiverId) -> Option<&HiverRegistration> {
-        self.hivers.get(id as usize)
+    pub fn get_hiver(&self, id: HiverId) -> Option<HiverRegistration> {
+        self.hivers.get(&id).map(|hiver| hiver.0)
+    }
+
+    pub fn find_hiver(&self, principal: &Principal) -> Option<HiverId> {
+        self.hivers.iter().find(|(_, hiver)| &hiver.0.principal == principal).map(|(id, _)| id)
     }
 
     pub fn get_available_time(&self, id: HiverId) -> Option<TimeUnits> {
-        self.available_time.get(id as usize).copied()
+        self.available_time.get(&id)
+    }
+
+    pub fn set_pricing(&mut self, hiver_id: HiverId, pricing: PricingPolicy) -> Result<(), String> {
+        let mut hiver = self.get_hiver(hiver_id).ok_or("Hiver not found")?;
+        hiver.pricing = Some(pricing);
+        self.hivers.insert(hiver_id, StorableHiver(hiver));
+
+        Ok(())
     }
 
-    pub fn quote(&self, hiver_id: HiverId, time_units: TimeUnits) -> Option<PriceQuote> {
+    /// prices the time units with the hiver's policy, `discount_value` being the discount the DAO
+    /// grants on the product
+    pub fn quote(
+        &self,
+        hiver_id: HiverId,
+        time_units: TimeUnits,
+        product_price: u128,
+        discount_value: DiscountValue,
+        cycle_number: u64,
+    ) -> Option<PriceQuote> {
         let hiver = self.get_hiver(hiver_id)?;
-        let price = hiver.price_per_time_unit.clone() * Nat::from(time_units);
-        let discount_value = time_units as f32;
+        let input = PricingInput {
+            product_price,
+            discount_value,
+            time_units,
+            price_per_time_unit: hiver.price_per_time_unit,
+            cycle_usage: self.cycle_usage.get(&(hiver_id, cycle_number)).unwrap_or(0),
+            cycle_cap: hiver.cycle_cap,
+        };
+        let breakdown = hiver.pricing.unwrap_or_default().quote(&input);
 
         Some(PriceQuote {
             time_units,
-            ckusdc_cost: price,
+            ckusdc_cost: breakdown.total.clone(),
             discount_value,
+            breakdown,
         })
     }
 
+    /// Holds the quote for the buyer for `QUOTE_TTL_NS`. Quotes share this lifetime, so the expired
+    /// ones are found at the start of the map and dropped on the way.
+    pub fn reserve_quote(
+        &mut self,
+        buyer: Principal,
+        hiver_id: HiverId,
+        product_price: u128,
+        quote: PriceQuote,
+        cycle_number: u64,
+        now: Timestamp,
+    ) -> QuoteReservation {
+        while let Some((id, expired)) = self.quotes.first_key_value()
+            && expired.0.expires_at <= now
+        {
+            self.quotes.remove(&id);
+        }
+
+        let id = *self.next_quote_id.get();
+        self.next_quote_id.set(id + 1).unwrap();
+        let reservation = QuoteReservation {
+            id,
+            buyer,
+            hiver_id,
+            product_price,
+            quote,
+            cycle_number,
+            expires_at: now + QUOTE_TTL_NS,
+        };
+        self.quotes.insert(id, StorableQuote(reservation.clone()));
+
+        reservation
+    }
+
+    /// removes the buyer's quote so it can be bought only once
+    pub fn take_quote(&mut self, id: QuoteId, buyer: Principal, now: Timestamp) -> Result<QuoteReservation, PurchaseError> {
+        match self.quotes.get(&id) {
+            Some(quote) if quote.0.buyer == buyer => {
+                self.quotes.remove(&id);
+                if quote.0.expires_at <= now {
+                    return Err(PurchaseError::QuoteExpired);
+                }
+                Ok(quote.0)
+            }
+            _ => Err(PurchaseError::QuoteNotFound),
+        }
+    }
+
     pub fn reserve_time(&mut self, hiver_id: HiverId, time_units: TimeUnits) -> Result<(), String> {
-        let idx = hiver_id as usize;
-        let available = self.available_time.get_mut(idx).ok_or("Hiver not found")?;
-        if *available < time_units {
+        let available = self.available_time.get(&hiver_id).ok_or("Hiver not found")?;
+        if available < time_units {
             return Err("Not enough available staking time".to_string());
         }
-        *available -= time_units;
+        self.available_time.insert(hiver_id, available - time_units);
         Ok(())
     }
 
     pub fn release_time(&mut self, hiver_id: HiverId, time_units: TimeUnits) {
-        if let Some(avail) = self.available_time.get_mut(hiver_id as usize) {
-            *avail = avail.saturating_add(time_units);
+        if let Some(avail) = self.available_time.get(&hiver_id) {
+            self.available_time.insert(hiver_id, avail.saturating_add(time_units));
         }
     }
 
@@ -84,7 +269,6 @@ impl HivingState {
         let used = self
             .cycle_usage
             .get(&(hiver_id, cycle_number))
-            .cloned()
             .unwrap_or(0);
         if used + time_units > reg.cycle_cap {
             return Err("Cycle cap exceeded".to_string());
@@ -92,56 +276,126 @@ impl HivingState {
         Ok(())
     }
 
+    pub fn release_cycle_usage(&mut self, hiver_id: HiverId, cycle_number: u64, time_units: TimeUnits) {
+        if let Some(used) = self.cycle_usage.get(&(hiver_id, cycle_number)) {
+            self.cycle_usage.insert((hiver_id, cycle_number), used.saturating_sub(time_units));
+        }
+    }
+
     pub fn mark_cycle_usage(
         &mut self,
         hiver_id: HiverId,
         cycle_number: u64,
         time_units: TimeUnits,
     ) {
-        let entry = self
-            .cycle_usage
-            .entry((hiver_id, cycle_number))
-            .or_insert(0);
-        *entry = entry.saturating_add(time_units);
+        let used = self.cycle_usage.get(&(hiver_id, cycle_number)).unwrap_or(0);
+        self.cycle_usage.insert((hiver_id, cycle_number), used.saturating_add(time_units));
     }
 
-    pub fn open_contract(
-        &mut self,
-        buyer: Account,
-        seller: candid::Principal,
-        time_units: TimeUnits,
-        price: Nat,
-        discount_value: f32,
-    ) -> ContractId {
+    pub fn open_contract(&mut self, buyer: Account, seller: Principal, quote: PriceQuote, cycle_number: u64, now: Timestamp) -> DiscountContract {
         let id = self.contracts.len() as ContractId;
         let contract = DiscountContract {
             id,
             buyer,
             seller,
-            time_units,
-            price,
-            discount_value,
-            status: ContractStatus::Paid,
+            time_units: quote.time_units,
+            price: quote.ckusdc_cost,
+            discount_value: quote.discount_value,
+            status: ContractStatus::Open,
+            discount_id: None,
+            cycle_number,
         };
-        self.contracts.push(contract);
-        id
+        self.contracts.insert(id, StorableContract(contract.clone()));
+        let opened = ContractTransition {
+            status: ContractStatus::Open,
+            timestamp: now,
+            caller: buyer.owner,
+            block_index: None,
+        };
+        self.transitions.insert(id, StorableTransitions(vec![opened]));
+        contract
     }
 
-    pub fn get_contract(&self, id: ContractId) -> Option<&DiscountContract> {
-        self.contracts.get(id as usize)
+    pub fn get_contract(&self, id: ContractId) -> Option<DiscountContract> {
+        self.contracts.get(&id).map(|contract| contract.0)
     }
 
-    pub fn mark_redeemed(&mut self, id: ContractId) -> Option<DiscountContract> {
-        if let Some(contract) = self.contracts.get_mut(id as usize) {
-            contract.status = ContractStatus::Redeemed;
-            return Some(contract.clone());
+    pub fn list_contracts(&self, buyer: Option<Account>, prev: Option<ContractId>, take: usize) -> Vec<DiscountContract> {
+        let start = match prev {
+            Some(prev) => RangeBound::Excluded(prev),
+            None => RangeBound::Unbounded,
+        };
+
+        self.contracts
+            .range((start, RangeBound::Unbounded))
+            .map(|(_, contract)| contract.0)
+            .filter(|contract| buyer.is_none_or(|buyer| contract.buyer == buyer))
+            .take(take)
+            .collect()
+    }
+
+    pub fn get_transitions(&self, id: ContractId) -> Option<Vec<ContractTransition>> {
+        self.transitions.get(&id).map(|transitions| transitions.0)
+    }
+
+    pub fn get_pending_mint(&self, id: ContractId) -> Option<u64> {
+        self.pending_mints.get(&id)
+    }
+
+    pub fn set_pending_mint(&mut self, id: ContractId, mint_key: u64) {
+        self.pending_mints.insert(id, mint_key);
+    }
+
+    pub fn remove_pending_mint(&mut self, id: ContractId) {
+        self.pending_mints.remove(&id);
+    }
+
+    pub fn get_escrow_transfer(&self, id: ContractId) -> Option<EscrowTransfer> {
+        self.escrow_transfers.get(&id)
+    }
+
+    pub fn set_escrow_transfer(&mut self, id: ContractId, transfer: EscrowTransfer) {
+        self.escrow_transfers.insert(id, transfer);
+    }
+
+    pub fn remove_escrow_transfer(&mut self, id: ContractId) {
+        self.escrow_transfers.remove(&id);
+    }
+
+    pub fn set_discount(&mut self, id: ContractId, discount_id: u128) {
+        if let Some(mut contract) = self.get_contract(id) {
+            contract.discount_id = Some(discount_id);
+            self.contracts.insert(id, StorableContract(contract));
         }
-        None
+    }
+
+    /// Moves the contract to the next status, rejecting jumps the lifecycle doesn't allow.
+    /// Cancelling gives the contract's time units back to the hiver and the purchase back to the buyer.
+    pub fn transition_contract(&mut self, id: ContractId, transition: ContractTransition) -> Result<DiscountContract, String> {
+        let mut contract = self.get_contract(id).ok_or("Contract not found")?;
+        if !contract.status.can_transition_to(&transition.status) {
+            return Err(format!("Contract can't move from {:?} to {:?}", contract.status, transition.status));
+        }
+        contract.status = transition.status.clone();
+        self.contracts.insert(id, StorableContract(contract.clone()));
+
+        if contract.status == ContractStatus::Cancelled {
+            self.release_purchase(contract.cycle_number, contract.buyer.owner);
+            if let Some(hiver_id) = self.find_hiver(&contract.seller) {
+                self.release_time(hiver_id, contract.time_units);
+                self.release_cycle_usage(hiver_id, contract.cycle_number, contract.time_units);
+            }
+        }
+        let mut transitions = self.get_transitions(id).unwrap_or_default();
+        transitions.push(transition);
+        self.transitions.insert(id, StorableTransitions(transitions));
+
+        Ok(contract)
     }
 }
 
 thread_local! {
-    static STATE: Rc<RefCell<HivingState>> = Rc::new(RefCell::new(HivingState::default()));
+    static STATE: Rc<RefCell<HivingState>> = Rc::new(RefCell::new(HivingState::init()));
 }
 
 pub fn with_state<F, R>(f: F) -> R
@@ -154,18 +408,12 @@ where
 pub fn build_discount_from_contract(
     contract: &DiscountContract,
 ) -> Discount {
-    let metadata = vec![("value".to_string(), MetadataValue::Text(contract.discount_value.to_string()))];
-    let mut discount = Discount::new(contract.discount_value, contract.buyer.clone());
-    discount.id = contract.id as u128;
-
-    // The DAO discount service will recreate metadata; we keep it here for clarity.
-    let _ = metadata;
-
-    discount
+    Discount::new(contract.id as u128, contract.discount_value, contract.buyer)
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, candid::CandidType)]
 pub struct HivingConfig {
+    pub owner: Principal,
     pub dao_canister_id: Principal,
     pub ckusdc_canister_id: Principal,
 }
@@ -173,27 +421,74 @@ pub struct HivingConfig {
 impl Default for HivingConfig {
     fn default() -> Self {
         Self {
+            owner: Principal::anonymous(),
             dao_canister_id: Principal::anonymous(),
             ckusdc_canister_id: Principal::anonymous(),
         }
     }
 }
 
+/// Escrow transfer of a contract, kept until the ledger's answer is known
+#[derive(Clone, Debug, Deserialize, Serialize, candid::CandidType, PartialEq)]
+pub struct EscrowTransfer {
+    pub to: Account,
+    pub created_at_time: Timestamp,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, candid::CandidType, PartialEq)]
+pub enum PurchaseError {
+    /// there is no quote with this id for the caller, or it was already bought
+    QuoteNotFound,
+    QuoteExpired,
+    Rejected { reason: String },
+}
+
+impl From<String> for PurchaseError {
+    fn from(reason: String) -> Self {
+        PurchaseError::Rejected { reason }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, candid::CandidType, PartialEq)]
+pub enum BuyerFilter {
+    #[default]
+    Open,
+    /// only listed buyers can purchase
+    Allowlist,
+    /// listed buyers can't purchase
+    Blocklist,
+}
+
+impl BuyerFilter {
+    pub fn allows(&self, listed: bool) -> bool {
+        match self {
+            BuyerFilter::Open => true,
+            BuyerFilter::Allowlist => listed,
+            BuyerFilter::Blocklist => !listed,
+        }
+    }
+}
+
+/// Who administers the canister and who can buy from it. The owner is kept in `HivingConfig`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, candid::CandidType)]
+pub struct AccessPolicy {
+    /// owner proposed by the current one, who becomes owner once they accept
+    pub pending_owner: Option<Principal>,
+    /// principals managing buyers, limits and hivers next to the owner
+    pub operators: Vec<Principal>,
+    pub buyer_filter: BuyerFilter,
+    pub max_purchases_per_cycle: Option<u32>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use candid::Principal;
+    use candid::{Nat, Principal};
 
     #[test]
     fn reserves_and_releases_time() {
-        let mut state = HivingState::default();
-        state.add_hiver(HiverRegistration {
-            principal: Principal::anonymous(),
-            metadata: vec![],
-            price_per_time_unit: Nat::from(1u32),
-            max_time_units: 10,
-            cycle_cap: 5,
-        });
+        let mut state = HivingState::init();
+        state.add_hiver(registration(Principal::anonymous(), 5));
 
         assert_eq!(Some(10), state.get_available_time(0));
         assert!(state.reserve_time(0, 4).is_ok());
@@ -204,18 +499,200 @@ mod tests {
 
     #[test]
     fn cycle_cap_blocks_excess() {
-        let mut state = HivingState::default();
-        state.add_hiver(HiverRegistration {
-            principal: Principal::anonymous(),
-            metadata: vec![],
-            price_per_time_unit: Nat::from(1u32),
-            max_time_units: 10,
-            cycle_cap: 3,
-        });
+        let mut state = HivingState::init();
+        state.add_hiver(registration(Principal::anonymous(), 3));
 
         assert!(state.check_cycle_cap(0, 1, 2).is_ok());
         assert!(state.check_cycle_cap(0, 1, 4).is_err());
         state.mark_cycle_usage(0, 1, 2);
         assert!(state.check_cycle_cap(0, 1, 2).is_err());
     }
+
+    fn quote(time_units: TimeUnits) -> PriceQuote {
+        let breakdown = PricingPolicy::FlatFee { fee: Nat::from(time_units) }.quote(&PricingInput {
+            product_price: 0,
+            discount_value: 0.0,
+            time_units,
+            price_per_time_unit: Nat::from(0u8),
+            cycle_usage: 0,
+            cycle_cap: 0,
+        });
+        PriceQuote {
+            time_units,
+            ckusdc_cost: breakdown.total.clone(),
+            discount_value: time_units as f32,
+            breakdown,
+        }
+    }
+
+    fn registration(principal: Principal, cycle_cap: TimeUnits) -> HiverRegistration {
+        HiverRegistration {
+            principal,
+            metadata: vec![],
+            price_per_time_unit: Nat::from(1u32),
+            max_time_units: 10,
+            cycle_cap,
+            payout_account: Account::from(principal),
+            pricing: None,
+        }
+    }
+
+    fn transition(status: ContractStatus) -> ContractTransition {
+        ContractTransition {
+            status,
+            timestamp: 0,
+            caller: Principal::anonymous(),
+            block_index: None,
+        }
+    }
+
+    #[test]
+    fn cancelled_contract_releases_time_cycle_usage_and_purchase() {
+        let mut state = HivingState::init();
+        let hiver = Principal::from_slice(&[1]);
+        let buyer = Principal::from_slice(&[2]);
+        state.add_hiver(registration(hiver, 3));
+        state.set_access_policy(AccessPolicy { max_purchases_per_cycle: Some(1), ..AccessPolicy::default() });
+
+        state.reserve_time(0, 3).unwrap();
+        state.mark_cycle_usage(0, 1, 3);
+        state.count_purchase(1, buyer).unwrap();
+        let id = state.open_contract(Account::from(buyer), hiver, quote(3), 1, 0).id;
+        assert!(state.check_cycle_cap(0, 1, 1).is_err());
+        assert!(state.count_purchase(1, buyer).is_err());
+
+        state.transition_contract(id, transition(ContractStatus::Cancelled)).unwrap();
+        assert_eq!(ContractStatus::Cancelled, state.get_contract(id).unwrap().status);
+        assert_eq!(Some(10), state.get_available_time(0));
+        assert!(state.check_cycle_cap(0, 1, 3).is_ok());
+        assert!(state.count_purchase(1, buyer).is_ok());
+    }
+
+    #[test]
+    fn illegal_transitions_are_rejected() {
+        let mut state = HivingState::init();
+        let id = state.open_contract(Account::from(Principal::anonymous()), Principal::anonymous(), quote(1), 1, 0).id;
+
+        assert!(state.transition_contract(id, transition(ContractStatus::Fulfilled)).is_err());
+        assert!(state.transition_contract(id, transition(ContractStatus::Paid)).is_ok());
+        assert!(state.transition_contract(id, transition(ContractStatus::Redeemed)).is_err());
+        assert!(state.transition_contract(id, transition(ContractStatus::Fulfilled)).is_ok());
+        assert!(state.transition_contract(id, transition(ContractStatus::Cancelled)).is_err());
+        assert!(state.transition_contract(id, transition(ContractStatus::Redeemed)).is_ok());
+
+        let history = state.get_transitions(id).unwrap().iter().map(|t| t.status.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            vec![ContractStatus::Open, ContractStatus::Paid, ContractStatus::Fulfilled, ContractStatus::Redeemed],
+            history
+        );
+    }
+
+    #[test]
+    fn migration_stamps_the_layout_version() {
+        let mut state = HivingState::init();
+        assert_eq!(0, state.migrate());
+        assert_eq!(LAYOUT_VERSION, state.migrate());
+    }
+
+    #[test]
+    fn quotes_follow_the_hiver_pricing_policy() {
+        let mut state = HivingState::init();
+        let mut hiver = registration(Principal::from_slice(&[1]), 4);
+        hiver.price_per_time_unit = Nat::from(100u32);
+        state.add_hiver(hiver);
+
+        let total = |state: &HivingState| state.quote(0, 2, 1_000, 10.0, 1).unwrap().ckusdc_cost;
+        assert_eq!(Nat::from(200u32), total(&state));
+
+        state.set_pricing(0, PricingPolicy::SavingsMarkup { basis_points: 1_000 }).unwrap();
+        assert_eq!(Nat::from(10u32), total(&state));
+        // beyond the precision of f64
+        let saved_amount = state.quote(0, 1, 18_014_398_509_481_986, 50.0, 1).unwrap().breakdown.saved_amount;
+        assert_eq!(Nat::from(9_007_199_254_740_993u64), saved_amount);
+
+        state.set_pricing(0, PricingPolicy::DemandSurge { max_surge_basis_points: 5_000 }).unwrap();
+        state.mark_cycle_usage(0, 1, 2);
+        let quote = state.quote(0, 2, 1_000, 10.0, 1).unwrap();
+        assert_eq!((Nat::from(200u32), Nat::from(50u32)), (quote.breakdown.base, quote.breakdown.surge));
+        assert_eq!(Nat::from(250u32), quote.ckusdc_cost);
+    }
+
+    #[test]
+    fn quotes_are_bought_once_before_expiry() {
+        let mut state = HivingState::init();
+        let (buyer, other) = (Principal::from_slice(&[1]), Principal::from_slice(&[2]));
+        let first = state.reserve_quote(buyer, 0, 100, quote(1), 1, 0);
+        let second = state.reserve_quote(buyer, 0, 100, quote(2), 1, 0);
+
+        assert_eq!(Err(PurchaseError::QuoteNotFound), state.take_quote(first.id, other, 1).map(|q| q.id));
+        assert_eq!(Ok(first.id), state.take_quote(first.id, buyer, 1).map(|q| q.id));
+        assert_eq!(Err(PurchaseError::QuoteNotFound), state.take_quote(first.id, buyer, 1).map(|q| q.id));
+
+        assert_eq!(Err(PurchaseError::QuoteExpired), state.take_quote(second.id, buyer, QUOTE_TTL_NS).map(|q| q.id));
+        assert_eq!(Err(PurchaseError::QuoteNotFound), state.take_quote(second.id, buyer, QUOTE_TTL_NS).map(|q| q.id));
+    }
+
+    #[test]
+    fn expired_quotes_are_dropped() {
+        let mut state = HivingState::init();
+        let buyer = Principal::from_slice(&[1]);
+        let stale = state.reserve_quote(buyer, 0, 100, quote(1), 1, 0);
+        let fresh = state.reserve_quote(buyer, 0, 100, quote(1), 1, QUOTE_TTL_NS);
+
+        assert_ne!(stale.id, fresh.id);
+        assert_eq!(1, state.quotes.len());
+        assert_eq!(Ok(fresh.id), state.take_quote(fresh.id, buyer, QUOTE_TTL_NS + 1).map(|q| q.id));
+    }
+
+    #[test]
+    fn buyers_are_filtered_and_rate_limited() {
+        let mut state = HivingState::init();
+        let (alice, bob) = (Principal::from_slice(&[1]), Principal::from_slice(&[2]));
+        state.set_buyer_listed(alice, true);
+        assert!(state.is_buyer_allowed(&bob));
+
+        state.set_access_policy(AccessPolicy {
+            buyer_filter: BuyerFilter::Allowlist,
+            max_purchases_per_cycle: Some(1),
+            ..AccessPolicy::default()
+        });
+        assert!(state.is_buyer_allowed(&alice));
+        assert!(!state.is_buyer_allowed(&bob));
+
+        assert!(state.count_purchase(1, alice).is_ok());
+        assert!(state.count_purchase(1, alice).is_err());
+        assert!(state.count_purchase(2, alice).is_ok());
+    }
+
+    #[test]
+    fn contracts_are_listed_per_buyer() {
+        let mut state = HivingState::init();
+        let (alice, bob) = (Account::from(Principal::from_slice(&[1])), Account::from(Principal::from_slice(&[2])));
+        for buyer in [alice, bob, alice] {
+            state.open_contract(buyer, Principal::anonymous(), quote(1), 1, 0);
+        }
+
+        let ids = |contracts: Vec<DiscountContract>| contracts.iter().map(|contract| contract.id).collect::<Vec<_>>();
+        assert_eq!(vec![0, 2], ids(state.list_contracts(Some(alice), None, 10)));
+        assert_eq!(vec![2], ids(state.list_contracts(Some(alice), Some(0), 10)));
+        assert_eq!(vec![0, 1], ids(state.list_contracts(None, None, 2)));
+    }
+
+    #[test]
+    fn unresolved_deliveries_are_kept_per_contract() {
+        let mut state = HivingState::init();
+        let buyer = Account::from(Principal::from_slice(&[1]));
+        let id = state.open_contract(buyer, Principal::anonymous(), quote(1), 1, 0).id;
+
+        state.set_pending_mint(id, 7);
+        state.set_escrow_transfer(id, EscrowTransfer { to: buyer, created_at_time: 5 });
+        assert_eq!(state.get_pending_mint(id), Some(7));
+        assert_eq!(state.get_escrow_transfer(id), Some(EscrowTransfer { to: buyer, created_at_time: 5 }));
+        assert_eq!(state.get_pending_mint(id + 1), None);
+
+        state.remove_pending_mint(id);
+        state.remove_escrow_transfer(id);
+        assert_eq!(state.get_pending_mint(id), None);
+        assert_eq!(state.get_escrow_transfer(id), None);
+    }
 }
@@ -1,22 +1,30 @@
 use crate::services;
 use crate::services::{DiscountQuotePool, InitArgs};
+use abstractions::hiving::PricingPolicy;
 use abstractions::Account;
 use candid::Principal;
-use ic_cdk::{init, query, update};
+use ic_cdk::{init, post_upgrade, query, update};
 
 #[init]
 fn init(init_args: InitArgs) {
     services::init(init_args);
+    services::start_heartbeat();
+}
+
+#[post_upgrade]
+fn post_upgrade(init_args: Option<InitArgs>) {
+    services::post_upgrade(init_args);
+    services::start_heartbeat();
 }
 
 #[update]
-async fn join_dao() {
-    services::join_dao().await;
+async fn join_dao() -> Result<(), String> {
+    services::join_dao().await
 }
 
 #[update]
-async fn leave_dao() {
-    services::leave_dao().await;
+async fn leave_dao() -> Result<(), String> {
+    services::leave_dao().await
 }
 
 #[update]
@@ -35,11 +43,16 @@ fn list_hivers() -> Vec<Account> {
 }
 
 #[update]
-async fn quote_discounts(price: u128) -> Vec<DiscountQuotePool> {
+fn set_pricing_policy(policy: PricingPolicy) -> Result<(), String> {
+    services::set_pricing_policy(policy)
+}
+
+#[update]
+async fn quote_discounts(price: u128) -> Result<Vec<DiscountQuotePool>, String> {
     services::quote_discounts(price).await
 }
 
 #[update]
-async fn buy_discount(hiver: Principal, price: u128) -> u128 {
+async fn buy_discount(hiver: Principal, price: u128) -> Result<u128, String> {
     services::buy_discount(hiver, price).await
 }
@@ -1,2 +1,3 @@
 mod api;
 mod services;
+mod stable_storage;
@@ -1,31 +1,123 @@
-use abstractions::dao::{DaoClient, DiscountRequest};
+use abstractions::ckusdc::CkUsdcClient;
+use abstractions::dao::{DaoClient, DaoError, DiscountRequest, MintDiscountError};
+use abstractions::hiving::{HivingHeartbeat, PricingInput, PricingPolicy, RegisterHivingCanisterArgs, HEARTBEAT_INTERVAL_NS};
 use abstractions::{Account, DiscountValue};
-use candid::{CandidType, Deserialize, Principal};
+use candid::{CandidType, Deserialize, Nat, Principal};
 use canister_runtime::CdkCallContext;
-use ic_cdk::api::msg_caller;
+use abstractions::runtime::ICallContext;
+use icrc_ledger_types::icrc1::transfer::TransferArg;
+use icrc_ledger_types::icrc2::transfer_from::TransferFromArgs;
+use ic_cdk::api::{canister_cycle_balance, canister_self, msg_caller, time};
+use ic_cdk::futures::spawn;
+use ic_cdk_timers::{clear_timer, set_timer, set_timer_interval, TimerId};
+use crate::stable_storage::{migrate, ConfigStorage, HiversStorage};
 use serde::Serialize;
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
+use std::time::Duration;
 
 pub fn init(init_args: InitArgs) {
-    let caller = msg_caller();
     let config = CanisterConfig {
-        owner: caller,
+        owner: init_args.owner.unwrap_or(msg_caller()),
         dao_address: init_args.dao_address,
+        ckusdc_address: Some(init_args.ckusdc_address),
+        pricing: None,
     };
+    migrate();
     let mut storage = ConfigStorage {};
     storage.set_config(config)
 }
 
-pub async fn join_dao() {
+/// Migrates the stored state. Canisters upgraded from a release that kept its state on the heap
+/// lost their config and need the init arguments again, with the upgrading controller as default
+/// owner.
+pub fn post_upgrade(init_args: Option<InitArgs>) {
+    let found_version = migrate();
+    let mut storage = ConfigStorage {};
+    let mut config = storage.get_config();
+    let owner = match init_args {
+        Some(init_args) => {
+            config.dao_address = init_args.dao_address;
+            config.ckusdc_address = Some(init_args.ckusdc_address);
+            init_args.owner
+        }
+        None if found_version == 0 => ic_cdk::trap("Upgrading a canister without stable state requires init arguments"),
+        None => return,
+    };
+    if let Some(owner) = owner {
+        config.owner = owner;
+    } else if found_version == 0 {
+        config.owner = msg_caller();
+    }
+
+    storage.set_config(config);
+}
+
+pub async fn join_dao() -> Result<(), String> {
+    ensure_owner()?;
     let dao = build_dao_service();
-    dao.hiving_join().await.unwrap();
+    let config = get_config();
+    let args = RegisterHivingCanisterArgs {
+        canister_id: canister_self(),
+        owner: config.owner,
+        metadata: Vec::new(),
+    };
+    match dao.hiving_join(args).await {
+        // joining again, e.g. after a reinstall, keeps the existing registration
+        Ok(Ok(())) | Ok(Err(DaoError::HivingCanisterAlreadyRegistered)) => Ok(()),
+        result => Err(format!("Failed to join the DAO: {:?}", result)),
+    }
 }
 
-pub async fn leave_dao() {
+pub async fn leave_dao() -> Result<(), String> {
+    ensure_owner()?;
     let dao = build_dao_service();
-    dao.hiving_leave().await.unwrap()
+    match dao.hiving_leave().await {
+        Ok(Ok(())) | Ok(Err(DaoError::HivingCanisterNotRegistered)) => Ok(()),
+        result => Err(format!("Failed to leave the DAO: {:?}", result)),
+    }
+}
+
+thread_local! {
+    /// timer reporting to the DAO, replaced when the DAO asks for another interval
+    static HEARTBEAT_TIMER: Cell<Option<TimerId>> = const { Cell::new(None) };
+    /// interval the DAO judges staleness by, known once it answered a report
+    static HEARTBEAT_INTERVAL: Cell<u64> = const { Cell::new(HEARTBEAT_INTERVAL_NS) };
+}
+
+/// Reports to the DAO right away and then once every interval it asks for, `HEARTBEAT_INTERVAL_NS`
+/// until it answered; the DAO marks canisters which stop reporting as stale. Timers do not survive
+/// upgrades, so this is called on init and post_upgrade
+pub fn start_heartbeat() {
+    set_timer(Duration::ZERO, || spawn(send_heartbeat()));
+    schedule_heartbeat(HEARTBEAT_INTERVAL_NS);
+}
+
+fn schedule_heartbeat(interval_ns: u64) {
+    HEARTBEAT_INTERVAL.with(|interval| interval.set(interval_ns));
+    if let Some(timer) = HEARTBEAT_TIMER.with(|timer| timer.take()) {
+        clear_timer(timer);
+    }
+    let timer = set_timer_interval(Duration::from_nanos(interval_ns), || spawn(send_heartbeat()));
+    HEARTBEAT_TIMER.with(|current| current.set(Some(timer)));
+}
+
+/// a failed report is only repeated with the next one
+async fn send_heartbeat() {
+
+    let heartbeat = HivingHeartbeat {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        cycles: Nat::from(canister_cycle_balance()),
+        owner: get_config().owner,
+    };
+    let dao = build_dao_service();
+    // the DAO refuses reports of canisters which have not joined yet
+    if let Ok(Ok(interval)) = dao.hiving_heartbeat(heartbeat).await
+        && interval > 0
+        && interval != HEARTBEAT_INTERVAL.with(|current| current.get())
+    {
+        schedule_heartbeat(interval);
+    }
 }
 
 pub fn list_hivers() -> Vec<Account> {
@@ -49,14 +141,31 @@ pub fn leave_pool() {
     storage.remove_hiver(&account);
 }
 
-pub async fn quote_discounts(price: u128) -> Vec<DiscountQuotePool> {
+/// Sets how the pool prices the discounts of its hivers. Only the owner can change it. The pool
+/// sells whole discounts rather than time, so the time based policies are refused
+pub fn set_pricing_policy(policy: PricingPolicy) -> Result<(), String> {
+    let mut storage = ConfigStorage {};
+    let mut config = storage.get_config();
+    if config.owner != msg_caller() {
+        return Err("Only the owner can set the pricing policy".to_string());
+    }
+    if is_time_based(&policy) {
+        return Err("The pool sells whole discounts, time based pricing policies do not apply".to_string());
+    }
+
+    config.pricing = Some(policy);
+    storage.set_config(config);
+    Ok(())
+}
+
+pub async fn quote_discounts(price: u128) -> Result<Vec<DiscountQuotePool>, String> {
     let storage = HiversStorage::new();
     let hivers = storage.get_hivers();
 
     let mut result = Vec::new();
 
     for hiver in hivers {
-        let quote = quote_discount(hiver, price).await;
+        let quote = quote_discount(hiver, price).await?;
         let hiver_quote = DiscountQuotePool {
             hiver: hiver.owner,
             discount_value: quote.discount_value,
@@ -65,37 +174,106 @@ pub async fn quote_discounts(price: u128) -> Vec<DiscountQuotePool> {
         result.push(hiver_quote);
     }
 
-    result
+    Ok(result)
 }
 
-async fn quote_discount(hiver: Account, product_price: u128) -> DiscountQuote {
+async fn quote_discount(hiver: Account, product_price: u128) -> Result<DiscountQuote, String> {
     let dao = build_dao_service();
 
-    let discount_value = dao.calculate_max_discount(&hiver, &product_price).await.unwrap();
-    let discount_price = calculate_discount_price(product_price, discount_value);
-
-    DiscountQuote {
+    let discount_value = match dao.calculate_max_discount(&hiver, &product_price).await {
+        Ok(Ok(discount_value)) => discount_value,
+        result => return Err(format!("Failed to calculate the discount: {:?}", result)),
+    };
+    let input = PricingInput {
+        product_price,
         discount_value,
-        price: discount_price,
-    }
-}
+        time_units: 1,
+        price_per_time_unit: Nat::from(0u8),
+        cycle_usage: 0,
+        cycle_cap: 0,
+    };
+    let breakdown = get_config().pricing_policy().quote(&input);
 
-fn calculate_discount_price(product_price: u128, discount_value: DiscountValue) -> u128 {
-    let discounted_money = product_price as f32 * (discount_value as f32 / 100.0);
-    let discount_price = (discounted_money * 0.1).round() as u128;
-    discount_price
+    Ok(DiscountQuote {
+        discount_value,
+        price: breakdown.total,
+    })
 }
 
-pub async fn buy_discount(hiver: Principal, price: u128) -> u128 {
-    let dao = build_dao_service();
+/// Buys a discount of a pool hiver for a product of the given price at the current quote. The
+/// quoted ckUSDC price is collected from the buyer through an ICRC-2 allowance into the pool, then
+/// the DAO is asked to mint the discount. The payment less the ledger fee goes on to the hiver once
+/// the discount is minted, or back to the buyer when the DAO refused it. Payments of purchases
+/// whose outcome is unknown stay with the pool.
+pub async fn buy_discount(hiver: Principal, price: u128) -> Result<u128, String> {
+    let buyer = Account::from(msg_caller());
     let hiver = Account::from(hiver);
-    let discount_value = dao.calculate_max_discount(&hiver, &price).await.unwrap();
+    if !HiversStorage::new().has_hiver(&hiver) {
+        return Err("Hiver is not in the pool".to_string());
+    }
+    let quote = quote_discount(hiver, price).await?;
 
-    let caller = msg_caller();
-    let discount_request = DiscountRequest::new(discount_value, Account::from(caller));
-    let nft = dao.mint_discount(hiver, discount_request).await.unwrap();
+    let ckusdc = build_ckusdc_client()?;
+    let fee = ckusdc.inner().fee().await.map_err(|err| format!("Failed to get the ckUSDC fee: {:?}", err))?;
+    if quote.price <= fee {
+        return Err("Price does not cover the ckUSDC transfer fee".to_string());
+    }
+    let payment = TransferFromArgs {
+        spender_subaccount: None,
+        from: buyer,
+        to: Account::from(canister_self()),
+        amount: quote.price.clone(),
+        fee: None,
+        memo: None,
+        created_at_time: Some(time()),
+    };
+    match ckusdc.inner().transfer_from(payment).await {
+        Ok(Ok(_)) => {}
+        Err(err) if !CdkCallContext::is_clean_reject(&err) => {
+            return Err(format!("Payment outcome unknown: {:?}", err));
+        }
+        result => return Err(format!("Failed to collect the payment: {:?}", result)),
+    }
 
-    nft
+    let mut request = DiscountRequest::new(price, buyer);
+    request.value = Some(quote.discount_value);
+    let amount = quote.price - fee;
+    match build_dao_service().mint_discount(hiver, request).await {
+        Ok(Ok(discount_id)) => {
+            // the discount is delivered either way, a failed payout stays with the pool
+            let _ = pay_out(&ckusdc, hiver, amount).await;
+            Ok(discount_id)
+        }
+        Ok(Err(DaoError::Mint(MintDiscountError::NftMintPending { mint_key }))) => {
+            Err(format!("Discount mint {} pending, the payment stays with the pool", mint_key))
+        }
+        Err(err) if !CdkCallContext::is_clean_reject(&err) => {
+            Err(format!("Discount mint outcome unknown, the payment stays with the pool: {:?}", err))
+        }
+        result => {
+            let refund = pay_out(&ckusdc, buyer, amount).await;
+            Err(format!("Failed to mint the discount: {:?}, refund: {:?}", result, refund))
+        }
+    }
+}
+
+async fn pay_out(ckusdc: &CkUsdcClient<CdkCallContext>, to: Account, amount: Nat) -> Result<Nat, String> {
+    let args = TransferArg {
+        from_subaccount: None,
+        to,
+        fee: None,
+        created_at_time: Some(time()),
+        memo: None,
+        amount,
+    };
+    match ckusdc.inner().transfer(args).await {
+        Ok(Ok(block_index)) => Ok(block_index),
+        result => Err(format!("Failed to transfer {:?}", result)),
+    }
+}
+
+fn is_time_based(policy: &PricingPolicy) -> bool {
+    matches!(policy, PricingPolicy::PerTimeUnit | PricingPolicy::DemandSurge { .. })
 }
 
 fn get_config() -> CanisterConfig {
@@ -103,9 +281,19 @@ fn get_config() -> CanisterConfig {
     storage.get_config()
 }
 
-#[derive(Clone, Debug, Deserialize, CandidType)]
-pub struct InitArgs {
-    pub dao_address: Principal,
+fn ensure_owner() -> Result<(), String> {
+    if msg_caller() != get_config().owner {
+        return Err("Only the owner can call this method".to_string());
+    }
+
+    Ok(())
+}
+
+pub use abstractions::hiving::HivingPoolInitArgs as InitArgs;
+
+fn build_ckusdc_client() -> Result<CkUsdcClient<CdkCallContext>, String> {
+    let canister_id = get_config().ckusdc_address.ok_or("The pool has no ckUSDC ledger")?;
+    Ok(CkUsdcClient::new(Rc::new(RefCell::new(CdkCallContext {})), canister_id))
 }
 
 fn build_dao_service() -> DaoClient<CdkCallContext> {
@@ -118,63 +306,45 @@ fn build_dao_service() -> DaoClient<CdkCallContext> {
     client
 }
 
-thread_local! {
-    static CONFIG_STORAGE: RefCell<Option<CanisterConfig>> = RefCell::new(None);
-    static HIVERS_STORAGE: RefCell<HashMap<Account, ()>> = RefCell::new(HashMap::new());
+#[derive(Clone, Debug, Deserialize, Serialize, CandidType)]
+pub struct CanisterConfig {
+    pub owner: Principal,
+    pub dao_address: Principal,
+    /// missing in configs stored before the pool charged for discounts, until the next upgrade sets it
+    pub ckusdc_address: Option<Principal>,
+    /// defaults to `DEFAULT_PRICING`
+    pub pricing: Option<PricingPolicy>,
 }
 
-struct ConfigStorage {}
+/// a tenth of the money the buyer saves with the discount
+const DEFAULT_PRICING: PricingPolicy = PricingPolicy::SavingsMarkup { basis_points: 1_000 };
 
-impl ConfigStorage {
-    pub fn set_config(&mut self, config: CanisterConfig) {
-        CONFIG_STORAGE.with(|cell| {
-            *cell.borrow_mut() = Some(config);
-        });
-    }
-
-    pub fn get_config(&self) -> CanisterConfig {
-        CONFIG_STORAGE.with(|cell| cell.borrow().clone().expect("ConfigStorage is not initialized"))
+impl CanisterConfig {
+    /// a time based policy stored before they were refused prices nothing, so it is replaced too
+    pub fn pricing_policy(&self) -> PricingPolicy {
+        self.pricing.clone().filter(|policy| !is_time_based(policy)).unwrap_or(DEFAULT_PRICING)
     }
 }
 
-struct HiversStorage {}
-
-impl HiversStorage {
-    pub fn new() -> Self {
-        Self {}
-    }
-
-    pub fn add_hiver(&mut self, hiver: Account) {
-        HIVERS_STORAGE.with(|cell| {
-            cell.borrow_mut().insert(hiver, ());
-        })
+impl Default for CanisterConfig {
+    fn default() -> Self {
+        Self {
+            owner: Principal::anonymous(),
+            dao_address: Principal::anonymous(),
+            ckusdc_address: None,
+            pricing: None,
+        }
     }
-
-    pub fn remove_hiver(&mut self, hiver: &Account) {
-        HIVERS_STORAGE.with(|cell| {
-            cell.borrow_mut().remove(hiver);
-        })
-    }
-
-    pub fn get_hivers(&self) -> Vec<Account> {
-        HIVERS_STORAGE.with(|cell| cell.borrow().iter().map(|(account, _)| account.clone()).collect())
-    }
-}
-
-#[derive(Clone, Debug)]
-pub struct CanisterConfig {
-    pub owner: Principal,
-    pub dao_address: Principal,
 }
 
 #[derive(Clone, Debug, Serialize, CandidType)]
 pub struct DiscountQuotePool {
     hiver: Principal,
     discount_value: DiscountValue,
-    price: u128,
+    price: Nat,
 }
 
 pub struct DiscountQuote {
     discount_value: DiscountValue,
-    price: u128,
+    price: Nat,
 }
@@ -0,0 +1,124 @@
+use crate::services::CanisterConfig;
+use abstractions::Account;
+use candid::Principal;
+use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, StableCell, Storable};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+
+type IcpMemory = VirtualMemory<DefaultMemoryImpl>;
+
+/// version of the stable memory layout written by this code
+pub const LAYOUT_VERSION: u32 = 1;
+
+const LAYOUT_VERSION_MEMORY_ID: MemoryId = MemoryId::new(1);
+const CONFIG_MEMORY_ID: MemoryId = MemoryId::new(2);
+const HIVERS_MEMORY_ID: MemoryId = MemoryId::new(3);
+
+thread_local! {
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
+        MemoryManager::init(DefaultMemoryImpl::default())
+    );
+
+    static LAYOUT_VERSION_CELL: RefCell<StableCell<u32, IcpMemory>> = RefCell::new(
+        StableCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(LAYOUT_VERSION_MEMORY_ID)), 0).unwrap()
+    );
+
+    static CONFIG_STORAGE: RefCell<StableCell<VersionedConfig, IcpMemory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(CONFIG_MEMORY_ID)),
+            VersionedConfig::V1(CanisterConfig::default()),
+        )
+        .unwrap()
+    );
+
+    /// hivers join with their default account, so they are keyed by principal
+    static HIVERS_STORAGE: RefCell<StableBTreeMap<Principal, (), IcpMemory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(HIVERS_MEMORY_ID)))
+    );
+}
+
+/// Brings the stored layout up to `LAYOUT_VERSION` and returns the version found. Version 0 is a
+/// fresh memory, either a new canister or one upgraded from a release that kept its state on the
+/// heap, which the upgrade wiped.
+pub fn migrate() -> u32 {
+    LAYOUT_VERSION_CELL.with(|cell| {
+        let stored = *cell.borrow().get();
+        if stored < LAYOUT_VERSION {
+            cell.borrow_mut().set(LAYOUT_VERSION).unwrap();
+        }
+
+        stored
+    })
+}
+
+pub struct ConfigStorage {}
+
+impl ConfigStorage {
+    pub fn set_config(&mut self, config: CanisterConfig) {
+        CONFIG_STORAGE.with(|cell| {
+            cell.borrow_mut().set(VersionedConfig::V1(config)).unwrap();
+        });
+    }
+
+    pub fn get_config(&self) -> CanisterConfig {
+        CONFIG_STORAGE.with(|cell| cell.borrow().get().clone().migrate())
+    }
+}
+
+pub struct HiversStorage {}
+
+impl HiversStorage {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn add_hiver(&mut self, hiver: Account) {
+        HIVERS_STORAGE.with(|cell| {
+            cell.borrow_mut().insert(hiver.owner, ());
+        })
+    }
+
+    pub fn remove_hiver(&mut self, hiver: &Account) {
+        HIVERS_STORAGE.with(|cell| {
+            cell.borrow_mut().remove(&hiver.owner);
+        })
+    }
+
+    pub fn has_hiver(&self, hiver: &Account) -> bool {
+        HIVERS_STORAGE.with(|cell| cell.borrow().contains_key(&hiver.owner))
+    }
+
+    pub fn get_hivers(&self) -> Vec<Account> {
+        HIVERS_STORAGE.with(|cell| cell.borrow().iter().map(|(owner, _)| Account::from(owner)).collect())
+    }
+}
+
+/// Stored config layouts. A new variant is added whenever a change of `CanisterConfig` cannot be
+/// covered by candid optional fields, together with the migration from the previous one
+#[derive(Clone, Deserialize, Serialize, candid::CandidType)]
+enum VersionedConfig {
+    V1(CanisterConfig),
+}
+
+impl VersionedConfig {
+    fn migrate(self) -> CanisterConfig {
+        match self {
+            VersionedConfig::V1(config) => config,
+        }
+    }
+}
+
+impl Storable for VersionedConfig {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
@@ -3,7 +3,7 @@ use std::rc::Rc;
 
 use abstractions::hiving::{
     ContractId, ContractStatus, DiscountContract, PoolJoinProof, PoolParticipant, PriceQuote,
-    TimeUnits,
+    PricingInput, PricingPolicy, TimeUnits,
 };
 use candid::{Nat, Principal};
 use icrc_ledger_types::icrc1::account::Account;
@@ -77,19 +77,25 @@ impl PoolState {
     }
 
     pub fn quote(&self, time_units: TimeUnits) -> PriceQuote {
-        if self.paused {
-            return PriceQuote {
-                time_units,
-                ckusdc_cost: Nat::from(0u32),
-                discount_value: 0.0,
-            };
-        }
-        let price = self.pricing_per_time_unit.clone() * Nat::from(time_units);
-        let discount_value = time_units as f32;
+        let (price_per_time_unit, discount_value) = if self.paused {
+            (Nat::from(0u32), 0.0)
+        } else {
+            (self.pricing_per_time_unit.clone(), time_units as f32)
+        };
+        let input = PricingInput {
+            product_price: 0,
+            discount_value,
+            time_units,
+            price_per_time_unit,
+            cycle_usage: 0,
+            cycle_cap: 0,
+        };
+        let breakdown = PricingPolicy::PerTimeUnit.quote(&input);
         PriceQuote {
             time_units,
-            ckusdc_cost: price,
+            ckusdc_cost: breakdown.total.clone(),
             discount_value,
+            breakdown,
         }
     }
 
@@ -114,6 +120,7 @@ impl PoolState {
         time_units: TimeUnits,
         price: Nat,
         discount_value: f32,
+        cycle_number: u64,
     ) -> ContractId {
         let id = self.contracts.len() as ContractId;
         let seller = candid::Principal::anonymous();
@@ -125,6 +132,8 @@ impl PoolState {
             price,
             discount_value,
             status: ContractStatus::Paid,
+            discount_id: None,
+            cycle_number,
         };
         self.contracts.push(contract);
         id
@@ -2,16 +2,22 @@ use ic_cdk::{init, post_upgrade, query, update};
 use icrc_ledger_types::icrc1::account::Account;
 use icrc_ledger_types::icrc::generic_metadata_value::MetadataValue;
 use crate::app;
-use crate::domain::types::{TransferArg, TransferResult};
+use crate::domain::types::{InitArgs, TransferArg, TransferResult};
 
 #[init]
-fn init() {
-    app::init_service()
+fn init(args: Option<InitArgs>) {
+    app::init_service();
+    if let Some(args) = args {
+        app::set_minting_account(args.minting_account);
+    }
 }
 
 #[post_upgrade]
-fn memories() {
-    app::init_service()
+fn memories(args: Option<InitArgs>) {
+    app::init_service();
+    if let Some(args) = args {
+        app::set_minting_account(args.minting_account);
+    }
 }
 
 #[query]
@@ -125,6 +131,11 @@ fn icrc7_supported_standards() -> Vec<(String, String)> {
 }
 
 #[update]
-fn privia_mint_token(owner: Account, metadata: Vec<(String, MetadataValue)>) -> u128 {
-    app::privia_mint_token(owner, metadata)
+fn privia_mint_token(owner: Account, metadata: Vec<(String, MetadataValue)>, mint_key: Option<u64>) -> u128 {
+    app::privia_mint_token(owner, metadata, mint_key)
+}
+
+#[update]
+fn privia_update_token_metadata(token_id: u128, metadata: Vec<(String, MetadataValue)>) -> Result<(), String> {
+    app::privia_update_token_metadata(token_id, metadata)
 }
@@ -122,6 +122,14 @@ pub fn icrc7_supported_standards() -> Vec<(String, String)> {
     vec![("ICRC-7".to_string(), "1.0.0".to_string())]
 }
 
-pub fn privia_mint_token(owner: Account, metadata: Vec<(String, MetadataValue)>) -> u128 {
-    with_service(|s| s.privia_mint_token(owner, metadata))
-}
\ No newline at end of file
+pub fn privia_mint_token(owner: Account, metadata: Vec<(String, MetadataValue)>, mint_key: Option<u64>) -> u128 {
+    with_service(|s| s.privia_mint_token(owner, metadata, mint_key))
+}
+
+pub fn set_minting_account(minting_account: Account) {
+    with_service(|s| s.set_minting_account(minting_account))
+}
+
+pub fn privia_update_token_metadata(token_id: u128, metadata: Vec<(String, MetadataValue)>) -> Result<(), String> {
+    with_service(|s| s.privia_update_token_metadata(token_id, metadata))
+}
@@ -1,13 +1,16 @@
+use candid::Principal;
 use icrc_ledger_types::icrc1::account::Account;
 use crate::domain::{CollectionMetadata, Token, TokenId};
 
 pub trait IMetadataStore {
     fn get_collection_metadata(&self) -> CollectionMetadata;
+    fn set_minting_account(&mut self, minting_account: Account);
 }
 
 pub trait ITokenStore {
     fn get(&self, id: &TokenId) -> Option<Token>;
     fn update_owner(&mut self, id: &TokenId, new_owner: Account);
+    fn update_data(&mut self, id: &TokenId, data: String);
     fn insert(&mut self, token: Token) -> u128;
     fn list(&self) -> Vec<Token>;
     fn list_ids(&self) -> Vec<TokenId>;
@@ -19,4 +22,6 @@ pub trait IIndexStore {
     fn next_index(&mut self) -> u64;
     fn is_duplicate(&self, created_at: u64) -> Option<u64>;
     fn record(&mut self, created_at: u64, index: u64);
+    fn find_mint(&self, minter: Principal, mint_key: u64) -> Option<TokenId>;
+    fn record_mint(&mut self, minter: Principal, mint_key: u64, token_id: TokenId);
 }
\ No newline at end of file
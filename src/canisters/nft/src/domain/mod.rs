@@ -322,7 +322,16 @@ impl NftService {
         }
     }
 
-    pub fn privia_mint_token(&self, owner: Account, metadata: Vec<(String, MetadataValue)>) -> u128 {
+    /// mints a new token. A repeated call from the same minter with the same mint key
+    /// returns the token minted by the first call instead of minting again
+    pub fn privia_mint_token(&self, owner: Account, metadata: Vec<(String, MetadataValue)>, mint_key: Option<u64>) -> u128 {
+        let minter = self.runtime.borrow().get_caller();
+        if let Some(mint_key) = mint_key
+            && let Some(token_id) = self.index.borrow().find_mint(minter, mint_key)
+        {
+            return token_id;
+        }
+
         let metadata_json = serde_json::to_string(&metadata).unwrap();
 
         let token = Token {
@@ -330,6 +339,40 @@ impl NftService {
             owner,
             data: metadata_json,
         };
-        self.tokens.borrow_mut().insert(token)
+        let token_id = self.tokens.borrow_mut().insert(token);
+        if let Some(mint_key) = mint_key {
+            self.index.borrow_mut().record_mint(minter, mint_key, token_id);
+        }
+
+        token_id
     }
-}
\ No newline at end of file
+
+    pub fn set_minting_account(&self, minting_account: Account) {
+        self.metadata.borrow_mut().set_minting_account(minting_account);
+    }
+
+    /// merges the given entries into the token metadata, overwriting the values of existing keys.
+    /// Only the minting account can update metadata
+    pub fn privia_update_token_metadata(&self, token_id: TokenId, metadata: Vec<(String, MetadataValue)>) -> Result<(), String> {
+        let caller = self.runtime.borrow().get_caller();
+        let minting_account = self.metadata.borrow().get_collection_metadata().minting_account;
+        if minting_account.map(|account| account.owner) != Some(caller) {
+            return Err("Only the minting account can update token metadata".to_string());
+        }
+
+        let mut current = self
+            .get_token_metadata(&token_id)
+            .ok_or_else(|| format!("Token with id '{}' not found", token_id))?;
+
+        for (key, value) in metadata {
+            match current.iter_mut().find(|md| md.0 == key) {
+                Some(md) => md.1 = value,
+                None => current.push((key, value)),
+            }
+        }
+
+        let metadata_json = serde_json::to_string(&current).unwrap();
+        self.tokens.borrow_mut().update_data(&token_id, metadata_json);
+        Ok(())
+    }
+}
@@ -16,6 +16,8 @@ pub struct CollectionMetadata {
     pub atomic_batch_transfers: Option<bool>,
     pub tx_window: Option<u128>,
     pub permitted_drift: Option<u128>,
+    /// the only principal allowed to update token metadata, the DAO
+    pub minting_account: Option<Account>,
 }
 
 impl Default for CollectionMetadata {
@@ -34,10 +36,17 @@ impl Default for CollectionMetadata {
             atomic_batch_transfers: None,
             tx_window: None,
             permitted_drift: None,
+            minting_account: None,
         }
     }
 }
 
+/// the install and upgrade argument; the remaining fields sent by the deploy script are ignored
+#[derive(Clone, CandidType, Deserialize)]
+pub struct InitArgs {
+    pub minting_account: Account,
+}
+
 pub type TokenId = u128;
 
 #[derive(Clone, Debug, CandidType, Deserialize)]
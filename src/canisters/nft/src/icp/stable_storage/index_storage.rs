@@ -1,17 +1,21 @@
+use candid::Principal;
 use ic_stable_structures::{StableBTreeMap, StableCell};
 use crate::domain::interfaces::storage::{IIndexStore};
-use crate::icp::stable_storage::{get_duplicate_memory, get_tx_index_memory, IcpMemory};
+use crate::domain::types::TokenId;
+use crate::icp::stable_storage::{get_duplicate_memory, get_mint_keys_memory, get_tx_index_memory, IcpMemory};
 
 pub struct IndexStoreStable {
     index: StableCell<u64, IcpMemory>,
-    seen: StableBTreeMap<u64, u64, IcpMemory>
+    seen: StableBTreeMap<u64, u64, IcpMemory>,
+    mints: StableBTreeMap<(Principal, u64), TokenId, IcpMemory>,
 }
 
 impl IndexStoreStable {
     pub fn init() -> Self {
         Self {
             index: StableCell::init(get_tx_index_memory(), 0).unwrap(),
-            seen: StableBTreeMap::init(get_duplicate_memory())
+            seen: StableBTreeMap::init(get_duplicate_memory()),
+            mints: StableBTreeMap::init(get_mint_keys_memory()),
         }
     }
 }
@@ -30,4 +34,12 @@ impl IIndexStore for IndexStoreStable {
     fn record(&mut self, created_at: u64, index: u64) {
         self.seen.insert(created_at, index);
     }
+
+    fn find_mint(&self, minter: Principal, mint_key: u64) -> Option<TokenId> {
+        self.mints.get(&(minter, mint_key))
+    }
+
+    fn record_mint(&mut self, minter: Principal, mint_key: u64, token_id: TokenId) {
+        self.mints.insert((minter, mint_key), token_id);
+    }
 }
\ No newline at end of file
@@ -3,6 +3,7 @@ use crate::domain::types::*;
 use crate::icp::stable_storage::{get_metadata_memory, IcpMemory};
 use ic_stable_structures::storable::Bound;
 use ic_stable_structures::{StableCell, Storable};
+use icrc_ledger_types::icrc1::account::Account;
 use std::borrow::Cow;
 
 pub struct MetadataStoreStable {
@@ -25,6 +26,12 @@ impl IMetadataStore for MetadataStoreStable {
     fn get_collection_metadata(&self) -> CollectionMetadata {
         self.collection_metadata.get().0.clone()
     }
+
+    fn set_minting_account(&mut self, minting_account: Account) {
+        let mut metadata = self.collection_metadata.get().0.clone();
+        metadata.minting_account = Some(minting_account);
+        self.collection_metadata.set(CollectionMetadataStorable(metadata)).unwrap();
+    }
 }
 
 struct CollectionMetadataStorable(pub CollectionMetadata);
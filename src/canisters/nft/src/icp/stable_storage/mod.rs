@@ -16,6 +16,7 @@ const TOKENS_MEMORY_ID: MemoryId = MemoryId::new(1);
 const TX_INDEX_MEMORY: MemoryId = MemoryId::new(2);
 const DUPLICATE_MEMORY: MemoryId = MemoryId::new(3);
 const METADATA_MEMORY: MemoryId = MemoryId::new(4);
+const MINT_KEYS_MEMORY: MemoryId = MemoryId::new(5);
 
 thread_local! {
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
@@ -39,3 +40,7 @@ fn get_metadata_memory() -> IcpMemory {
     MEMORY_MANAGER.with(|m| m.borrow().get(METADATA_MEMORY))
 }
 
+fn get_mint_keys_memory() -> IcpMemory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(MINT_KEYS_MEMORY))
+}
+
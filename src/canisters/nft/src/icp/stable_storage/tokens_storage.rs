@@ -34,6 +34,17 @@ impl ITokenStore for TokenStoreStable {
         self.tokens.insert(*id, StorableToken(token));
     }
 
+    fn update_data(&mut self, id: &TokenId, data: String) {
+        let token = self.tokens.get(id).map(|token| token.clone());
+        if token.is_none() {
+            panic!("Token with id '{}' not found", id)
+        }
+
+        let mut token = token.unwrap();
+        token.data = data;
+        self.tokens.insert(*id, StorableToken(token));
+    }
+
     fn insert(&mut self, mut token: Token) -> u128 {
         let id = self.tokens.len() as u128;
         token.id = id;
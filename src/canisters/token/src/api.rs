@@ -88,4 +88,9 @@ fn privia_staking_log(target: Account, from: Option<u64>, to: Option<u64>) -> St
     app::staking::get_staking_log(target, from, to)
 }
 
+#[query]
+fn privia_stakers(prev: Option<Account>, take: u32) -> Vec<Account> {
+    app::staking::get_stakers(prev, take)
+}
+
 ic_cdk::export_candid!();
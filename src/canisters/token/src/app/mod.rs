@@ -125,4 +125,11 @@ pub mod staking {
         let service = build_staking_service();
         service.borrow().get_staking_log(target, from, to)
     }
+
+    pub fn get_stakers(prev: Option<Account>, take: u32) -> Vec<Account> {
+        const MAX_TAKE: u32 = 100;
+
+        let service = build_staking_service();
+        service.borrow().get_stakers(prev, take.min(MAX_TAKE) as usize)
+    }
 }
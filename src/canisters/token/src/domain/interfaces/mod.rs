@@ -7,6 +7,8 @@ use crate::domain::token::TokenConfiguration;
 pub trait IStakingStore {
     fn get_log_entries(&self, address: Account, from: u64, to: u64) -> Vec<StakingLogEntry>;
     fn add_log_entry(&mut self, address: Account, log: &StakingLogEntry);
+    /// accounts with a staking log, ordered, starting after prev
+    fn get_stakers(&self, prev: Option<Account>, take: usize) -> Vec<Account>;
 }
 
 pub trait IBalanceStore {
@@ -114,6 +114,10 @@ impl StakingService {
         }
     }
 
+    pub fn get_stakers(&self, prev: Option<Account>, take: usize) -> Vec<Account> {
+        self.staking_store.borrow().get_stakers(prev, take)
+    }
+
     fn update_staking(&self, ctx: StakingContext) {
         if ctx.from.is_some() {
             self.staking_store.borrow_mut().add_log_entry(
@@ -12,6 +12,7 @@ use ic_stable_structures::{
 };
 use icrc_ledger_types::icrc3::transactions::Transaction;
 use std::borrow::Cow;
+use std::ops::Bound as RangeBound;
 use std::cell::RefCell;
 
 type IcpMemory = VirtualMemory<DefaultMemoryImpl>;
@@ -268,6 +269,22 @@ impl IStakingStore for StakingStoreStable {
         self.log_index
             .insert((account, log_entry.timestamp.clone()), entry_index);
     }
+
+    fn get_stakers(&self, prev: Option<Account>, take: usize) -> Vec<Account> {
+        let mut result = Vec::new();
+        let mut start = prev.map_or(RangeBound::Unbounded, |prev| RangeBound::Excluded((BoundedAccount(prev), u64::MAX)));
+
+        // every staker has many log entries, so the index is walked one account at a time
+        while result.len() < take {
+            let Some(((account, _), _)) = self.log_index.range((start, RangeBound::Unbounded)).next() else {
+                break;
+            };
+            result.push(account.0);
+            start = RangeBound::Excluded((account, u64::MAX));
+        }
+
+        result
+    }
 }
 
 #[derive(Clone, Ord, PartialOrd, PartialEq, Eq)]
@@ -330,7 +347,7 @@ impl Storable for BoundedAccount {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use candid::Principal;
+    use candid::{Nat, Principal};
 
     #[test]
     fn test_bounded_account_storable() {
@@ -350,6 +367,27 @@ mod tests {
         assert_eq!(decoded.0.subaccount, Some([42u8; 32]));
     }
 
+    #[test]
+    fn test_get_stakers_pages_distinct_accounts() {
+        let entry = |timestamp| StakingLogEntry {
+            previous_amount: Nat::from(0u8),
+            current_amount: Nat::from(1u8),
+            timestamp,
+        };
+        let alice = Account::from(Principal::from_slice(&[1]));
+        let bob = Account::from(Principal::from_slice(&[2]));
+        let carol = Account::from(Principal::from_slice(&[3]));
+
+        let mut store = StakingStoreStable::init();
+        for account in [alice, bob, carol] {
+            store.add_log_entry(account, &entry(1));
+            store.add_log_entry(account, &entry(2));
+        }
+
+        assert_eq!(store.get_stakers(None, 2), vec![alice, bob]);
+        assert_eq!(store.get_stakers(Some(bob), 2), vec![carol]);
+    }
+
     #[test]
     fn test_bounded_account_storable_none_subaccount() {
         let owner = Principal::management_canister(); // Another valid principal
@@ -1,7 +1,12 @@
-use crate::dao::{Cycle, DiscountRequest, Proposal, ProposalType, Vote, VoteOption};
+use crate::dao::{
+    Cycle, CycleEmission, DaoError, DaoEventFilter, DaoEventPage, Discount, DiscountMintAudit, DiscountMintState, DiscountQuota, DiscountRequest, DiscountValidity, HivingCanister, HivingCanisterStatus,
+    Merchant, Proposal, ProposalType, ReconciliationReport, StakingRewardsClaim, Vote, VoteOption,
+};
+use crate::hiving::{HivingHeartbeat, RegisterHivingCanisterArgs};
 use crate::runtime::{CallMode, ICallContext};
-use crate::DiscountValue;
-use candid::{Encode, Nat, Principal};
+use crate::{DiscountValue, MetadataValue};
+use candid::{CandidType, Encode, Nat, Principal};
+use serde::Deserialize;
 use icrc_ledger_types::icrc1::account::Account;
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -11,92 +16,381 @@ pub struct DaoClient<R: ICallContext> {
     pub canister_id: Principal,
 }
 
+impl<R: ICallContext> Clone for DaoClient<R> {
+    fn clone(&self) -> Self {
+        Self {
+            runtime: self.runtime.clone(),
+            canister_id: self.canister_id,
+        }
+    }
+}
+
 impl<R: ICallContext> DaoClient<R> {
+    /// clones the call context first, so no borrow is held while the call is awaited
+    async fn call<Out>(&self, mode: CallMode, method: &str, args: &[u8]) -> Result<Out, R::Error>
+    where
+        Out: CandidType + for<'de> Deserialize<'de>,
+    {
+        let runtime = self.runtime.borrow().clone();
+        runtime.call(self.canister_id, mode, method, args).await
+    }
+
     // hiving
 
-    pub async fn hiving_join(&self) -> Result<(), R::Error> {
+    pub async fn hiving_join(&self, args: RegisterHivingCanisterArgs) -> Result<Result<(), DaoError>, R::Error> {
         let method = "hiving_join";
+        let args = Encode!(&args).unwrap();
+        let args = args.as_slice();
 
-        self.runtime.borrow().call(self.canister_id, CallMode::Update, method, &[]).await
+        self.call(CallMode::Update, method, args).await
     }
 
-    pub async fn hiving_leave(&self) -> Result<(), R::Error> {
+    pub async fn hiving_leave(&self) -> Result<Result<(), DaoError>, R::Error> {
         let method = "hiving_leave";
 
-        self.runtime.borrow().call(self.canister_id, CallMode::Update, method, &[]).await
+        self.call(CallMode::Update, method, &[]).await
+    }
+
+    pub async fn hiving_heartbeat(&self, heartbeat: HivingHeartbeat) -> Result<Result<u64, DaoError>, R::Error> {
+        let method = "hiving_heartbeat";
+        let args = Encode!(&heartbeat).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Update, method, args).await
+    }
+
+    pub async fn hiving_update_metadata(&self, metadata: Vec<(String, MetadataValue)>) -> Result<Result<(), DaoError>, R::Error> {
+        let method = "hiving_update_metadata";
+        let args = Encode!(&metadata).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Update, method, args).await
+    }
+
+    pub async fn hiving_approve(&self, canister_id: Principal) -> Result<Result<(), DaoError>, R::Error> {
+        let method = "hiving_approve";
+        let args = Encode!(&canister_id).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Update, method, args).await
+    }
+
+    pub async fn hiving_suspend(&self, canister_id: Principal) -> Result<Result<(), DaoError>, R::Error> {
+        let method = "hiving_suspend";
+        let args = Encode!(&canister_id).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Update, method, args).await
+    }
+
+    pub async fn hiving_reactivate(&self, canister_id: Principal) -> Result<Result<(), DaoError>, R::Error> {
+        let method = "hiving_reactivate";
+        let args = Encode!(&canister_id).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Update, method, args).await
+    }
+
+    pub async fn hiving_get(&self, canister_id: Principal) -> Result<Result<Option<HivingCanister>, DaoError>, R::Error> {
+        let method = "hiving_get";
+        let args = Encode!(&canister_id).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Query, method, args).await
+    }
+
+    pub async fn hiving_list(
+        &self,
+        status: Option<HivingCanisterStatus>,
+        prev: Option<Principal>,
+        take: Option<u32>,
+    ) -> Result<Result<Vec<HivingCanister>, DaoError>, R::Error> {
+        let method = "hiving_list";
+        let args = Encode!(&status, &prev, &take).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Query, method, args).await
+    }
+
+    pub async fn hiving_authorize(&self, canister_id: Principal) -> Result<Result<(), DaoError>, R::Error> {
+        let method = "hiving_authorize";
+        let args = Encode!(&canister_id).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Update, method, args).await
+    }
+
+    pub async fn hiving_revoke(&self, canister_id: Principal) -> Result<Result<(), DaoError>, R::Error> {
+        let method = "hiving_revoke";
+        let args = Encode!(&canister_id).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Update, method, args).await
     }
 
     // voting
 
-    pub async fn voting_create_proposal(&self, proposal_type: ProposalType, data: String) -> Result<u64, R::Error> {
+    pub async fn voting_create_proposal(&self, proposal_type: ProposalType, data: String) -> Result<Result<u64, DaoError>, R::Error> {
         let method = "voting_create_proposal";
         let args = Encode!(&proposal_type, &data).unwrap();
         let args = args.as_slice();
 
-        self.runtime.borrow().call(self.canister_id, CallMode::Update, method, args).await
+        self.call(CallMode::Update, method, args).await
     }
 
-    pub async fn voting_get_proposal(&self, proposal_id: u64) -> Result<Option<Proposal>, R::Error> {
+    pub async fn voting_get_proposal(&self, proposal_id: u64) -> Result<Result<Option<Proposal>, DaoError>, R::Error> {
         let method = "voting_get_proposal";
         let args = Encode!(&proposal_id).unwrap();
         let args = args.as_slice();
 
-        self.runtime.borrow().call(self.canister_id, CallMode::Query, method, args).await
+        self.call(CallMode::Query, method, args).await
     }
 
-    pub async fn voting_vote(&self, proposal_id: u64, vote: VoteOption) -> Result<u64, R::Error> {
+    pub async fn voting_vote(&self, proposal_id: u64, vote: VoteOption) -> Result<Result<u64, DaoError>, R::Error> {
         let method = "voting_vote";
         let args = Encode!(&proposal_id, &vote).unwrap();
         let args = args.as_slice();
 
-        self.runtime.borrow().call(self.canister_id, CallMode::Update, method, args).await
+        self.call(CallMode::Update, method, args).await
+    }
+
+    pub async fn voting_execute_proposal(&self, proposal_id: u64) -> Result<Result<(), DaoError>, R::Error> {
+        let method = "voting_execute_proposal";
+        let args = Encode!(&proposal_id).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Update, method, args).await
     }
 
-    pub async fn voting_get_vote(&self, vote_id: u64) -> Result<Option<Vote>, R::Error> {
+    pub async fn voting_get_vote(&self, vote_id: u64) -> Result<Result<Option<Vote>, DaoError>, R::Error> {
         let method = "voting_get_vote";
         let args = Encode!(&vote_id).unwrap();
         let args = args.as_slice();
 
-        self.runtime.borrow().call(self.canister_id, CallMode::Query, method, args).await
+        self.call(CallMode::Query, method, args).await
     }
 
-    pub async fn voting_get_all_votes(&self, proposal_id: u64) -> Result<Vec<Vote>, R::Error> {
+    pub async fn voting_get_all_votes(&self, proposal_id: u64) -> Result<Result<Vec<Vote>, DaoError>, R::Error> {
         let method = "voting_get_all_votes";
         let args = Encode!(&proposal_id).unwrap();
         let args = args.as_slice();
 
-        self.runtime.borrow().call(self.canister_id, CallMode::Query, method, args).await
+        self.call(CallMode::Query, method, args).await
     }
 
     // discounts
 
-    pub async fn mint_discount(&self, hiver: Account, request: DiscountRequest) -> Result<u128, R::Error> {
+    pub async fn mint_discount(&self, hiver: Account, request: DiscountRequest) -> Result<Result<u128, DaoError>, R::Error> {
         let method = "mint_discount";
         let args = Encode!(&hiver, &request).unwrap();
         let args = args.as_slice();
 
-        self.runtime.borrow().call(self.canister_id, CallMode::Update, method, args).await
+        self.call(CallMode::Update, method, args).await
     }
 
-    pub async fn get_staking_score(&self, principal: Account) -> Result<Nat, R::Error> {
+    pub async fn get_discount_mint(&self, mint_key: u64) -> Result<Result<Option<DiscountMintState>, DaoError>, R::Error> {
+        let method = "get_discount_mint";
+        let args = Encode!(&mint_key).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Query, method, args).await
+    }
+
+    pub async fn get_discount_quota(&self, buyer: Account, hiver: Account) -> Result<Result<DiscountQuota, DaoError>, R::Error> {
+        let method = "get_discount_quota";
+        let args = Encode!(&buyer, &hiver).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Query, method, args).await
+    }
+
+    pub async fn get_discount(&self, discount_id: u128) -> Result<Result<Discount, DaoError>, R::Error> {
+        let method = "get_discount";
+        let args = Encode!(&discount_id).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Update, method, args).await
+    }
+
+    pub async fn audit_discount_mints(&self) -> Result<Result<DiscountMintAudit, DaoError>, R::Error> {
+        let method = "audit_discount_mints";
+        let args = Encode!().unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Update, method, args).await
+    }
+
+    pub async fn list_discounts_by_cycle(&self, cycle_number: u64, prev: Option<u128>, take: Option<u32>) -> Result<Result<Vec<Discount>, DaoError>, R::Error> {
+        let method = "list_discounts_by_cycle";
+        let args = Encode!(&cycle_number, &prev, &take).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Query, method, args).await
+    }
+
+    pub async fn list_discounts_by_owner(&self, owner: Account, prev: Option<u128>, take: Option<u32>) -> Result<Result<Vec<Discount>, DaoError>, R::Error> {
+        let method = "list_discounts_by_owner";
+        let args = Encode!(&owner, &prev, &take).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Query, method, args).await
+    }
+
+    pub async fn list_discounts_by_hiver(&self, hiver: Account, prev: Option<u128>, take: Option<u32>) -> Result<Result<Vec<Discount>, DaoError>, R::Error> {
+        let method = "list_discounts_by_hiver";
+        let args = Encode!(&hiver, &prev, &take).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Query, method, args).await
+    }
+
+    pub async fn get_staking_score(&self, principal: Account) -> Result<Result<Nat, DaoError>, R::Error> {
         let method = "get_staking_score";
         let args = Encode!(&principal).unwrap();
         let args = args.as_slice();
 
-        self.runtime.borrow().call(self.canister_id, CallMode::Query, method, args).await
+        self.call(CallMode::Query, method, args).await
     }
 
-    pub async fn calculate_max_discount(&self, principal: &Account, price: &u128) -> Result<DiscountValue, R::Error> {
+    pub async fn calculate_max_discount(&self, principal: &Account, price: &u128) -> Result<Result<DiscountValue, DaoError>, R::Error> {
         let method = "calculate_discount";
         let args = Encode!(principal, price).unwrap();
         let args = args.as_slice();
 
-        self.runtime.borrow().call(self.canister_id, CallMode::Update, method, args).await
+        self.call(CallMode::Update, method, args).await
     }
 
-    pub async fn get_current_cycle(&self) -> Result<Cycle, R::Error> {
+    pub async fn get_current_cycle(&self) -> Result<Result<Cycle, DaoError>, R::Error> {
         let method = "get_current_cycle";
 
-        self.runtime.borrow().call(self.canister_id, CallMode::Query, method, &[]).await
+        self.call(CallMode::Query, method, &[]).await
+    }
+
+    pub async fn get_cycle(&self, cycle_number: u64) -> Result<Result<Cycle, DaoError>, R::Error> {
+        let method = "get_cycle";
+        let args = Encode!(&cycle_number).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Query, method, args).await
+    }
+
+    pub async fn list_cycles(&self, from: u64, to: u64) -> Result<Result<Vec<Cycle>, DaoError>, R::Error> {
+        let method = "list_cycles";
+        let args = Encode!(&from, &to).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Query, method, args).await
+    }
+
+    pub async fn get_next_voting_cycle(&self) -> Result<Result<Cycle, DaoError>, R::Error> {
+        let method = "get_next_voting_cycle";
+        let args = Encode!().unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Query, method, args).await
+    }
+
+    // merchants
+
+    pub async fn merchant_register(&self, merchant_id: Principal, name: String) -> Result<Result<(), DaoError>, R::Error> {
+        let method = "merchant_register";
+        let args = Encode!(&merchant_id, &name).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Update, method, args).await
+    }
+
+    pub async fn merchant_remove(&self, merchant_id: Principal) -> Result<Result<(), DaoError>, R::Error> {
+        let method = "merchant_remove";
+        let args = Encode!(&merchant_id).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Update, method, args).await
+    }
+
+    pub async fn merchant_list(&self) -> Result<Result<Vec<Merchant>, DaoError>, R::Error> {
+        let method = "merchant_list";
+
+        self.call(CallMode::Query, method, &[]).await
+    }
+
+    pub async fn redeem_discount(&self, token_id: u128, customer: Account, order_ref: String) -> Result<Result<Discount, DaoError>, R::Error> {
+        let method = "redeem_discount";
+        let args = Encode!(&token_id, &customer, &order_ref).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Update, method, args).await
+    }
+
+    pub async fn verify_discount(&self, token_id: u128) -> Result<Result<DiscountValidity, DaoError>, R::Error> {
+        let method = "verify_discount";
+        let args = Encode!(&token_id).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Query, method, args).await
+    }
+
+    // reconciliation
+
+    pub async fn reconciliation_start(&self) -> Result<Result<(), DaoError>, R::Error> {
+        let method = "reconciliation_start";
+        let args = Encode!().unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Update, method, args).await
+    }
+
+    pub async fn reconciliation_report(&self) -> Result<Result<Option<ReconciliationReport>, DaoError>, R::Error> {
+        let method = "reconciliation_report";
+        let args = Encode!().unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Query, method, args).await
+    }
+
+    // emission
+
+    pub async fn claim_staking_rewards(&self) -> Result<Result<StakingRewardsClaim, DaoError>, R::Error> {
+        let method = "claim_staking_rewards";
+        let args = Encode!().unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Update, method, args).await
+    }
+
+    pub async fn get_cycle_emission(&self, cycle_number: u64) -> Result<Result<Option<CycleEmission>, DaoError>, R::Error> {
+        let method = "get_cycle_emission";
+        let args = Encode!(&cycle_number).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Query, method, args).await
+    }
+
+    // voter rewards
+
+    pub async fn claim_voter_rewards(&self) -> Result<Result<Nat, DaoError>, R::Error> {
+        let method = "claim_voter_rewards";
+        let args = Encode!().unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Update, method, args).await
+    }
+
+    pub async fn get_voter_rewards(&self, voter: Principal) -> Result<Result<Nat, DaoError>, R::Error> {
+        let method = "get_voter_rewards";
+        let args = Encode!(&voter).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Query, method, args).await
+    }
+
+    // events
+
+    pub async fn get_events(&self, start: u64, limit: Option<u32>, filter: Option<DaoEventFilter>) -> Result<Result<DaoEventPage, DaoError>, R::Error> {
+        let method = "get_events";
+        let args = Encode!(&start, &limit, &filter).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Query, method, args).await
     }
 }
@@ -1,16 +1,23 @@
+use crate::hiving::HivingHeartbeat;
 use crate::{DiscountValue, Timestamp};
-use candid::{CandidType, Deserialize, Principal};
+use candid::{CandidType, Deserialize, Nat, Principal};
 use icrc_ledger_types::icrc1::account::Account;
 use icrc_ledger_types::icrc::generic_metadata_value::MetadataValue;
 use serde::Serialize;
 
-#[derive(Clone, Debug, Deserialize, Serialize, CandidType)]
+#[derive(Clone, Debug, Deserialize, Serialize, CandidType, PartialEq)]
 pub enum ProposalType {
     UpdateCode,
     Generic,
+    /// rolls an approved WASM version out to the canisters the DAO spawned
+    FleetUpgrade { kind: WasmKind, version: u32 },
+    /// reinstalls the version the fleet ran before its current rollout
+    FleetRollback { kind: WasmKind },
+    /// continues a paused or completed rollout, retrying the failed canisters
+    FleetResume { kind: WasmKind },
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize, CandidType)]
+#[derive(Clone, Debug, Deserialize, Serialize, CandidType, PartialEq)]
 pub enum VoteOption {
     Approve,
     Decline,
@@ -26,6 +33,7 @@ pub struct Proposal {
     pub data: String,
     pub start: Timestamp,
     pub end: Timestamp,
+    pub executed_on: Option<Timestamp>,
     // pub state: ProposalState
 }
 
@@ -47,6 +55,7 @@ impl Proposal {
             data,
             start,
             end,
+            executed_on: None,
             // state: ProposalState::Pending
         }
     }
@@ -67,6 +76,8 @@ pub struct Vote {
     pub created_by: Principal,
     pub proposal_id: u64,
     pub result: VoteOption,
+    /// token balance of the voter when voting; votes cast before votes were weighted have none
+    pub weight: Option<Nat>,
 }
 
 impl Vote {
@@ -75,6 +86,7 @@ impl Vote {
         voter: Principal,
         created_on: Timestamp,
         result: VoteOption,
+        weight: Nat,
     ) -> Self {
         Self {
             id: 0,
@@ -82,6 +94,7 @@ impl Vote {
             created_by: voter,
             proposal_id,
             result,
+            weight: Some(weight),
         }
     }
 }
@@ -98,42 +111,480 @@ pub struct Discount {
     pub id: u128,
     pub value: DiscountValue,
     pub owner: Account,
+    /// hiver whose quota the discount was minted from
+    pub hiver: Option<Account>,
+    pub expires_on: Option<Timestamp>,
+    pub redemption: Option<DiscountRedemption>,
+}
+
+/// Validity window requested for a discount: either a number of full cycles following
+/// the cycle of minting, or an absolute timestamp
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum DiscountExpiry {
+    Cycles(u64),
+    Timestamp(Timestamp),
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct DiscountRedemption {
+    pub merchant: Principal,
+    pub order_ref: String,
+    pub redeemed_on: Timestamp,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, PartialEq)]
+pub enum DiscountValidity {
+    Valid,
+    Redeemed,
+    Expired,
+    NotFound,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct Merchant {
+    pub id: Principal,
+    pub name: String,
+    pub registered_on: Timestamp,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, PartialEq)]
+pub enum HivingCanisterStatus {
+    Pending,
+    Approved,
+    Suspended,
+    /// approved canister which stopped sending heartbeats, until it is reactivated
+    Stale,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct HivingCanister {
+    pub canister_id: Principal,
+    pub owner: Principal,
+    pub metadata: Vec<(String, MetadataValue)>,
+    pub joined_on: Timestamp,
+    pub status: HivingCanisterStatus,
+    /// code installed by the DAO factory, None for canisters deployed by their owners
+    pub wasm: Option<InstalledWasm>,
+    pub last_heartbeat: Option<HivingHeartbeat>,
+    /// last heartbeat or reactivation, None while the canister has not reported since joining
+    pub last_seen_on: Option<Timestamp>,
+}
+
+/// Code the DAO can install into the canisters it spawns
+#[derive(Clone, Copy, Debug, CandidType, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WasmKind {
+    Hiving,
+    HivingPool,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub struct InstalledWasm {
+    pub kind: WasmKind,
+    pub version: u32,
+}
+
+/// WASM approved by governance. Versions of a kind are numbered from 1
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct WasmModule {
+    pub kind: WasmKind,
+    pub version: u32,
+    /// sha256 of the whole module
+    pub hash: Vec<u8>,
+    pub size: u64,
+    pub chunk_count: u32,
+    pub approved_on: Timestamp,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct SpawnConfig {
+    pub kind: WasmKind,
+    /// ckUSDC ledger of the spawned canister
+    pub ckusdc_address: Option<Principal>,
+    pub metadata: Vec<(String, MetadataValue)>,
+    /// cycles given to the canister on top of the creation fee
+    pub cycles: Option<Nat>,
+}
+
+/// Rollout of an approved WASM version to every registered canister running code of its kind
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub struct FleetUpgrade {
+    pub kind: WasmKind,
+    pub target_version: u32,
+    /// version the fleet ran before the rollout, installed again by a rollback
+    pub previous_version: Option<u32>,
+    pub status: FleetUpgradeStatus,
+    pub started_on: Timestamp,
+}
+
+#[derive(Clone, Copy, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub enum FleetUpgradeStatus {
+    Running,
+    Paused,
+    Completed,
+}
+
+/// Progress of a fleet upgrade on one canister
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub struct CanisterUpgrade {
+    pub canister_id: Principal,
+    /// version the canister ran when it was last looked at
+    pub version: Option<u32>,
+    pub status: CanisterUpgradeStatus,
+    pub updated_on: Timestamp,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub enum CanisterUpgradeStatus {
+    Pending,
+    Upgraded,
+    Failed { reason: String },
+    /// the DAO is not a controller of the canister
+    Skipped,
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize)]
 pub struct DiscountRequest {
-    pub value: DiscountValue,
+    /// price of the product the discount is requested for; the DAO derives the discount value from it
+    pub price: u128,
     pub owner: Account,
+    pub expiry: Option<DiscountExpiry>,
+    /// value quoted to the buyer beforehand. It is minted as is unless it exceeds the discount the
+    /// hiver can currently grant
+    pub value: Option<DiscountValue>,
 }
 
 impl DiscountRequest {
-    pub fn new(value: DiscountValue, owner: Account) -> Self {
+    pub fn new(price: u128, owner: Account) -> Self {
         Self {
-            value,
-            owner
+            price,
+            owner,
+            expiry: None,
+            value: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, PartialEq)]
+pub enum MintStatus {
+    Pending,
+    Compensated,
+}
+
+/// Discount being minted. The record is written before the NFT canister is called and holds
+/// the reserved quota until the mint is either confirmed or compensated
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct MintRecord {
+    /// idempotency key passed to the NFT canister
+    pub key: u64,
+    pub cycle_number: u64,
+    pub hiver: Account,
+    pub discount: Discount,
+    pub created_on: Timestamp,
+    pub attempts: u32,
+    pub status: MintStatus,
+}
+
+/// Outcome of a discount mint, looked up by the key of a mint left pending
+#[derive(Clone, Debug, CandidType, Deserialize, PartialEq)]
+pub enum DiscountMintState {
+    Pending,
+    Minted { discount_id: u128 },
+    Compensated,
+}
+
+/// numbers to check the discounts known to the DAO against the NFT supply
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct DiscountMintAudit {
+    pub discounts: u64,
+    pub pending: u64,
+    pub compensated: u64,
+    pub nft_supply: u128,
+}
+
+/// discounts still available in the current cycle
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct DiscountQuota {
+    pub cycle: u64,
+    pub buyer_remaining: u32,
+    pub hiver_remaining: u128,
+}
+
+/// Disagreement between a discount stored by the DAO and its NFT
+#[derive(Clone, Debug, CandidType, Deserialize, PartialEq)]
+pub enum DiscountDrift {
+    /// the NFT exists but the DAO has no discount for it
+    Missing { token_id: u128 },
+    /// the DAO has a discount whose NFT does not exist
+    Orphaned { token_id: u128 },
+    ValueMismatch { token_id: u128, dao_value: DiscountValue, nft_value: Option<DiscountValue> },
+    OwnerChanged { token_id: u128, dao_owner: Account, nft_owner: Account },
+}
+
+/// Result of comparing the DAO discounts with the NFT canister.
+/// Only the first drifts are kept; `drift_count` counts all of them
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ReconciliationReport {
+    pub started_on: Timestamp,
+    pub finished_on: Option<Timestamp>,
+    pub checked_tokens: u64,
+    pub checked_discounts: u64,
+    pub drift_count: u64,
+    pub drifts: Vec<DiscountDrift>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, PartialEq)]
+pub enum MintDiscountError {
+    NotHivingCanister,
+    HiverNotAuthorized,
+    DiscountsPerCycleLimitReached,
+    WalletLimitReached,
+    MintInProgress,
+    NoDiscountAvailable,
+    QuotedValueUnavailable { max_value: DiscountValue },
+    InvalidExpiry { reason: String },
+    NftMintFailed { reason: String },
+    /// the outcome of the NFT mint is unknown; the pending mint is resolved by the recovery job
+    NftMintPending { mint_key: u64 },
+}
+
+/// Staking rewards of a cycle, split among the accounts registered for it
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CycleEmission {
+    pub budget: Nat,
+    pub total_score: Nat,
+    pub participants: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct StakingRewardsClaim {
+    /// finished cycles paid by this claim
+    pub cycles: Vec<u64>,
+    /// rewards of the finished cycles paid by this claim
+    pub amount: Nat,
+    pub block_index: Option<Nat>,
+}
+
+/// Voter reward pool of a voting cycle, credited to the voters of its proposals
+#[derive(Clone, Debug, CandidType, Deserialize, PartialEq)]
+pub struct VoterRewardAllocation {
+    pub cycle_number: u64,
+    pub pool: Nat,
+    pub total_weight: Nat,
+    pub proposals: u64,
+    pub voters: u64,
+}
+
+/// Change of the DAO state recorded in the event log
+#[derive(Clone, Debug, CandidType, Deserialize, PartialEq)]
+pub enum DaoEvent {
+    ProposalCreated { proposal_id: u64, proposal_type: ProposalType },
+    VoteCast { proposal_id: u64, vote_id: u64, vote: VoteOption },
+    ProposalExecuted { proposal_id: u64 },
+    DiscountMinted { discount_id: u128, owner: Account, hiver: Account, value: DiscountValue },
+    /// a mint that never reached the NFT canister gave its quota back
+    DiscountMintCompensated { mint_key: u64, owner: Account, hiver: Account },
+    DiscountRedeemed { discount_id: u128, merchant: Principal, order_ref: String },
+    DiscountExpired { discount_id: u128 },
+    /// the NFT of an expired discount could not be marked, it is retried later
+    DiscountExpiryFailed { discount_id: u128, reason: String },
+    HivingCanisterJoined { canister_id: Principal, status: HivingCanisterStatus },
+    HivingCanisterLeft { canister_id: Principal },
+    HivingCanisterStatusChanged { canister_id: Principal, status: HivingCanisterStatus },
+    HiverJoined { canister_id: Principal, hiver: Account },
+    HiverLeft { canister_id: Principal, hiver: Account },
+    MerchantRegistered { merchant_id: Principal, name: String },
+    MerchantRemoved { merchant_id: Principal },
+    ConfigUpdated,
+    CycleEpochScheduled { start_cycle: u64, cycle_len_ns: u64 },
+    StakingRewardsClaimed { account: Account, cycles: Vec<u64>, amount: Nat },
+    VoterRewardsAllocated { allocation: VoterRewardAllocation },
+    VoterRewardsClaimed { voter: Principal, amount: Nat },
+    WasmApproved { kind: WasmKind, version: u32 },
+    HivingCanisterSpawned { canister_id: Principal, owner: Principal, wasm: InstalledWasm },
+    FleetUpgradeStarted { kind: WasmKind, target_version: u32, previous_version: Option<u32> },
+    FleetUpgradeStatusChanged { kind: WasmKind, status: FleetUpgradeStatus },
+    HivingCanisterUpgraded { canister_id: Principal, wasm: InstalledWasm },
+    HivingCanisterUpgradeFailed { canister_id: Principal, reason: String },
+}
+
+#[derive(Clone, Copy, Debug, CandidType, Deserialize, PartialEq)]
+pub enum DaoEventKind {
+    ProposalCreated,
+    VoteCast,
+    ProposalExecuted,
+    DiscountMinted,
+    DiscountMintCompensated,
+    DiscountRedeemed,
+    DiscountExpired,
+    DiscountExpiryFailed,
+    HivingCanisterJoined,
+    HivingCanisterLeft,
+    HivingCanisterStatusChanged,
+    HiverJoined,
+    HiverLeft,
+    MerchantRegistered,
+    MerchantRemoved,
+    ConfigUpdated,
+    CycleEpochScheduled,
+    StakingRewardsClaimed,
+    VoterRewardsAllocated,
+    VoterRewardsClaimed,
+    WasmApproved,
+    HivingCanisterSpawned,
+    FleetUpgradeStarted,
+    FleetUpgradeStatusChanged,
+    HivingCanisterUpgraded,
+    HivingCanisterUpgradeFailed,
+}
+
+impl DaoEvent {
+    pub fn kind(&self) -> DaoEventKind {
+        match self {
+            DaoEvent::ProposalCreated { .. } => DaoEventKind::ProposalCreated,
+            DaoEvent::VoteCast { .. } => DaoEventKind::VoteCast,
+            DaoEvent::ProposalExecuted { .. } => DaoEventKind::ProposalExecuted,
+            DaoEvent::DiscountMinted { .. } => DaoEventKind::DiscountMinted,
+            DaoEvent::DiscountMintCompensated { .. } => DaoEventKind::DiscountMintCompensated,
+            DaoEvent::DiscountRedeemed { .. } => DaoEventKind::DiscountRedeemed,
+            DaoEvent::DiscountExpired { .. } => DaoEventKind::DiscountExpired,
+            DaoEvent::DiscountExpiryFailed { .. } => DaoEventKind::DiscountExpiryFailed,
+            DaoEvent::HivingCanisterJoined { .. } => DaoEventKind::HivingCanisterJoined,
+            DaoEvent::HivingCanisterLeft { .. } => DaoEventKind::HivingCanisterLeft,
+            DaoEvent::HivingCanisterStatusChanged { .. } => DaoEventKind::HivingCanisterStatusChanged,
+            DaoEvent::HiverJoined { .. } => DaoEventKind::HiverJoined,
+            DaoEvent::HiverLeft { .. } => DaoEventKind::HiverLeft,
+            DaoEvent::MerchantRegistered { .. } => DaoEventKind::MerchantRegistered,
+            DaoEvent::MerchantRemoved { .. } => DaoEventKind::MerchantRemoved,
+            DaoEvent::ConfigUpdated => DaoEventKind::ConfigUpdated,
+            DaoEvent::CycleEpochScheduled { .. } => DaoEventKind::CycleEpochScheduled,
+            DaoEvent::StakingRewardsClaimed { .. } => DaoEventKind::StakingRewardsClaimed,
+            DaoEvent::VoterRewardsAllocated { .. } => DaoEventKind::VoterRewardsAllocated,
+            DaoEvent::VoterRewardsClaimed { .. } => DaoEventKind::VoterRewardsClaimed,
+            DaoEvent::WasmApproved { .. } => DaoEventKind::WasmApproved,
+            DaoEvent::HivingCanisterSpawned { .. } => DaoEventKind::HivingCanisterSpawned,
+            DaoEvent::FleetUpgradeStarted { .. } => DaoEventKind::FleetUpgradeStarted,
+            DaoEvent::FleetUpgradeStatusChanged { .. } => DaoEventKind::FleetUpgradeStatusChanged,
+            DaoEvent::HivingCanisterUpgraded { .. } => DaoEventKind::HivingCanisterUpgraded,
+            DaoEvent::HivingCanisterUpgradeFailed { .. } => DaoEventKind::HivingCanisterUpgradeFailed,
         }
     }
 }
 
+#[derive(Clone, Debug, CandidType, Deserialize, PartialEq)]
+pub struct DaoEventRecord {
+    /// position in the log, events are numbered from 0 without gaps
+    pub id: u64,
+    pub timestamp: Timestamp,
+    pub caller: Principal,
+    pub event: DaoEvent,
+}
+
+/// events matching all the set fields are returned
+#[derive(Clone, Debug, Default, CandidType, Deserialize)]
+pub struct DaoEventFilter {
+    pub kinds: Option<Vec<DaoEventKind>>,
+    pub caller: Option<Principal>,
+}
+
+impl DaoEventFilter {
+    pub fn matches(&self, record: &DaoEventRecord) -> bool {
+        self.kinds.as_ref().is_none_or(|kinds| kinds.contains(&record.event.kind()))
+            && self.caller.is_none_or(|caller| caller == record.caller)
+    }
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct DaoEventPage {
+    pub events: Vec<DaoEventRecord>,
+    /// id to continue reading from, None once the end of the log is reached
+    pub next: Option<u64>,
+}
+
+/// Error returned by every DAO endpoint
+#[derive(Clone, Debug, CandidType, Deserialize, PartialEq)]
+pub enum DaoError {
+    /// the caller is not allowed to perform the operation
+    Unauthorized { reason: String },
+    InvalidArgument { reason: String },
+    ProposalNotFound,
+    VotingNotActive,
+    VoteInProgress,
+    AlreadyVoted,
+    NoStakingBalance,
+    ClaimInProgress,
+    HivingCanisterNotRegistered,
+    HivingCanisterAlreadyRegistered,
+    MerchantNotRegistered,
+    DiscountNotFound,
+    DiscountExpired,
+    DiscountAlreadyRedeemed,
+    RedemptionInProgress,
+    /// the cycle calendar has no genesis yet or the time is before it
+    CalendarNotStarted,
+    Mint(MintDiscountError),
+    /// a call to the token or NFT canister failed
+    CallFailed { reason: String },
+}
+
+impl DaoError {
+    pub fn call_failed<E: std::fmt::Debug>(err: E) -> Self {
+        DaoError::CallFailed { reason: format!("{:?}", err) }
+    }
+}
+
+impl From<MintDiscountError> for DaoError {
+    fn from(err: MintDiscountError) -> Self {
+        DaoError::Mint(err)
+    }
+}
+
 impl Discount {
     pub fn new(id: u128, value: DiscountValue, owner: Account) -> Self {
         Self {
             id,
             value,
-            owner
+            owner,
+            hiver: None,
+            expires_on: None,
+            redemption: None,
         }
     }
+
+    pub fn validity(&self, now: Timestamp) -> DiscountValidity {
+        if self.redemption.is_some() {
+            return DiscountValidity::Redeemed;
+        }
+
+        match self.expires_on {
+            Some(expires_on) if expires_on <= now => DiscountValidity::Expired,
+            _ => DiscountValidity::Valid,
+        }
+    }
+
+    pub fn to_metadata(&self) -> Vec<(String, MetadataValue)> {
+        let mut metadata = Vec::from([
+            ("value".to_string(), MetadataValue::Text(self.value.to_string())),
+        ]);
+        if let Some(expires_on) = self.expires_on {
+            metadata.push(("expires_on".to_string(), MetadataValue::Nat(expires_on.into())));
+        }
+
+        metadata
+    }
 }
 
-impl DiscountRequest {
+impl DiscountRedemption {
     pub fn to_metadata(&self) -> Vec<(String, MetadataValue)> {
         Vec::from([
-            ("value".to_string(), MetadataValue::Text(self.value.to_string())),
+            ("redeemed_by".to_string(), MetadataValue::Text(self.merchant.to_text())),
+            ("order_ref".to_string(), MetadataValue::Text(self.order_ref.clone())),
+            ("redeemed_on".to_string(), MetadataValue::Nat(self.redeemed_on.into())),
         ])
     }
 }
 
-#[derive(CandidType, Deserialize)]
+
+#[derive(Clone, Debug, CandidType, Deserialize, PartialEq)]
 pub struct Cycle {
     pub number: u64,
     pub start: Timestamp,
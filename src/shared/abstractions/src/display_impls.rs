@@ -59,6 +59,9 @@ impl Display for Vote {
             "  Created on: {}",
             nanos_to_localtime_str(self.created_on)
         )?;
+        if let Some(weight) = &self.weight {
+            writeln!(f, "  Weight: {}", weight)?;
+        }
         writeln!(f, "  Result: {}", self.result)
     }
 }
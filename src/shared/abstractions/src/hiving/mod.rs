@@ -1,15 +1,23 @@
 use candid::{CandidType, Deserialize, Nat, Principal};
 use icrc_ledger_types::icrc::generic_metadata_value::MetadataValue;
-use icrc_ledger_types::icrc1::account::Account;
+use icrc_ledger_types::icrc1::account::{Account, Subaccount};
 use serde::Serialize;
 
-use crate::Tokens;
+use crate::{Timestamp, Tokens};
+
+mod pricing;
+
+pub use pricing::*;
 
 pub type HiverId = u64;
 pub type PoolId = u64;
 pub type ContractId = u64;
+pub type QuoteId = u64;
 pub type TimeUnits = u64;
 
+/// how often hiving canisters report to the DAO that they are alive
+pub const HEARTBEAT_INTERVAL_NS: u64 = 60 * 60 * 1_000_000_000;
+
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
 pub struct HiverRegistration {
     pub principal: Principal,
@@ -17,6 +25,10 @@ pub struct HiverRegistration {
     pub price_per_time_unit: Nat,
     pub max_time_units: TimeUnits,
     pub cycle_cap: TimeUnits,
+    /// account receiving the ckUSDC paid for the hiver's discounts
+    pub payout_account: Account,
+    /// priced per time unit when not set
+    pub pricing: Option<PricingPolicy>,
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
@@ -32,9 +44,24 @@ pub struct PriceQuote {
     pub time_units: TimeUnits,
     pub ckusdc_cost: Nat,
     pub discount_value: f32,
+    pub breakdown: PriceBreakdown,
 }
 
+/// Quote held for the buyer who requested it. Buying it honours exactly its price and discount
+/// value until it expires, and consumes it
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct QuoteReservation {
+    pub id: QuoteId,
+    pub buyer: Principal,
+    pub hiver_id: HiverId,
+    pub product_price: u128,
+    pub quote: PriceQuote,
+    /// cycle the quote was priced in
+    pub cycle_number: u64,
+    pub expires_at: Timestamp,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
 pub enum ContractStatus {
     Open,
     Paid,
@@ -43,6 +70,29 @@ pub enum ContractStatus {
     Cancelled,
 }
 
+impl ContractStatus {
+    /// Open -> Paid -> Fulfilled -> Redeemed, with Open and Paid contracts cancellable
+    pub fn can_transition_to(&self, next: &ContractStatus) -> bool {
+        matches!(
+            (self, next),
+            (ContractStatus::Open, ContractStatus::Paid)
+                | (ContractStatus::Open, ContractStatus::Cancelled)
+                | (ContractStatus::Paid, ContractStatus::Fulfilled)
+                | (ContractStatus::Paid, ContractStatus::Cancelled)
+                | (ContractStatus::Fulfilled, ContractStatus::Redeemed)
+        )
+    }
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct ContractTransition {
+    pub status: ContractStatus,
+    pub timestamp: Timestamp,
+    pub caller: Principal,
+    /// ledger block of the escrow transfer made with the transition, if any
+    pub block_index: Option<Nat>,
+}
+
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
 pub struct DiscountContract {
     pub id: ContractId,
@@ -52,14 +102,54 @@ pub struct DiscountContract {
     pub price: Nat,
     pub discount_value: f32,
     pub status: ContractStatus,
+    /// DAO discount minted for the buyer once the contract is fulfilled
+    pub discount_id: Option<u128>,
+    pub cycle_number: u64,
+}
+
+impl DiscountContract {
+    /// subaccount of the hiving canister holding the buyer's payment while the contract is paid
+    pub fn escrow_subaccount(&self) -> Subaccount {
+        let mut subaccount = [0u8; 32];
+        subaccount[24..].copy_from_slice(&self.id.to_be_bytes());
+        subaccount
+    }
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct HivingInitArgs {
+    pub dao_address: Principal,
+    /// ckUSDC ledger, or a local ICRC-2 ledger standing in for it
+    pub ckusdc_address: Principal,
+    /// defaults to the installing principal
+    pub owner: Option<Principal>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct HivingPoolInitArgs {
+    pub dao_address: Principal,
+    /// ckUSDC ledger discounts are paid with, or a local ICRC-2 ledger standing in for it
+    pub ckusdc_address: Principal,
+    /// defaults to the installing principal
+    pub owner: Option<Principal>,
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
 pub struct RegisterHivingCanisterArgs {
     pub canister_id: Principal,
+    pub owner: Principal,
     pub metadata: Vec<(String, MetadataValue)>,
 }
 
+/// Liveness report a registered hiving canister sends to the DAO every `HEARTBEAT_INTERVAL_NS`
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub struct HivingHeartbeat {
+    /// release of the canister code
+    pub version: String,
+    pub cycles: Nat,
+    pub owner: Principal,
+}
+
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
 pub struct PoolJoinProof {
     pub principal: Principal,
@@ -0,0 +1,84 @@
+use candid::{CandidType, Deserialize, Nat};
+use serde::Serialize;
+
+use crate::DiscountValue;
+use super::TimeUnits;
+
+const BASIS_POINTS: u32 = 10_000;
+
+/// How a hiver prices the discounts minted from their staking score
+#[derive(Clone, Debug, Default, CandidType, Deserialize, Serialize, PartialEq)]
+pub enum PricingPolicy {
+    /// share of the money the buyer saves with the discount
+    SavingsMarkup { basis_points: u32 },
+    /// same price for every discount
+    FlatFee { fee: Nat },
+    /// `price_per_time_unit` of the hiver's registration for each time unit sold
+    #[default]
+    PerTimeUnit,
+    /// price per time unit, raised with the share of the cycle cap already sold in the current
+    /// cycle, up to `max_surge_basis_points` on top when the cap is reached
+    DemandSurge { max_surge_basis_points: u32 },
+}
+
+/// What a price can depend on
+#[derive(Clone, Debug)]
+pub struct PricingInput {
+    pub product_price: u128,
+    pub discount_value: DiscountValue,
+    pub time_units: TimeUnits,
+    pub price_per_time_unit: Nat,
+    /// time units already sold in the current cycle
+    pub cycle_usage: TimeUnits,
+    pub cycle_cap: TimeUnits,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct PriceBreakdown {
+    pub policy: PricingPolicy,
+    /// money the buyer saves on the product with the discount
+    pub saved_amount: Nat,
+    pub base: Nat,
+    /// demand surcharge on top of the base price
+    pub surge: Nat,
+    pub total: Nat,
+}
+
+impl PricingPolicy {
+    pub fn quote(&self, input: &PricingInput) -> PriceBreakdown {
+        let saved_amount = Nat::from(input.product_price) * Nat::from(discount_basis_points(input.discount_value)) / Nat::from(BASIS_POINTS);
+        let per_time_unit = input.price_per_time_unit.clone() * Nat::from(input.time_units);
+
+        let (base, surge) = match self {
+            PricingPolicy::SavingsMarkup { basis_points } => {
+                (saved_amount.clone() * Nat::from(*basis_points) / Nat::from(BASIS_POINTS), Nat::from(0u8))
+            }
+            PricingPolicy::FlatFee { fee } => (fee.clone(), Nat::from(0u8)),
+            PricingPolicy::PerTimeUnit => (per_time_unit, Nat::from(0u8)),
+            PricingPolicy::DemandSurge { max_surge_basis_points } => {
+                let surge = if input.cycle_cap == 0 {
+                    Nat::from(0u8)
+                } else {
+                    let used = input.cycle_usage.min(input.cycle_cap);
+                    per_time_unit.clone() * Nat::from(*max_surge_basis_points) * Nat::from(used)
+                        / (Nat::from(BASIS_POINTS) * Nat::from(input.cycle_cap))
+                };
+                (per_time_unit, surge)
+            }
+        };
+
+        PriceBreakdown {
+            policy: self.clone(),
+            saved_amount,
+            total: base.clone() + surge.clone(),
+            base,
+            surge,
+        }
+    }
+}
+
+/// The discount percentage in whole basis points, so that prices are computed on integers. The
+/// float is only rounded once, far below the precision of a basis point
+fn discount_basis_points(discount_value: DiscountValue) -> u32 {
+    (discount_value as f64 * 100.0).round().clamp(0.0, BASIS_POINTS as f64) as u32
+}
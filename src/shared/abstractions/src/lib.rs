@@ -1,6 +1,8 @@
 pub mod nft;
 pub mod dao;
+pub mod hiving;
 pub mod token;
+pub mod ckusdc;
 pub mod runtime;
 
 #[cfg(feature = "with-chrono")]
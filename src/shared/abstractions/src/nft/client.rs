@@ -1,6 +1,7 @@
 use crate::nft::{TransferArg, TransferResult};
 use crate::runtime::{CallMode, ICallContext};
-use candid::Encode;
+use candid::{CandidType, Encode};
+use serde::Deserialize;
 use icrc_ledger_types::icrc::generic_metadata_value::MetadataValue;
 use icrc_ledger_types::icrc1::account::Account;
 use std::cell::RefCell;
@@ -11,12 +12,27 @@ pub struct NftClient<R: ICallContext> {
     pub canister_id: candid::Principal,
 }
 
+impl<R: ICallContext> Clone for NftClient<R> {
+    fn clone(&self) -> Self {
+        Self {
+            runtime: self.runtime.clone(),
+            canister_id: self.canister_id,
+        }
+    }
+}
+
 impl<R: ICallContext> NftClient<R> {
+    /// clones the call context first, so no borrow is held while the call is awaited
+    async fn call<Out>(&self, mode: CallMode, method: &str, args: &[u8]) -> Result<Out, R::Error>
+    where
+        Out: CandidType + for<'de> Deserialize<'de>,
+    {
+        let runtime = self.runtime.borrow().clone();
+        runtime.call(self.canister_id, mode, method, args).await
+    }
+
     pub async fn icrc7_total_supply(&self) -> Result<u128, R::Error> {
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Query, "icrc7_total_supply", &[])
-            .await
+        self.call(CallMode::Query, "icrc7_total_supply", &[]).await
     }
 
     pub async fn icrc7_balance_of(
@@ -27,10 +43,7 @@ impl<R: ICallContext> NftClient<R> {
         let args = Encode!(&accounts).unwrap();
         let args = args.as_slice();
 
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Query, method, args)
-            .await
+        self.call(CallMode::Query, method, args).await
     }
 
     pub async fn icrc7_tokens(
@@ -42,10 +55,7 @@ impl<R: ICallContext> NftClient<R> {
         let args = Encode!(&prev, &take).unwrap();
         let args = args.as_slice();
 
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Query, method, args)
-            .await
+        self.call(CallMode::Query, method, args).await
     }
 
     pub async fn icrc7_tokens_of(
@@ -58,10 +68,7 @@ impl<R: ICallContext> NftClient<R> {
         let args = Encode!(&account, &prev, &take).unwrap();
         let args = args.as_slice();
 
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Query, method, args)
-            .await
+        self.call(CallMode::Query, method, args).await
     }
 
     pub async fn icrc7_owner_of(
@@ -72,150 +79,65 @@ impl<R: ICallContext> NftClient<R> {
         let args = Encode!(&token_ids).unwrap();
         let args = args.as_slice();
 
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Query, method, args)
-            .await
+        self.call(CallMode::Query, method, args).await
     }
 
     pub async fn icrc7_collection_metadata(
         &self,
     ) -> Result<Vec<(String, MetadataValue)>, R::Error> {
-        self.runtime
-            .borrow()
-            .call(
-                self.canister_id,
-                CallMode::Query,
-                "icrc7_collection_metadata",
-                &[],
-            )
-            .await
+        self.call(CallMode::Query, "icrc7_collection_metadata", &[],).await
     }
 
     pub async fn icrc7_symbol(&self) -> Result<String, R::Error> {
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Query, "icrc7_symbol", &[])
-            .await
+        self.call(CallMode::Query, "icrc7_symbol", &[]).await
     }
 
     pub async fn icrc7_name(&self) -> Result<String, R::Error> {
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Query, "icrc7_name", &[])
-            .await
+        self.call(CallMode::Query, "icrc7_name", &[]).await
     }
 
     pub async fn icrc7_description(&self) -> Result<Option<String>, R::Error> {
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Query, "icrc7_description", &[])
-            .await
+        self.call(CallMode::Query, "icrc7_description", &[]).await
     }
 
     pub async fn icrc7_logo(&self) -> Result<Option<String>, R::Error> {
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Query, "icrc7_logo", &[])
-            .await
+        self.call(CallMode::Query, "icrc7_logo", &[]).await
     }
 
     pub async fn icrc7_supply_cap(&self) -> Result<Option<u128>, R::Error> {
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Query, "icrc7_supply_cap", &[])
-            .await
+        self.call(CallMode::Query, "icrc7_supply_cap", &[]).await
     }
 
     pub async fn icrc7_max_query_batch_size(&self) -> Result<Option<u128>, R::Error> {
-        self.runtime
-            .borrow()
-            .call(
-                self.canister_id,
-                CallMode::Query,
-                "icrc7_max_query_batch_size",
-                &[],
-            )
-            .await
+        self.call(CallMode::Query, "icrc7_max_query_batch_size", &[],).await
     }
 
     pub async fn icrc7_max_update_batch_size(&self) -> Result<Option<u128>, R::Error> {
-        self.runtime
-            .borrow()
-            .call(
-                self.canister_id,
-                CallMode::Query,
-                "icrc7_max_update_batch_size",
-                &[],
-            )
-            .await
+        self.call(CallMode::Query, "icrc7_max_update_batch_size", &[],).await
     }
 
     pub async fn icrc7_default_take_value(&self) -> Result<Option<u128>, R::Error> {
-        self.runtime
-            .borrow()
-            .call(
-                self.canister_id,
-                CallMode::Query,
-                "icrc7_default_take_value",
-                &[],
-            )
-            .await
+        self.call(CallMode::Query, "icrc7_default_take_value", &[],).await
     }
 
     pub async fn icrc7_max_take_value(&self) -> Result<Option<u128>, R::Error> {
-        self.runtime
-            .borrow()
-            .call(
-                self.canister_id,
-                CallMode::Query,
-                "icrc7_max_take_value",
-                &[],
-            )
-            .await
+        self.call(CallMode::Query, "icrc7_max_take_value", &[],).await
     }
 
     pub async fn icrc7_max_memo_size(&self) -> Result<Option<u128>, R::Error> {
-        self.runtime
-            .borrow()
-            .call(
-                self.canister_id,
-                CallMode::Query,
-                "icrc7_max_memo_size",
-                &[],
-            )
-            .await
+        self.call(CallMode::Query, "icrc7_max_memo_size", &[],).await
     }
 
     pub async fn icrc7_atomic_batch_transfers(&self) -> Result<Option<bool>, R::Error> {
-        self.runtime
-            .borrow()
-            .call(
-                self.canister_id,
-                CallMode::Query,
-                "icrc7_atomic_batch_transfers",
-                &[],
-            )
-            .await
+        self.call(CallMode::Query, "icrc7_atomic_batch_transfers", &[],).await
     }
 
     pub async fn icrc7_tx_window(&self) -> Result<Option<u128>, R::Error> {
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Query, "icrc7_tx_window", &[])
-            .await
+        self.call(CallMode::Query, "icrc7_tx_window", &[]).await
     }
 
     pub async fn icrc7_permitted_drift(&self) -> Result<Option<u128>, R::Error> {
-        self.runtime
-            .borrow()
-            .call(
-                self.canister_id,
-                CallMode::Query,
-                "icrc7_permitted_drift",
-                &[],
-            )
-            .await
+        self.call(CallMode::Query, "icrc7_permitted_drift", &[],).await
     }
 
     pub async fn icrc7_token_metadata(
@@ -226,22 +148,11 @@ impl<R: ICallContext> NftClient<R> {
         let args = Encode!(&token_ids).unwrap();
         let args = args.as_slice();
 
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Query, method, args)
-            .await
+        self.call(CallMode::Query, method, args).await
     }
 
     pub async fn icrc7_supported_standards(&self) -> Result<Vec<(String, String)>, R::Error> {
-        self.runtime
-            .borrow()
-            .call(
-                self.canister_id,
-                CallMode::Query,
-                "icrc7_supported_standards",
-                &[],
-            )
-            .await
+        self.call(CallMode::Query, "icrc7_supported_standards", &[],).await
     }
 
     pub async fn icrc7_transfer(
@@ -252,24 +163,31 @@ impl<R: ICallContext> NftClient<R> {
         let args = Encode!(&args).unwrap();
         let args = args.as_slice();
 
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Update, method, args)
-            .await
+        self.call(CallMode::Update, method, args).await
     }
 
     pub async fn privia_mint_token(
         &self,
         owner: Account,
         metadata: Vec<(String, MetadataValue)>,
+        mint_key: Option<u64>,
     ) -> Result<u128, R::Error> {
         let method = "privia_mint_token";
-        let args = Encode!(&owner, &metadata).unwrap();
+        let args = Encode!(&owner, &metadata, &mint_key).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Update, method, args).await
+    }
+
+    pub async fn privia_update_token_metadata(
+        &self,
+        token_id: u128,
+        metadata: Vec<(String, MetadataValue)>,
+    ) -> Result<Result<(), String>, R::Error> {
+        let method = "privia_update_token_metadata";
+        let args = Encode!(&token_id, &metadata).unwrap();
         let args = args.as_slice();
 
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Update, method, args)
-            .await
+        self.call(CallMode::Update, method, args).await
     }
-}
\ No newline at end of file
+}
@@ -8,10 +8,13 @@ use crate::Timestamp;
 pub trait ICanisterRuntime {
     fn get_caller(&self) -> Principal;
     fn get_time(&self) -> Timestamp;
+    fn is_controller(&self, principal: &Principal) -> bool;
+    fn get_canister_id(&self) -> Principal;
 }
 
+/// Contexts are cheap to clone, so clients clone them instead of holding a borrow across a call
 #[async_trait]
-pub trait ICallContext {
+pub trait ICallContext: Clone {
     type Error;
 
     async fn call<'a, Out>(
@@ -23,6 +26,10 @@ pub trait ICallContext {
     ) -> Result<Out, Self::Error>
     where
         Out: CandidType + for<'de> Deserialize<'de>;
+
+    /// true if the failed call certainly had no effect on the callee,
+    /// false if its outcome is unknown
+    fn is_clean_reject(error: &Self::Error) -> bool;
 }
 
 pub enum CallMode {
@@ -30,3 +37,34 @@ pub enum CallMode {
     Update,
 }
 
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InstallMode {
+    Install,
+    /// replaces the code and state of a canister, which may also be empty
+    Reinstall,
+    Upgrade,
+}
+
+/// Calls to the management canister made by canisters managing other canisters
+#[async_trait]
+pub trait ICanisterManager {
+    /// creates a canister controlled by `controllers` holding `cycles` on top of the creation fee
+    async fn create_canister(&self, controllers: Vec<Principal>, cycles: u128) -> Result<Principal, String>;
+
+    /// stores a chunk of at most 1 MiB in the canister's chunk store and returns its sha256
+    async fn upload_chunk(&self, canister_id: Principal, chunk: Vec<u8>) -> Result<Vec<u8>, String>;
+
+    /// installs the module made of the chunks stored in the canister's chunk store
+    async fn install_chunked_code(
+        &self,
+        canister_id: Principal,
+        mode: InstallMode,
+        chunk_hashes: Vec<Vec<u8>>,
+        module_hash: Vec<u8>,
+        arg: Vec<u8>,
+    ) -> Result<(), String>;
+
+    /// controllers of any canister, readable without being one of them
+    async fn get_controllers(&self, canister_id: Principal) -> Result<Vec<Principal>, String>;
+}
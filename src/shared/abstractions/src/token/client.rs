@@ -1,6 +1,7 @@
 use std::cell::RefCell;
 use std::rc::Rc;
-use candid::{Encode, Nat};
+use candid::{CandidType, Encode, Nat};
+use serde::Deserialize;
 use icrc_ledger_types::{
     icrc1::{
         account::Account,
@@ -19,7 +20,25 @@ pub struct TokenClient<R: ICallContext> {
     pub canister_id: candid::Principal,
 }
 
+impl<R: ICallContext> Clone for TokenClient<R> {
+    fn clone(&self) -> Self {
+        Self {
+            runtime: self.runtime.clone(),
+            canister_id: self.canister_id,
+        }
+    }
+}
+
 impl<R: ICallContext> TokenClient<R> {
+    /// clones the call context first, so no borrow is held while the call is awaited
+    async fn call<Out>(&self, mode: CallMode, method: &str, args: &[u8]) -> Result<Out, R::Error>
+    where
+        Out: CandidType + for<'de> Deserialize<'de>,
+    {
+        let runtime = self.runtime.borrow().clone();
+        runtime.call(self.canister_id, mode, method, args).await
+    }
+
     pub async fn privia_staking_log(
         &self,
         account: Account,
@@ -30,10 +49,15 @@ impl<R: ICallContext> TokenClient<R> {
         let args = Encode!(&account, &log_start, &log_end).unwrap();
         let args = args.as_slice();
 
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Query, method, args)
-            .await
+        self.call(CallMode::Query, method, args).await
+    }
+
+    pub async fn privia_stakers(&self, prev: Option<Account>, take: u32) -> Result<Vec<Account>, R::Error> {
+        let method = "privia_stakers";
+        let args = Encode!(&prev, &take).unwrap();
+        let args = args.as_slice();
+
+        self.call(CallMode::Query, method, args).await
     }
 
     pub async fn balance_of(&self, account: Account) -> Result<Nat, R::Error> {
@@ -41,64 +65,35 @@ impl<R: ICallContext> TokenClient<R> {
         let args = Encode!(&account).unwrap();
         let args = args.as_slice();
 
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Query, method, args)
-            .await
+        self.call(CallMode::Query, method, args).await
     }
 
     pub async fn decimals(&self) -> Result<u8, R::Error> {
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Query, "icrc1_decimals", &[])
-            .await
+        self.call(CallMode::Query, "icrc1_decimals", &[]).await
     }
 
     pub async fn name(&self) -> Result<String, R::Error> {
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Query, "icrc1_name", &[])
-            .await
+        self.call(CallMode::Query, "icrc1_name", &[]).await
     }
 
     pub async fn metadata(&self) -> Result<Vec<(String, crate::MetadataValue)>, R::Error> {
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Query, "icrc1_metadata", &[])
-            .await
+        self.call(CallMode::Query, "icrc1_metadata", &[]).await
     }
 
     pub async fn symbol(&self) -> Result<String, R::Error> {
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Query, "icrc1_symbol", &[])
-            .await
+        self.call(CallMode::Query, "icrc1_symbol", &[]).await
     }
 
     pub async fn total_supply(&self) -> Result<Nat, R::Error> {
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Query, "icrc1_total_supply", &[])
-            .await
+        self.call(CallMode::Query, "icrc1_total_supply", &[]).await
     }
 
     pub async fn fee(&self) -> Result<Nat, R::Error> {
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Query, "icrc1_fee", &[])
-            .await
+        self.call(CallMode::Query, "icrc1_fee", &[]).await
     }
 
     pub async fn minting_account(&self) -> Result<Option<Account>, R::Error> {
-        self.runtime
-            .borrow()
-            .call(
-                self.canister_id,
-                CallMode::Query,
-                "icrc1_minting_account",
-                &[],
-            )
-            .await
+        self.call(CallMode::Query, "icrc1_minting_account", &[],).await
     }
 
     pub async fn transfer(
@@ -108,10 +103,7 @@ impl<R: ICallContext> TokenClient<R> {
         let method = "icrc1_transfer";
         let args = &Encode!(&args).unwrap();
 
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Update, method, args)
-            .await
+        self.call(CallMode::Update, method, args).await
     }
 
     pub async fn transfer_from(
@@ -122,10 +114,7 @@ impl<R: ICallContext> TokenClient<R> {
         let args = Encode!(&args).unwrap();
         let args = args.as_slice();
 
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Update, method, args)
-            .await
+        self.call(CallMode::Update, method, args).await
     }
 
     pub async fn approve(
@@ -136,9 +125,6 @@ impl<R: ICallContext> TokenClient<R> {
         let args = Encode!(&args).unwrap();
         let args = args.as_slice();
 
-        self.runtime
-            .borrow()
-            .call(self.canister_id, CallMode::Update, method, args)
-            .await
+        self.call(CallMode::Update, method, args).await
     }
 }
\ No newline at end of file
@@ -1,10 +1,14 @@
-use abstractions::runtime::{CallMode, ICallContext, ICanisterRuntime};
+use abstractions::runtime::{CallMode, ICallContext, ICanisterManager, ICanisterRuntime, InstallMode};
 use async_trait::async_trait;
 use candid::{CandidType, Principal};
-use ic_cdk::call::Call;
+use ic_cdk::call::{Call, CallErrorExt};
+use ic_cdk::management_canister::{
+    self, CanisterInfoArgs, CanisterInstallMode, CanisterSettings, ChunkHash, CreateCanisterArgs, InstallChunkedCodeArgs, UploadChunkArgs,
+};
 use serde::Deserialize;
 use abstractions::Timestamp;
 
+#[derive(Clone)]
 pub struct CdkCallContext;
 
 #[async_trait]
@@ -30,6 +34,10 @@ impl ICallContext for CdkCallContext {
 
         Ok(call_result)
     }
+
+    fn is_clean_reject(error: &Self::Error) -> bool {
+        error.is_clean_reject()
+    }
 }
 
 pub struct RuntimeIcp {}
@@ -48,4 +56,70 @@ impl ICanisterRuntime for RuntimeIcp {
     fn get_time(&self) -> Timestamp {
         ic_cdk::api::time()
     }
+
+    fn is_controller(&self, principal: &Principal) -> bool {
+        ic_cdk::api::is_controller(principal)
+    }
+
+    fn get_canister_id(&self) -> Principal {
+        ic_cdk::api::canister_self()
+    }
+}
+
+pub struct CdkCanisterManager;
+
+#[async_trait]
+impl ICanisterManager for CdkCanisterManager {
+    async fn create_canister(&self, controllers: Vec<Principal>, cycles: u128) -> Result<Principal, String> {
+        let args = CreateCanisterArgs {
+            settings: Some(CanisterSettings {
+                controllers: Some(controllers),
+                ..CanisterSettings::default()
+            }),
+        };
+        let result = management_canister::create_canister_with_extra_cycles(&args, cycles)
+            .await
+            .map_err(|err| format!("{:?}", err))?;
+
+        Ok(result.canister_id)
+    }
+
+    async fn upload_chunk(&self, canister_id: Principal, chunk: Vec<u8>) -> Result<Vec<u8>, String> {
+        let args = UploadChunkArgs { canister_id, chunk };
+        let result = management_canister::upload_chunk(&args).await.map_err(|err| format!("{:?}", err))?;
+
+        Ok(result.hash)
+    }
+
+    async fn install_chunked_code(
+        &self,
+        canister_id: Principal,
+        mode: InstallMode,
+        chunk_hashes: Vec<Vec<u8>>,
+        module_hash: Vec<u8>,
+        arg: Vec<u8>,
+    ) -> Result<(), String> {
+        let mode = match mode {
+            InstallMode::Install => CanisterInstallMode::Install,
+            InstallMode::Reinstall => CanisterInstallMode::Reinstall,
+            InstallMode::Upgrade => CanisterInstallMode::Upgrade(None),
+        };
+        let args = InstallChunkedCodeArgs {
+            mode,
+            target_canister: canister_id,
+            store_canister: None,
+            chunk_hashes_list: chunk_hashes.into_iter().map(|hash| ChunkHash { hash }).collect(),
+            wasm_module_hash: module_hash,
+            arg,
+        };
+
+        management_canister::install_chunked_code(&args).await.map_err(|err| format!("{:?}", err))
+    }
+
+    async fn get_controllers(&self, canister_id: Principal) -> Result<Vec<Principal>, String> {
+        let args = CanisterInfoArgs { canister_id, num_requested_changes: None };
+        let result = management_canister::canister_info(&args).await.map_err(|err| format!("{:?}", err))?;
+
+        Ok(result.controllers)
+    }
 }
@@ -1,6 +1,6 @@
 use crate::utils::helpers::*;
 use crate::utils::{Actor, Actors, AgentCallContext};
-use abstractions::dao::{Cycle, DaoClient, Discount, Proposal, ProposalType, VoteOption};
+use abstractions::dao::{Cycle, DaoClient, DaoError, Discount, Proposal, ProposalType, VoteOption};
 use abstractions::nft::NftClient;
 use abstractions::token::TokenClient;
 use chrono::TimeDelta;
@@ -199,6 +199,7 @@ This change would require an update to the staking smart contract logic. Once ap
             .dao
             .voting_create_proposal(ProposalType::Generic, text)
             .await
+            .unwrap()
             .unwrap();
 
         let proposal = self
@@ -206,6 +207,7 @@ This change would require an update to the staking smart contract logic. Once ap
             .voting_get_proposal(proposal_id)
             .await
             .unwrap()
+            .unwrap()
             .unwrap();
 
         console_log(format!("Proposal created: {}", proposal));
@@ -234,7 +236,7 @@ This change would require an update to the staking smart contract logic. Once ap
 
     async fn print_balance_and_score(&self, actor: &Actor) {
         let balance = self.token.balance_of(actor.account).await.unwrap();
-        let score = self.dao.get_staking_score(actor.account).await.unwrap();
+        let score = self.dao.get_staking_score(actor.account).await.unwrap().unwrap();
         println!(
             "  Wallet {} = {} PVT (Staking score = {})",
             actor.name, balance, score
@@ -243,11 +245,12 @@ This change would require an update to the staking smart contract logic. Once ap
 
     async fn print_balance_score_discount(&self, actor: &Actor, price: u128) {
         let balance = self.token.balance_of(actor.account).await.unwrap();
-        let score = self.dao.get_staking_score(actor.account).await.unwrap();
+        let score = self.dao.get_staking_score(actor.account).await.unwrap().unwrap();
         let discount = self
             .dao
             .calculate_max_discount(&actor.account, &price)
             .await
+            .unwrap()
             .unwrap();
         println!(
             "  Wallet {} = {} PVT (Staking Score = {}, Discount = {}%)",
@@ -256,7 +259,7 @@ This change would require an update to the staking smart contract logic. Once ap
     }
 
     async fn print_current_cycle(&self) -> Cycle {
-        let current_cycle = self.dao.get_current_cycle().await.unwrap();
+        let current_cycle = self.dao.get_current_cycle().await.unwrap().unwrap();
         console_log(format!("Current cycle: {}", current_cycle));
         current_cycle
     }
@@ -271,16 +274,24 @@ This change would require an update to the staking smart contract logic. Once ap
 
     async fn process_vote_result(
         &self,
-        vote_result: Result<u64, AgentError>,
+        vote_result: Result<Result<u64, DaoError>, AgentError>,
     ) -> Result<(), VotingError> {
         {
             match vote_result {
-                Ok(id) => {
+                Ok(Ok(id)) => {
                     console_log(format!("Vote accepted, vote_id: {id}",));
-                    let vote = self.dao.voting_get_vote(id).await.unwrap();
+                    let vote = self.dao.voting_get_vote(id).await.unwrap().unwrap();
                     console_log(format!("{}", vote.unwrap()));
                     Ok(())
                 }
+                Ok(Err(err)) => {
+                    console_log(format!("Vote rejected: {:?}", err));
+                    match err {
+                        DaoError::VotingNotActive => Err(VotingError::ProposalNotActive),
+                        DaoError::NoStakingBalance => Err(VotingError::ZeroBalnce),
+                        _ => Err(VotingError::Generic),
+                    }
+                }
                 Err(e) => match e {
                     AgentError::CertifiedReject {
                         reject,
@@ -288,14 +299,7 @@ This change would require an update to the staking smart contract logic. Once ap
                     } => {
                         console_log("Vote rejected".to_string());
                         pretty_print_reject(&reject.reject_message);
-
-                        if reject.reject_message.contains("not active") {
-                            Err(VotingError::ProposalNotActive)
-                        } else if reject.reject_message.contains("balance is zero") {
-                            Err(VotingError::ZeroBalnce)
-                        } else {
-                            Err(VotingError::Generic)
-                        }
+                        Err(VotingError::Generic)
                     }
                     _ => {
                         console_log(format!("Vote rejected, error: {e}"));
@@ -33,22 +33,25 @@ pub async fn check(dao: &DaoClient<AgentCallContext>, actors: &Actors) {
     // let res = dao.voting_get_all_votes(proposal_id).await.unwrap();
     // println!("voting_get_all_votes: {:?}", res);
 
-    let res = dao.get_current_cycle().await.unwrap();
+    let res = dao.get_current_cycle().await.unwrap().unwrap();
     println!("get_current_cycle: {}", res);
 
-    let res = dao.get_staking_score(discounter).await.unwrap();
+    let res = dao.get_staking_score(discounter).await.unwrap().unwrap();
     println!("get_staking_score: {}", res);
 
     let price = 1500u128;
-    let discount_value = dao.calculate_max_discount(&discounter, &price).await.unwrap();
+    let discount_value = dao.calculate_max_discount(&discounter, &price).await.unwrap().unwrap();
     println!("calculate_max_discount: {}", discount_value);
 
     let discount = DiscountRequest {
-        value: discount_value,
-        owner: discounter
+        price,
+        owner: discounter,
+        expiry: None,
+        value: None,
     };
-    let token_id = dao.mint_discount(discounter, discount).await.unwrap();
-    println!("mint_discount: {}", token_id);
+    // only hiving canisters authorized by the hiver may mint, so a direct call is rejected
+    let res = dao.mint_discount(discounter, discount).await.unwrap();
+    println!("mint_discount: {:?}", res);
 
 }
 
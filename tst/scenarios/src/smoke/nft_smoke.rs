@@ -77,7 +77,7 @@ pub async fn check(nft: &NftClient<AgentCallContext>, actors: &Actors) {
         ("value".to_string(), MetadataValue::Nat(Nat::from(15u8))),
         ("max_price".to_string(), MetadataValue::Nat(Nat::from(15u8)))
     ]);
-    let res = nft.privia_mint_token(owner, metadata).await.unwrap();
+    let res = nft.privia_mint_token(owner, metadata, None).await.unwrap();
     println!("privia_mint_token: {}", res);
 
     let token_id  = Nat::from(res);
@@ -18,6 +18,7 @@ use std::{
 };
 use crate::AppSettings;
 
+#[derive(Clone)]
 pub struct AgentCallContext {
     pub agent: Agent,
 }
@@ -60,6 +61,11 @@ impl ICallContext for AgentCallContext {
         let result = Decode!(response.as_slice(), Out).map_err(|e| Self::Error::from(e))?;
         Ok(result)
     }
+
+    /// scenarios treat every failure as possibly executed
+    fn is_clean_reject(_error: &Self::Error) -> bool {
+        false
+    }
 }
 
 